@@ -5,6 +5,10 @@ to apply migrations from files. When deployed, the application will have the mig
 contents embedded. During development, the CLI tool can use a `Migrant.toml` configuration file,
 while the application can embed the settings to avoid configuration in deployed environments.
 
+The migrations here are listed by hand. To embed every migration in a CLI-managed directory
+instead, call `migrant_lib::build::embed_migrations` from a build script and pass
+`migrant_lib::embed_migrations!()` to `Config::use_migrations`.
+
 NOTE: The feature-gates are only required here so the example will compile when running
       tests with and without features. In regular usage, the `cfg`s are not required since
       the specified database feature should be enabled in your `Cargo.toml` entry.
@@ -0,0 +1,150 @@
+/*!
+Async database drivers
+
+Server databases get native async connections (`tokio-postgres`,
+`mysql_async`). Sqlite and DuckDB have no async driver:
+[`AsyncConfig`](crate::AsyncConfig) runs their operations on tokio's blocking pool through the synchronous
+[`Config`](crate::Config) instead, so it never appears here.
+*/
+use std::fmt;
+
+use crate::config::Config;
+use crate::errors::*;
+use crate::DbKind;
+
+#[cfg(feature = "mysql")]
+pub(crate) mod mysql;
+#[cfg(feature = "postgres")]
+pub(crate) mod pg;
+
+/// A live async connection to one of the supported server databases
+pub(crate) enum AsyncDbConnection {
+    #[cfg(feature = "postgres")]
+    Postgres(pg::AsyncPgConn),
+    #[cfg(feature = "mysql")]
+    MySql(mysql::AsyncMySqlConn),
+}
+
+impl fmt::Debug for AsyncDbConnection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self {
+            #[cfg(feature = "postgres")]
+            AsyncDbConnection::Postgres(_) => "postgres",
+            #[cfg(feature = "mysql")]
+            AsyncDbConnection::MySql(_) => "mysql",
+            #[allow(unreachable_patterns)]
+            _ => "unknown",
+        };
+        write!(f, "AsyncDbConnection({})", kind)
+    }
+}
+
+/// Dispatch an async method call to the active backend connection
+macro_rules! dispatch {
+    ($self:expr, $conn:ident => $body:expr) => {
+        match $self {
+            #[cfg(feature = "postgres")]
+            AsyncDbConnection::Postgres($conn) => $body.await,
+            #[cfg(feature = "mysql")]
+            AsyncDbConnection::MySql($conn) => $body.await,
+            #[allow(unreachable_patterns)]
+            _ => Err(Error::FeatureRequired("postgres / mysql")),
+        }
+    };
+}
+
+// method arguments are unused in the fallback arm when no server features are enabled
+#[allow(unused_variables)]
+impl AsyncDbConnection {
+    /// Open a new connection for the given (server database) config
+    pub(crate) async fn connect(config: &Config) -> Result<Self> {
+        match config.database_type() {
+            DbKind::Postgres => {
+                #[cfg(feature = "postgres")]
+                {
+                    let conn_str = config.connect_string()?;
+                    Ok(AsyncDbConnection::Postgres(
+                        pg::AsyncPgConn::connect(&conn_str, &config.tls_files()).await?,
+                    ))
+                }
+                #[cfg(not(feature = "postgres"))]
+                Err(Error::FeatureRequired("postgres"))
+            }
+            DbKind::MySql => {
+                #[cfg(feature = "mysql")]
+                {
+                    let conn_str = config.connect_string()?;
+                    Ok(AsyncDbConnection::MySql(
+                        mysql::AsyncMySqlConn::connect(&conn_str, &config.mysql_tls()).await?,
+                    ))
+                }
+                #[cfg(not(feature = "mysql"))]
+                Err(Error::FeatureRequired("mysql"))
+            }
+            kind => Err(Error::Config(format!(
+                "No async server connection for database-type: {}",
+                kind
+            ))),
+        }
+    }
+
+    /// Check whether the `__migrant_migrations` table exists
+    pub(crate) async fn migration_table_exists(&mut self) -> Result<bool> {
+        dispatch!(self, c => c.migration_table_exists())
+    }
+
+    /// Create the `__migrant_migrations` table if missing, returning `true` if created
+    pub(crate) async fn setup_migration_table(&mut self) -> Result<bool> {
+        dispatch!(self, c => c.setup_migration_table())
+    }
+
+    /// Select all applied migration tags
+    pub(crate) async fn applied_tags(&mut self) -> Result<Vec<String>> {
+        dispatch!(self, c => c.applied_tags())
+    }
+
+    /// Record a migration tag as applied
+    pub(crate) async fn insert_tag(&mut self, tag: &str) -> Result<()> {
+        dispatch!(self, c => c.insert_tag(tag))
+    }
+
+    /// Remove a migration tag from the applied set
+    pub(crate) async fn remove_tag(&mut self, tag: &str) -> Result<()> {
+        dispatch!(self, c => c.remove_tag(tag))
+    }
+
+    /// Execute a batch of sql statements
+    pub(crate) async fn execute_batch(&mut self, sql: &str) -> Result<()> {
+        dispatch!(self, c => c.execute_batch(sql))
+    }
+
+    /// Begin a transaction on this connection
+    pub(crate) async fn begin(&mut self) -> Result<()> {
+        dispatch!(self, c => c.begin())
+    }
+
+    /// Commit the current transaction on this connection
+    pub(crate) async fn commit(&mut self) -> Result<()> {
+        dispatch!(self, c => c.commit())
+    }
+
+    /// Roll back the current transaction on this connection
+    pub(crate) async fn rollback(&mut self) -> Result<()> {
+        dispatch!(self, c => c.rollback())
+    }
+
+    /// Acquire the session-level advisory lock that serializes migration runs
+    pub(crate) async fn acquire_lock(&mut self) -> Result<()> {
+        dispatch!(self, c => c.acquire_lock())
+    }
+
+    /// Try to acquire the advisory lock without waiting
+    pub(crate) async fn try_acquire_lock(&mut self) -> Result<bool> {
+        dispatch!(self, c => c.try_acquire_lock())
+    }
+
+    /// Release the session-level advisory lock
+    pub(crate) async fn release_lock(&mut self) -> Result<()> {
+        dispatch!(self, c => c.release_lock())
+    }
+}
@@ -0,0 +1,159 @@
+/*!
+Async MySQL driver, backed by `mysql_async`
+*/
+use mysql_async::{prelude::Queryable, ClientIdentity, Conn, Opts, OptsBuilder, SslOpts};
+
+use crate::config::MySqlTls;
+use crate::drivers::mysql::{use_tls, ADVISORY_LOCK_NAME};
+use crate::drivers::sql;
+use crate::errors::*;
+use crate::macros::{bail, err};
+
+/// Build the `SslOpts` for a connection, `None` when TLS is not configured
+fn ssl_opts(tls: &MySqlTls) -> Result<Option<SslOpts>> {
+    if !use_tls(tls)? {
+        return Ok(None);
+    }
+    let identity = tls.files.client_cert.clone().map(|cert| {
+        let identity = ClientIdentity::new(cert.into());
+        match &tls.files.client_key_password {
+            Some(password) => identity.with_password(password.clone()),
+            None => identity,
+        }
+    });
+    let root_certs = tls
+        .files
+        .root_cert
+        .iter()
+        .cloned()
+        .map(Into::into)
+        .collect();
+    Ok(Some(
+        SslOpts::default()
+            .with_root_certs(root_certs)
+            .with_client_identity(identity)
+            .with_danger_skip_domain_validation(tls.skip_domain_validation),
+    ))
+}
+
+/// A live async mysql connection
+pub(crate) struct AsyncMySqlConn {
+    conn: Conn,
+}
+
+impl AsyncMySqlConn {
+    pub(crate) async fn connect(conn_str: &str, tls: &MySqlTls) -> Result<Self> {
+        let mut opts = Opts::from_url(conn_str)
+            .map_err(|e| err!(Config, "Error parsing mysql connection string: {}", e))?;
+        // TLS parameters in the connection string apply unless settings override them
+        if let Some(ssl) = ssl_opts(tls)? {
+            opts = OptsBuilder::from_opts(opts).ssl_opts(ssl).into();
+        }
+        let conn = Conn::new(opts).await?;
+        Ok(Self { conn })
+    }
+
+    pub(crate) async fn migration_table_exists(&mut self) -> Result<bool> {
+        let rows: Vec<u32> = self.conn.query(sql::MYSQL_MIGRATION_TABLE_EXISTS).await?;
+        if rows.len() != 1 {
+            bail!(
+                Migration,
+                "Migration table check: expected 1 returned row, got {}",
+                rows.len()
+            )
+        }
+        Ok(rows[0] == 1)
+    }
+
+    pub(crate) async fn setup_migration_table(&mut self) -> Result<bool> {
+        if self.migration_table_exists().await? {
+            return Ok(false);
+        }
+        self.conn.query_drop(sql::MYSQL_CREATE_TABLE).await?;
+        Ok(true)
+    }
+
+    pub(crate) async fn applied_tags(&mut self) -> Result<Vec<String>> {
+        Ok(self.conn.query(sql::GET_MIGRATIONS).await?)
+    }
+
+    pub(crate) async fn insert_tag(&mut self, tag: &str) -> Result<()> {
+        self.conn
+            .exec_drop(sql::INSERT_MIGRATION_MYSQL, (tag,))
+            .await?;
+        Ok(())
+    }
+
+    pub(crate) async fn remove_tag(&mut self, tag: &str) -> Result<()> {
+        self.conn
+            .exec_drop(sql::REMOVE_MIGRATION_MYSQL, (tag,))
+            .await?;
+        Ok(())
+    }
+
+    pub(crate) async fn execute_batch(&mut self, stmt: &str) -> Result<()> {
+        if stmt.is_empty() {
+            return Ok(());
+        }
+        self.conn
+            .query_drop(stmt)
+            .await
+            .map_err(|e| err!(Migration, "{}", e))
+    }
+
+    pub(crate) async fn begin(&mut self) -> Result<()> {
+        self.execute_batch("begin").await
+    }
+
+    pub(crate) async fn commit(&mut self) -> Result<()> {
+        self.execute_batch("commit").await
+    }
+
+    pub(crate) async fn rollback(&mut self) -> Result<()> {
+        self.execute_batch("rollback").await
+    }
+
+    /// Take the named advisory lock, waiting until it is available (a negative
+    /// `GET_LOCK` timeout waits indefinitely). MySQL releases it automatically
+    /// if this connection (session) drops.
+    pub(crate) async fn acquire_lock(&mut self) -> Result<()> {
+        let got: Option<Option<i64>> = self
+            .conn
+            .query_first(format!("select get_lock('{}', -1)", ADVISORY_LOCK_NAME))
+            .await
+            .map_err(|e| err!(Migration, "{}", e))?;
+        match got {
+            Some(Some(1)) => Ok(()),
+            _ => bail!(
+                Migration,
+                "could not acquire mysql advisory lock `{}`",
+                ADVISORY_LOCK_NAME
+            ),
+        }
+    }
+
+    /// Take the named advisory lock if it is free, without waiting
+    pub(crate) async fn try_acquire_lock(&mut self) -> Result<bool> {
+        let got: Option<Option<i64>> = self
+            .conn
+            .query_first(format!("select get_lock('{}', 0)", ADVISORY_LOCK_NAME))
+            .await
+            .map_err(|e| err!(Migration, "{}", e))?;
+        match got {
+            Some(Some(1)) => Ok(true),
+            Some(Some(0)) => Ok(false),
+            _ => bail!(
+                Migration,
+                "could not acquire mysql advisory lock `{}`",
+                ADVISORY_LOCK_NAME
+            ),
+        }
+    }
+
+    pub(crate) async fn release_lock(&mut self) -> Result<()> {
+        self.conn
+            .query_drop(format!("select release_lock('{}')", ADVISORY_LOCK_NAME))
+            .await
+            .map_err(|e| err!(Migration, "{}", e))
+    }
+}
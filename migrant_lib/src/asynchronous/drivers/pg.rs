@@ -0,0 +1,131 @@
+/*!
+Async postgres driver, backed by `tokio-postgres`
+*/
+use tokio_postgres::tls::{MakeTlsConnect, TlsConnect};
+use tokio_postgres::{Client, NoTls, Socket};
+
+use crate::config::TlsFiles;
+use crate::drivers::pg::{error_message, ADVISORY_LOCK_KEY};
+use crate::drivers::{pg_tls, sql};
+use crate::errors::*;
+use crate::macros::err;
+
+/// A live async postgres connection
+pub(crate) struct AsyncPgConn {
+    client: Client,
+}
+
+/// Connect and spawn the connection's IO task onto the current runtime.
+/// The task ends when the client (and so this connection) is dropped.
+async fn spawn_connection<T>(conn_str: &str, tls: T) -> Result<Client>
+where
+    T: MakeTlsConnect<Socket>,
+    T::Stream: Send + 'static,
+    T::TlsConnect: Send,
+    <T::TlsConnect as TlsConnect<Socket>>::Future: Send,
+{
+    let (client, connection) = tokio_postgres::connect(conn_str, tls).await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            log::error!("postgres connection error: {}", e);
+        }
+    });
+    Ok(client)
+}
+
+impl AsyncPgConn {
+    /// Connect to postgres, with TLS chosen the same way as the synchronous
+    /// driver (see `PgConn::connect`).
+    pub(crate) async fn connect(conn_str: &str, files: &TlsFiles) -> Result<Self> {
+        let client = match pg_tls::connector(conn_str, files)? {
+            (conn_str, Some(tls)) => spawn_connection(&conn_str, tls).await?,
+            (conn_str, None) => spawn_connection(&conn_str, NoTls).await?,
+        };
+        Ok(Self { client })
+    }
+
+    pub(crate) async fn migration_table_exists(&mut self) -> Result<bool> {
+        let row = self
+            .client
+            .query_one(sql::PG_MIGRATION_TABLE_EXISTS, &[])
+            .await?;
+        Ok(row.get(0))
+    }
+
+    pub(crate) async fn setup_migration_table(&mut self) -> Result<bool> {
+        if self.migration_table_exists().await? {
+            return Ok(false);
+        }
+        self.client.execute(sql::CREATE_TABLE, &[]).await?;
+        Ok(true)
+    }
+
+    pub(crate) async fn applied_tags(&mut self) -> Result<Vec<String>> {
+        let rows = self.client.query(sql::GET_MIGRATIONS, &[]).await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    pub(crate) async fn insert_tag(&mut self, tag: &str) -> Result<()> {
+        self.client
+            .execute(sql::INSERT_MIGRATION_PG_SQLITE, &[&tag])
+            .await?;
+        Ok(())
+    }
+
+    pub(crate) async fn remove_tag(&mut self, tag: &str) -> Result<()> {
+        self.client
+            .execute(sql::REMOVE_MIGRATION_PG_SQLITE, &[&tag])
+            .await?;
+        Ok(())
+    }
+
+    pub(crate) async fn execute_batch(&mut self, stmt: &str) -> Result<()> {
+        if stmt.is_empty() {
+            return Ok(());
+        }
+        self.client
+            .batch_execute(stmt)
+            .await
+            .map_err(|e| err!(Migration, "{}", error_message(&e)))
+    }
+
+    pub(crate) async fn begin(&mut self) -> Result<()> {
+        self.execute_batch("begin").await
+    }
+
+    pub(crate) async fn commit(&mut self) -> Result<()> {
+        self.execute_batch("commit").await
+    }
+
+    pub(crate) async fn rollback(&mut self) -> Result<()> {
+        self.execute_batch("rollback").await
+    }
+
+    /// Take the session-level advisory lock, waiting until it is available.
+    /// Postgres releases it automatically if this connection (session) drops.
+    pub(crate) async fn acquire_lock(&mut self) -> Result<()> {
+        self.client
+            .execute("select pg_advisory_lock($1)", &[&ADVISORY_LOCK_KEY])
+            .await
+            .map_err(|e| err!(Migration, "{}", error_message(&e)))?;
+        Ok(())
+    }
+
+    /// Take the session-level advisory lock if it is free, without waiting
+    pub(crate) async fn try_acquire_lock(&mut self) -> Result<bool> {
+        let row = self
+            .client
+            .query_one("select pg_try_advisory_lock($1)", &[&ADVISORY_LOCK_KEY])
+            .await
+            .map_err(|e| err!(Migration, "{}", error_message(&e)))?;
+        Ok(row.get(0))
+    }
+
+    pub(crate) async fn release_lock(&mut self) -> Result<()> {
+        self.client
+            .execute("select pg_advisory_unlock($1)", &[&ADVISORY_LOCK_KEY])
+            .await
+            .map_err(|e| err!(Migration, "{}", error_message(&e)))?;
+        Ok(())
+    }
+}
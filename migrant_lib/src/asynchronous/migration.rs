@@ -0,0 +1,197 @@
+/*!
+Async programmable migrations
+*/
+use std::path::PathBuf;
+
+use super::{AsyncConfig, AsyncMigrationFuture};
+use crate::config::Config;
+use crate::errors::*;
+use crate::migratable::Migratable;
+use crate::migration::MigrationMetadata;
+use crate::migrator::Direction;
+use crate::DbKind;
+
+/// Database connection information passed to `AsyncFnMigration` functions
+///
+/// Owns a clone of the migrator's [`AsyncConfig`], so it can be moved into the
+/// returned future.
+#[derive(Debug, Clone)]
+pub struct AsyncConnConfig {
+    config: AsyncConfig,
+}
+
+impl AsyncConnConfig {
+    pub(crate) fn new(config: &AsyncConfig) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    /// Return the database type
+    pub fn database_type(&self) -> DbKind {
+        self.config.database_type()
+    }
+
+    /// Return a connection string for postgres or mysql
+    pub fn connect_string(&self) -> Result<String> {
+        self.config.connect_string()
+    }
+
+    /// Return a sqlite database path.
+    /// In-memory databases return `:memory:`; use
+    /// [`sqlite_connection`](AsyncConnConfig::sqlite_connection) to operate on them.
+    pub fn database_path(&self) -> Result<PathBuf> {
+        self.config.database_path()
+    }
+
+    /// Return a shared handle to the live sqlite connection.
+    ///
+    /// The handle is synchronous; run any substantial work with it on tokio's
+    /// blocking pool (`tokio::task::spawn_blocking`).
+    #[cfg(feature = "sqlite")]
+    pub fn sqlite_connection(
+        &self,
+    ) -> Result<std::sync::Arc<std::sync::Mutex<rusqlite::Connection>>> {
+        self.config.sqlite_connection()
+    }
+}
+
+/// No-op to use with `AsyncFnMigration`
+pub fn async_noop(_: AsyncConnConfig) -> AsyncMigrationFuture {
+    Box::pin(async { Ok(()) })
+}
+
+/// Define an async programmable migration
+///
+/// The async counterpart of [`FnMigration`](crate::FnMigration): `up` and `down`
+/// are functions taking an [`AsyncConnConfig`] and returning a boxed future.
+/// These migrations can only be applied by an [`AsyncMigrator`](crate::AsyncMigrator);
+/// the synchronous `Migrator` reports an error for them.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use migrant_lib::{AsyncConnConfig, AsyncFnMigration};
+/// # use migrant_lib::asynchronous::AsyncMigrationFuture;
+/// fn add_data(config: AsyncConnConfig) -> AsyncMigrationFuture {
+///     Box::pin(async move {
+///         let _conn_str = config.connect_string()?;
+///         // do stuff...
+///         Ok(())
+///     })
+/// }
+///
+/// AsyncFnMigration::with_tag("add-user-data")
+///     .up(add_data)
+///     .down(migrant_lib::asynchronous::async_noop);
+/// ```
+#[derive(Clone, Debug)]
+pub struct AsyncFnMigration<T, U> {
+    pub(crate) tag: String,
+    pub(crate) up: Option<T>,
+    pub(crate) down: Option<U>,
+    pub(crate) metadata: MigrationMetadata,
+}
+
+impl<T, U> AsyncFnMigration<T, U>
+where
+    T: 'static + Clone + Send + Sync + Fn(AsyncConnConfig) -> AsyncMigrationFuture,
+    U: 'static + Clone + Send + Sync + Fn(AsyncConnConfig) -> AsyncMigrationFuture,
+{
+    /// Create a new `AsyncFnMigration` with the given tag
+    pub fn with_tag(tag: &str) -> Self {
+        Self {
+            tag: tag.to_owned(),
+            up: None,
+            down: None,
+            metadata: MigrationMetadata::default(),
+        }
+    }
+
+    /// Describe this migration, see [`MigrationMetadata`]
+    pub fn with_metadata(mut self, metadata: MigrationMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Function to use for `up` migrations
+    pub fn up(mut self, f_up: T) -> Self {
+        self.up = Some(f_up);
+        self
+    }
+
+    /// Function to use for `down` migrations
+    pub fn down(mut self, f_down: U) -> Self {
+        self.down = Some(f_down);
+        self
+    }
+
+    /// Box this migration up so it can be stored with other migrations
+    pub fn boxed(self) -> Box<dyn Migratable> {
+        Box::new(self)
+    }
+
+    fn sync_error(&self) -> Box<dyn std::error::Error> {
+        format!(
+            "`{}` is an async function migration and can only be applied by an `AsyncMigrator`",
+            self.tag
+        )
+        .into()
+    }
+}
+
+impl<T, U> Migratable for AsyncFnMigration<T, U>
+where
+    T: 'static + Clone + Send + Sync + Fn(AsyncConnConfig) -> AsyncMigrationFuture,
+    U: 'static + Clone + Send + Sync + Fn(AsyncConnConfig) -> AsyncMigrationFuture,
+{
+    fn apply_up(&self, _: &Config) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        match self.up {
+            Some(_) => Err(self.sync_error()),
+            None => Ok(()),
+        }
+    }
+
+    fn apply_down(&self, _: &Config) -> std::result::Result<(), Box<dyn std::error::Error>> {
+        match self.down {
+            Some(_) => Err(self.sync_error()),
+            None => Ok(()),
+        }
+    }
+
+    fn apply_async(
+        &self,
+        config: &AsyncConfig,
+        direction: Direction,
+    ) -> Option<AsyncMigrationFuture> {
+        let conn = AsyncConnConfig::new(config);
+        Some(match direction {
+            Direction::Up => match self.up {
+                Some(ref up) => up(conn),
+                None => async_noop(conn),
+            },
+            Direction::Down => match self.down {
+                Some(ref down) => down(conn),
+                None => async_noop(conn),
+            },
+        })
+    }
+
+    fn tag(&self) -> String {
+        self.tag.to_owned()
+    }
+
+    fn description(&self, _: &Direction) -> String {
+        self.tag()
+    }
+
+    fn metadata(&self, _config: &Config) -> MigrationMetadata {
+        self.metadata.clone()
+    }
+
+    /// Like `FnMigration`, function migrations run arbitrary code, so the
+    /// migrator does not wrap them in a transaction. Always `false`.
+    fn use_transaction(&self, _config: &Config, _direction: Direction) -> bool {
+        false
+    }
+}
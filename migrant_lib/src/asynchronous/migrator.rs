@@ -0,0 +1,407 @@
+/*!
+Async migration application
+*/
+use std::collections::HashSet;
+
+use super::AsyncConfig;
+use crate::backup;
+use crate::errors::*;
+use crate::macros::bail;
+use crate::migratable::Migratable;
+use crate::migration::describe_error;
+use crate::migrator::{CancelToken, Direction, ForceMode, Migrator, Report, Step};
+use crate::util::print_flush;
+
+/// Async migration applicator
+///
+/// Mirrors [`Migrator`]: the same settings, transaction wrapping, advisory
+/// locking and [`Report`], driven by an [`AsyncConfig`].
+#[derive(Debug, Clone)]
+pub struct AsyncMigrator {
+    config: AsyncConfig,
+    direction: Direction,
+    force: ForceMode,
+    fake: bool,
+    all: bool,
+    show_output: bool,
+    synchronized: bool,
+    cancel: Option<CancelToken>,
+    backup: Option<String>,
+}
+
+impl AsyncMigrator {
+    /// Initialize a new `AsyncMigrator` with a given `&AsyncConfig`
+    pub fn with_config(config: &AsyncConfig) -> Self {
+        Self {
+            config: config.clone(),
+            direction: Direction::Up,
+            force: ForceMode::Off,
+            fake: false,
+            all: false,
+            show_output: true,
+            synchronized: true,
+            cancel: None,
+            backup: None,
+        }
+    }
+
+    /// Set `direction`. Default is `Up`. See [`Migrator::direction`].
+    pub fn direction(mut self, dir: Direction) -> Self {
+        self.direction = dir;
+        self
+    }
+
+    /// Set how the run handles a migration that fails to apply.
+    /// See [`Migrator::force`].
+    pub fn force(mut self, force: ForceMode) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Set `fake` to fake application of migrations. See [`Migrator::fake`].
+    pub fn fake(mut self, fake: bool) -> Self {
+        self.fake = fake;
+        self
+    }
+
+    /// Set `all` to run all remaining available migrations in the given `direction`
+    pub fn all(mut self, all: bool) -> Self {
+        self.all = all;
+        self
+    }
+
+    /// Toggle migration application output. Default is `true`
+    pub fn show_output(mut self, show_output: bool) -> Self {
+        self.show_output = show_output;
+        self
+    }
+
+    /// Serialize migration runs across processes using a database advisory lock.
+    /// Default is `true`. See [`Migrator::synchronized`].
+    pub fn synchronized(mut self, synchronized: bool) -> Self {
+        self.synchronized = synchronized;
+        self
+    }
+
+    /// Stop the run early once `token` is cancelled. See [`Migrator::cancel_on`].
+    ///
+    /// Dropping the `apply` future also stops a run, but may do so mid-migration;
+    /// a cancelled token lets the current migration finish first.
+    pub fn cancel_on(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Back the sqlite database up before a run with migrations to apply, and
+    /// restore it if the run fails. See [`Migrator::backup_before_run`].
+    pub fn backup_before_run<T: Into<String>>(mut self, path_template: T) -> Self {
+        self.backup = Some(path_template.into());
+        self
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(CancelToken::is_cancelled)
+    }
+
+    /// Apply migrations using the current configuration.
+    /// See [`Migrator::apply`].
+    pub async fn apply(&self) -> Result<Report> {
+        let mut config = self.config.clone();
+        if self.is_cancelled() {
+            return Ok(Report::new(self.direction).cancelled());
+        }
+
+        // Take the advisory lock before re-reading applied state, exactly as
+        // the synchronous migrator does.
+        let lock = if self.synchronized && config.config.uses_migration_lock() {
+            match self.cancel {
+                Some(ref token) => {
+                    if !config
+                        .acquire_migration_lock_unless_cancelled(token)
+                        .await?
+                    {
+                        return Ok(Report::new(self.direction).cancelled());
+                    }
+                }
+                None => config.acquire_migration_lock().await?,
+            }
+            Some(MigrationLock::new(&config))
+        } else {
+            None
+        };
+        let lock_generation = lock.as_ref().map(|_| config.connection_generation());
+
+        let res = self.run(&mut config, lock_generation).await;
+        if let Some(lock) = lock {
+            lock.release().await;
+        }
+        res
+    }
+
+    /// Apply migrations until complete (`all`) or a single one has been applied
+    async fn run(&self, config: &mut AsyncConfig, lock_generation: Option<u64>) -> Result<Report> {
+        config.refresh_applied().await?;
+
+        let backup = if self.fake {
+            None
+        } else {
+            let template = self.backup.clone();
+            let direction = self.direction;
+            config
+                .blocking(move |c| backup::before_run(c, template.as_deref(), direction))
+                .await?
+        };
+        if let Some(ref path) = backup {
+            self.println(&format!("Backed up the database to {:?}", path));
+        }
+        match (self.run_steps(config, lock_generation).await, backup) {
+            (Err(e), Some(path)) => {
+                self.println(&format!("Restoring the database from {:?}", path));
+                Err(config
+                    .blocking(move |c| Ok(backup::restore_after_failure(c, &path, e)))
+                    .await
+                    .unwrap_or_else(|e| e))
+            }
+            (res, _) => res,
+        }
+    }
+
+    /// Apply migrations one at a time, until complete (`all`) or a single one
+    /// has been applied
+    async fn run_steps(
+        &self,
+        config: &mut AsyncConfig,
+        lock_generation: Option<u64>,
+    ) -> Result<Report> {
+        let mut skipped = HashSet::new();
+        let mut report = Report::new(self.direction);
+        loop {
+            if self.is_cancelled() {
+                return Ok(report.cancelled());
+            }
+            self.check_lock_still_held(config, lock_generation)?;
+            match self
+                .apply_next(config, &mut skipped, lock_generation)
+                .await?
+            {
+                Step::Applied(tag) => {
+                    report.push(tag);
+                    if !self.all {
+                        return Ok(report);
+                    }
+                }
+                Step::Skipped => {
+                    if !self.all {
+                        return Ok(report);
+                    }
+                }
+                Step::Complete => return Ok(report),
+            }
+            config.refresh_applied().await?;
+        }
+    }
+
+    /// See `Migrator::check_lock_still_held`
+    fn check_lock_still_held(
+        &self,
+        config: &AsyncConfig,
+        lock_generation: Option<u64>,
+    ) -> Result<()> {
+        if let Some(generation) = lock_generation {
+            if config.connection_generation() != generation {
+                bail!(
+                    Migration,
+                    "The database connection was lost mid-run and re-established; \
+                     the migration advisory lock was released with the original \
+                     session. Aborting this run -- re-run migrations."
+                )
+            }
+        }
+        Ok(())
+    }
+
+    /// Try applying the next available migration in the specified `Direction`
+    async fn apply_next(
+        &self,
+        config: &AsyncConfig,
+        skipped: &mut HashSet<String>,
+        lock_generation: Option<u64>,
+    ) -> Result<Step> {
+        let migrations = Migrator::available_migrations(&config.config)?;
+        let next = match Migrator::next_available(
+            self.direction,
+            &migrations,
+            &config.config.applied,
+            skipped,
+        )? {
+            Some(next) => next,
+            None => return Ok(Step::Complete),
+        };
+
+        self.print(&format!(
+            "Applying[{}]: {}",
+            self.direction,
+            next.description(&self.direction)
+        ));
+
+        let tag = next.tag();
+
+        if self.fake {
+            self.println("  ✓ (fake)");
+            self.record_tag(config, &tag).await?;
+            return Ok(Step::Applied(tag));
+        }
+
+        let transactional = next.use_transaction(&config.config, self.direction);
+        if transactional {
+            config.begin_transaction().await?;
+        }
+
+        match self.apply_and_record(config, next, &tag).await {
+            Ok(()) => {
+                if transactional {
+                    config.commit_transaction().await?;
+                }
+                self.println("  ✓");
+                Ok(Step::Applied(tag))
+            }
+            Err(msg) => {
+                if transactional {
+                    config.rollback_transaction().await;
+                }
+                self.println("");
+                match self.force {
+                    ForceMode::Off => bail!(Migration, "Migration was unsuccessful...\n{}", msg),
+                    ForceMode::AcceptFailures => {
+                        self.println(&format!(
+                            " ** Error ** (Continuing and recording the migration \
+                             as applied because force is `accept-failures`)\n ** {}",
+                            msg
+                        ));
+                        self.check_lock_still_held(config, lock_generation)?;
+                        self.record_tag(config, &tag).await?;
+                        Ok(Step::Applied(tag))
+                    }
+                    ForceMode::SkipFailures => {
+                        self.println(&format!(
+                            " ** Error ** (Continuing without recording because force \
+                             is `skip-failures`; the migration will be retried on the \
+                             next run)\n ** {}",
+                            msg
+                        ));
+                        skipped.insert(tag);
+                        Ok(Step::Skipped)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Apply the migration in the current direction and record its bookkeeping
+    /// row. Returns the failure's display string on error.
+    async fn apply_and_record(
+        &self,
+        config: &AsyncConfig,
+        next: &dyn Migratable,
+        tag: &str,
+    ) -> std::result::Result<(), String> {
+        self.apply_migration(config, next).await?;
+        self.record_tag(config, tag)
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    /// Run a migration: awaiting its async implementation if it has one,
+    /// otherwise running its SQL on the async connection, otherwise running
+    /// its synchronous implementation on tokio's blocking pool.
+    async fn apply_migration(
+        &self,
+        config: &AsyncConfig,
+        next: &dyn Migratable,
+    ) -> std::result::Result<(), String> {
+        if let Some(fut) = next.apply_async(config, self.direction) {
+            return fut.await.map_err(|e| e.to_string());
+        }
+        let sql = match next.sql(&config.config, self.direction) {
+            Some(Ok(sql)) => Some(sql),
+            Some(Err(e)) => return Err(e.to_string()),
+            None => None,
+        };
+        match sql {
+            Some(sql) => config
+                .execute_sql(sql)
+                .await
+                .map_err(|e| describe_error(&next.description(&self.direction), e).to_string()),
+            None => {
+                let migration = next.clone_migratable_box();
+                let direction = self.direction;
+                config
+                    .blocking(move |c| {
+                        match direction {
+                            Direction::Up => migration.apply_up(c),
+                            Direction::Down => migration.apply_down(c),
+                        }
+                        .map_err(|e| Error::Migration(e.to_string()))
+                    })
+                    .await
+                    .map_err(|e| match e {
+                        Error::Migration(msg) => msg,
+                        e => e.to_string(),
+                    })
+            }
+        }
+    }
+
+    /// Record the migration as applied (`Up`) or un-applied (`Down`)
+    async fn record_tag(&self, config: &AsyncConfig, tag: &str) -> Result<()> {
+        match self.direction {
+            Direction::Up => config.insert_migration_tag(tag.to_owned()).await,
+            Direction::Down => config.delete_migration_tag(tag.to_owned()).await,
+        }
+    }
+
+    fn print(&self, s: &str) {
+        if self.show_output {
+            print_flush(s);
+        }
+    }
+
+    fn println(&self, s: &str) {
+        if self.show_output {
+            println!("{}", s);
+        }
+    }
+}
+
+/// Guard for the migration advisory lock held by an async run.
+///
+/// `apply` releases the lock explicitly once the run finishes. If the run's
+/// future is dropped before then (cancelled, or a panic), the guard's `Drop`
+/// spawns the release onto the current runtime instead, since it cannot await.
+struct MigrationLock {
+    config: Option<AsyncConfig>,
+}
+
+impl MigrationLock {
+    fn new(config: &AsyncConfig) -> Self {
+        Self {
+            config: Some(config.clone()),
+        }
+    }
+
+    async fn release(mut self) {
+        if let Some(config) = self.config.take() {
+            config.release_migration_lock().await;
+        }
+    }
+}
+
+impl Drop for MigrationLock {
+    fn drop(&mut self) {
+        if let Some(config) = self.config.take() {
+            if let Ok(handle) = tokio::runtime::Handle::try_current() {
+                handle.spawn(async move { config.release_migration_lock().await });
+            }
+        }
+    }
+}
@@ -0,0 +1,383 @@
+/*!
+Async migration API (`async` feature)
+
+[`AsyncConfig`] and [`AsyncMigrator`] mirror [`Config`] and
+[`Migrator`](crate::Migrator) for applications running on a tokio runtime.
+Postgres and MySQL are driven by `tokio-postgres` and `mysql_async`. Sqlite
+and DuckDB have no async driver, so their operations run through the
+synchronous `Config` on tokio's blocking pool.
+
+The same [`Migratable`] definitions are shared with the synchronous API.
+SQL migrations (`EmbeddedMigration`, `FileMigration`) run over the async
+connection, [`AsyncFnMigration`]s are awaited, and any other migration (such as
+a synchronous `FnMigration`) is run on the blocking pool with a `ConnConfig`.
+For postgres and mysql, that `ConnConfig` has no live connection: the run's
+transaction and migration lock are on the async one, so its live-connection
+methods return a `Config` error instead of opening a connection outside them.
+*/
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use log::debug;
+use tokio::sync::Mutex;
+
+use crate::config::{missing_migration_table, Config, Settings, LOCK_POLL_INTERVAL};
+use crate::errors::*;
+use crate::macros::err;
+use crate::migratable::Migratable;
+use crate::migrator::CancelToken;
+use crate::ops::{self, MigrationStatus};
+use crate::statements;
+use crate::DbKind;
+
+mod drivers;
+mod migration;
+mod migrator;
+
+use drivers::AsyncDbConnection;
+
+pub use migration::{async_noop, AsyncConnConfig, AsyncFnMigration};
+pub use migrator::AsyncMigrator;
+
+/// A boxed, `Send` future
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// The future returned by an async migration function
+pub type AsyncMigrationFuture =
+    BoxFuture<'static, std::result::Result<(), Box<dyn std::error::Error + Send + Sync>>>;
+
+/// Full project configuration for the async API
+///
+/// Wraps a [`Config`] -- settings, managed migrations and applied state -- and
+/// replaces its blocking connection with an async one for server databases.
+/// Like `Config`, the connection is opened on first use and shared by all
+/// clones.
+#[derive(Debug, Clone)]
+pub struct AsyncConfig {
+    pub(crate) config: Config,
+    conn: Arc<Mutex<Option<AsyncDbConnection>>>,
+    /// Bumped whenever an established connection is dropped, see
+    /// `Config::connection_generation`
+    conn_generation: Arc<AtomicU64>,
+}
+
+/// Run a block with the live async server connection, establishing the
+/// connection first if necessary. Mirrors `Config::with_conn`: on error the
+/// connection is recovered in place with a rollback, and only dropped if that
+/// fails too.
+macro_rules! with_server_conn {
+    ($self:expr, $conn:ident => $body:expr) => {{
+        let mut guard = $self.conn.lock().await;
+        let connected = match *guard {
+            Some(_) => Ok(()),
+            None => AsyncDbConnection::connect(&$self.config)
+                .await
+                .map(|conn| *guard = Some(conn)),
+        };
+        match connected {
+            Err(e) => Err(e),
+            Ok(()) => {
+                let $conn = guard.as_mut().expect("connection just established");
+                let res = $body;
+                if res.is_err() && $conn.rollback().await.is_err() {
+                    *guard = None;
+                    $self.conn_generation.fetch_add(1, Ordering::SeqCst);
+                }
+                res
+            }
+        }
+    }};
+}
+
+/// Run a connection operation: on tokio's blocking pool through the wrapped
+/// `Config` for sqlite and duckdb, or on the async connection for server databases
+macro_rules! conn_op {
+    ($self:expr, $sync:ident => $blocking:expr, $conn:ident => $body:expr) => {
+        match $self.database_type() {
+            kind if kind.is_embedded() => $self.blocking(move |$sync| $blocking).await,
+            _ => with_server_conn!($self, $conn => $body.await),
+        }
+    };
+}
+
+impl AsyncConfig {
+    fn from_config(config: Config) -> Self {
+        Self {
+            config: Self::wrap(config),
+            conn: Arc::new(Mutex::new(None)),
+            conn_generation: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Initialize an `AsyncConfig` from a settings file at the given path.
+    /// This does not query the database for applied migrations.
+    pub fn from_settings_file<T: AsRef<Path>>(path: T) -> Result<Self> {
+        Ok(Self::from_config(Config::from_settings_file(path)?))
+    }
+
+    /// Initialize an `AsyncConfig` using an explicitly created `Settings` object.
+    /// This does not query the database for applied migrations.
+    pub fn with_settings(settings: Settings) -> Self {
+        Self::from_config(Config::with_settings(settings))
+    }
+
+    /// Define an explicit set of `Migratable` migrations to use.
+    /// See [`Config::use_migrations`].
+    pub fn use_migrations<T: AsRef<[Box<dyn Migratable>]>>(
+        &mut self,
+        migrations: T,
+    ) -> Result<&mut Self> {
+        self.config.use_migrations(migrations)?;
+        Ok(self)
+    }
+
+    /// Migrations are explicitly defined
+    pub fn is_explicit(&self) -> bool {
+        self.config.is_explicit()
+    }
+
+    /// Toggle cli compatible tag validation.
+    /// See [`Config::use_cli_compatible_tags`].
+    pub fn use_cli_compatible_tags(&mut self, compat: bool) -> &mut Self {
+        self.config.use_cli_compatible_tags(compat);
+        self
+    }
+
+    /// Check the current cli compatibility
+    pub fn is_cli_compatible(&self) -> bool {
+        self.config.is_cli_compatible()
+    }
+
+    /// Define a SQL template variable. See [`Config::variable`].
+    pub fn variable<K: Into<String>, V: Into<String>>(&mut self, name: K, value: V) -> &mut Self {
+        self.config.variable(name, value);
+        self
+    }
+
+    /// Prepare a `Config` to be wrapped: server databases are only reached
+    /// through the async connection, see `Config::lock_conn`
+    fn wrap(mut config: Config) -> Config {
+        config.async_server = !config.database_type().is_embedded();
+        config
+    }
+
+    /// Run a closure with the wrapped `Config` on tokio's blocking pool
+    pub(crate) async fn blocking<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Config) -> Result<T> + Send + 'static,
+    {
+        let config = self.config.clone();
+        tokio::task::spawn_blocking(move || f(&config))
+            .await
+            .map_err(|e| err!(Migration, "Blocking database task failed: {}", e))?
+    }
+
+    /// Generation of the current connection, see `Config::connection_generation`
+    pub(crate) fn connection_generation(&self) -> u64 {
+        match self.database_type() {
+            kind if kind.is_embedded() => self.config.connection_generation(),
+            _ => self.conn_generation.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Execute a batch of sql statements on the database one statement at a
+    /// time, see `Config::execute_sql`
+    pub(crate) async fn execute_sql(&self, sql: String) -> Result<()> {
+        if self.database_type().is_embedded() {
+            return self.blocking(move |c| c.execute_sql(&sql)).await;
+        }
+        let statements = statements::split(&sql, self.database_type())?;
+        with_server_conn!(self, conn => {
+            let mut res = Ok(());
+            for statement in &statements {
+                if let Err(e) = conn.execute_batch(&statement.sql).await {
+                    res = Err(statement.failed(statements.len(), e));
+                    break;
+                }
+            }
+            res
+        })
+    }
+
+    /// Begin a transaction on the live connection
+    pub(crate) async fn begin_transaction(&self) -> Result<()> {
+        conn_op!(self, c => c.begin_transaction(), conn => conn.begin())
+    }
+
+    /// Commit the current transaction on the live connection
+    pub(crate) async fn commit_transaction(&self) -> Result<()> {
+        conn_op!(self, c => c.commit_transaction(), conn => conn.commit())
+    }
+
+    /// Roll back the current transaction on the live connection. Best-effort,
+    /// see `Config::rollback_transaction`.
+    pub(crate) async fn rollback_transaction(&self) {
+        let _: Result<()> = conn_op!(self, c => {
+            c.rollback_transaction();
+            Ok(())
+        }, conn => conn.rollback());
+    }
+
+    /// Acquire the lock that serializes migration runs. See
+    /// `Config::acquire_migration_lock`.
+    pub(crate) async fn acquire_migration_lock(&self) -> Result<()> {
+        conn_op!(self, c => c.acquire_migration_lock(), conn => conn.acquire_lock())
+    }
+
+    /// Acquire the migration advisory lock, polling so the wait ends once
+    /// `cancel` is cancelled. Returns whether the lock was acquired. See
+    /// `Config::acquire_migration_lock_unless_cancelled`.
+    pub(crate) async fn acquire_migration_lock_unless_cancelled(
+        &self,
+        cancel: &CancelToken,
+    ) -> Result<bool> {
+        let started = std::time::Instant::now();
+        loop {
+            if cancel.is_cancelled() {
+                return Ok(false);
+            }
+            let acquired = conn_op!(self, c => c.with_conn(|conn| conn.try_acquire_lock()),
+                conn => conn.try_acquire_lock())?;
+            if acquired {
+                return Ok(true);
+            }
+            self.config.check_lock_wait(started)?;
+            tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Release the migration advisory lock. Best-effort, see
+    /// `Config::release_migration_lock`.
+    pub(crate) async fn release_migration_lock(&self) {
+        let _: Result<()> = conn_op!(self, c => {
+            c.release_migration_lock();
+            Ok(())
+        }, conn => conn.release_lock());
+    }
+
+    /// Insert given tag into database migration table
+    pub(crate) async fn insert_migration_tag(&self, tag: String) -> Result<()> {
+        conn_op!(self, c => c.insert_migration_tag(&tag), conn => conn.insert_tag(&tag))
+    }
+
+    /// Remove a given tag from the database migration table
+    pub(crate) async fn delete_migration_tag(&self, tag: String) -> Result<()> {
+        conn_op!(self, c => c.delete_migration_tag(&tag), conn => conn.remove_tag(&tag))
+    }
+
+    /// Load the applied migrations from the database migration table
+    pub(crate) async fn load_applied(&self) -> Result<Vec<String>> {
+        if self.database_type().is_embedded() {
+            return self.blocking(|c| c.load_applied()).await;
+        }
+        let applied = with_server_conn!(self, conn => {
+            if !conn.migration_table_exists().await? {
+                return Err(missing_migration_table());
+            }
+            conn.applied_tags().await
+        })?;
+        self.config.check_applied(applied)
+    }
+
+    /// Re-read applied migrations from the database in place, see
+    /// `Config::refresh_applied`
+    pub(crate) async fn refresh_applied(&mut self) -> Result<()> {
+        self.config.applied = self.load_applied().await?;
+        Ok(())
+    }
+
+    /// Queries the database to reload the current applied migrations.
+    ///
+    /// If the `AsyncConfig` was initialized from a settings file, the settings
+    /// will also be reloaded from the file. Returns a new `AsyncConfig` instance.
+    /// See [`Config::reload`].
+    pub async fn reload(&self) -> Result<AsyncConfig> {
+        let reloaded = self.config.reload_settings()?;
+        let mut config = if reloaded.settings == self.config.settings {
+            Self {
+                config: Self::wrap(reloaded),
+                conn: Arc::clone(&self.conn),
+                conn_generation: Arc::clone(&self.conn_generation),
+            }
+        } else {
+            Self::from_config(reloaded)
+        };
+        config.refresh_applied().await?;
+        Ok(config)
+    }
+
+    /// Confirm the database can be accessed and setup the database
+    /// migrations table if it doesn't already exist. See [`Config::setup`].
+    pub async fn setup(&self) -> Result<bool> {
+        if self.database_type().is_embedded() {
+            return self.blocking(|c| c.setup()).await;
+        }
+        debug!(" ** Confirming database credentials...");
+        let connected: Result<()> = with_server_conn!(self, _conn => Ok(()));
+        if let Err(e) = connected {
+            return Err(self.config.connect_failed(e));
+        }
+        debug!("    - Connection confirmed ✓");
+        let table_created = with_server_conn!(self, conn => conn.setup_migration_table().await)?;
+        if table_created {
+            debug!("    - `__migrant_migrations` table created ✓");
+        } else {
+            debug!("    - `__migrant_migrations` table already exists ✓");
+        }
+        Ok(table_created)
+    }
+
+    /// Return the status of all migrations being managed.
+    /// See [`migration_statuses`](crate::migration_statuses).
+    pub fn migration_statuses(&self) -> Result<Vec<MigrationStatus>> {
+        ops::migration_statuses(&self.config)
+    }
+
+    /// Preview the managed migrations that have not yet been applied.
+    /// See [`pending_migrations`](crate::pending_migrations).
+    pub fn pending_migrations(&self) -> Result<Vec<String>> {
+        ops::pending_migrations(&self.config)
+    }
+
+    /// Return the absolute path to the directory containing migration folders
+    pub fn migration_location(&self) -> Result<PathBuf> {
+        self.config.migration_location()
+    }
+
+    /// Return the database type
+    pub fn database_type(&self) -> DbKind {
+        self.config.database_type()
+    }
+
+    /// Return the absolute path to the database file (sqlite / duckdb only)
+    pub fn database_path(&self) -> Result<PathBuf> {
+        self.config.database_path()
+    }
+
+    /// Generate a database connection string (postgres / mysql only)
+    pub fn connect_string(&self) -> Result<String> {
+        self.config.connect_string()
+    }
+
+    /// Return a shared handle to the live sqlite connection.
+    /// See [`Config::sqlite_connection`].
+    #[cfg(feature = "sqlite")]
+    pub fn sqlite_connection(
+        &self,
+    ) -> Result<std::sync::Arc<std::sync::Mutex<rusqlite::Connection>>> {
+        self.config.sqlite_connection()
+    }
+
+    /// Return a shared handle to the live duckdb connection.
+    /// See [`Config::duckdb_connection`].
+    #[cfg(feature = "duckdb")]
+    pub fn duckdb_connection(
+        &self,
+    ) -> Result<std::sync::Arc<std::sync::Mutex<duckdb::Connection>>> {
+        self.config.duckdb_connection()
+    }
+}
@@ -0,0 +1,229 @@
+/*!
+Sqlite backups taken before a migration run
+*/
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+
+use crate::config::{Config, DbSettings};
+use crate::errors::*;
+use crate::macros::{bail, err};
+use crate::migrator::{Direction, Migrator};
+use crate::DbKind;
+
+/// Backup path placeholder for the database's file name
+const DATABASE: &str = "{database}";
+/// Backup path placeholder for the time the backup is taken
+const TIMESTAMP: &str = "{timestamp}";
+/// UTC to the millisecond, so backup names sort in the order they were taken
+const TIMESTAMP_FORMAT: &str = "%Y%m%d%H%M%S%3f";
+/// Backup file name used in the settings' `backup_dir`
+const BACKUP_DIR_FILE_NAME: &str = "{database}.{timestamp}.bak";
+
+/// Render a backup path template
+fn render(template: &str, database: &str, now: DateTime<Utc>) -> String {
+    template
+        .replace(DATABASE, database)
+        .replace(TIMESTAMP, &now.format(TIMESTAMP_FORMAT).to_string())
+}
+
+/// The explicit template, or one in the settings' `backup_dir`
+fn template(config: &Config, explicit: Option<&str>) -> Option<String> {
+    if let Some(template) = explicit {
+        return Some(template.to_owned());
+    }
+    match config.settings.inner {
+        DbSettings::Sqlite(ref s) => s.sqlite.backup_dir.as_ref().map(|dir| {
+            Path::new(dir)
+                .join(BACKUP_DIR_FILE_NAME)
+                .to_string_lossy()
+                .into_owned()
+        }),
+        _ => None,
+    }
+}
+
+/// The number of backups to keep, from the settings
+fn keep(config: &Config) -> Option<usize> {
+    match config.settings.inner {
+        DbSettings::Sqlite(ref s) => s.sqlite.backup_keep,
+        _ => None,
+    }
+}
+
+/// Back the database up before a run, if a backup template is given or
+/// configured and the run has a migration to apply in `direction`. Returns the
+/// backup's path. In-memory databases are not backed up.
+pub(crate) fn before_run(
+    config: &Config,
+    explicit: Option<&str>,
+    direction: Direction,
+) -> Result<Option<PathBuf>> {
+    let template = match template(config, explicit) {
+        Some(template) => template,
+        None => return Ok(None),
+    };
+    if config.database_type() != DbKind::Sqlite {
+        bail!(
+            Config,
+            "Backups before a migration run are only supported for sqlite, not {}",
+            config.database_type()
+        )
+    }
+    if config.settings.inner.is_memory() {
+        return Ok(None);
+    }
+    let available = Migrator::available_migrations(config)?;
+    if Migrator::next_available(direction, &available, &config.applied, &HashSet::new())?.is_none()
+    {
+        return Ok(None);
+    }
+
+    let database = config.database_path()?;
+    let database = database
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| err!(PathError, "Invalid database file name: {:?}", database))?;
+    let keep = keep(config);
+    if keep.is_some() && !is_prunable(&template) {
+        bail!(
+            Config,
+            "Backup path {:?} can only hold `{}` in its file name when `backup_keep` is set",
+            template,
+            TIMESTAMP
+        )
+    }
+    let base = config.relative_base_dir()?;
+    let path = base.join(render(&template, database, Utc::now()));
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| err!(Config, "Failed creating backup directory {:?}: {}", dir, e))?;
+    }
+    config.backup_database(&path)?;
+    if let Some(keep) = keep {
+        prune(&base.join(template.replace(DATABASE, database)), keep)?;
+    }
+    Ok(Some(path))
+}
+
+/// Restore the backup taken before a run that failed with `error`, returning
+/// the error to report
+pub(crate) fn restore_after_failure(config: &Config, backup: &Path, error: Error) -> Error {
+    match config.restore_database_unlocked(backup) {
+        Ok(()) => error,
+        Err(restore) => err!(
+            Migration,
+            "{}\n ** Restoring the database from the backup {:?} failed too: {}",
+            error,
+            backup,
+            restore
+        ),
+    }
+}
+
+/// Whether old backups from `template` can be found for pruning: its
+/// `{timestamp}`, if any, is in the file name rather than a directory
+fn is_prunable(template: &str) -> bool {
+    match Path::new(template).parent() {
+        Some(dir) => !dir.to_string_lossy().contains(TIMESTAMP),
+        None => true,
+    }
+}
+
+/// Whether `name` is a backup file name rendered from the `prefix{timestamp}suffix`
+/// template
+fn is_backup(name: &str, prefix: &str, suffix: &str) -> bool {
+    name.len() > prefix.len() + suffix.len()
+        && name.starts_with(prefix)
+        && name.ends_with(suffix)
+        && name[prefix.len()..name.len() - suffix.len()]
+            .bytes()
+            .all(|b| b.is_ascii_digit())
+}
+
+/// Delete the oldest backups matching `template` (its file name holding a
+/// `{timestamp}`) until only `keep` remain. A template without a timestamp
+/// overwrites one file, so there's nothing to delete.
+fn prune(template: &Path, keep: usize) -> Result<()> {
+    let (dir, name) = match (
+        template.parent(),
+        template.file_name().and_then(|n| n.to_str()),
+    ) {
+        (Some(dir), Some(name)) => (dir, name),
+        _ => return Ok(()),
+    };
+    let (prefix, suffix) = match name.split_once(TIMESTAMP) {
+        Some(parts) => parts,
+        None => return Ok(()),
+    };
+    let mut backups = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| is_backup(name, prefix, suffix))
+        .collect::<Vec<_>>();
+    backups.sort();
+    let excess = backups.len().saturating_sub(keep);
+    for name in &backups[..excess] {
+        let path = dir.join(name);
+        fs::remove_file(&path)
+            .map_err(|e| err!(Config, "Failed deleting old backup {:?}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_fills_placeholders() {
+        let now = DateTime::parse_from_rfc3339("2026-10-19T08:30:05.042Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            render("backups/{database}.{timestamp}.bak", "app.db", now),
+            "backups/app.db.20261019083005042.bak"
+        );
+        assert_eq!(render("latest.bak", "app.db", now), "latest.bak");
+    }
+
+    #[test]
+    fn prunable_templates_hold_the_timestamp_in_the_file_name() {
+        assert!(is_prunable("backups/{database}.{timestamp}.bak"));
+        assert!(is_prunable("{timestamp}.bak"));
+        assert!(is_prunable("backups/latest.bak"));
+        assert!(!is_prunable("backups/{timestamp}/{database}.bak"));
+        assert!(!is_prunable("{timestamp}/{database}.bak"));
+    }
+
+    #[test]
+    fn prune_keeps_the_newest_matching_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let names = [
+            "app.db.20260101000000000.bak",
+            "app.db.20260102000000000.bak",
+            "app.db.20260103000000000.bak",
+            "app.db.notes.bak",
+            "other.db.20260101000000000.bak",
+        ];
+        for name in names {
+            fs::write(dir.path().join(name), "").unwrap();
+        }
+        prune(&dir.path().join("app.db.{timestamp}.bak"), 1).unwrap();
+
+        let mut left = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        left.sort();
+        assert_eq!(
+            left,
+            [
+                "app.db.20260103000000000.bak",
+                "app.db.notes.bak",
+                "other.db.20260101000000000.bak",
+            ]
+        );
+    }
+}
@@ -0,0 +1,252 @@
+/*!
+Embed a migrations directory at compile time, from a build script
+
+[`embed_migrations`] scans a migrations directory the same way the `migrant`
+CLI does and generates the [`EmbeddedMigration`](crate::EmbeddedMigration)s for
+it, so new migrations are picked up on the next build instead of being listed
+by hand. The [`embed_migrations!`](crate::embed_migrations) macro then expands
+to the generated `Vec<Box<dyn Migratable>>`.
+
+In `build.rs`, with `migrant_lib` also listed under `[build-dependencies]`:
+
+```rust,ignore
+fn main() {
+    migrant_lib::build::embed_migrations("migrations").unwrap();
+}
+```
+
+In the application:
+
+```rust,ignore
+let mut config = migrant_lib::Config::with_settings(settings);
+config.use_cli_compatible_tags(true);
+config.use_migrations(migrant_lib::embed_migrations!())?;
+```
+
+Directory migrations embed their `up.sql` / `down.sql` and any per-backend
+variants (`up.postgres.sql`, ...), and single-file migrations are split into
+their sections. Tags are the full `<version>_<tag>` names the CLI records,
+and `-- migrant:no-transaction` directives are kept with the SQL. The build
+script re-runs whenever anything in the directory changes.
+*/
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::errors::*;
+use crate::macros::{bail, err};
+use crate::migration::split_sections;
+use crate::ops::{scan_migrations, DirectionFiles, MigrationFiles};
+use crate::tags::VersionScheme;
+use crate::DbKind;
+
+/// Name of the generated file in `OUT_DIR`, included by `embed_migrations!`
+const GENERATED_FILE: &str = "migrant_embedded_migrations.rs";
+
+/// Generate the embedded migrations for the migrations directory `dir` into
+/// `OUT_DIR`, for the [`embed_migrations!`](crate::embed_migrations) macro.
+///
+/// Call this from a build script. A relative `dir` is relative to the
+/// package's manifest directory. Malformed migrations (a missing `up` or
+/// `down` file, invalid tags or single-file sections) fail the build.
+///
+/// Migration names are expected to be timestamped, see
+/// [`embed_migrations_with_scheme`] for other version schemes.
+pub fn embed_migrations<T: AsRef<Path>>(dir: T) -> Result<()> {
+    embed_migrations_with_scheme(dir, VersionScheme::Timestamp)
+}
+
+/// Like [`embed_migrations`], for a project whose migration names follow the
+/// given [`VersionScheme`]
+pub fn embed_migrations_with_scheme<T: AsRef<Path>>(dir: T, scheme: VersionScheme) -> Result<()> {
+    let manifest_dir = env::var_os("CARGO_MANIFEST_DIR").ok_or_else(|| {
+        err!(
+            Config,
+            "`CARGO_MANIFEST_DIR` is not set, call this from build.rs"
+        )
+    })?;
+    let out_dir = env::var_os("OUT_DIR")
+        .ok_or_else(|| err!(Config, "`OUT_DIR` is not set, call this from build.rs"))?;
+    let dir = PathBuf::from(manifest_dir).join(dir);
+    println!("cargo:rerun-if-changed={}", dir.display());
+
+    let source = generate(&dir, scheme)?;
+    fs::write(Path::new(&out_dir).join(GENERATED_FILE), source)?;
+    Ok(())
+}
+
+/// Rust source for an expression building the migrations under `dir`
+fn generate(dir: &Path, scheme: VersionScheme) -> Result<String> {
+    let dir = fs::canonicalize(dir)
+        .map_err(|e| err!(PathError, "Invalid migrations directory {:?}: {}", dir, e))?;
+    let mut source = String::from(
+        "// @generated by `migrant_lib::build::embed_migrations`\n\
+         ::std::vec![\n",
+    );
+    for found in scan_migrations(&dir, scheme)? {
+        let tag = found.full_tag();
+        if !scheme.is_valid_full_tag(&tag) {
+            bail!(
+                TagError,
+                "Invalid migration tag `{}`, tags must follow `{}`",
+                tag,
+                scheme.pattern()
+            )
+        }
+        match found.files {
+            MigrationFiles::SingleFile(path) => {
+                split_sections(&fs::read_to_string(&path)?).map_err(|e| match e {
+                    Error::Migration(msg) => err!(Migration, "{:?}: {}", path, msg),
+                    e => e,
+                })?;
+                writeln!(
+                    source,
+                    "    ::migrant_lib::EmbeddedMigration::from_sections({:?}, include_str!({}))\n\
+                     \x20       .expect(\"sections are checked by `migrant_lib::build`\")\n\
+                     \x20       .boxed(),",
+                    tag,
+                    path_literal(&path)?
+                )
+                .expect("write to String");
+            }
+            MigrationFiles::Directory { up, down } => {
+                writeln!(
+                    source,
+                    "    ::migrant_lib::EmbeddedMigration::with_tag({:?})",
+                    tag
+                )
+                .expect("write to String");
+                write_direction(&mut source, &tag, "up", &up)?;
+                write_direction(&mut source, &tag, "down", &down)?;
+                source.push_str("        .boxed(),\n");
+            }
+        }
+    }
+    source.push_str("]\n");
+    Ok(source)
+}
+
+/// Builder calls embedding one direction's generic file and variants
+fn write_direction(
+    source: &mut String,
+    tag: &str,
+    direction: &str,
+    files: &DirectionFiles,
+) -> Result<()> {
+    if let Some(ref script) = files.script {
+        bail!(
+            Migration,
+            "Script migration {:?} cannot be embedded, only SQL migrations can",
+            script
+        )
+    }
+    if files.generic.is_none() && files.variants.is_empty() {
+        bail!(
+            MigrationNotFound,
+            "{} migration not found for tag: {}, expected `{}.sql` or `{}.<backend>.sql`",
+            if direction == "up" { "Up" } else { "Down" },
+            tag,
+            direction,
+            direction
+        )
+    }
+    if let Some(ref path) = files.generic {
+        writeln!(
+            source,
+            "        .{}(include_str!({}))",
+            direction,
+            path_literal(path)?
+        )
+        .expect("write to String");
+    }
+    let mut variants = files.variants.iter().collect::<Vec<_>>();
+    variants.sort_by(|a, b| a.1.cmp(&b.1));
+    for (kind, path) in variants {
+        let kind = match kind {
+            DbKind::Sqlite => "Sqlite",
+            DbKind::Postgres => "Postgres",
+            DbKind::MySql => "MySql",
+            DbKind::DuckDb => "DuckDb",
+        };
+        writeln!(
+            source,
+            "        .{}_for(::migrant_lib::DbKind::{}, include_str!({}))",
+            direction,
+            kind,
+            path_literal(path)?
+        )
+        .expect("write to String");
+    }
+    Ok(())
+}
+
+/// A string literal of `path`, for `include_str!`
+fn path_literal(path: &Path) -> Result<String> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| err!(PathError, "Invalid utf8 path: {:?}", path))?;
+    Ok(format!("{:?}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::migration::SINGLE_FILE_TEMPLATE;
+
+    #[test]
+    fn generates_directory_and_single_file_migrations() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let mig_dir = root.join("20200101000000_first");
+        fs::create_dir(&mig_dir).unwrap();
+        for file in ["up.sql", "up.postgres.sql", "down.sql"] {
+            fs::write(mig_dir.join(file), "").unwrap();
+        }
+        fs::write(root.join("20200102000000_second.sql"), SINGLE_FILE_TEMPLATE).unwrap();
+
+        let source = generate(root, VersionScheme::Timestamp).unwrap();
+        let root = fs::canonicalize(root).unwrap();
+        let literal = |path: PathBuf| path_literal(&path).unwrap();
+        let first = root.join("20200101000000_first");
+        let expected = format!(
+            "// @generated by `migrant_lib::build::embed_migrations`\n\
+             ::std::vec![\n\
+             \x20   ::migrant_lib::EmbeddedMigration::with_tag(\"20200101000000_first\")\n\
+             \x20       .up(include_str!({}))\n\
+             \x20       .up_for(::migrant_lib::DbKind::Postgres, include_str!({}))\n\
+             \x20       .down(include_str!({}))\n\
+             \x20       .boxed(),\n\
+             \x20   ::migrant_lib::EmbeddedMigration::from_sections(\"20200102000000_second\", include_str!({}))\n\
+             \x20       .expect(\"sections are checked by `migrant_lib::build`\")\n\
+             \x20       .boxed(),\n\
+             ]\n",
+            literal(first.join("up.sql")),
+            literal(first.join("up.postgres.sql")),
+            literal(first.join("down.sql")),
+            literal(root.join("20200102000000_second.sql")),
+        );
+        assert_eq!(source, expected);
+    }
+
+    #[test]
+    fn malformed_migrations_fail_generation() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let mig_dir = root.join("20200101000000_first");
+        fs::create_dir(&mig_dir).unwrap();
+        fs::write(mig_dir.join("up.sqlite.sql"), "").unwrap();
+        match generate(root, VersionScheme::Timestamp) {
+            Err(Error::MigrationNotFound(msg)) => assert!(msg.contains("down.sql"), "{}", msg),
+            other => panic!("expected MigrationNotFound, got: {:?}", other),
+        }
+
+        fs::write(mig_dir.join("down.sql"), "").unwrap();
+        fs::write(root.join("20200102000000_second.sql"), "select 1;").unwrap();
+        let err = generate(root, VersionScheme::Timestamp)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("20200102000000_second.sql"), "{}", err);
+        assert!(err.contains("Missing `-- migrate:up`"), "{}", err);
+    }
+}
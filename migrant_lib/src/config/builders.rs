@@ -3,12 +3,14 @@ Settings builders
 */
 use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use crate::errors::*;
 use crate::macros::{bail, err};
+use crate::value::Value;
 use crate::SQLITE_MEMORY_PATH;
 
-use super::settings::{DbSettings, ServerSettings, Settings, SqliteSettings};
+use super::settings::{DbSettings, FileSettings, ServerSettings, Settings, SqliteOptions};
 
 fn path_to_string(p: &Path) -> Result<String> {
     p.to_str()
@@ -21,6 +23,7 @@ fn path_to_string(p: &Path) -> Result<String> {
 pub struct SqliteSettingsBuilder {
     pub(crate) database_path: Option<String>,
     pub(crate) migration_location: Option<String>,
+    pub(crate) sqlite: SqliteOptions,
 }
 
 impl SqliteSettingsBuilder {
@@ -63,23 +66,154 @@ impl SqliteSettingsBuilder {
         Ok(self)
     }
 
+    /// Set the `journal_mode` pragma: `delete`, `truncate`, `persist`,
+    /// `memory`, `wal` or `off`
+    pub fn journal_mode(mut self, mode: &str) -> Self {
+        self.sqlite.journal_mode = Some(mode.to_owned());
+        self
+    }
+
+    /// Set the `synchronous` pragma: `off`, `normal`, `full` or `extra`
+    pub fn synchronous(mut self, mode: &str) -> Self {
+        self.sqlite.synchronous = Some(mode.to_owned());
+        self
+    }
+
+    /// Enable or disable foreign key enforcement
+    pub fn foreign_keys(mut self, enabled: bool) -> Self {
+        self.sqlite.foreign_keys = Some(enabled);
+        self
+    }
+
+    /// Wait up to `timeout` for a database locked by another connection
+    /// instead of failing immediately with `SQLITE_BUSY`
+    pub fn busy_timeout(mut self, timeout: Duration) -> Self {
+        self.sqlite.busy_timeout = Some(timeout.as_millis() as u64);
+        self
+    }
+
+    /// Fail a migration run that waits longer than `timeout` for another
+    /// process's migration lock, see
+    /// [`Migrator::synchronized`](crate::Migrator::synchronized). By default
+    /// a run waits until the lock is free.
+    pub fn lock_timeout(mut self, timeout: Duration) -> Self {
+        self.sqlite.lock_timeout = Some(timeout.as_millis() as u64);
+        self
+    }
+
+    /// Set any other pragma on connect, such as `cache_size` or `temp_store`.
+    /// Extra pragmas are applied after the ones with dedicated methods, in
+    /// name order.
+    pub fn pragma<V: Into<Value>>(mut self, name: &str, value: V) -> Self {
+        self.sqlite.pragmas.insert(name.to_owned(), value.into());
+        self
+    }
+
+    /// Open the database read-only
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.sqlite.read_only = read_only;
+        self
+    }
+
+    /// Whether a missing database file is created, default `true`. When
+    /// disabled, connecting to (or `Config::setup` with) a missing database
+    /// file fails.
+    pub fn create(mut self, create: bool) -> Self {
+        self.sqlite.create = Some(create);
+        self
+    }
+
+    /// Back the database up into `dir` before every migration run with
+    /// pending migrations, see
+    /// [`Migrator::backup_before_run`](crate::Migrator::backup_before_run).
+    /// A relative `dir` is handled like a relative `migration_location`.
+    pub fn backup_dir<T: AsRef<Path>>(mut self, dir: T) -> Result<Self> {
+        self.sqlite.backup_dir = Some(path_to_string(dir.as_ref())?);
+        Ok(self)
+    }
+
+    /// Keep only the `keep` newest backups, deleting older ones after each
+    /// backup. All backups are kept by default. Backup paths must then hold
+    /// their `{timestamp}` in the file name, so `backup_dir` can't contain one.
+    pub fn backup_keep(mut self, keep: usize) -> Self {
+        self.sqlite.backup_keep = Some(keep);
+        self
+    }
+
     /// Build a `Settings` object
     pub fn build(&self) -> Result<Settings> {
-        let database_path = self
-            .database_path
-            .clone()
-            .ok_or_else(|| err!(Config, "Missing `database_path` parameter"))?;
-        if database_path != SQLITE_MEMORY_PATH && !Path::new(&database_path).is_absolute() {
-            bail!(
-                Config,
-                "Explicit settings database path must be absolute: {:?}",
-                database_path
-            )
-        }
-        Ok(Settings::new(DbSettings::Sqlite(SqliteSettings {
-            database_path,
-            migration_location: self.migration_location.clone(),
-        })))
+        let mut settings = build_file_settings(&self.database_path, &self.migration_location)?;
+        settings.sqlite = self.sqlite.clone().validated()?;
+        Ok(Settings::new(DbSettings::Sqlite(settings)))
+    }
+}
+
+/// Validate the fields shared by the embedded-database (sqlite, duckdb) builders
+fn build_file_settings(
+    database_path: &Option<String>,
+    migration_location: &Option<String>,
+) -> Result<FileSettings> {
+    let database_path = database_path
+        .clone()
+        .ok_or_else(|| err!(Config, "Missing `database_path` parameter"))?;
+    if database_path != SQLITE_MEMORY_PATH && !Path::new(&database_path).is_absolute() {
+        bail!(
+            Config,
+            "Explicit settings database path must be absolute: {:?}",
+            database_path
+        )
+    }
+    Ok(FileSettings {
+        database_path,
+        migration_location: migration_location.clone(),
+        sqlite: SqliteOptions::default(),
+    })
+}
+
+/// DuckDB settings builder
+#[derive(Debug, Clone, Default)]
+pub struct DuckDbSettingsBuilder {
+    pub(crate) database_path: Option<String>,
+    pub(crate) migration_location: Option<String>,
+}
+
+impl DuckDbSettingsBuilder {
+    /// Initialize an empty builder
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    /// **Required** (unless `memory` is used) -- Set the path of a database file.
+    ///
+    /// The special path `:memory:` selects an in-memory database. Relative paths
+    /// are handled as for [`SqliteSettingsBuilder::database_path`].
+    pub fn database_path<T: AsRef<Path>>(mut self, p: T) -> Result<Self> {
+        self.database_path = Some(path_to_string(p.as_ref())?);
+        Ok(self)
+    }
+
+    /// Use an in-memory database, kept alive by the built `Config` and its clones
+    pub fn memory(mut self) -> Self {
+        self.database_path = Some(SQLITE_MEMORY_PATH.to_string());
+        self
+    }
+
+    /// Set directory to look for migration files.
+    ///
+    /// This can be an absolute or relative path. An absolute path should be preferred.
+    /// If a relative path is provided, the path will be assumed relative to either the
+    /// settings file's directory if a settings file exists, or the current directory.
+    pub fn migration_location<T: AsRef<Path>>(mut self, p: T) -> Result<Self> {
+        self.migration_location = Some(path_to_string(p.as_ref())?);
+        Ok(self)
+    }
+
+    /// Build a `Settings` object
+    pub fn build(&self) -> Result<Settings> {
+        Ok(Settings::new(DbSettings::DuckDb(build_file_settings(
+            &self.database_path,
+            &self.migration_location,
+        )?)))
     }
 }
 
@@ -91,8 +225,14 @@ pub(crate) struct ServerSettingsBuilder {
     pub(crate) database_password: Option<String>,
     pub(crate) database_host: Option<String>,
     pub(crate) database_port: Option<String>,
+    pub(crate) database_socket: Option<String>,
     pub(crate) database_params: Option<BTreeMap<String, String>>,
     pub(crate) ssl_cert_file: Option<PathBuf>,
+    pub(crate) ssl_client_cert: Option<PathBuf>,
+    pub(crate) ssl_client_key: Option<PathBuf>,
+    pub(crate) ssl_client_key_password: Option<String>,
+    pub(crate) ssl_required: Option<bool>,
+    pub(crate) ssl_skip_domain_validation: Option<bool>,
     pub(crate) migration_location: Option<String>,
 }
 
@@ -113,8 +253,14 @@ impl ServerSettingsBuilder {
                 .ok_or_else(|| err!(Config, "Missing `database_password` parameter"))?,
             database_host: self.database_host.clone(),
             database_port: self.database_port.clone(),
+            database_socket: self.database_socket.clone(),
             database_params: self.database_params.clone(),
             ssl_cert_file: self.ssl_cert_file.clone(),
+            ssl_client_cert: self.ssl_client_cert.clone(),
+            ssl_client_key: self.ssl_client_key.clone(),
+            ssl_client_key_password: self.ssl_client_key_password.clone(),
+            ssl_required: self.ssl_required,
+            ssl_skip_domain_validation: self.ssl_skip_domain_validation,
             migration_location: self.migration_location.clone(),
         })
     }
@@ -161,6 +307,13 @@ macro_rules! server_builder_methods {
             self
         }
 
+        /// Connect over a unix-domain socket instead of TCP: the socket's
+        /// directory for postgres, or the socket file for mysql.
+        pub fn database_socket(mut self, path: &str) -> Self {
+            self.inner.database_socket = Some(path.into());
+            self
+        }
+
         /// Set a collection of database connection parameters.
         pub fn database_params(mut self, params: &[(&str, &str)]) -> Self {
             self.inner.set_params(params);
@@ -199,6 +352,26 @@ impl PostgresSettingsBuilder {
         self
     }
 
+    /// Set a client certificate for mutual TLS: a PEM certificate, paired with
+    /// an [`ssl_client_key`](Self::ssl_client_key), or a PKCS#12 archive
+    /// holding both the certificate and its key.
+    pub fn ssl_client_cert<P: AsRef<Path>>(mut self, file: P) -> Self {
+        self.inner.ssl_client_cert = Some(file.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set the PEM PKCS#8 private key of a PEM `ssl_client_cert`
+    pub fn ssl_client_key<P: AsRef<Path>>(mut self, file: P) -> Self {
+        self.inner.ssl_client_key = Some(file.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set the password of a PKCS#12 `ssl_client_cert`
+    pub fn ssl_client_key_password(mut self, password: &str) -> Self {
+        self.inner.ssl_client_key_password = Some(password.into());
+        self
+    }
+
     /// Build a `Settings` object
     pub fn build(&self) -> Result<Settings> {
         Ok(Settings::new(DbSettings::Postgres(self.inner.build()?)))
@@ -219,6 +392,42 @@ impl MySqlSettingsBuilder {
 
     server_builder_methods!();
 
+    /// Set a root certificate (PEM or DER) the server's certificate must chain to.
+    /// Connections require TLS once this is set.
+    pub fn ssl_cert_file<P: AsRef<Path>>(mut self, file: P) -> Self {
+        self.inner.ssl_cert_file = Some(file.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set a client certificate for mutual TLS: a PKCS#12 archive holding
+    /// both the certificate and its key. Connections require TLS once this is set.
+    pub fn ssl_client_cert<P: AsRef<Path>>(mut self, file: P) -> Self {
+        self.inner.ssl_client_cert = Some(file.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set the password of the PKCS#12 `ssl_client_cert`
+    pub fn ssl_client_key_password(mut self, password: &str) -> Self {
+        self.inner.ssl_client_key_password = Some(password.into());
+        self
+    }
+
+    /// Require TLS, verified against the system roots unless an
+    /// [`ssl_cert_file`](Self::ssl_cert_file) is set. Connecting fails if
+    /// the server does not support TLS. MySQL TLS can't be optional, so
+    /// `false` fails to connect alongside settings that turn TLS on.
+    pub fn ssl_required(mut self, required: bool) -> Self {
+        self.inner.ssl_required = Some(required);
+        self
+    }
+
+    /// Accept a server certificate issued for a different host name. The
+    /// certificate chain is still verified. Connections require TLS once this is set.
+    pub fn ssl_skip_domain_validation(mut self, skip: bool) -> Self {
+        self.inner.ssl_skip_domain_validation = Some(skip);
+        self
+    }
+
     /// Build a `Settings` object
     pub fn build(&self) -> Result<Settings> {
         Ok(Settings::new(DbSettings::MySql(self.inner.build()?)))
@@ -262,6 +471,54 @@ mod tests {
         }
     }
 
+    #[test]
+    fn sqlite_connection_options_are_validated() {
+        let settings = SqliteSettingsBuilder::empty()
+            .memory()
+            .journal_mode("WAL")
+            .read_only(true)
+            .build()
+            .unwrap();
+        match settings.inner {
+            DbSettings::Sqlite(s) => {
+                assert_eq!(s.sqlite.journal_mode.as_deref(), Some("wal"));
+                assert!(!s.sqlite.creates_missing());
+            }
+            other => panic!("expected sqlite settings, got {:?}", other),
+        }
+
+        let builder = SqliteSettingsBuilder::empty().memory();
+        assert!(builder.clone().journal_mode("fast").build().is_err());
+        assert!(builder.clone().pragma("cache size", 1).build().is_err());
+        assert!(builder.clone().pragma("key", Value::Null).build().is_err());
+        let timestamped = builder.backup_dir("backups/{timestamp}").unwrap();
+        assert!(timestamped.clone().build().is_ok());
+        assert!(timestamped.backup_keep(2).build().unwrap_err().is_config());
+    }
+
+    #[test]
+    fn duckdb_owned_setters_chain_and_build() {
+        let settings = DuckDbSettingsBuilder::empty()
+            .database_path("/abs/path/to/my.duckdb")
+            .unwrap()
+            .migration_location("/abs/migrations")
+            .unwrap()
+            .build()
+            .unwrap();
+        match settings.inner {
+            DbSettings::DuckDb(s) => {
+                assert_eq!(s.database_path, "/abs/path/to/my.duckdb");
+                assert_eq!(s.migration_location.as_deref(), Some("/abs/migrations"));
+            }
+            other => panic!("expected duckdb settings, got {:?}", other),
+        }
+        assert!(DuckDbSettingsBuilder::empty()
+            .database_path("relative.duckdb")
+            .unwrap()
+            .build()
+            .is_err());
+    }
+
     #[test]
     fn postgres_owned_server_setters_chain_and_build() {
         let settings = PostgresSettingsBuilder::empty()
@@ -272,6 +529,9 @@ mod tests {
             .database_port(4444)
             .database_params(&[("sslmode", "require")])
             .ssl_cert_file("/certs/db.pem")
+            .ssl_client_cert("/certs/client.pem")
+            .ssl_client_key("/certs/client.key")
+            .ssl_client_key_password("hunter2")
             .migration_location("/abs/migrations")
             .unwrap()
             .build()
@@ -288,6 +548,9 @@ mod tests {
                     Some(&"require".to_string())
                 );
                 assert_eq!(s.ssl_cert_file, Some(PathBuf::from("/certs/db.pem")));
+                assert_eq!(s.ssl_client_cert, Some(PathBuf::from("/certs/client.pem")));
+                assert_eq!(s.ssl_client_key, Some(PathBuf::from("/certs/client.key")));
+                assert_eq!(s.ssl_client_key_password.as_deref(), Some("hunter2"));
                 assert_eq!(s.migration_location.as_deref(), Some("/abs/migrations"));
             }
             other => panic!("expected postgres settings, got {:?}", other),
@@ -301,12 +564,27 @@ mod tests {
             .database_user("me")
             .database_password("secret")
             .database_port(3307)
+            .database_socket("/run/mysqld/mysqld.sock")
+            .ssl_cert_file("/certs/ca.pem")
+            .ssl_client_cert("/certs/client.p12")
+            .ssl_client_key_password("hunter2")
+            .ssl_required(true)
+            .ssl_skip_domain_validation(true)
             .build()
             .unwrap();
         match settings.inner {
             DbSettings::MySql(s) => {
                 assert_eq!(s.database_name, "mydb");
                 assert_eq!(s.database_port.as_deref(), Some("3307"));
+                assert_eq!(
+                    s.database_socket.as_deref(),
+                    Some("/run/mysqld/mysqld.sock")
+                );
+                assert_eq!(s.ssl_cert_file, Some(PathBuf::from("/certs/ca.pem")));
+                assert_eq!(s.ssl_client_cert, Some(PathBuf::from("/certs/client.p12")));
+                assert_eq!(s.ssl_client_key_password.as_deref(), Some("hunter2"));
+                assert_eq!(s.ssl_required, Some(true));
+                assert_eq!(s.ssl_skip_domain_validation, Some(true));
             }
             other => panic!("expected mysql settings, got {:?}", other),
         }
@@ -7,11 +7,14 @@ use std::path::{Path, PathBuf};
 use crate::errors::*;
 use crate::macros::{bail, err};
 use crate::util::{open_file_in_fg, prompt, write_to_path};
+use crate::value::Value;
 use crate::{DbKind, CONFIG_FILE};
 
 use super::builders::{
-    MySqlSettingsBuilder, PostgresSettingsBuilder, ServerSettingsBuilder, SqliteSettingsBuilder,
+    DuckDbSettingsBuilder, MySqlSettingsBuilder, PostgresSettingsBuilder, ServerSettingsBuilder,
+    SqliteSettingsBuilder,
 };
+use super::settings::SqliteOptions;
 use super::Config;
 
 static SQLITE_CONFIG_TEMPLATE: &str = r#"
@@ -25,6 +28,55 @@ database_type = "sqlite"
 database_path = "__DB_PATH__"
 
 migration_location = "__MIG_LOC__"  # default "migrations"
+# version_scheme = "sequence"      # "timestamp" (default), "sequence" or "semver"
+
+# Optional: migrate many tenant databases with `--tenants`
+# [tenants]
+# sqlite_glob = "tenants/*.db"
+# parallelism = 4             # default 4
+# on_failure = "continue"     # "continue" (default) or "stop"
+
+# Optional: values for `{{ name }}` placeholders in migration SQL
+# [variables]
+# app_role = "app_rw"         # or: "env:APP_ROLE"
+
+# Optional: connection options, applied whenever a connection is opened
+# [sqlite]
+# journal_mode = "wal"        # delete, truncate, persist, memory, wal or off
+# synchronous = "normal"      # off, normal, full or extra
+# foreign_keys = true
+# busy_timeout = 5000         # milliseconds to wait on a locked database
+# lock_timeout = 60000        # milliseconds to wait on another migrator, default forever
+# read_only = false           # open the database read-only
+# create = true               # create a missing database file, default true
+# backup_dir = "backups"      # back up before each run with pending migrations
+# backup_keep = 5             # backups to keep in backup_dir, default all
+# pragmas = { cache_size = -64000, temp_store = "memory" }
+
+"#;
+
+static DUCKDB_CONFIG_TEMPLATE: &str = r#"
+# Required, do not edit
+database_type = "duckdb"
+
+# Required: Absolute or relative path to your database file.
+#           If a relative path is provided, it will be assumed
+#           to be relative to this config file dir: `__CONFIG_DIR__/`
+# ex.) database_name = "db/db.duckdb"
+database_path = "__DB_PATH__"
+
+migration_location = "__MIG_LOC__"  # default "migrations"
+# version_scheme = "sequence"      # "timestamp" (default), "sequence" or "semver"
+
+# Optional: migrate many tenant databases with `--tenants`
+# [tenants]
+# sqlite_glob = "tenants/*.duckdb"
+# parallelism = 4             # default 4
+# on_failure = "continue"     # "continue" (default) or "stop"
+
+# Optional: values for `{{ name }}` placeholders in migration SQL
+# [variables]
+# app_role = "app_rw"         # or: "env:APP_ROLE"
 
 "#;
 
@@ -36,15 +88,37 @@ database_type = "postgres"
 database_name = "__DB_NAME__"
 database_user = "__DB_USER__"
 database_password = "__DB_PASS__"
+# Any value may instead come from "env:VAR", "file:/run/secrets/db_password"
+# (trimmed contents) or "cmd:<command>" (stdout, needs MIGRANT_ALLOW_CMD_SECRETS=1)
 
 # Configurable database info
 database_host = "__DB_HOST__"         # default "localhost"
 database_port = "__DB_PORT__"              # default "5432"
+# database_socket = "/var/run/postgresql"  # connect over the unix socket in this directory
 migration_location = "__MIG_LOC__"  # default "migrations"
+# version_scheme = "sequence"      # "timestamp" (default), "sequence" or "semver"
 
 # Optional customer ssl cert file
 # ssl_cert_file = "path/to/certificate.crt.pem.key"
 
+# Optional client certificate for mutual TLS: a PEM cert and PKCS#8 key,
+# or a PKCS#12 archive (without ssl_client_key) and its password.
+# Set `sslmode` in [database_params] to disable, prefer, require,
+# verify-ca or verify-full.
+# ssl_client_cert = "path/to/client.crt.pem"
+# ssl_client_key = "path/to/client.key.pem"
+# ssl_client_key_password = "env:PG_CLIENT_KEY_PASSWORD"
+
+# Optional: migrate one schema per tenant with `--tenants`
+# [tenants]
+# list = ["acme", "globex"]   # or: query = "select name from tenants"
+# parallelism = 4             # default 4
+# on_failure = "continue"     # "continue" (default) or "stop"
+
+# Optional: values for `{{ name }}` placeholders in migration SQL
+# [variables]
+# app_role = "app_rw"         # or: "env:APP_ROLE"
+
 # Extra database connection parameters
 # with the format:
 # [database_params]
@@ -60,11 +134,32 @@ database_type = "mysql"
 database_name = "__DB_NAME__"
 database_user = "__DB_USER__"
 database_password = "__DB_PASS__"
+# Any value may instead come from "env:VAR", "file:/run/secrets/db_password"
+# (trimmed contents) or "cmd:<command>" (stdout, needs MIGRANT_ALLOW_CMD_SECRETS=1)
 
 # Configurable database info
 database_host = "__DB_HOST__"         # default "localhost"
 database_port = "__DB_PORT__"              # default "3306"
+# database_socket = "/run/mysqld/mysqld.sock"  # connect over this unix socket
 migration_location = "__MIG_LOC__"  # default "migrations"
+# version_scheme = "sequence"      # "timestamp" (default), "sequence" or "semver"
+
+# Optional TLS. Setting any of these requires a TLS connection.
+# ssl_cert_file = "path/to/ca.pem"    # root certificate to verify the server against
+# ssl_client_cert = "path/to/client.p12"  # PKCS#12 client certificate and key
+# ssl_client_key_password = "env:MYSQL_CLIENT_KEY_PASSWORD"
+# ssl_required = true                 # TLS verified against the system roots
+# ssl_skip_domain_validation = true   # accept a certificate for another host name
+
+# Optional: migrate one database per tenant with `--tenants`
+# [tenants]
+# list = ["acme", "globex"]   # or: query = "select name from tenants"
+# parallelism = 4             # default 4
+# on_failure = "continue"     # "continue" (default) or "stop"
+
+# Optional: values for `{{ name }}` placeholders in migration SQL
+# [variables]
+# app_role = "app_rw"         # or: "env:APP_ROLE"
 
 # Extra database connection parameters
 # with the format:
@@ -76,6 +171,7 @@ migration_location = "__MIG_LOC__"  # default "migrations"
 #[derive(Debug, Clone)]
 enum DatabaseConfigOptions {
     Sqlite(SqliteSettingsBuilder),
+    DuckDb(DuckDbSettingsBuilder),
     Postgres(PostgresSettingsBuilder),
     MySql(MySqlSettingsBuilder),
 }
@@ -162,6 +258,59 @@ fn render_server_template(
     content
 }
 
+/// Render the sqlite builder's connection options as a `[sqlite]` table,
+/// or nothing if none are set
+fn render_sqlite_options(options: &SqliteOptions) -> String {
+    if *options == SqliteOptions::default() {
+        return String::new();
+    }
+    let mut table = String::from("[sqlite]\n");
+    for (key, mode) in [
+        ("journal_mode", &options.journal_mode),
+        ("synchronous", &options.synchronous),
+    ] {
+        if let Some(mode) = mode {
+            table.push_str(&format!("{} = {:?}\n", key, mode));
+        }
+    }
+    if let Some(enabled) = options.foreign_keys {
+        table.push_str(&format!("foreign_keys = {}\n", enabled));
+    }
+    if let Some(ms) = options.busy_timeout {
+        table.push_str(&format!("busy_timeout = {}\n", ms));
+    }
+    if let Some(ms) = options.lock_timeout {
+        table.push_str(&format!("lock_timeout = {}\n", ms));
+    }
+    if options.read_only {
+        table.push_str("read_only = true\n");
+    }
+    if let Some(create) = options.create {
+        table.push_str(&format!("create = {}\n", create));
+    }
+    if let Some(ref dir) = options.backup_dir {
+        table.push_str(&format!("backup_dir = {:?}\n", dir));
+    }
+    if let Some(keep) = options.backup_keep {
+        table.push_str(&format!("backup_keep = {}\n", keep));
+    }
+    if !options.pragmas.is_empty() {
+        table.push_str("\n[sqlite.pragmas]\n");
+        for (name, value) in &options.pragmas {
+            let value = match value {
+                Value::Integer(n) => n.to_string(),
+                Value::Real(f) => format!("{:?}", f),
+                Value::Text(s) => format!("{:?}", s),
+                // rejected by `SqliteOptions::validated`
+                Value::Null | Value::Blob(_) => continue,
+            };
+            table.push_str(&format!("{} = {}\n", name, value));
+        }
+    }
+    table.push('\n');
+    table
+}
+
 impl SettingsFileInitializer {
     pub(crate) fn new<T: AsRef<Path>>(dir: T) -> Self {
         Self {
@@ -207,6 +356,29 @@ impl SettingsFileInitializer {
         self
     }
 
+    /// Specify DuckDB database options
+    ///
+    /// ## Example:
+    ///
+    /// ```rust,no_run
+    /// # use std::env;
+    /// use migrant_lib::Config;
+    /// use migrant_lib::config::DuckDbSettingsBuilder;
+    /// # fn main() { run().unwrap() }
+    /// # fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// Config::init_in(env::current_dir()?)
+    ///     .with_duckdb_options(
+    ///         DuckDbSettingsBuilder::empty()
+    ///             .database_path("/abs/path/to/my.duckdb")?)
+    ///     .initialize()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_duckdb_options(mut self, options: DuckDbSettingsBuilder) -> Self {
+        self.database_options = Some(DatabaseConfigOptions::DuckDb(options));
+        self
+    }
+
     /// Specify Postgres database options
     ///
     /// ## Example:
@@ -291,7 +463,7 @@ impl SettingsFileInitializer {
     /// Prompt the user for a database type, returning default options for it
     fn prompt_for_database_options() -> Result<DatabaseConfigOptions> {
         println!("\n ** Gathering database information...");
-        let db_kind = prompt(" database type (sqlite|postgres|mysql) >> ")?;
+        let db_kind = prompt(" database type (sqlite|postgres|mysql|duckdb) >> ")?;
         let db_kind = db_kind
             .parse::<DbKind>()
             .map_err(|_| err!(Config, "unsupported database type: {}", db_kind))?;
@@ -300,6 +472,10 @@ impl SettingsFileInitializer {
                 let options = SqliteSettingsBuilder::empty().migration_location("migrations")?;
                 DatabaseConfigOptions::Sqlite(options)
             }
+            DbKind::DuckDb => {
+                let options = DuckDbSettingsBuilder::empty().migration_location("migrations")?;
+                DatabaseConfigOptions::DuckDb(options)
+            }
             DbKind::Postgres => {
                 let options = PostgresSettingsBuilder::empty().migration_location("migrations")?;
                 DatabaseConfigOptions::Postgres(options)
@@ -311,6 +487,38 @@ impl SettingsFileInitializer {
         })
     }
 
+    /// Fill in an embedded (sqlite / duckdb) database config template
+    fn render_file_template(
+        &self,
+        template: &str,
+        config_path: &Path,
+        database_path: Option<&String>,
+        migration_location: Option<&String>,
+    ) -> Result<String> {
+        let config_dir = config_path.parent().and_then(Path::to_str).ok_or_else(|| {
+            err!(
+                PathError,
+                "Unable to determine config dir: {:?}",
+                config_path
+            )
+        })?;
+        Ok(template
+            .replace("__CONFIG_DIR__", config_dir)
+            .replace(
+                "__DB_PATH__",
+                &value_or(database_path, self.with_env_defaults, "DATABASE_PATH", ""),
+            )
+            .replace(
+                "__MIG_LOC__",
+                &value_or(
+                    migration_location,
+                    self.with_env_defaults,
+                    "MIGRATION_LOCATION",
+                    "migrations",
+                ),
+            ))
+    }
+
     /// Generate a template config file using provided parameters or prompting the user.
     /// If running interactively, the file will be opened for editing and `Config::setup`
     /// will be run automatically.
@@ -343,6 +551,7 @@ impl SettingsFileInitializer {
         };
         let db_kind = match db_options {
             DatabaseConfigOptions::Sqlite(_) => DbKind::Sqlite,
+            DatabaseConfigOptions::DuckDb(_) => DbKind::DuckDb,
             DatabaseConfigOptions::Postgres(_) => DbKind::Postgres,
             DatabaseConfigOptions::MySql(_) => DbKind::MySql,
         };
@@ -365,34 +574,21 @@ impl SettingsFileInitializer {
                 "3306",
             ),
             DatabaseConfigOptions::Sqlite(ref opts) => {
-                let config_dir = config_path.parent().and_then(Path::to_str).ok_or_else(|| {
-                    err!(
-                        PathError,
-                        "Unable to determine config dir: {:?}",
-                        config_path
-                    )
-                })?;
-                SQLITE_CONFIG_TEMPLATE
-                    .replace("__CONFIG_DIR__", config_dir)
-                    .replace(
-                        "__DB_PATH__",
-                        &value_or(
-                            opts.database_path.as_ref(),
-                            self.with_env_defaults,
-                            "DATABASE_PATH",
-                            "",
-                        ),
-                    )
-                    .replace(
-                        "__MIG_LOC__",
-                        &value_or(
-                            opts.migration_location.as_ref(),
-                            self.with_env_defaults,
-                            "MIGRATION_LOCATION",
-                            "migrations",
-                        ),
-                    )
+                let mut content = self.render_file_template(
+                    SQLITE_CONFIG_TEMPLATE,
+                    &config_path,
+                    opts.database_path.as_ref(),
+                    opts.migration_location.as_ref(),
+                )?;
+                content.push_str(&render_sqlite_options(&opts.sqlite.clone().validated()?));
+                content
             }
+            DatabaseConfigOptions::DuckDb(ref opts) => self.render_file_template(
+                DUCKDB_CONFIG_TEMPLATE,
+                &config_path,
+                opts.database_path.as_ref(),
+                opts.migration_location.as_ref(),
+            )?,
         };
         write_to_path(&config_path, content.as_bytes())?;
 
@@ -1,30 +1,57 @@
 /*!
 Configuration
 */
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::env;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
 
-use log::{debug, error};
+use log::{debug, error, warn};
 
-use crate::drivers::DbConnection;
+use crate::drivers::{self, DbConnection, MigrationDriver};
 use crate::errors::*;
 use crate::macros::{bail, err};
 use crate::migratable::Migratable;
-use crate::{tags, DbKind, DT_FORMAT, SQLITE_MEMORY_PATH};
+use crate::migrator::CancelToken;
+use crate::statements;
+use crate::template;
+use crate::tenants::{self, Tenant, TenantResult};
+use crate::{DbKind, SQLITE_MEMORY_PATH};
+
+/// How often a cancellable run retries a held migration lock
+pub(crate) const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(200);
 
 mod builders;
 mod init;
 mod settings;
 
-pub use builders::{MySqlSettingsBuilder, PostgresSettingsBuilder, SqliteSettingsBuilder};
+pub use builders::{
+    DuckDbSettingsBuilder, MySqlSettingsBuilder, PostgresSettingsBuilder, SqliteSettingsBuilder,
+};
 pub use init::SettingsFileInitializer;
 pub use settings::Settings;
 
 pub(crate) use settings::DbSettings;
+#[cfg(feature = "mysql")]
+pub(crate) use settings::MySqlTls;
+#[cfg(feature = "sqlite")]
+pub(crate) use settings::SqliteOptions;
+#[cfg(feature = "postgres")]
+pub(crate) use settings::TlsFiles;
+
+/// Where a `Config`'s connection comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConnSource {
+    /// Opened from the settings, and re-opened if dropped
+    Settings,
+    /// Handed over by the application, see `Config::from_postgres_client`
+    Adopted,
+    /// An application-supplied driver, see `Config::with_driver`
+    Driver,
+}
 
 /// Full project configuration
 ///
@@ -40,6 +67,16 @@ pub struct Config {
     pub(crate) applied: Vec<String>,
     pub(crate) migrations: Option<Vec<Box<dyn Migratable>>>,
     pub(crate) cli_compatible: bool,
+    /// SQL template variables set with `Config::variable`, taking precedence
+    /// over the settings file's `[variables]`
+    pub(crate) variables: BTreeMap<String, String>,
+    /// The tenant this config was derived for, see `Config::for_tenant`
+    pub(crate) tenant: Option<Tenant>,
+    conn_source: ConnSource,
+    /// Set on the `Config` an `AsyncConfig` wraps for a server database. Its
+    /// runs hold their transaction and migration lock on the async
+    /// connection, so this blocking one is never opened, see `lock_conn`.
+    pub(crate) async_server: bool,
     conn: Arc<Mutex<Option<DbConnection>>>,
     /// Bumped whenever an established connection is dropped (and will be
     /// re-established on next use). Session-scoped state -- notably the
@@ -58,6 +95,10 @@ impl Config {
             applied: vec![],
             migrations: None,
             cli_compatible: false,
+            variables: BTreeMap::new(),
+            tenant: None,
+            conn_source: ConnSource::Settings,
+            async_server: false,
             conn: Arc::new(Mutex::new(None)),
             conn_generation: Arc::new(AtomicU64::new(0)),
         }
@@ -97,6 +138,210 @@ impl Config {
         Self::from_parts(settings, None)
     }
 
+    /// Run migrations through an application-supplied [`MigrationDriver`]
+    /// instead of a connection built from the settings.
+    ///
+    /// The settings still provide everything but the connection: where
+    /// migrations live, the version scheme, and -- through `database_type` --
+    /// the SQL dialect migration files are split with. The driver is shared by
+    /// this `Config`'s clones and reloads, and is never replaced by a
+    /// settings-built connection. Backend-specific helpers (such as
+    /// `sqlite_connection`), tenants and [`AsyncConfig`](crate::AsyncConfig)
+    /// are unavailable with a custom driver.
+    ///
+    /// ```rust,no_run
+    /// # fn run(driver: Box<dyn migrant_lib::MigrationDriver>) -> Result<(), Box<dyn std::error::Error>> {
+    /// // connection details are unused, only the dialect matters
+    /// let settings = migrant_lib::Settings::configure_postgres()
+    ///     .database_name("app")
+    ///     .database_user("app")
+    ///     .database_password("")
+    ///     .migration_location("/abs/path/to/migrations")?
+    ///     .build()?;
+    /// let mut config = migrant_lib::Config::with_settings(settings);
+    /// config.with_driver(driver);
+    /// config.setup()?;
+    /// let config = config.reload()?;
+    /// migrant_lib::Migrator::with_config(&config).all(true).apply()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_driver(&mut self, driver: Box<dyn MigrationDriver>) -> &mut Self {
+        self.conn = Arc::new(Mutex::new(Some(DbConnection::Custom(driver))));
+        self.conn_generation = Arc::new(AtomicU64::new(0));
+        self.conn_source = ConnSource::Driver;
+        self
+    }
+
+    /// Initialize a `Config` that runs migrations over an already open
+    /// postgres client -- with whatever TLS, authentication and session
+    /// settings the application gave it -- instead of connecting with the
+    /// settings.
+    ///
+    /// The settings must be postgres settings; their connection details are
+    /// unused. As with [`Config::with_driver`], the client is shared by this
+    /// `Config`'s clones and reloads, is never replaced by a settings-built
+    /// connection, and tenants are unsupported. Migration runs take the usual
+    /// advisory lock on the client's session.
+    ///
+    /// ```rust,no_run
+    /// # fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let client = postgres::Client::connect("host=db user=app", postgres::NoTls)?;
+    /// let settings = migrant_lib::Settings::configure_postgres()
+    ///     .database_name("app")
+    ///     .database_user("app")
+    ///     .database_password("")
+    ///     .migration_location("/abs/path/to/migrations")?
+    ///     .build()?;
+    /// let config = migrant_lib::Config::from_postgres_client(settings, client)?;
+    /// config.setup()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "postgres")]
+    pub fn from_postgres_client(settings: Settings, client: postgres::Client) -> Result<Config> {
+        use crate::drivers::{pg::PgConn, Handle};
+        let conn = PgConn::adopt(Handle::Owned(client));
+        Self::adopting(
+            settings,
+            DbKind::Postgres,
+            DbConnection::Postgres(Box::new(conn)),
+        )
+    }
+
+    /// Like [`Config::from_postgres_client`], for a client checked out of a
+    /// connection pool (an `r2d2::PooledConnection`, or any other guard
+    /// dereferencing to a `postgres::Client`). The checkout is held until the
+    /// last clone of the `Config` is dropped. See also `Config::from_r2d2_pool`.
+    #[cfg(feature = "postgres")]
+    pub fn from_pooled_postgres_client<C>(settings: Settings, client: C) -> Result<Config>
+    where
+        C: std::ops::DerefMut<Target = postgres::Client> + Send + 'static,
+    {
+        use crate::drivers::{pg::PgConn, Handle};
+        let conn = PgConn::adopt(Handle::Pooled(Box::new(client)));
+        Self::adopting(
+            settings,
+            DbKind::Postgres,
+            DbConnection::Postgres(Box::new(conn)),
+        )
+    }
+
+    /// Initialize a `Config` that runs migrations over an already open mysql
+    /// connection. See [`Config::from_postgres_client`].
+    #[cfg(feature = "mysql")]
+    pub fn from_mysql_conn(settings: Settings, conn: mysql::Conn) -> Result<Config> {
+        use crate::drivers::{mysql::MySqlConn, Handle};
+        let conn = MySqlConn::adopt(Handle::Owned(conn));
+        Self::adopting(settings, DbKind::MySql, DbConnection::MySql(Box::new(conn)))
+    }
+
+    /// Like [`Config::from_mysql_conn`], for a connection checked out of a
+    /// connection pool (a `mysql::PooledConn`, an `r2d2::PooledConnection`, or
+    /// any other guard dereferencing to a `mysql::Conn`). The checkout is held
+    /// until the last clone of the `Config` is dropped.
+    #[cfg(feature = "mysql")]
+    pub fn from_pooled_mysql_conn<C>(settings: Settings, conn: C) -> Result<Config>
+    where
+        C: std::ops::DerefMut<Target = mysql::Conn> + Send + 'static,
+    {
+        use crate::drivers::{mysql::MySqlConn, Handle};
+        let conn = MySqlConn::adopt(Handle::Pooled(Box::new(conn)));
+        Self::adopting(settings, DbKind::MySql, DbConnection::MySql(Box::new(conn)))
+    }
+
+    /// Initialize a `Config` that runs migrations over an already open sqlite
+    /// connection, keeping the pragmas, extensions and functions the
+    /// application set up on it. See [`Config::from_postgres_client`].
+    ///
+    /// The settings must be sqlite settings; their `database_path` is neither
+    /// opened nor created by `Config::setup`. The connection is available to
+    /// function migrations
+    /// through [`ConnConfig::sqlite_connection`](crate::ConnConfig::sqlite_connection).
+    #[cfg(feature = "sqlite")]
+    pub fn from_rusqlite_connection(
+        settings: Settings,
+        conn: rusqlite::Connection,
+    ) -> Result<Config> {
+        let conn = crate::drivers::sqlite::SqliteConn::adopt(conn);
+        Self::adopting(settings, DbKind::Sqlite, DbConnection::Sqlite(conn))
+    }
+
+    /// Check out a connection from an `r2d2` pool of postgres clients or
+    /// mysql connections (such as `r2d2_postgres`'s) and run migrations over
+    /// it. See [`Config::from_pooled_postgres_client`].
+    #[cfg(feature = "r2d2")]
+    pub fn from_r2d2_pool<M>(settings: Settings, pool: &r2d2::Pool<M>) -> Result<Config>
+    where
+        M: r2d2::ManageConnection,
+        M::Connection: crate::drivers::PoolableConnection,
+    {
+        let conn = pool
+            .get()
+            .map_err(|e| err!(Migration, "Error checking out a pooled connection: {}", e))?;
+        crate::drivers::PoolableConnection::adopt_checkout(settings, conn)
+    }
+
+    #[cfg_attr(
+        not(any(feature = "sqlite", feature = "postgres", feature = "mysql")),
+        allow(dead_code)
+    )]
+    fn adopting(settings: Settings, kind: DbKind, conn: DbConnection) -> Result<Config> {
+        let mut config = Self::from_parts(settings, None);
+        if config.database_type() != kind {
+            bail!(
+                Config,
+                "Cannot run migrations over a {} connection with {} settings",
+                kind,
+                config.database_type()
+            )
+        }
+        config.conn = Arc::new(Mutex::new(Some(conn)));
+        config.conn_source = ConnSource::Adopted;
+        Ok(config)
+    }
+
+    /// Whether the connection was supplied by the application, so it can't be
+    /// re-established from the settings
+    fn has_external_conn(&self) -> bool {
+        self.conn_source != ConnSource::Settings
+    }
+
+    /// Whether migration runs on this config take the migration lock: server
+    /// databases, sqlite files and custom drivers do. An in-memory database
+    /// can't be shared with another process, and a duckdb file is locked by
+    /// the process that opens it, so neither has a lock to take.
+    pub(crate) fn uses_migration_lock(&self) -> bool {
+        match self.database_type() {
+            _ if self.conn_source == ConnSource::Driver => true,
+            DbKind::Sqlite => !self.settings.inner.is_memory(),
+            DbKind::DuckDb => false,
+            DbKind::Postgres | DbKind::MySql => true,
+        }
+    }
+
+    /// How long a run waits for the migration lock: the sqlite
+    /// `lock_timeout`, unbounded otherwise
+    fn migration_lock_timeout(&self) -> Option<Duration> {
+        match self.settings.inner {
+            DbSettings::Sqlite(ref s) => s.sqlite.lock_timeout.map(Duration::from_millis),
+            _ => None,
+        }
+    }
+
+    /// Fail a wait for the migration lock, begun at `started`, that has
+    /// outlasted the lock timeout
+    pub(crate) fn check_lock_wait(&self, started: Instant) -> Result<()> {
+        match self.migration_lock_timeout() {
+            Some(timeout) if started.elapsed() >= timeout => bail!(
+                Migration,
+                "Timed out after {}ms waiting for the migration lock held by another migrator",
+                timeout.as_millis()
+            ),
+            _ => Ok(()),
+        }
+    }
+
     /// Initialize a new settings file in the given directory
     pub fn init_in<T: AsRef<Path>>(dir: T) -> SettingsFileInitializer {
         SettingsFileInitializer::new(dir.as_ref())
@@ -105,22 +350,21 @@ impl Config {
     /// Run a closure with the live database connection, establishing
     /// the connection first if necessary.
     pub(crate) fn with_conn<T>(&self, f: impl FnOnce(&mut DbConnection) -> Result<T>) -> Result<T> {
-        let mut guard = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
-        if guard.is_none() {
-            *guard = Some(DbConnection::connect(self)?);
-        }
+        let mut guard = self.lock_conn()?;
         let conn = guard.as_mut().expect("connection just established");
         let res = f(conn);
-        if res.is_err() && self.database_type() != DbKind::Sqlite {
+        if res.is_err() && !self.database_type().is_embedded() {
             // A server error may leave the connection stuck in an
             // aborted-transaction state. Recover it in place with a rollback
             // rather than dropping it, so session-scoped state -- notably the
             // migration advisory lock -- survives the error and is still held
             // when a `force`d run continues past it. Only if the rollback fails
             // (a genuinely dead connection) do we drop it, so the next
-            // operation reconnects. Sqlite is excluded: its driver handles
-            // rollback and an in-memory database would be lost if dropped.
-            if conn.rollback().is_err() {
+            // operation reconnects. Embedded databases are excluded: their
+            // drivers handle rollback and an in-memory database would be lost
+            // if dropped. An application-supplied connection can't be
+            // re-established, so it is kept.
+            if conn.rollback().is_err() && !self.has_external_conn() {
                 *guard = None;
                 self.conn_generation.fetch_add(1, Ordering::SeqCst);
             }
@@ -128,15 +372,47 @@ impl Config {
         res
     }
 
+    /// Lock the live database connection, establishing it first if necessary.
+    /// The guard is always `Some`. Nothing else can use the connection until
+    /// it is dropped, see `ConnConfig::postgres_client`.
+    ///
+    /// Fails for the server config wrapped by an `AsyncConfig`: a blocking
+    /// connection opened here would sit outside the async run's transaction
+    /// and migration lock.
+    pub(crate) fn lock_conn(&self) -> Result<MutexGuard<'_, Option<DbConnection>>> {
+        if self.async_server {
+            bail!(
+                Config,
+                "The live {} connection is async under `AsyncMigrator`, so synchronous \
+                 function migrations can't use it; use an `AsyncFnMigration`, or open a \
+                 connection from `ConnConfig::connect_string`",
+                self.database_type()
+            )
+        }
+        let mut guard = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+        if guard.is_none() {
+            *guard = Some(DbConnection::connect(self)?);
+        }
+        Ok(guard)
+    }
+
     /// Generation of the current connection. Changes whenever an established
     /// connection had to be dropped (see `conn_generation`).
     pub(crate) fn connection_generation(&self) -> u64 {
         self.conn_generation.load(Ordering::SeqCst)
     }
 
-    /// Execute a batch of sql statements on the database
+    /// Execute a batch of sql statements on the database, split and run one
+    /// statement at a time so a failure names the statement that failed
     pub(crate) fn execute_sql(&self, sql: &str) -> Result<()> {
-        self.with_conn(|conn| conn.execute_batch(sql))
+        let statements = statements::split(sql, self.database_type())?;
+        self.with_conn(|conn| {
+            for statement in &statements {
+                conn.execute_batch(&statement.sql)
+                    .map_err(|e| statement.failed(statements.len(), e))?;
+            }
+            Ok(())
+        })
     }
 
     /// Begin a transaction on the live connection
@@ -158,16 +434,40 @@ impl Config {
         let _ = self.with_conn(|conn| conn.rollback());
     }
 
-    /// Acquire the session-level advisory lock that serializes migration runs.
-    /// Blocks until the lock is available. No-op for sqlite.
+    /// Acquire the lock that serializes migration runs, blocking until it is
+    /// available or the lock timeout passes
     pub(crate) fn acquire_migration_lock(&self) -> Result<()> {
-        self.with_conn(|conn| conn.acquire_lock())
+        if self.migration_lock_timeout().is_none() {
+            return self.with_conn(|conn| conn.acquire_lock());
+        }
+        self.acquire_migration_lock_unless_cancelled(&CancelToken::new())
+            .map(|_| ())
+    }
+
+    /// Acquire the migration advisory lock like `acquire_migration_lock`, but
+    /// poll for it so the wait ends once `cancel` is cancelled. Returns whether
+    /// the lock was acquired.
+    pub(crate) fn acquire_migration_lock_unless_cancelled(
+        &self,
+        cancel: &CancelToken,
+    ) -> Result<bool> {
+        let started = Instant::now();
+        loop {
+            if cancel.is_cancelled() {
+                return Ok(false);
+            }
+            if self.with_conn(|conn| conn.try_acquire_lock())? {
+                return Ok(true);
+            }
+            self.check_lock_wait(started)?;
+            std::thread::sleep(LOCK_POLL_INTERVAL);
+        }
     }
 
     /// Release the migration advisory lock.
     ///
-    /// Best-effort: server databases release session-level locks automatically
-    /// when the connection drops, so failures to issue an explicit unlock (for
+    /// Best-effort: the lock is released automatically when its connection
+    /// drops, so failures to issue an explicit unlock (for
     /// example after the connection was reconnected on an error path) are
     /// ignored.
     pub(crate) fn release_migration_lock(&self) {
@@ -208,6 +508,75 @@ impl Config {
         })
     }
 
+    /// Return a shared handle to the live duckdb connection, establishing the
+    /// connection first if necessary. Like
+    /// [`sqlite_connection`](Config::sqlite_connection), this is how application
+    /// code reaches an in-memory (`:memory:`) database.
+    #[cfg(feature = "duckdb")]
+    pub fn duckdb_connection(&self) -> Result<Arc<Mutex<duckdb::Connection>>> {
+        self.with_conn(|conn| match conn {
+            DbConnection::DuckDb(d) => Ok(d.handle()),
+            #[allow(unreachable_patterns)]
+            _ => Err(err!(
+                Config,
+                "Cannot get a duckdb connection for database-type: {}",
+                self.database_type()
+            )),
+        })
+    }
+
+    /// Write an online backup of the sqlite database to `path`, replacing any
+    /// file already there. The database stays usable while it is copied.
+    pub fn backup_database<T: AsRef<Path>>(&self, path: T) -> Result<()> {
+        let path = path.as_ref();
+        self.with_conn(|conn| match conn {
+            #[cfg(feature = "sqlite")]
+            DbConnection::Sqlite(s) => s.backup_to(path),
+            #[allow(unreachable_patterns)]
+            _ => Err(err!(
+                Config,
+                "Cannot back up to {:?} for database-type: {}, only sqlite databases are supported",
+                path,
+                self.database_type()
+            )),
+        })
+    }
+
+    /// Replace the sqlite database's contents with a backup written by
+    /// [`backup_database`](Config::backup_database) or by a migration run's
+    /// [`backup_before_run`](crate::Migrator::backup_before_run).
+    ///
+    /// The migration lock is held while restoring, so a restore never
+    /// interleaves with another process's migration run. This config's applied
+    /// migrations are not refreshed, see [`reload`](Config::reload).
+    pub fn restore_database<T: AsRef<Path>>(&self, backup: T) -> Result<()> {
+        let backup = backup.as_ref();
+        let locked = self.uses_migration_lock();
+        if locked {
+            self.acquire_migration_lock()?;
+        }
+        let res = self.restore_database_unlocked(backup);
+        if locked {
+            self.release_migration_lock();
+        }
+        res
+    }
+
+    /// Restore a backup on a connection already holding the migration lock
+    pub(crate) fn restore_database_unlocked(&self, backup: &Path) -> Result<()> {
+        self.with_conn(|conn| match conn {
+            #[cfg(feature = "sqlite")]
+            DbConnection::Sqlite(s) => s.restore_from(backup),
+            #[allow(unreachable_patterns)]
+            _ => Err(err!(
+                Config,
+                "Cannot restore {:?} for database-type: {}, only sqlite databases are supported",
+                backup,
+                self.database_type()
+            )),
+        })
+    }
+
     /// Define an explicit set of `Migratable` migrations to use.
     ///
     /// The order of definition is the order in which they will be applied.
@@ -275,21 +644,24 @@ impl Config {
         let mut seen = HashSet::with_capacity(migrations.len());
         for mig in migrations {
             let tag = mig.tag();
+            let scheme = self.settings.version_scheme;
             if self.cli_compatible {
-                if !tags::is_valid_full_tag(&tag) {
+                if !scheme.is_valid_full_tag(&tag) {
                     bail!(
                         TagError,
-                        "When `cli_compatible=true` tags must be timestamped, \
-                         following: `[0-9]{{14}}_[a-z0-9-]+`. Found tag: `{}`",
+                        "When `cli_compatible=true` tags must be versioned, \
+                         following: `{}`. Found tag: `{}`",
+                        scheme.pattern(),
                         tag
                     )
                 }
-            } else if !tags::is_valid_opt_stamped_tag(&tag) {
+            } else if !scheme.is_valid_opt_versioned_tag(&tag) {
                 bail!(
                     TagError,
                     "When `cli_compatible=false` (default) tags may only contain, \
-                     `[a-z0-9-]` and may be optionally prefixed with a timestamp \
-                     following: `([0-9]{{14}}_)?[a-z0-9-]+`. Found tag: `{}`",
+                     `[a-z0-9-]` and may be optionally prefixed with a version \
+                     following: `({})?[a-z0-9-]+`. Found tag: `{}`",
+                    scheme.pattern().trim_end_matches("[a-z0-9-]+"),
                     tag
                 )
             }
@@ -339,23 +711,62 @@ impl Config {
         self.cli_compatible
     }
 
+    /// Define a SQL template variable, overriding any value of the same name
+    /// from the settings file's `[variables]` table.
+    ///
+    /// Once any variable is defined, `{{ name }}` placeholders in `FileMigration`
+    /// and `EmbeddedMigration` SQL are replaced with their values before the SQL
+    /// is run, and a placeholder naming an undefined variable is an error.
+    /// Without any variables, migration SQL is run exactly as written.
+    ///
+    /// ```rust,no_run
+    /// # fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut config = migrant_lib::Config::from_settings_file("Migrant.toml")?;
+    /// config.variable("app_role", "app_rw");
+    /// let config = config.reload()?;
+    /// # let _ = config;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn variable<K: Into<String>, V: Into<String>>(&mut self, name: K, value: V) -> &mut Self {
+        self.variables.insert(name.into(), value.into());
+        self
+    }
+
+    /// Render `{{ name }}` placeholders in migration SQL, see `Config::variable`.
+    /// SQL is returned unchanged when no variables are defined.
+    pub(crate) fn render_sql(&self, sql: String) -> Result<String> {
+        if self.variables.is_empty() && self.settings.variables.is_empty() {
+            return Ok(sql);
+        }
+        template::render(&sql, |name| {
+            self.variables
+                .get(name)
+                .or_else(|| self.settings.variables.get(name))
+                .map(String::as_str)
+        })
+    }
+
     /// Check that migration tags conform to naming requirements.
-    /// If CLI compatibility is enabled, then tags must be prefixed with a timestamp
-    /// following: `[0-9]{14}_[a-z0-9-]+` which is the format generated by the migrant
+    /// If CLI compatibility is enabled, then tags must be prefixed with a version
+    /// of the configured `VersionScheme`, by default a timestamp following:
+    /// `[0-9]{14}_[a-z0-9-]+` which is the format generated by the migrant
     /// CLI tool and `migrant_lib::new`. When CLI compatibility is disabled (default),
     /// tags may only contain `[a-z0-9-]`, but can still be optionally prefixed with
-    /// a timestamp following: `([0-9]{14}_)?[a-z0-9-]+`.
+    /// a version: `([0-9]{14}_)?[a-z0-9-]+`.
     fn check_saved_tag(&self, tag: &str) -> Result<()> {
+        let scheme = self.settings.version_scheme;
         if self.cli_compatible {
-            if !tags::is_valid_full_tag(tag) {
+            if !scheme.is_valid_full_tag(tag) {
                 bail!(
                     Migration,
                     "Found a non-conforming tag in the database: `{}`. \
-                     Generated/CLI-compatible tags must follow `[0-9]{{14}}_[a-z0-9-]+`",
-                    tag
+                     Generated/CLI-compatible tags must follow `{}`",
+                    tag,
+                    scheme.pattern()
                 )
             }
-        } else if !tags::is_valid_opt_stamped_tag(tag) {
+        } else if !scheme.is_valid_opt_versioned_tag(tag) {
             bail!(
                 Migration,
                 "Found a non-conforming tag in the database: `{}`. \
@@ -375,29 +786,115 @@ impl Config {
     /// If the `Config` was initialized from a settings file, the settings
     /// will also be reloaded from the file. Returns a new `Config` instance.
     pub fn reload(&self) -> Result<Config> {
+        let mut config = self.reload_settings()?;
+        config.applied = config.load_applied()?;
+        Ok(config)
+    }
+
+    /// The settings-file half of `Config::reload`: re-read the settings file
+    /// (if any), carrying over the live connection when the settings are
+    /// unchanged, along with the migrations and tag mode. Applied migrations
+    /// are left for the caller to load.
+    pub(crate) fn reload_settings(&self) -> Result<Config> {
         let mut config = match self.settings_path.as_ref() {
             Some(path) => {
                 let mut reloaded = Config::from_settings_file(path)?;
+                if let Some(ref tenant) = self.tenant {
+                    reloaded = reloaded.for_tenant(tenant)?;
+                }
                 // `from_settings_file` builds a fresh, unconnected config. If the
                 // settings on disk are unchanged, carry over our live connection
                 // so that in-memory (`:memory:`) databases -- whose entire state
                 // lives in the connection handle -- are not silently discarded.
                 // If the settings changed, keep the fresh connection so the
                 // reloaded config connects using the new settings.
-                if reloaded.settings == self.settings {
+                // An application-supplied connection is always carried over:
+                // the settings can't rebuild it.
+                if reloaded.settings == self.settings || self.has_external_conn() {
                     reloaded.conn = Arc::clone(&self.conn);
                     reloaded.conn_generation = Arc::clone(&self.conn_generation);
+                    reloaded.conn_source = self.conn_source;
                 }
                 reloaded
             }
             None => self.clone(),
         };
         config.cli_compatible = self.cli_compatible;
+        config.variables = self.variables.clone();
         config.migrations = self.migrations.clone();
-        config.applied = config.load_applied()?;
         Ok(config)
     }
 
+    /// Resolve the configured `[tenants]` into the list of tenant targets.
+    ///
+    /// A tenant `query` is run against this config's database.
+    pub fn tenants(&self) -> Result<Vec<Tenant>> {
+        match self.settings.tenants {
+            Some(ref settings) => tenants::resolve_tenants(self, settings),
+            None => bail!(Config, "No `[tenants]` are configured in the settings"),
+        }
+    }
+
+    /// Derive the `Config` for a single tenant: the same settings and
+    /// migrations, pointed at the tenant's schema (postgres), database (mysql)
+    /// or database file (sqlite). The derived config has its own connection,
+    /// and its migration runs take a tenant-scoped advisory lock.
+    pub fn for_tenant(&self, tenant: &Tenant) -> Result<Config> {
+        if self.has_external_conn() {
+            bail!(
+                Config,
+                "Tenants are not supported with an application-supplied connection"
+            )
+        }
+        let inner =
+            tenants::tenant_db_settings(&self.settings.inner, tenant, &self.relative_base_dir()?)?;
+        let settings = Settings {
+            variables: self.settings.variables.clone(),
+            version_scheme: self.settings.version_scheme,
+            ..Settings::new(inner)
+        };
+        let mut config = Self::from_parts(settings, self.settings_path.clone());
+        config.migrations = self.migrations.clone();
+        config.cli_compatible = self.cli_compatible;
+        config.variables = self.variables.clone();
+        config.tenant = Some(tenant.clone());
+        Ok(config)
+    }
+
+    /// The tenant this config was derived for, if any
+    pub fn tenant(&self) -> Option<&Tenant> {
+        self.tenant.as_ref()
+    }
+
+    /// Run `f` against each configured tenant's derived config (see
+    /// `Config::for_tenant`), migrating up to the configured `parallelism`
+    /// tenants at once. Under `OnTenantFailure::Stop` no new tenant is started
+    /// after a failure. Returns one result per tenant, in tenant order.
+    ///
+    /// ```rust,no_run
+    /// # fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// let config = migrant_lib::Config::from_settings_file("Migrant.toml")?;
+    /// for result in config.for_each_tenant(|tenant| {
+    ///     migrant_lib::migration_statuses(&tenant.reload()?)
+    /// })? {
+    ///     println!("{}: {:?}", result.tenant(), result.result());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn for_each_tenant<T, F>(&self, f: F) -> Result<Vec<TenantResult<T>>>
+    where
+        T: Send,
+        F: Fn(&Config) -> Result<T> + Sync,
+    {
+        tenants::fan_out(self, f)
+    }
+
+    /// Run a query returning one text column, collecting its values
+    pub(crate) fn query_column(&self, sql: &str) -> Result<Vec<String>> {
+        self.with_conn(|conn| conn.query_column(sql))
+    }
+
     /// Re-read applied migrations from the database in place, without
     /// re-reading the settings file or touching the live connection (unlike
     /// `Config::reload`). Used by the migrator so a run stays on the
@@ -410,33 +907,30 @@ impl Config {
     /// Load the applied migrations from the database migration table
     pub(crate) fn load_applied(&self) -> Result<Vec<String>> {
         if !self.migration_table_exists()? {
-            bail!(
-                Migration,
-                "`__migrant_migrations` table is missing, maybe try re-setting-up? -> `setup`"
-            )
+            return Err(missing_migration_table());
         }
 
         let applied = self.with_conn(|conn| conn.applied_tags())?;
+        self.check_applied(applied)
+    }
+
+    /// Validate applied tags read from the database, ordering them
+    /// by version when running in cli-compatible mode
+    pub(crate) fn check_applied(&self, applied: Vec<String>) -> Result<Vec<String>> {
         for tag in &applied {
             self.check_saved_tag(tag)?;
         }
         if !self.cli_compatible {
             return Ok(applied);
         }
-        // Applied cli-compatible (timestamp-prefixed) tags are ordered chronologically
-        let mut stamped = applied
+        // Applied cli-compatible (version-prefixed) tags are ordered by version
+        let scheme = self.settings.version_scheme;
+        let mut versioned = applied
             .into_iter()
-            .map(|tag| {
-                let stamp = tag
-                    .split('_')
-                    .next()
-                    .ok_or_else(|| err!(TagError, "Invalid tag format: {:?}", tag))?;
-                let stamp = chrono::NaiveDateTime::parse_from_str(stamp, DT_FORMAT)?;
-                Ok((stamp, tag))
-            })
+            .map(|tag| Ok((scheme.split_tag(&tag)?.1, tag)))
             .collect::<Result<Vec<_>>>()?;
-        stamped.sort_by_key(|(stamp, _)| *stamp);
-        Ok(stamped.into_iter().map(|(_, tag)| tag).collect())
+        versioned.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(versioned.into_iter().map(|(_, tag)| tag).collect())
     }
 
     /// Check if a `__migrant_migrations` table exists
@@ -459,63 +953,39 @@ impl Config {
     pub fn setup(&self) -> Result<bool> {
         debug!(" ** Confirming database credentials...");
         match self.settings.inner {
+            // the application set up the connection
+            _ if self.has_external_conn() => (),
             DbSettings::Sqlite(ref s) => {
                 if !s.is_memory() {
                     let path = self.database_path()?;
                     debug!("    - checking if db file already exists...");
-                    if create_file_if_missing(&path)? {
+                    if !s.sqlite.creates_missing() {
+                        if !path.exists() {
+                            bail!(
+                                Config,
+                                "Database file {:?} does not exist and the sqlite settings \
+                                 don't allow creating it",
+                                path
+                            )
+                        }
+                        debug!("    - db already exists ✓");
+                    } else if create_file_if_missing(&path)? {
                         debug!("    - db not found... creating now... ✓");
                     } else {
                         debug!("    - db already exists ✓");
                     }
                 }
             }
-            DbSettings::Postgres(ref s) => {
-                if let Err(e) = self.with_conn(|_| Ok(())) {
-                    error!(" ERROR: Unable to connect to postgres database");
-                    error!("        Please initialize your database and user and then run `setup`");
-                    error!("\n  ex) sudo -u postgres createdb {}", s.database_name);
-                    error!("      sudo -u postgres createuser {}", s.database_user);
-                    error!(
-                        "      sudo -u postgres psql -c \"alter user {} with password '****'\"",
-                        s.database_user
-                    );
-                    error!("");
-                    bail!(
-                        Config,
-                        "Cannot connect to postgres database. \
-                         Do the database & user exist? -> {}",
-                        e
-                    );
+            DbSettings::DuckDb(ref s) => {
+                if !s.is_memory() {
+                    // duckdb creates the file when connecting, but won't open
+                    // an empty one
+                    create_parent_dir(&self.database_path()?)?;
                 }
-                debug!("    - Connection confirmed ✓");
             }
-            DbSettings::MySql(ref s) => {
+            DbSettings::Postgres(_) | DbSettings::MySql(_) => {
                 if let Err(e) = self.with_conn(|_| Ok(())) {
-                    let localhost = String::from("localhost");
-                    let host = s.database_host.as_ref().unwrap_or(&localhost);
-                    error!(" ERROR: Unable to connect to mysql database");
-                    error!("        Please initialize your database and user and then run `setup`");
-                    error!(
-                        "\n  ex) mysql -u root -p -e \"create database {};\"",
-                        s.database_name
-                    );
-                    error!(
-                        "      mysql -u root -p -e \"create user '{}'@'{}' identified by '*****';\"",
-                        s.database_user, host
-                    );
-                    error!(
-                        "      mysql -u root -p -e \"grant all privileges on {}.* to '{}'@'{}';\"",
-                        s.database_name, s.database_user, host
-                    );
-                    error!("      mysql -u root -p -e \"flush privileges;\"");
-                    error!("");
-                    bail!(
-                        Config,
-                        "Cannot connect to mysql database. \
-                         Do the database & user exist? -> {}",
-                        e
-                    );
+                    return Err(self.connect_failed(e));
                 }
                 debug!("    - Connection confirmed ✓");
             }
@@ -532,6 +1002,106 @@ impl Config {
         Ok(table_created)
     }
 
+    /// Wait for a server database to accept connections.
+    ///
+    /// Connecting is retried while the database can't be reached -- the
+    /// connection is refused, reset or times out, its socket file doesn't
+    /// exist yet, or the server is still starting up -- sleeping `backoff`
+    /// before the first retry and twice as long before each one after. Any
+    /// other failure, such as rejected credentials, is returned right away.
+    /// Gives up with [`Error::DatabaseUnavailable`] once `timeout` has passed.
+    ///
+    /// `timeout` is checked between attempts, so the wait can overrun it by
+    /// one connection attempt. Bound that with the driver's own connect
+    /// timeout, e.g. a `connect_timeout` (seconds) database param for
+    /// postgres or `tcp_connect_timeout_ms` for mysql.
+    ///
+    /// Embedded databases (sqlite, duckdb) and application-supplied
+    /// connections are available immediately.
+    pub fn wait_for_database(&self, timeout: Duration, backoff: Duration) -> Result<()> {
+        if self.database_type().is_embedded() || self.has_external_conn() {
+            return Ok(());
+        }
+        // too far off to represent: wait indefinitely
+        let deadline = Instant::now().checked_add(timeout);
+        let socket = self.settings.inner.uses_socket();
+        let mut delay = backoff;
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let e = match self.lock_conn() {
+                Ok(_) => return Ok(()),
+                Err(e) if !drivers::is_unreachable(&e, socket) => return Err(e),
+                Err(e) => e,
+            };
+            let remaining = match deadline {
+                Some(deadline) => deadline.saturating_duration_since(Instant::now()),
+                None => Duration::MAX,
+            };
+            if remaining.is_zero() {
+                bail!(
+                    DatabaseUnavailable,
+                    "Gave up waiting for the database after {} attempt(s) in {:?}: {}",
+                    attempt,
+                    timeout,
+                    e
+                )
+            }
+            let sleep = delay.min(remaining);
+            warn!(
+                "Database unavailable (attempt {}), retrying in {:?}: {}",
+                attempt, sleep, e
+            );
+            std::thread::sleep(sleep);
+            delay = delay.saturating_mul(2);
+        }
+    }
+
+    /// Log setup hints for a server database that could not be connected to,
+    /// returning the error to surface from `setup`
+    pub(crate) fn connect_failed(&self, e: Error) -> Error {
+        match self.settings.inner {
+            DbSettings::Postgres(ref s) => {
+                error!(" ERROR: Unable to connect to postgres database");
+                error!("        Please initialize your database and user and then run `setup`");
+                error!("\n  ex) sudo -u postgres createdb {}", s.database_name);
+                error!("      sudo -u postgres createuser {}", s.database_user);
+                error!(
+                    "      sudo -u postgres psql -c \"alter user {} with password '****'\"",
+                    s.database_user
+                );
+                error!("");
+            }
+            DbSettings::MySql(ref s) => {
+                let localhost = String::from("localhost");
+                let host = s.database_host.as_ref().unwrap_or(&localhost);
+                error!(" ERROR: Unable to connect to mysql database");
+                error!("        Please initialize your database and user and then run `setup`");
+                error!(
+                    "\n  ex) mysql -u root -p -e \"create database {};\"",
+                    s.database_name
+                );
+                error!(
+                    "      mysql -u root -p -e \"create user '{}'@'{}' identified by '*****';\"",
+                    s.database_user, host
+                );
+                error!(
+                    "      mysql -u root -p -e \"grant all privileges on {}.* to '{}'@'{}';\"",
+                    s.database_name, s.database_user, host
+                );
+                error!("      mysql -u root -p -e \"flush privileges;\"");
+                error!("");
+            }
+            DbSettings::Sqlite(_) | DbSettings::DuckDb(_) => return e,
+        }
+        err!(
+            Config,
+            "Cannot connect to {} database. Do the database & user exist? -> {}",
+            self.database_type(),
+            e
+        )
+    }
+
     /// Return the absolute path to the directory containing migration folders
     ///
     /// The location returned is dependent on whether an absolute or relative path
@@ -555,7 +1125,7 @@ impl Config {
 
     /// The directory that relative settings paths are resolved against:
     /// the settings file's directory if one exists, the current directory otherwise
-    fn relative_base_dir(&self) -> Result<PathBuf> {
+    pub(crate) fn relative_base_dir(&self) -> Result<PathBuf> {
         match self.settings_path.as_ref() {
             Some(settings_path) => Ok(settings_path
                 .parent()
@@ -584,9 +1154,9 @@ impl Config {
     }
 
     /// Return the absolute path to the database file. This is intended for
-    /// sqlite databases only. In-memory sqlite databases return `:memory:`.
+    /// sqlite and duckdb databases only. In-memory databases return `:memory:`.
     pub fn database_path(&self) -> Result<PathBuf> {
-        if self.settings.inner.is_memory_sqlite() {
+        if self.settings.inner.is_memory() {
             return Ok(PathBuf::from(SQLITE_MEMORY_PATH));
         }
         let path = self.settings.inner.database_path()?;
@@ -601,23 +1171,39 @@ impl Config {
     }
 
     /// Generate a database connection string.
-    /// Not intended for file-based databases (sqlite)
+    /// Not intended for file-based databases (sqlite, duckdb)
     pub fn connect_string(&self) -> Result<String> {
         self.settings.inner.connect_string()
     }
 
-    /// Return the custom ssl cert file, if any (postgres only)
+    /// Return the custom ssl cert file, if any (postgres, mysql)
     pub fn ssl_cert_file(&self) -> Option<PathBuf> {
         self.settings.inner.ssl_cert_file()
     }
-}
 
-/// Create a file (and any missing parent directories) if it doesn't exist,
-/// returning `true` if the file was created
-fn create_file_if_missing(path: &Path) -> Result<bool> {
-    if path.exists() {
-        return Ok(false);
+    /// Certificate files for TLS connections (postgres only)
+    #[cfg(feature = "postgres")]
+    pub(crate) fn tls_files(&self) -> TlsFiles {
+        self.settings.inner.tls_files()
     }
+
+    /// TLS options for mysql connections
+    #[cfg(feature = "mysql")]
+    pub(crate) fn mysql_tls(&self) -> MySqlTls {
+        self.settings.inner.mysql_tls()
+    }
+}
+
+/// Error for a database without the `__migrant_migrations` table
+pub(crate) fn missing_migration_table() -> Error {
+    err!(
+        Migration,
+        "`__migrant_migrations` table is missing, maybe try re-setting-up? -> `setup`"
+    )
+}
+
+/// Create any missing parent directories of a database file
+fn create_parent_dir(path: &Path) -> Result<()> {
     let db_dir = path
         .parent()
         .ok_or_else(|| err!(PathError, "Unable to determine parent path: {:?}", path))?;
@@ -628,7 +1214,16 @@ fn create_file_if_missing(path: &Path) -> Result<bool> {
             db_dir,
             e
         )
-    })?;
+    })
+}
+
+/// Create a file (and any missing parent directories) if it doesn't exist,
+/// returning `true` if the file was created
+fn create_file_if_missing(path: &Path) -> Result<bool> {
+    if path.exists() {
+        return Ok(false);
+    }
+    create_parent_dir(path)?;
     fs::File::create(path)
         .map_err(|e| err!(Config, "Failed creating database file {:?}: {}", path, e))?;
     Ok(true)
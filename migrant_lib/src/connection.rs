@@ -1,13 +1,31 @@
 /*!
 Database connection information passed to function-migrations
 */
+#[cfg(any(feature = "postgres", feature = "mysql"))]
+use std::ops::{Deref, DerefMut};
+#[cfg(any(feature = "postgres", feature = "mysql"))]
+use std::sync::MutexGuard;
+
+use crate::drivers::sql;
+#[cfg(any(feature = "postgres", feature = "mysql"))]
+use crate::drivers::DbConnection;
 use crate::errors::*;
+#[cfg(any(feature = "postgres", feature = "mysql"))]
+use crate::macros::bail;
+use crate::value::{Row, Value};
 use crate::{Config, DbKind};
 
 /// Database connection information
 ///
 /// Passed to `FnMigration` functions so they can connect to (or reuse a
 /// connection to) the database being migrated.
+///
+/// The live-connection methods ([`execute`](ConnConfig::execute),
+/// [`query_rows`](ConnConfig::query_rows), [`postgres_client`](ConnConfig::postgres_client),
+/// ...) use the migrator's own connection. Under an `AsyncMigrator` that
+/// connection is async for postgres and mysql, so there these methods return a
+/// `Config` error rather than open a connection outside the run's transaction
+/// and migration lock; use an `AsyncFnMigration` instead.
 pub struct ConnConfig<'a> {
     config: &'a Config,
 }
@@ -56,4 +74,230 @@ impl<'a> ConnConfig<'a> {
     ) -> Result<std::sync::Arc<std::sync::Mutex<rusqlite::Connection>>> {
         self.config.sqlite_connection()
     }
+
+    /// Execute a single statement on the live connection, returning the number
+    /// of rows it changed.
+    ///
+    /// Parameters are bound to `?` placeholders on every backend (on postgres
+    /// they are rewritten to `$1`, `$2`, ..., so use the function form of
+    /// postgres' `?` json operators). Statements run in the migrator's
+    /// transaction when the migration opts into one. A failed statement does
+    /// not roll that transaction back by itself (postgres aborts it, mysql
+    /// leaves it open), so return the error: failing the migration is what
+    /// makes the migrator roll it back. A synchronous function migration run
+    /// by an `AsyncMigrator` on postgres or mysql gets a `Config` error here
+    /// instead, see [`ConnConfig`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// fn backfill(config: migrant_lib::ConnConfig) -> Result<(), Box<dyn std::error::Error>> {
+    ///     config.execute(
+    ///         "update users set display_name = name where display_name is null and id > ?",
+    ///         &[100.into()],
+    ///     )?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn execute(&self, sql: &str, params: &[Value]) -> Result<u64> {
+        self.config.with_conn(|conn| conn.execute(sql, params))
+    }
+
+    /// Run a query on the live connection, collecting its rows.
+    ///
+    /// Placeholders, transactions and the `AsyncMigrator` limitation work as
+    /// for [`execute`](ConnConfig::execute).
+    /// Column values are converted to [`Value`]s: booleans read as integers, and
+    /// postgres columns of other types (`numeric`, timestamps, ...) must be cast
+    /// to `text` in the query.
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// fn backfill(config: migrant_lib::ConnConfig) -> Result<(), Box<dyn std::error::Error>> {
+    ///     for row in config.query_rows("select id, email from users", &[])? {
+    ///         let email = row.column("email").and_then(|v| v.as_str()).unwrap_or_default();
+    ///         config.execute(
+    ///             "update users set email = ? where id = ?",
+    ///             &[email.to_lowercase().into(), row.values()[0].clone()],
+    ///         )?;
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn query_rows(&self, sql: &str, params: &[Value]) -> Result<Vec<Row>> {
+        self.config.with_conn(|conn| conn.query_rows(sql, params))
+    }
+
+    /// Execute a batch of `;`-separated statements on the live connection,
+    /// split the same way as migration SQL so an error names the failing
+    /// statement. Like [`execute`](ConnConfig::execute), not available under
+    /// an `AsyncMigrator` on postgres or mysql.
+    pub fn execute_batch(&self, sql: &str) -> Result<()> {
+        self.config.execute_sql(sql)
+    }
+
+    /// Check whether `table` exists: in the current database on mysql, in the
+    /// current schema on duckdb, and resolved through the `search_path` (or
+    /// schema-qualified) on postgres. Runs on the live connection like
+    /// [`query_rows`](ConnConfig::query_rows).
+    pub fn table_exists(&self, table: &str) -> Result<bool> {
+        let query = match self.database_type() {
+            DbKind::Sqlite => sql::SQLITE_TABLE_EXISTS,
+            DbKind::Postgres => sql::PG_TABLE_EXISTS,
+            DbKind::MySql => sql::MYSQL_TABLE_EXISTS,
+            DbKind::DuckDb => sql::DUCKDB_TABLE_EXISTS,
+        };
+        self.exists(query, &[table.into()])
+    }
+
+    /// Check whether `table` exists and has a column named `column`
+    pub fn column_exists(&self, table: &str, column: &str) -> Result<bool> {
+        let query = match self.database_type() {
+            DbKind::Sqlite => sql::SQLITE_COLUMN_EXISTS,
+            DbKind::Postgres => sql::PG_COLUMN_EXISTS,
+            DbKind::MySql => sql::MYSQL_COLUMN_EXISTS,
+            DbKind::DuckDb => sql::DUCKDB_COLUMN_EXISTS,
+        };
+        self.exists(query, &[table.into(), column.into()])
+    }
+
+    /// Run a query selecting a single boolean
+    fn exists(&self, query: &str, params: &[Value]) -> Result<bool> {
+        let rows = self.query_rows(query, params)?;
+        Ok(rows
+            .first()
+            .and_then(|row| row.get(0))
+            .and_then(Value::as_i64)
+            .is_some_and(|exists| exists != 0))
+    }
+
+    /// Return a shared handle to the live duckdb connection, see
+    /// [`sqlite_connection`](ConnConfig::sqlite_connection)
+    #[cfg(feature = "duckdb")]
+    pub fn duckdb_connection(
+        &self,
+    ) -> Result<std::sync::Arc<std::sync::Mutex<duckdb::Connection>>> {
+        self.config.duckdb_connection()
+    }
+
+    /// Borrow the live postgres connection the migrator runs on.
+    ///
+    /// Statements run on it take part in the migrator's transaction when the
+    /// migration opts into one (see [`FnMigration::in_transaction`](crate::FnMigration::in_transaction)),
+    /// and run in the session holding the migration advisory lock.
+    ///
+    /// The connection is locked until the returned client is dropped, so drop
+    /// it before calling other `ConnConfig` methods that use the database.
+    /// Under an `AsyncMigrator` this is a `Config` error, see [`ConnConfig`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # #[cfg(feature = "postgres")]
+    /// fn add_data(config: migrant_lib::ConnConfig) -> Result<(), Box<dyn std::error::Error>> {
+    ///     let mut client = config.postgres_client()?;
+    ///     client.execute("insert into users (name) values ($1)", &[&"me"])?;
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "postgres")]
+    pub fn postgres_client(&self) -> Result<PostgresClient<'a>> {
+        let guard = self.config.lock_conn()?;
+        match *guard {
+            Some(DbConnection::Postgres(_)) => Ok(PostgresClient { guard }),
+            _ => bail!(
+                Config,
+                "Cannot get a postgres client for database-type: {}",
+                self.database_type()
+            ),
+        }
+    }
+
+    /// Borrow the live mysql connection the migrator runs on.
+    ///
+    /// Like [`postgres_client`](ConnConfig::postgres_client), statements take
+    /// part in the migrator's transaction and session, and the connection is
+    /// locked until the returned connection is dropped. Under an
+    /// `AsyncMigrator` this is a `Config` error, see [`ConnConfig`].
+    ///
+    /// # Example
+    ///
+    /// ```rust,no_run
+    /// # #[cfg(feature = "mysql")]
+    /// fn add_data(config: migrant_lib::ConnConfig) -> Result<(), Box<dyn std::error::Error>> {
+    ///     use migrant_lib::mysql::prelude::Queryable;
+    ///     let mut conn = config.mysql_connection()?;
+    ///     conn.exec_drop("insert into users (name) values (?)", ("me",))?;
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "mysql")]
+    pub fn mysql_connection(&self) -> Result<MySqlConnection<'a>> {
+        let guard = self.config.lock_conn()?;
+        match *guard {
+            Some(DbConnection::MySql(_)) => Ok(MySqlConnection { guard }),
+            _ => bail!(
+                Config,
+                "Cannot get a mysql connection for database-type: {}",
+                self.database_type()
+            ),
+        }
+    }
+}
+
+/// The migrator's live postgres connection, lent by
+/// [`ConnConfig::postgres_client`]. Dereferences to a [`postgres::Client`].
+#[cfg(feature = "postgres")]
+pub struct PostgresClient<'a> {
+    guard: MutexGuard<'a, Option<DbConnection>>,
+}
+
+#[cfg(feature = "postgres")]
+impl Deref for PostgresClient<'_> {
+    type Target = postgres::Client;
+    fn deref(&self) -> &postgres::Client {
+        match *self.guard {
+            Some(DbConnection::Postgres(ref conn)) => conn.client(),
+            _ => unreachable!("checked by `ConnConfig::postgres_client`"),
+        }
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl DerefMut for PostgresClient<'_> {
+    fn deref_mut(&mut self) -> &mut postgres::Client {
+        match *self.guard {
+            Some(DbConnection::Postgres(ref mut conn)) => conn.client_mut(),
+            _ => unreachable!("checked by `ConnConfig::postgres_client`"),
+        }
+    }
+}
+
+/// The migrator's live mysql connection, lent by
+/// [`ConnConfig::mysql_connection`]. Dereferences to a [`mysql::Conn`].
+#[cfg(feature = "mysql")]
+pub struct MySqlConnection<'a> {
+    guard: MutexGuard<'a, Option<DbConnection>>,
+}
+
+#[cfg(feature = "mysql")]
+impl Deref for MySqlConnection<'_> {
+    type Target = mysql::Conn;
+    fn deref(&self) -> &mysql::Conn {
+        match *self.guard {
+            Some(DbConnection::MySql(ref conn)) => conn.conn(),
+            _ => unreachable!("checked by `ConnConfig::mysql_connection`"),
+        }
+    }
+}
+
+#[cfg(feature = "mysql")]
+impl DerefMut for MySqlConnection<'_> {
+    fn deref_mut(&mut self) -> &mut mysql::Conn {
+        match *self.guard {
+            Some(DbConnection::MySql(ref mut conn)) => conn.conn_mut(),
+            _ => unreachable!("checked by `ConnConfig::mysql_connection`"),
+        }
+    }
 }
@@ -0,0 +1,249 @@
+/*!
+DuckDB driver
+
+Like sqlite, duckdb is embedded: the connection handle is kept alive (and
+shared between `Config` clones) so in-memory (`:memory:`) databases survive
+across operations.
+*/
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use duckdb::{params_from_iter, Connection};
+
+use super::sql;
+use crate::errors::*;
+use crate::macros::err;
+use crate::value::{Row, Value};
+use crate::SQLITE_MEMORY_PATH;
+
+/// A live duckdb connection
+///
+/// The handle is reference counted so it can be shared with
+/// function-migrations via [`ConnConfig`](crate::ConnConfig).
+pub(crate) struct DuckDbConn {
+    handle: Arc<Mutex<Connection>>,
+}
+
+impl DuckDbConn {
+    /// Open a connection to a database file, or an in-memory database
+    /// if the path is `:memory:`
+    pub(crate) fn open(path: &str) -> Result<Self> {
+        let conn = if path == SQLITE_MEMORY_PATH {
+            Connection::open_in_memory()?
+        } else {
+            Connection::open(path)?
+        };
+        Ok(Self {
+            handle: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Return a shared reference to the underlying connection
+    pub(crate) fn handle(&self) -> Arc<Mutex<Connection>> {
+        self.handle.clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Connection> {
+        self.handle
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    pub(crate) fn migration_table_exists(&self) -> Result<bool> {
+        let conn = self.lock();
+        let exists: bool =
+            conn.query_row(sql::DUCKDB_MIGRATION_TABLE_EXISTS, [], |row| row.get(0))?;
+        Ok(exists)
+    }
+
+    pub(crate) fn setup_migration_table(&self) -> Result<bool> {
+        if self.migration_table_exists()? {
+            return Ok(false);
+        }
+        self.lock().execute_batch(sql::CREATE_TABLE)?;
+        Ok(true)
+    }
+
+    pub(crate) fn applied_tags(&self) -> Result<Vec<String>> {
+        self.query_column(sql::GET_MIGRATIONS)
+    }
+
+    pub(crate) fn query_column(&self, query: &str) -> Result<Vec<String>> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare(query)?;
+        let values = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<std::result::Result<Vec<String>, _>>()?;
+        Ok(values)
+    }
+
+    pub(crate) fn insert_tag(&self, tag: &str) -> Result<()> {
+        self.lock()
+            .execute(sql::INSERT_MIGRATION_PG_SQLITE, [tag])?;
+        Ok(())
+    }
+
+    pub(crate) fn remove_tag(&self, tag: &str) -> Result<()> {
+        self.lock()
+            .execute(sql::REMOVE_MIGRATION_PG_SQLITE, [tag])?;
+        Ok(())
+    }
+
+    pub(crate) fn execute_batch(&self, stmt: &str) -> Result<()> {
+        if stmt.is_empty() {
+            return Ok(());
+        }
+        let conn = self.lock();
+        let res = conn.execute_batch(stmt);
+        if res.is_err() && !conn.is_autocommit() {
+            // A failed statement aborts duckdb's open transaction; roll it back
+            // so later operations on the shared connection aren't poisoned.
+            let _ = conn.execute_batch("rollback");
+        }
+        res.map_err(|e| err!(Migration, "{}", e))
+    }
+
+    pub(crate) fn execute(&self, stmt: &str, params: &[Value]) -> Result<u64> {
+        let changed = self
+            .lock()
+            .execute(stmt, params_from_iter(params))
+            .map_err(|e| err!(Migration, "{}", e))?;
+        Ok(changed as u64)
+    }
+
+    /// Run a query with `?` placeholders. Columns of types without a `Value`
+    /// equivalent are an error; cast them to `varchar` in the query.
+    pub(crate) fn query_rows(&self, query: &str, params: &[Value]) -> Result<Vec<Row>> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare(query).map_err(|e| err!(Migration, "{}", e))?;
+        let mut rows = stmt
+            .query(params_from_iter(params))
+            .map_err(|e| err!(Migration, "{}", e))?;
+        let columns: Arc<[String]> = rows
+            .as_ref()
+            .map(|stmt| stmt.column_names())
+            .unwrap_or_default()
+            .into();
+        let mut out = Vec::new();
+        while let Some(row) = rows.next().map_err(|e| err!(Migration, "{}", e))? {
+            let values = columns
+                .iter()
+                .enumerate()
+                .map(|(i, name)| {
+                    let value = row.get_ref(i).map_err(|e| err!(Migration, "{}", e))?;
+                    Value::from_duckdb(value).ok_or_else(|| {
+                        err!(
+                            Migration,
+                            "column `{}` has a duckdb type without a `Value` equivalent; \
+                             cast it to varchar in the query",
+                            name
+                        )
+                    })
+                })
+                .collect::<Result<_>>()?;
+            out.push(Row::new(columns.clone(), values));
+        }
+        Ok(out)
+    }
+
+    pub(crate) fn begin(&self) -> Result<()> {
+        self.lock()
+            .execute_batch("begin")
+            .map_err(|e| err!(Migration, "{}", e))
+    }
+
+    pub(crate) fn commit(&self) -> Result<()> {
+        self.lock()
+            .execute_batch("commit")
+            .map_err(|e| err!(Migration, "{}", e))
+    }
+
+    pub(crate) fn rollback(&self) -> Result<()> {
+        self.lock()
+            .execute_batch("rollback")
+            .map_err(|e| err!(Migration, "{}", e))
+    }
+
+    /// DuckDB has no advisory lock (a database file is opened for writing by
+    /// one process at a time), so migration-run locking is a no-op.
+    pub(crate) fn acquire_lock(&self) -> Result<()> {
+        Ok(())
+    }
+
+    pub(crate) fn try_acquire_lock(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    pub(crate) fn release_lock(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migration_table_lifecycle() {
+        let conn = DuckDbConn::open(SQLITE_MEMORY_PATH).unwrap();
+
+        assert!(
+            !conn.migration_table_exists().unwrap(),
+            "no table before setup"
+        );
+        assert!(conn.setup_migration_table().unwrap(), "table created");
+        assert!(!conn.setup_migration_table().unwrap(), "setup idempotent");
+        assert!(conn.migration_table_exists().unwrap(), "table exists");
+
+        conn.insert_tag("initial").unwrap();
+        conn.insert_tag("alter1").unwrap();
+        assert_eq!(2, conn.applied_tags().unwrap().len());
+
+        conn.remove_tag("alter1").unwrap();
+        conn.remove_tag("initial").unwrap();
+        assert_eq!(0, conn.applied_tags().unwrap().len());
+    }
+
+    #[test]
+    fn execute_batch_rolls_back_failed_transactions() {
+        let conn = DuckDbConn::open(SQLITE_MEMORY_PATH).unwrap();
+        conn.execute_batch("create table t(x integer);").unwrap();
+        let res = conn.execute_batch("begin; insert into t values (1); nonsense;");
+        assert!(res.is_err());
+        // connection must still be usable and outside a transaction
+        conn.execute_batch("insert into t values (2);").unwrap();
+        let count: i64 = conn
+            .lock()
+            .query_row("select count(*) from t", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(1, count, "failed batch was rolled back");
+    }
+
+    #[test]
+    fn query_rows_converts_values() {
+        let conn = DuckDbConn::open(SQLITE_MEMORY_PATH).unwrap();
+        let rows = conn
+            .query_rows(
+                "select ?::integer as i, true as b, 1.5::double as r, ? as t, \
+                 '\\x01'::blob as bl, null as n",
+                &[7.into(), "x".into()],
+            )
+            .unwrap();
+        assert_eq!(rows[0].columns()[0], "i");
+        assert_eq!(
+            rows[0].values(),
+            [
+                Value::Integer(7),
+                Value::Integer(1),
+                Value::Real(1.5),
+                Value::from("x"),
+                Value::Blob(vec![1]),
+                Value::Null,
+            ]
+        );
+        let err = conn
+            .query_rows("select [1, 2] as list", &[])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("`list`"), "{}", err);
+    }
+}
@@ -4,12 +4,18 @@ Database drivers
 Each enabled backend provides a connection type holding a live database
 connection. All migration-table operations go through [`DbConnection`],
 which is lazily established by [`Config`](crate::Config) and kept alive
-for the life of the config (and all of its clones).
+for the life of the config (and all of its clones). Applications can supply
+their own backend by implementing [`MigrationDriver`].
 */
 use std::fmt;
+use std::io;
 
 use crate::config::Config;
+#[cfg(feature = "r2d2")]
+use crate::config::Settings;
 use crate::errors::*;
+use crate::macros::bail;
+use crate::value::{Row, Value};
 use crate::DbKind;
 
 #[allow(dead_code)] // per-backend statements are unused when their feature is disabled
@@ -27,18 +33,268 @@ pub(crate) mod sql {
 
     pub static SQLITE_MIGRATION_TABLE_EXISTS: &str = "select exists(select 1 from sqlite_master where type = 'table' and name = '__migrant_migrations');";
     pub static PG_MIGRATION_TABLE_EXISTS: &str =
-        "select exists(select 1 from pg_tables where tablename = '__migrant_migrations');";
+        "select to_regclass('__migrant_migrations') is not null;";
     pub static MYSQL_MIGRATION_TABLE_EXISTS: &str = "select exists(select 1 from information_schema.tables where table_name='__migrant_migrations' and table_schema = database()) as tag;";
+    pub static DUCKDB_MIGRATION_TABLE_EXISTS: &str = "select exists(select 1 from information_schema.tables where table_name = '__migrant_migrations' and table_schema = current_schema());";
+
+    pub static SQLITE_TABLE_EXISTS: &str =
+        "select exists(select 1 from sqlite_master where type = 'table' and name = ?)";
+    pub static PG_TABLE_EXISTS: &str = "select to_regclass(?) is not null";
+    pub static MYSQL_TABLE_EXISTS: &str = "select exists(select 1 from information_schema.tables where table_schema = database() and table_name = ?)";
+    pub static DUCKDB_TABLE_EXISTS: &str = "select exists(select 1 from information_schema.tables where table_schema = current_schema() and table_name = ?)";
+
+    pub static SQLITE_COLUMN_EXISTS: &str =
+        "select exists(select 1 from pragma_table_info(?) where name = ?)";
+    pub static PG_COLUMN_EXISTS: &str = "select exists(select 1 from pg_attribute where attrelid = to_regclass(?) and attname = ? and attnum > 0 and not attisdropped)";
+    pub static MYSQL_COLUMN_EXISTS: &str = "select exists(select 1 from information_schema.columns where table_schema = database() and table_name = ? and column_name = ?)";
+    pub static DUCKDB_COLUMN_EXISTS: &str = "select exists(select 1 from information_schema.columns where table_schema = current_schema() and table_name = ? and column_name = ?)";
 }
 
+#[cfg(feature = "duckdb")]
+pub(crate) mod duckdb;
 #[cfg(feature = "mysql")]
 pub(crate) mod mysql;
 #[cfg(feature = "postgres")]
 pub(crate) mod pg;
+#[cfg(feature = "postgres")]
+pub(crate) mod pg_tls;
 #[cfg(feature = "sqlite")]
 pub(crate) mod sqlite;
 
-/// A live connection to one of the supported databases
+/// A connection type [`Config::from_r2d2_pool`] can run migrations over:
+/// `postgres::Client` (with the `postgres` feature) or `mysql::Conn` (with the
+/// `mysql` feature). This trait is sealed.
+#[cfg(feature = "r2d2")]
+pub trait PoolableConnection: Sized + Send + 'static + private::Sealed {
+    #[doc(hidden)]
+    fn adopt_checkout<M>(settings: Settings, conn: r2d2::PooledConnection<M>) -> Result<Config>
+    where
+        M: r2d2::ManageConnection<Connection = Self>;
+}
+
+#[cfg(feature = "r2d2")]
+mod private {
+    pub trait Sealed {}
+}
+
+#[cfg(all(feature = "r2d2", feature = "postgres"))]
+impl private::Sealed for ::postgres::Client {}
+
+#[cfg(all(feature = "r2d2", feature = "postgres"))]
+impl PoolableConnection for ::postgres::Client {
+    fn adopt_checkout<M>(settings: Settings, conn: r2d2::PooledConnection<M>) -> Result<Config>
+    where
+        M: r2d2::ManageConnection<Connection = Self>,
+    {
+        Config::from_pooled_postgres_client(settings, conn)
+    }
+}
+
+#[cfg(all(feature = "r2d2", feature = "mysql"))]
+impl private::Sealed for ::mysql::Conn {}
+
+#[cfg(all(feature = "r2d2", feature = "mysql"))]
+impl PoolableConnection for ::mysql::Conn {
+    fn adopt_checkout<M>(settings: Settings, conn: r2d2::PooledConnection<M>) -> Result<Config>
+    where
+        M: r2d2::ManageConnection<Connection = Self>,
+    {
+        Config::from_pooled_mysql_conn(settings, conn)
+    }
+}
+
+/// A server connection a driver either owns -- opened from the settings or
+/// handed over by the application -- or borrows as a connection-pool checkout,
+/// which goes back to its pool when the driver is dropped
+#[cfg(any(feature = "postgres", feature = "mysql"))]
+pub(crate) enum Handle<T> {
+    Owned(T),
+    Pooled(Box<dyn std::ops::DerefMut<Target = T> + Send>),
+}
+
+#[cfg(any(feature = "postgres", feature = "mysql"))]
+impl<T> std::ops::Deref for Handle<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self {
+            Handle::Owned(conn) => conn,
+            Handle::Pooled(conn) => conn,
+        }
+    }
+}
+
+#[cfg(any(feature = "postgres", feature = "mysql"))]
+impl<T> std::ops::DerefMut for Handle<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        match self {
+            Handle::Owned(conn) => conn,
+            Handle::Pooled(conn) => conn,
+        }
+    }
+}
+
+/// A database backend supplied by the application
+///
+/// Implement this to migrate a database migrant has no built-in driver for
+/// (CockroachDB, libsql, an in-house store, ...) and install it with
+/// [`Config::with_driver`](crate::Config::with_driver). The driver holds one
+/// live connection: a migration run takes the lock, opens transactions, runs
+/// migration SQL and records tags all on it, so every method must act on the
+/// same session.
+///
+/// Errors should be reported as [`Error::Migration`] (or another `Error`
+/// variant that fits). Migration SQL is split into statements using the
+/// dialect of the settings' `database_type` before it reaches
+/// [`execute_batch`](MigrationDriver::execute_batch).
+///
+/// ```rust
+/// use migrant_lib::{MigrationDriver, Result};
+///
+/// /// Keeps the applied tags in memory and ignores migration SQL
+/// #[derive(Default)]
+/// struct Recorder {
+///     table: Option<Vec<String>>,
+/// }
+///
+/// impl MigrationDriver for Recorder {
+///     fn migration_table_exists(&mut self) -> Result<bool> {
+///         Ok(self.table.is_some())
+///     }
+///     fn setup_migration_table(&mut self) -> Result<bool> {
+///         let created = self.table.is_none();
+///         self.table.get_or_insert_with(Vec::new);
+///         Ok(created)
+///     }
+///     fn applied_tags(&mut self) -> Result<Vec<String>> {
+///         Ok(self.table.clone().unwrap_or_default())
+///     }
+///     fn insert_tag(&mut self, tag: &str) -> Result<()> {
+///         self.table.get_or_insert_with(Vec::new).push(tag.to_owned());
+///         Ok(())
+///     }
+///     fn remove_tag(&mut self, tag: &str) -> Result<()> {
+///         self.table.get_or_insert_with(Vec::new).retain(|t| t != tag);
+///         Ok(())
+///     }
+///     fn execute_batch(&mut self, _sql: &str) -> Result<()> { Ok(()) }
+///     fn begin(&mut self) -> Result<()> { Ok(()) }
+///     fn commit(&mut self) -> Result<()> { Ok(()) }
+///     fn rollback(&mut self) -> Result<()> { Ok(()) }
+///     fn acquire_lock(&mut self) -> Result<()> { Ok(()) }
+///     fn release_lock(&mut self) -> Result<()> { Ok(()) }
+/// }
+/// ```
+pub trait MigrationDriver: Send {
+    /// Check whether the `__migrant_migrations` table exists
+    fn migration_table_exists(&mut self) -> Result<bool>;
+
+    /// Create the `__migrant_migrations` table if missing, returning `true` if created
+    fn setup_migration_table(&mut self) -> Result<bool>;
+
+    /// Select all applied migration tags
+    fn applied_tags(&mut self) -> Result<Vec<String>>;
+
+    /// Record a migration tag as applied
+    fn insert_tag(&mut self, tag: &str) -> Result<()>;
+
+    /// Remove a migration tag from the applied set
+    fn remove_tag(&mut self, tag: &str) -> Result<()>;
+
+    /// Execute one or more sql statements
+    fn execute_batch(&mut self, sql: &str) -> Result<()>;
+
+    /// Begin a transaction
+    fn begin(&mut self) -> Result<()>;
+
+    /// Commit the current transaction
+    fn commit(&mut self) -> Result<()>;
+
+    /// Roll back the current transaction. Also called to recover the session
+    /// after any failed operation, so it must tolerate having no transaction
+    /// open.
+    fn rollback(&mut self) -> Result<()>;
+
+    /// Acquire the lock that serializes migration runs, blocking until it is
+    /// available. Implement it as a no-op if the database can't be migrated
+    /// concurrently.
+    fn acquire_lock(&mut self) -> Result<()>;
+
+    /// Try to acquire the migration lock without waiting, returning whether it
+    /// was acquired. Used by cancellable runs to poll for the lock; defaults to
+    /// a blocking [`acquire_lock`](MigrationDriver::acquire_lock).
+    fn try_acquire_lock(&mut self) -> Result<bool> {
+        self.acquire_lock().map(|()| true)
+    }
+
+    /// Release the migration lock
+    fn release_lock(&mut self) -> Result<()>;
+
+    /// Run a query returning one text column, collecting its values. Needed
+    /// for a tenant `query`; unsupported by default.
+    fn query_column(&mut self, query: &str) -> Result<Vec<String>> {
+        bail!(
+            Config,
+            "This driver does not support `query_column`: {}",
+            query
+        )
+    }
+
+    /// Execute one statement with `?` placeholders, returning the rows changed.
+    /// Backs [`ConnConfig::execute`](crate::ConnConfig::execute); unsupported
+    /// by default.
+    fn execute(&mut self, sql: &str, params: &[Value]) -> Result<u64> {
+        let _ = params;
+        bail!(Config, "This driver does not support `execute`: {}", sql)
+    }
+
+    /// Run a query with `?` placeholders, collecting its rows. Backs
+    /// [`ConnConfig::query_rows`](crate::ConnConfig::query_rows); unsupported
+    /// by default.
+    fn query_rows(&mut self, sql: &str, params: &[Value]) -> Result<Vec<Row>> {
+        let _ = params;
+        bail!(Config, "This driver does not support `query_rows`: {}", sql)
+    }
+}
+
+/// Is this an io failure to reach the database (refused, reset, timed out)
+/// that may go away once the server is up? A missing file only counts when
+/// connecting over a `socket`, which the server creates as it starts.
+pub(crate) fn io_unreachable(kind: io::ErrorKind, socket: bool) -> bool {
+    use io::ErrorKind::*;
+    match kind {
+        NotFound => socket,
+        kind => matches!(
+            kind,
+            ConnectionRefused
+                | ConnectionReset
+                | ConnectionAborted
+                | NotConnected
+                | TimedOut
+                | HostUnreachable
+                | NetworkUnreachable
+                | AddrNotAvailable
+                | UnexpectedEof
+        ),
+    }
+}
+
+/// Did connecting fail because the database can't be reached yet, rather
+/// than e.g. because it rejected the credentials? Only these failures are
+/// worth retrying, see `Config::wait_for_database`.
+pub(crate) fn is_unreachable(e: &Error, socket: bool) -> bool {
+    match e {
+        Error::Io(e) => io_unreachable(e.kind(), socket),
+        #[cfg(feature = "postgres")]
+        Error::Postgres(e) => pg::is_unreachable(e, socket),
+        #[cfg(feature = "mysql")]
+        Error::MySql(e) => mysql::is_unreachable(e, socket),
+        _ => false,
+    }
+}
+
+/// A live connection to one of the supported databases, or an
+/// application-supplied driver
 ///
 /// Server connections are boxed to keep the enum small
 pub(crate) enum DbConnection {
@@ -48,6 +304,9 @@ pub(crate) enum DbConnection {
     Postgres(Box<pg::PgConn>),
     #[cfg(feature = "mysql")]
     MySql(Box<mysql::MySqlConn>),
+    #[cfg(feature = "duckdb")]
+    DuckDb(duckdb::DuckDbConn),
+    Custom(Box<dyn MigrationDriver>),
 }
 
 impl fmt::Debug for DbConnection {
@@ -59,8 +318,9 @@ impl fmt::Debug for DbConnection {
             DbConnection::Postgres(_) => "postgres",
             #[cfg(feature = "mysql")]
             DbConnection::MySql(_) => "mysql",
-            #[allow(unreachable_patterns)]
-            _ => "unknown",
+            #[cfg(feature = "duckdb")]
+            DbConnection::DuckDb(_) => "duckdb",
+            DbConnection::Custom(_) => "custom",
         };
         write!(f, "DbConnection({})", kind)
     }
@@ -76,14 +336,13 @@ macro_rules! dispatch {
             DbConnection::Postgres($conn) => $body,
             #[cfg(feature = "mysql")]
             DbConnection::MySql($conn) => $body,
-            #[allow(unreachable_patterns)]
-            _ => Err(Error::FeatureRequired("sqlite / postgres / mysql")),
+            #[cfg(feature = "duckdb")]
+            DbConnection::DuckDb($conn) => $body,
+            DbConnection::Custom($conn) => $body,
         }
     };
 }
 
-// method arguments are unused in the fallback arm when no db features are enabled
-#[allow(unused_variables)]
 impl DbConnection {
     /// Open a new connection for the given config
     pub(crate) fn connect(config: &Config) -> Result<Self> {
@@ -92,7 +351,13 @@ impl DbConnection {
                 #[cfg(feature = "sqlite")]
                 {
                     let path = config.database_path_string()?;
-                    Ok(DbConnection::Sqlite(sqlite::SqliteConn::open(&path)?))
+                    let options = match config.settings.inner {
+                        crate::config::DbSettings::Sqlite(ref s) => &s.sqlite,
+                        _ => unreachable!("sqlite database type"),
+                    };
+                    Ok(DbConnection::Sqlite(sqlite::SqliteConn::open(
+                        &path, options,
+                    )?))
                 }
                 #[cfg(not(feature = "sqlite"))]
                 Err(Error::FeatureRequired("sqlite"))
@@ -101,11 +366,11 @@ impl DbConnection {
                 #[cfg(feature = "postgres")]
                 {
                     let conn_str = config.connect_string()?;
-                    let cert = config.ssl_cert_file();
-                    Ok(DbConnection::Postgres(Box::new(pg::PgConn::connect(
-                        &conn_str,
-                        cert.as_deref(),
-                    )?)))
+                    let mut conn = pg::PgConn::connect(&conn_str, &config.tls_files())?;
+                    if let Some(tenant) = config.tenant() {
+                        conn.scope_lock_to_tenant(tenant.name());
+                    }
+                    Ok(DbConnection::Postgres(Box::new(conn)))
                 }
                 #[cfg(not(feature = "postgres"))]
                 Err(Error::FeatureRequired("postgres"))
@@ -114,13 +379,24 @@ impl DbConnection {
                 #[cfg(feature = "mysql")]
                 {
                     let conn_str = config.connect_string()?;
-                    Ok(DbConnection::MySql(Box::new(mysql::MySqlConn::connect(
-                        &conn_str,
-                    )?)))
+                    let mut conn = mysql::MySqlConn::connect(&conn_str, &config.mysql_tls())?;
+                    if let Some(tenant) = config.tenant() {
+                        conn.scope_lock_to_tenant(tenant.name());
+                    }
+                    Ok(DbConnection::MySql(Box::new(conn)))
                 }
                 #[cfg(not(feature = "mysql"))]
                 Err(Error::FeatureRequired("mysql"))
             }
+            DbKind::DuckDb => {
+                #[cfg(feature = "duckdb")]
+                {
+                    let path = config.database_path_string()?;
+                    Ok(DbConnection::DuckDb(duckdb::DuckDbConn::open(&path)?))
+                }
+                #[cfg(not(feature = "duckdb"))]
+                Err(Error::FeatureRequired("duckdb"))
+            }
         }
     }
 
@@ -139,6 +415,11 @@ impl DbConnection {
         dispatch!(self, c => c.applied_tags())
     }
 
+    /// Run a query returning one text column, collecting its values
+    pub(crate) fn query_column(&mut self, query: &str) -> Result<Vec<String>> {
+        dispatch!(self, c => c.query_column(query))
+    }
+
     /// Record a migration tag as applied
     pub(crate) fn insert_tag(&mut self, tag: &str) -> Result<()> {
         dispatch!(self, c => c.insert_tag(tag))
@@ -154,6 +435,16 @@ impl DbConnection {
         dispatch!(self, c => c.execute_batch(sql))
     }
 
+    /// Execute one statement with `?` placeholders, returning the rows changed
+    pub(crate) fn execute(&mut self, sql: &str, params: &[Value]) -> Result<u64> {
+        dispatch!(self, c => c.execute(sql, params))
+    }
+
+    /// Run a query with `?` placeholders, collecting its rows
+    pub(crate) fn query_rows(&mut self, sql: &str, params: &[Value]) -> Result<Vec<Row>> {
+        dispatch!(self, c => c.query_rows(sql, params))
+    }
+
     /// Begin a transaction on this connection
     pub(crate) fn begin(&mut self) -> Result<()> {
         dispatch!(self, c => c.begin())
@@ -169,15 +460,22 @@ impl DbConnection {
         dispatch!(self, c => c.rollback())
     }
 
-    /// Acquire the session-level advisory lock that serializes migration runs.
+    /// Acquire the lock that serializes migration runs: a session-level
+    /// advisory lock on server databases, a lock file next to a sqlite file.
     ///
-    /// Blocks until the lock is available. Sqlite has no advisory lock (and no
-    /// cross-process migration concurrency to guard against), so it is a no-op.
+    /// Blocks until the lock is available. A no-op for in-memory and duckdb
+    /// databases.
     pub(crate) fn acquire_lock(&mut self) -> Result<()> {
         dispatch!(self, c => c.acquire_lock())
     }
 
-    /// Release the session-level advisory lock. No-op for sqlite.
+    /// Try to acquire the migration lock without waiting, returning whether it
+    /// was acquired
+    pub(crate) fn try_acquire_lock(&mut self) -> Result<bool> {
+        dispatch!(self, c => c.try_acquire_lock())
+    }
+
+    /// Release the migration lock
     pub(crate) fn release_lock(&mut self) -> Result<()> {
         dispatch!(self, c => c.release_lock())
     }
@@ -185,7 +483,8 @@ impl DbConnection {
 
 #[cfg(test)]
 mod tests {
-    use super::sql;
+    use super::{io_unreachable, sql};
+    use std::io::ErrorKind;
 
     /// Regression guard for F2: the mysql migration-table-exists check must be
     /// scoped to the current database via `table_schema = database()`, otherwise
@@ -199,4 +498,12 @@ mod tests {
             sql::MYSQL_MIGRATION_TABLE_EXISTS
         );
     }
+
+    #[test]
+    fn a_missing_file_is_only_unreachable_over_a_socket() {
+        assert!(io_unreachable(ErrorKind::ConnectionRefused, false));
+        assert!(io_unreachable(ErrorKind::NotFound, true));
+        assert!(!io_unreachable(ErrorKind::NotFound, false));
+        assert!(!io_unreachable(ErrorKind::PermissionDenied, true));
+    }
 }
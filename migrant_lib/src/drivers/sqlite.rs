@@ -3,38 +3,101 @@ Sqlite driver
 
 The connection handle is kept alive (and shared between `Config` clones)
 so that in-memory (`:memory:`) databases survive across operations.
+
+Migration runs are serialized across processes with an exclusive lock on a
+sidecar file next to the database (`<database>-migrant-lock`), taken by
+holding an open `begin exclusive` transaction on it. The lock is an OS file
+lock, so it is released automatically if the process dies mid-run.
 */
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
 
-use rusqlite::Connection;
+use rusqlite::{params_from_iter, Connection, ErrorCode, OpenFlags, MAIN_DB};
 
 use super::sql;
+use crate::config::{SqliteOptions, LOCK_POLL_INTERVAL};
 use crate::errors::*;
-use crate::macros::err;
+use crate::macros::{bail, err};
+use crate::value::{Row, Value};
 
 /// Path value indicating an in-memory sqlite database
 pub(crate) const MEMORY_PATH: &str = ":memory:";
 
+/// Set the configured pragmas on a new connection. The busy timeout comes
+/// first so the others wait out a locked database too.
+fn apply_options(conn: &Connection, options: &SqliteOptions) -> Result<()> {
+    let pragma = |name: &str, value: &dyn rusqlite::ToSql| {
+        conn.pragma_update(None, name, value)
+            .map_err(|e| err!(Migration, "Error setting sqlite pragma `{}`: {}", name, e))
+    };
+    if let Some(ms) = options.busy_timeout {
+        conn.busy_timeout(Duration::from_millis(ms))?;
+    }
+    if let Some(ref mode) = options.journal_mode {
+        pragma("journal_mode", mode)?;
+    }
+    if let Some(ref mode) = options.synchronous {
+        pragma("synchronous", mode)?;
+    }
+    if let Some(enabled) = options.foreign_keys {
+        pragma("foreign_keys", &enabled)?;
+    }
+    for (name, value) in &options.pragmas {
+        pragma(name, value)?;
+    }
+    Ok(())
+}
+
+/// The migration lock file of a database file
+fn lock_path(database_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}-migrant-lock", database_path))
+}
+
 /// A live sqlite connection
 ///
 /// The handle is reference counted so it can be shared with
 /// function-migrations via [`ConnConfig`](crate::ConnConfig).
 pub(crate) struct SqliteConn {
     handle: Arc<Mutex<Connection>>,
+    /// The migration lock file, `None` for in-memory databases
+    lock_path: Option<PathBuf>,
+    /// Connection to the lock file holding its exclusive lock, while the
+    /// migration lock is held
+    lock: Option<Connection>,
 }
 
 impl SqliteConn {
     /// Open a connection to a database file, or an in-memory database
-    /// if the path is `:memory:`
-    pub(crate) fn open(path: &str) -> Result<Self> {
+    /// if the path is `:memory:`, with the configured open flags and pragmas
+    pub(crate) fn open(path: &str, options: &SqliteOptions) -> Result<Self> {
+        let mut flags = OpenFlags::SQLITE_OPEN_NO_MUTEX | OpenFlags::SQLITE_OPEN_URI;
+        if options.read_only {
+            flags |= OpenFlags::SQLITE_OPEN_READ_ONLY;
+        } else {
+            flags |= OpenFlags::SQLITE_OPEN_READ_WRITE;
+            if options.creates_missing() {
+                flags |= OpenFlags::SQLITE_OPEN_CREATE;
+            }
+        }
         let conn = if path == MEMORY_PATH {
-            Connection::open_in_memory()?
+            Connection::open_in_memory_with_flags(flags)?
         } else {
-            Connection::open(path)?
+            Connection::open_with_flags(path, flags)?
         };
-        Ok(Self {
+        apply_options(&conn, options)?;
+        Ok(Self::adopt(conn))
+    }
+
+    /// Wrap a connection opened by the application
+    pub(crate) fn adopt(conn: Connection) -> Self {
+        // in-memory and temporary databases have no path
+        let lock_path = conn.path().filter(|p| !p.is_empty()).map(lock_path);
+        Self {
             handle: Arc::new(Mutex::new(conn)),
-        })
+            lock_path,
+            lock: None,
+        }
     }
 
     /// Return a shared reference to the underlying connection
@@ -72,6 +135,15 @@ impl SqliteConn {
         Ok(tags)
     }
 
+    pub(crate) fn query_column(&self, query: &str) -> Result<Vec<String>> {
+        let conn = self.lock();
+        let mut stmt = conn.prepare(query)?;
+        let values = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<std::result::Result<Vec<String>, _>>()?;
+        Ok(values)
+    }
+
     pub(crate) fn insert_tag(&self, tag: &str) -> Result<()> {
         self.lock()
             .execute(sql::INSERT_MIGRATION_PG_SQLITE, [tag])?;
@@ -98,6 +170,33 @@ impl SqliteConn {
         res.map_err(|e| err!(Migration, "{}", e))
     }
 
+    pub(crate) fn execute(&self, stmt: &str, params: &[Value]) -> Result<u64> {
+        let changed = self
+            .lock()
+            .execute(stmt, params_from_iter(params))
+            .map_err(|e| err!(Migration, "{}", e))?;
+        Ok(changed as u64)
+    }
+
+    pub(crate) fn query_rows(&self, query: &str, params: &[Value]) -> Result<Vec<Row>> {
+        let conn = self.lock();
+        let run = || -> rusqlite::Result<Vec<Row>> {
+            let mut stmt = conn.prepare(query)?;
+            let columns: std::sync::Arc<[String]> =
+                stmt.column_names().into_iter().map(String::from).collect();
+            let mut rows = stmt.query(params_from_iter(params))?;
+            let mut out = Vec::new();
+            while let Some(row) = rows.next()? {
+                let values = (0..columns.len())
+                    .map(|i| row.get_ref(i).map(Value::from))
+                    .collect::<rusqlite::Result<_>>()?;
+                out.push(Row::new(columns.clone(), values));
+            }
+            Ok(out)
+        };
+        run().map_err(|e| err!(Migration, "{}", e))
+    }
+
     pub(crate) fn begin(&self) -> Result<()> {
         self.lock()
             .execute_batch("begin")
@@ -116,14 +215,78 @@ impl SqliteConn {
             .map_err(|e| err!(Migration, "{}", e))
     }
 
-    /// Sqlite has no cross-process advisory lock (a single connection already
-    /// serializes writers, and there is no server to coordinate through), so
-    /// migration-run locking is a no-op.
-    pub(crate) fn acquire_lock(&self) -> Result<()> {
+    /// Copy the database to a backup file with sqlite's online backup API,
+    /// replacing any existing file at `path`
+    pub(crate) fn backup_to(&self, path: &Path) -> Result<()> {
+        self.lock().backup(MAIN_DB, path, None).map_err(|e| {
+            err!(
+                Migration,
+                "Error backing up the database to {:?}: {}",
+                path,
+                e
+            )
+        })
+    }
+
+    /// Replace the database's contents with a backup file's
+    pub(crate) fn restore_from(&self, path: &Path) -> Result<()> {
+        // sqlite would restore a missing file as an empty database
+        if !path.is_file() {
+            bail!(PathError, "Backup file not found: {:?}", path)
+        }
+        self.lock()
+            .restore(MAIN_DB, path, None::<fn(rusqlite::backup::Progress)>)
+            .map_err(|e| {
+                err!(
+                    Migration,
+                    "Error restoring the database from {:?}: {}",
+                    path,
+                    e
+                )
+            })
+    }
+
+    /// Take the migration lock, waiting for as long as another process
+    /// holds it
+    pub(crate) fn acquire_lock(&mut self) -> Result<()> {
+        while !self.try_acquire_lock()? {
+            std::thread::sleep(LOCK_POLL_INTERVAL);
+        }
         Ok(())
     }
 
-    pub(crate) fn release_lock(&self) -> Result<()> {
+    /// Take the migration lock if it is free, without waiting. In-memory
+    /// databases can't be shared between processes and always succeed.
+    pub(crate) fn try_acquire_lock(&mut self) -> Result<bool> {
+        let path = match self.lock_path {
+            Some(ref path) if self.lock.is_none() => path,
+            _ => return Ok(true),
+        };
+        let locked = || -> rusqlite::Result<Connection> {
+            let conn = Connection::open(path)?;
+            conn.busy_timeout(Duration::ZERO)?;
+            conn.execute_batch("begin exclusive")?;
+            Ok(conn)
+        };
+        match locked() {
+            Ok(conn) => {
+                self.lock = Some(conn);
+                Ok(true)
+            }
+            Err(e) if e.sqlite_error_code() == Some(ErrorCode::DatabaseBusy) => Ok(false),
+            Err(e) => Err(err!(
+                Migration,
+                "Error taking the sqlite migration lock {:?}: {}",
+                path,
+                e
+            )),
+        }
+    }
+
+    /// Release the migration lock by closing the lock file's connection. The
+    /// file itself is left in place for the next run.
+    pub(crate) fn release_lock(&mut self) -> Result<()> {
+        self.lock = None;
         Ok(())
     }
 }
@@ -134,7 +297,7 @@ mod tests {
 
     #[test]
     fn migration_table_lifecycle() {
-        let conn = SqliteConn::open(MEMORY_PATH).unwrap();
+        let conn = SqliteConn::open(MEMORY_PATH, &SqliteOptions::default()).unwrap();
 
         assert!(
             !conn.migration_table_exists().unwrap(),
@@ -159,7 +322,7 @@ mod tests {
 
     #[test]
     fn execute_batch_rolls_back_failed_transactions() {
-        let conn = SqliteConn::open(MEMORY_PATH).unwrap();
+        let conn = SqliteConn::open(MEMORY_PATH, &SqliteOptions::default()).unwrap();
         conn.execute_batch("create table t(x integer);").unwrap();
         let res = conn.execute_batch("begin; insert into t values (1); nonsense;");
         assert!(res.is_err());
@@ -171,4 +334,90 @@ mod tests {
             .unwrap();
         assert_eq!(1, count, "failed batch was rolled back");
     }
+
+    #[test]
+    fn open_applies_options() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.db");
+        let path = path.to_str().unwrap();
+        let options = SqliteOptions {
+            journal_mode: Some("wal".into()),
+            synchronous: Some("normal".into()),
+            foreign_keys: Some(true),
+            busy_timeout: Some(1500),
+            pragmas: [("user_version".to_owned(), Value::Integer(7))].into(),
+            ..SqliteOptions::default()
+        };
+        let conn = SqliteConn::open(path, &options).unwrap();
+        let pragma = |name: &str| -> Value {
+            conn.lock()
+                .query_row(&format!("pragma {}", name), [], |row| {
+                    row.get_ref(0).map(Value::from)
+                })
+                .unwrap()
+        };
+        assert_eq!(pragma("journal_mode"), Value::from("wal"));
+        assert_eq!(pragma("synchronous"), Value::Integer(1));
+        assert_eq!(pragma("foreign_keys"), Value::Integer(1));
+        assert_eq!(pragma("busy_timeout"), Value::Integer(1500));
+        assert_eq!(pragma("user_version"), Value::Integer(7));
+        drop(conn);
+
+        let read_only = SqliteOptions {
+            read_only: true,
+            ..SqliteOptions::default()
+        };
+        let conn = SqliteConn::open(path, &read_only).unwrap();
+        assert!(conn.execute_batch("create table t (x integer)").is_err());
+
+        let no_create = SqliteOptions {
+            create: Some(false),
+            ..SqliteOptions::default()
+        };
+        let missing = dir.path().join("missing.db");
+        assert!(SqliteConn::open(missing.to_str().unwrap(), &no_create).is_err());
+        assert!(!missing.exists());
+    }
+
+    #[test]
+    fn migration_lock_excludes_other_connections() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.db");
+        let path = path.to_str().unwrap();
+        let mut first = SqliteConn::open(path, &SqliteOptions::default()).unwrap();
+        let mut second = SqliteConn::open(path, &SqliteOptions::default()).unwrap();
+
+        first.acquire_lock().unwrap();
+        assert!(first.try_acquire_lock().unwrap(), "lock is reentrant");
+        assert!(!second.try_acquire_lock().unwrap(), "lock is held");
+        // the database itself stays usable while the lock is held
+        second.execute_batch("create table t (x integer)").unwrap();
+
+        first.release_lock().unwrap();
+        assert!(second.try_acquire_lock().unwrap(), "lock was released");
+        second.release_lock().unwrap();
+
+        let mut memory = SqliteConn::open(MEMORY_PATH, &SqliteOptions::default()).unwrap();
+        assert!(memory.lock_path.is_none());
+        memory.acquire_lock().unwrap();
+    }
+
+    #[test]
+    fn backup_and_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup = dir.path().join("app.db.bak");
+        let conn = SqliteConn::open(MEMORY_PATH, &SqliteOptions::default()).unwrap();
+        conn.execute_batch("create table t (x integer); insert into t values (1);")
+            .unwrap();
+        conn.backup_to(&backup).unwrap();
+
+        conn.execute_batch("insert into t values (2); create table u (y integer);")
+            .unwrap();
+        conn.restore_from(&backup).unwrap();
+        let rows = conn.query_rows("select x from t", &[]).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert!(conn.execute_batch("select * from u").is_err());
+
+        assert!(conn.restore_from(&dir.path().join("missing.bak")).is_err());
+    }
 }
@@ -41,6 +41,11 @@ pub enum Error {
     #[error("FeatureRequired: this operation requires the `{0}` cargo feature")]
     FeatureRequired(&'static str),
 
+    /// A server database could not be reached before giving up, see
+    /// [`Config::wait_for_database`](crate::Config::wait_for_database)
+    #[error("DatabaseUnavailable: {0}")]
+    DatabaseUnavailable(String),
+
     /// IO error
     #[error(transparent)]
     Io(#[from] std::io::Error),
@@ -71,6 +76,16 @@ pub enum Error {
     #[cfg(feature = "mysql")]
     #[error(transparent)]
     MySql(#[from] mysql::Error),
+
+    /// DuckDB driver error
+    #[cfg(feature = "duckdb")]
+    #[error(transparent)]
+    DuckDb(#[from] duckdb::Error),
+
+    /// Async MySQL driver error
+    #[cfg(all(feature = "async", feature = "mysql"))]
+    #[error(transparent)]
+    MySqlAsync(#[from] mysql_async::Error),
 }
 
 impl Error {
@@ -108,6 +123,11 @@ impl Error {
     pub fn is_feature_required(&self) -> bool {
         matches!(self, Error::FeatureRequired(_))
     }
+
+    /// `true` for [`Error::DatabaseUnavailable`]
+    pub fn is_database_unavailable(&self) -> bool {
+        matches!(self, Error::DatabaseUnavailable(_))
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,981 @@
+/*!
+Import migrations from other migration tools
+
+[`Importer`] converts another tool's migrations directory into migrant's
+`<timestamp>_<tag>/up.sql|down.sql` layout and, when the database holds that
+tool's bookkeeping table, records the migrations it lists as applied in
+`__migrant_migrations`.
+*/
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, NaiveDateTime, TimeDelta};
+use log::warn;
+use walkdir::WalkDir;
+
+use crate::config::Config;
+use crate::errors::*;
+use crate::macros::{bail, err};
+use crate::migration::NO_TRANSACTION_DIRECTIVE;
+use crate::ops::scan_migrations;
+use crate::tags::VersionScheme;
+use crate::{DbKind, Direction, DT_FORMAT, SQLITE_MEMORY_PATH};
+
+/// A migration tool whose migrations can be imported
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ImportSource {
+    /// diesel: `<version>_<name>/up.sql|down.sql` directories,
+    /// bookkeeping in `__diesel_schema_migrations`
+    Diesel,
+    /// sqlx: `<version>_<description>.sql` or `.up.sql|.down.sql` files,
+    /// bookkeeping in `_sqlx_migrations`
+    Sqlx,
+    /// refinery: `V<version>__<name>.sql` files,
+    /// bookkeeping in `refinery_schema_history`
+    Refinery,
+    /// golang-migrate: `<version>_<title>.up.sql|.down.sql` files,
+    /// bookkeeping in `schema_migrations`
+    GolangMigrate,
+    /// Flyway: `V<version>__<description>.sql` and `U<version>__<description>.sql`
+    /// files, bookkeeping in `flyway_schema_history`
+    Flyway,
+}
+
+impl ImportSource {
+    /// Name of the tool's bookkeeping table, unless configured otherwise
+    pub fn history_table(&self) -> &'static str {
+        match self {
+            ImportSource::Diesel => "__diesel_schema_migrations",
+            ImportSource::Sqlx => "_sqlx_migrations",
+            ImportSource::Refinery => "refinery_schema_history",
+            ImportSource::GolangMigrate => "schema_migrations",
+            ImportSource::Flyway => "flyway_schema_history",
+        }
+    }
+}
+
+impl std::str::FromStr for ImportSource {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "diesel" => ImportSource::Diesel,
+            "sqlx" => ImportSource::Sqlx,
+            "refinery" => ImportSource::Refinery,
+            "golang-migrate" => ImportSource::GolangMigrate,
+            "flyway" => ImportSource::Flyway,
+            _ => bail!(
+                Config,
+                "Unknown migration tool `{}`, expected one of: \
+                 diesel, sqlx, refinery, golang-migrate, flyway",
+                s
+            ),
+        })
+    }
+}
+
+impl fmt::Display for ImportSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImportSource::Diesel => write!(f, "diesel"),
+            ImportSource::Sqlx => write!(f, "sqlx"),
+            ImportSource::Refinery => write!(f, "refinery"),
+            ImportSource::GolangMigrate => write!(f, "golang-migrate"),
+            ImportSource::Flyway => write!(f, "flyway"),
+        }
+    }
+}
+
+/// Migration importer
+///
+/// Reads the `source` tool's migrations from a directory and writes them as
+/// migration directories under the config's `migration_location`. Migrations
+/// keep the tool's order: versions that are already `YYYYMMDDHHMMSS`
+/// timestamps keep them, and other versions (`1`, `2.1`, ...) are given
+/// consecutive timestamps from `19700101000000`. Under a sequence or semver
+/// [`VersionScheme`], migrations are instead numbered after the existing
+/// ones. Migrations without a down migration get a `down.sql` holding only a
+/// comment.
+///
+/// Unless disabled with `record_applied`, the tool's bookkeeping table is
+/// then read from the configured database, and every imported migration it
+/// lists as applied is recorded in `__migrant_migrations`, so it is not
+/// applied again.
+///
+/// ```rust,no_run
+/// # use migrant_lib::{Config, ImportSource, Importer};
+/// # fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// # let config = Config::from_settings_file("Migrant.toml")?;
+/// let report = Importer::new(&config, ImportSource::Diesel, "old/migrations").import()?;
+/// for tag in report.recorded() {
+///     println!("already applied: {}", tag);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Importer {
+    config: Config,
+    source: ImportSource,
+    dir: PathBuf,
+    history_table: Option<String>,
+    record_applied: bool,
+}
+
+impl Importer {
+    /// Initialize a new `Importer` of `source` migrations in `dir`
+    pub fn new<T: AsRef<Path>>(config: &Config, source: ImportSource, dir: T) -> Self {
+        Self {
+            config: config.clone(),
+            source,
+            dir: dir.as_ref().to_path_buf(),
+            history_table: None,
+            record_applied: true,
+        }
+    }
+
+    /// Set the name of the tool's bookkeeping table.
+    /// Default is [`ImportSource::history_table`].
+    pub fn history_table<T: Into<String>>(mut self, table: T) -> Self {
+        self.history_table = Some(table.into());
+        self
+    }
+
+    /// Record migrations listed in the tool's bookkeeping table as applied.
+    /// Default is `true`. When `false`, the database is not accessed.
+    pub fn record_applied(mut self, record_applied: bool) -> Self {
+        self.record_applied = record_applied;
+        self
+    }
+
+    /// Convert the migrations and record the applied ones.
+    ///
+    /// Everything is read and checked before anything is written: a malformed
+    /// source migration, a tag that already exists in the migration location,
+    /// or a `dirty` golang-migrate database fails the import without changes.
+    /// The migrations are staged beside the migration location, moved into it,
+    /// and then the applied ones are recorded in a transaction. If moving or
+    /// recording fails, the migrations already moved are moved back out, so
+    /// the error leaves neither files nor rows behind.
+    pub fn import(&self) -> Result<ImportReport> {
+        let table = self
+            .history_table
+            .as_deref()
+            .unwrap_or(self.source.history_table());
+        if table.is_empty()
+            || !table
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'_')
+        {
+            bail!(
+                Config,
+                "Invalid history table name `{}`, expected [A-Za-z0-9_]",
+                table
+            )
+        }
+        if !self.dir.is_dir() {
+            bail!(
+                PathError,
+                "Migration directory to import not found: {:?}",
+                self.dir
+            )
+        }
+
+        let mut report = ImportReport::default();
+        let mut migrations = self.scan(&mut report)?;
+        migrations.sort_by(|a, b| a.version.cmp(&b.version));
+        for pair in migrations.windows(2) {
+            if pair[0].version == pair[1].version {
+                bail!(
+                    Migration,
+                    "Duplicate {} migration version `{}`: {:?} and {:?}",
+                    self.source,
+                    pair[1].raw_version,
+                    pair[0].path,
+                    pair[1].path
+                )
+            }
+        }
+        let scheme = self.config.settings.version_scheme;
+        let location = self.config.migration_location()?;
+        let existing = if location.is_dir() {
+            if fs::canonicalize(&location)? == fs::canonicalize(&self.dir)? {
+                bail!(
+                    Config,
+                    "Cannot import migrations into the directory they are imported from: {:?}",
+                    location
+                )
+            }
+            scan_migrations(&location, scheme)?
+        } else {
+            vec![]
+        };
+        let tags = assign_tags(
+            &migrations,
+            scheme,
+            existing.iter().map(|found| found.version.as_str()),
+        )?;
+        let existing = existing
+            .iter()
+            .map(|found| found.full_tag())
+            .collect::<HashSet<_>>();
+        if let Some(tag) = tags.iter().find(|tag| existing.contains(*tag)) {
+            bail!(
+                TagError,
+                "Migration `{}` already exists in {:?}",
+                tag,
+                location
+            )
+        }
+
+        let history = if self.record_applied {
+            self.read_history(table)?
+        } else {
+            None
+        };
+
+        // Write into a staging directory next to the migration location, move
+        // the migrations in, and only then record the applied ones: the rows
+        // commit last, and on a failure before that the moved migrations go
+        // back to the staging directory, leaving neither files nor rows behind.
+        let staging = staging_dir(&location)?;
+        let created = !location.exists();
+        let mut moved = vec![];
+        let res = self
+            .stage(&staging, &migrations, &tags)
+            .and_then(|()| {
+                fs::create_dir_all(&location)?;
+                for tag in &tags {
+                    fs::rename(staging.join(tag), location.join(tag))?;
+                    moved.push(tag);
+                }
+                Ok(())
+            })
+            .and_then(|()| self.record_history(table, history, &migrations, &tags, &mut report));
+        if res.is_err() {
+            for tag in moved {
+                let _ = fs::rename(location.join(tag), staging.join(tag));
+            }
+            if created {
+                let _ = fs::remove_dir(&location);
+            }
+        }
+        let _ = fs::remove_dir_all(&staging);
+        res?;
+        report.migrations = tags;
+        Ok(report)
+    }
+
+    /// Write the converted migrations into `staging`
+    fn stage(&self, staging: &Path, migrations: &[SourceMigration], tags: &[String]) -> Result<()> {
+        for (migration, tag) in migrations.iter().zip(tags) {
+            let mig_dir = staging.join(tag);
+            fs::create_dir_all(&mig_dir)?;
+            fs::write(mig_dir.join("up.sql"), migration.up_sql())?;
+            fs::write(mig_dir.join("down.sql"), migration.down_sql(self.source))?;
+        }
+        Ok(())
+    }
+
+    /// Record the migrations `history` lists as applied
+    fn record_history(
+        &self,
+        table: &str,
+        history: Option<History>,
+        migrations: &[SourceMigration],
+        tags: &[String],
+        report: &mut ImportReport,
+    ) -> Result<()> {
+        let Some(history) = history else {
+            return Ok(());
+        };
+        report.history_found = true;
+        let versions = migrations
+            .iter()
+            .map(|m| &m.version)
+            .collect::<HashSet<_>>();
+        for version in history.applied.iter().filter(|v| !versions.contains(v)) {
+            report.skipped.push(format!(
+                "version {} is applied according to `{}` but has no migration file",
+                format_version(version),
+                table
+            ));
+        }
+        let applied = migrations
+            .iter()
+            .zip(tags)
+            .filter(|(migration, _)| history.is_applied(&migration.version))
+            .map(|(_, tag)| tag.clone())
+            .collect::<Vec<_>>();
+        if !applied.is_empty() {
+            self.record(&applied)?;
+        }
+        report.recorded = applied;
+        Ok(())
+    }
+
+    /// Read the source directory's migrations, noting files that can't be imported
+    fn scan(&self, report: &mut ImportReport) -> Result<Vec<SourceMigration>> {
+        match self.source {
+            ImportSource::Diesel => self.scan_diesel(),
+            ImportSource::Sqlx => self.scan_files(report, |name| {
+                let (stem, direction) = split_direction(name, true)?;
+                let (version, description) = stem.split_once('_')?;
+                Some((version, description, direction))
+            }),
+            ImportSource::GolangMigrate => self.scan_files(report, |name| {
+                let (stem, direction) = split_direction(name, false)?;
+                let (version, title) = stem.split_once('_')?;
+                Some((version, title, direction))
+            }),
+            ImportSource::Refinery => self.scan_files(report, |name| {
+                let stem = name.strip_suffix(".sql")?;
+                let rest = stem.strip_prefix('V').or_else(|| stem.strip_prefix('U'))?;
+                let (version, name) = rest.split_once("__")?;
+                Some((version, name, Some(Direction::Up)))
+            }),
+            ImportSource::Flyway => self.scan_files(report, |name| {
+                let stem = name.strip_suffix(".sql")?;
+                let (direction, rest) = match stem.split_at_checked(1)? {
+                    ("V", rest) => (Direction::Up, rest),
+                    ("U", rest) => (Direction::Down, rest),
+                    _ => return None,
+                };
+                let (version, description) = rest.split_once("__")?;
+                Some((version, description, Some(direction)))
+            }),
+        }
+    }
+
+    /// diesel migration directories, with an optional `metadata.toml`
+    fn scan_diesel(&self) -> Result<Vec<SourceMigration>> {
+        let mut migrations = vec![];
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if !path.is_dir() {
+                continue;
+            }
+            let dir_name = file_name(&path)?;
+            let Some((raw_version, name)) = dir_name.split_once('_') else {
+                warn!(
+                    "Ignoring directory without a `<version>_<name>` name: {:?}",
+                    path
+                );
+                continue;
+            };
+            let up_path = path.join("up.sql");
+            if !up_path.is_file() {
+                bail!(
+                    MigrationNotFound,
+                    "Missing `up.sql` in diesel migration {:?}",
+                    path
+                )
+            }
+            let down_path = path.join("down.sql");
+            let down = if down_path.is_file() {
+                Some(fs::read_to_string(&down_path)?)
+            } else {
+                None
+            };
+            let metadata = path.join("metadata.toml");
+            let no_transaction = if metadata.is_file() {
+                let metadata: toml::Value = toml::from_str(&fs::read_to_string(&metadata)?)?;
+                metadata
+                    .get("run_in_transaction")
+                    .and_then(toml::Value::as_bool)
+                    == Some(false)
+            } else {
+                false
+            };
+            migrations.push(SourceMigration {
+                version: self.parse_version(raw_version, &path)?,
+                raw_version: raw_version.to_owned(),
+                name: name.to_owned(),
+                up: fs::read_to_string(&up_path)?,
+                down,
+                no_transaction,
+                path,
+            });
+        }
+        Ok(migrations)
+    }
+
+    /// File-per-migration layouts. `parse_name` splits a file name into its
+    /// version, name and direction (`None` for an irreversible sqlx migration).
+    fn scan_files(
+        &self,
+        report: &mut ImportReport,
+        parse_name: impl Fn(&str) -> Option<(&str, &str, Option<Direction>)>,
+    ) -> Result<Vec<SourceMigration>> {
+        // Flyway searches subdirectories, the other tools only the directory itself
+        let max_depth = if self.source == ImportSource::Flyway {
+            usize::MAX
+        } else {
+            1
+        };
+        let mut files = vec![];
+        for entry in WalkDir::new(&self.dir).min_depth(1).max_depth(max_depth) {
+            let entry = entry.map_err(|e| err!(PathError, "{}", e))?;
+            if entry.file_type().is_file() {
+                files.push(entry.into_path());
+            }
+        }
+        files.sort();
+
+        let mut by_version: BTreeMap<Version, SourceMigration> = BTreeMap::new();
+        let mut down_files = vec![];
+        for path in files {
+            let name = file_name(&path)?;
+            if path.extension() != Some(OsStr::new("sql")) {
+                if self.source == ImportSource::Refinery && name.ends_with(".rs") {
+                    report
+                        .skipped
+                        .push(format!("{:?}: Rust migrations cannot be imported", path));
+                }
+                continue;
+            }
+            if self.source == ImportSource::Flyway && name.starts_with("R__") {
+                report.skipped.push(format!(
+                    "{:?}: repeatable migrations have no version and cannot be imported",
+                    path
+                ));
+                continue;
+            }
+            let Some((raw_version, name, direction)) = parse_name(name) else {
+                warn!(
+                    "Ignoring sql file not named like a {} migration: {:?}",
+                    self.source, path
+                );
+                continue;
+            };
+            let version = self.parse_version(raw_version, &path)?;
+            let (sql, no_transaction) = self.read_sql(&path)?;
+            if direction == Some(Direction::Down) {
+                down_files.push((version, sql, path));
+                continue;
+            }
+            if let Some(existing) = by_version.get(&version) {
+                bail!(
+                    Migration,
+                    "Duplicate {} migration version `{}`: {:?} and {:?}",
+                    self.source,
+                    raw_version,
+                    existing.path,
+                    path
+                )
+            }
+            by_version.insert(
+                version.clone(),
+                SourceMigration {
+                    version,
+                    raw_version: raw_version.to_owned(),
+                    name: name.to_owned(),
+                    up: sql,
+                    down: None,
+                    no_transaction,
+                    path,
+                },
+            );
+        }
+        for (version, sql, path) in down_files {
+            match by_version.get_mut(&version) {
+                Some(migration) => migration.down = Some(sql),
+                None => bail!(
+                    MigrationNotFound,
+                    "Down migration {:?} has no matching up migration",
+                    path
+                ),
+            }
+        }
+        Ok(by_version.into_values().collect())
+    }
+
+    /// Read a migration file, translating the tool's no-transaction marker
+    fn read_sql(&self, path: &Path) -> Result<(String, bool)> {
+        let sql = fs::read_to_string(path)?;
+        match self.source {
+            // sqlx: `-- no-transaction` as the first line
+            ImportSource::Sqlx => {
+                let (first, rest) = sql.split_once('\n').unwrap_or((&sql, ""));
+                if first.trim() == "-- no-transaction" {
+                    return Ok((rest.to_owned(), true));
+                }
+            }
+            // Flyway: `executeInTransaction=false` in a `<script>.conf` file
+            ImportSource::Flyway => {
+                let mut conf = path.as_os_str().to_owned();
+                conf.push(".conf");
+                let conf = PathBuf::from(conf);
+                if conf.is_file() {
+                    let no_transaction = fs::read_to_string(&conf)?.lines().any(|line| {
+                        line.split_once('=').is_some_and(|(key, value)| {
+                            key.trim() == "executeInTransaction" && value.trim() == "false"
+                        })
+                    });
+                    return Ok((sql, no_transaction));
+                }
+            }
+            _ => (),
+        }
+        Ok((sql, false))
+    }
+
+    /// Parse a version from a file name or bookkeeping row
+    fn parse_version(&self, raw: &str, path: &Path) -> Result<Version> {
+        parse_version(self.source, raw).ok_or_else(|| {
+            err!(
+                Migration,
+                "Unrecognized {} migration version `{}` in {:?}",
+                self.source,
+                raw,
+                path
+            )
+        })
+    }
+
+    /// The versions the tool's bookkeeping table lists as applied, or `None`
+    /// when the database or the table doesn't exist
+    fn read_history(&self, table: &str) -> Result<Option<History>> {
+        let config = &self.config;
+        let kind = config.database_type();
+        if kind.is_embedded() {
+            let path = config.database_path_string()?;
+            if path != SQLITE_MEMORY_PATH && !Path::new(&path).exists() {
+                return Ok(None);
+            }
+        }
+        let exists = match kind {
+            DbKind::Sqlite => format!(
+                "SELECT name FROM sqlite_master WHERE type = 'table' AND name = '{}'",
+                table
+            ),
+            DbKind::Postgres | DbKind::DuckDb => format!(
+                "SELECT CAST(table_name AS TEXT) FROM information_schema.tables \
+                 WHERE table_schema = current_schema() AND table_name = '{}'",
+                table
+            ),
+            DbKind::MySql => format!(
+                "SELECT CAST(table_name AS CHAR) FROM information_schema.tables \
+                 WHERE table_schema = DATABASE() AND table_name = '{}'",
+                table
+            ),
+        };
+        if config.query_column(&exists)?.is_empty() {
+            return Ok(None);
+        }
+
+        let text = |column: &str| match kind {
+            DbKind::MySql => format!("CAST({} AS CHAR)", column),
+            _ => format!("CAST({} AS TEXT)", column),
+        };
+        let path = Path::new(table);
+        let parse = |rows: Vec<String>| {
+            rows.iter()
+                .map(|raw| self.parse_version(raw, path))
+                .collect::<Result<Vec<_>>>()
+        };
+        let mut history = History::default();
+        match self.source {
+            ImportSource::Diesel | ImportSource::Refinery => {
+                let rows =
+                    config.query_column(&format!("SELECT {} FROM {}", text("version"), table))?;
+                history.applied.extend(parse(rows)?);
+            }
+            ImportSource::Sqlx => {
+                let rows = config.query_column(&format!(
+                    "SELECT {} FROM {} WHERE success",
+                    text("version"),
+                    table
+                ))?;
+                history.applied.extend(parse(rows)?);
+            }
+            // a single row holding the current version: everything up to it is applied
+            ImportSource::GolangMigrate => {
+                let dirty = config.query_column(&format!(
+                    "SELECT {} FROM {} WHERE dirty",
+                    text("version"),
+                    table
+                ))?;
+                if let Some(version) = dirty.first() {
+                    bail!(
+                        Migration,
+                        "golang-migrate's `{}` is dirty at version {}: a migration failed \
+                         part-way. Fix the database and `migrate force` the version before \
+                         importing",
+                        table,
+                        version
+                    )
+                }
+                let rows =
+                    config.query_column(&format!("SELECT {} FROM {}", text("version"), table))?;
+                history.up_to = parse(rows)?.into_iter().max();
+            }
+            // replay applied, undone and baseline rows in installation order
+            ImportSource::Flyway => {
+                let order = format!("FROM {} WHERE success ORDER BY installed_rank", table);
+                let types = config.query_column(&format!("SELECT {} {}", text("type"), order))?;
+                let versions = config.query_column(&format!(
+                    "SELECT COALESCE({}, '') {}",
+                    text("version"),
+                    order
+                ))?;
+                for (kind, raw) in types.iter().zip(&versions) {
+                    if raw.is_empty() {
+                        continue;
+                    }
+                    let version = self.parse_version(raw, path)?;
+                    match kind.as_str() {
+                        "BASELINE" => history.up_to = Some(version),
+                        "SCHEMA" | "DELETE" => (),
+                        kind if kind.starts_with("UNDO_") => {
+                            history.applied.remove(&version);
+                        }
+                        _ => {
+                            history.applied.insert(version);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(Some(history))
+    }
+
+    /// Record `tags` as applied in `__migrant_migrations`, all or nothing
+    fn record(&self, tags: &[String]) -> Result<()> {
+        let config = &self.config;
+        config.setup()?;
+        let already = config.load_applied()?.into_iter().collect::<HashSet<_>>();
+        config.begin_transaction()?;
+        let res = tags
+            .iter()
+            .filter(|tag| !already.contains(*tag))
+            .try_for_each(|tag| config.insert_migration_tag(tag));
+        match res {
+            Ok(()) => config.commit_transaction(),
+            Err(e) => {
+                config.rollback_transaction();
+                Err(e)
+            }
+        }
+    }
+}
+
+/// What an [`Importer`] did
+#[derive(Debug, Clone, Default)]
+pub struct ImportReport {
+    migrations: Vec<String>,
+    recorded: Vec<String>,
+    skipped: Vec<String>,
+    history_found: bool,
+}
+
+impl ImportReport {
+    /// Tags of the migrations written, in order
+    pub fn migrations(&self) -> &[String] {
+        &self.migrations
+    }
+
+    /// Tags of the imported migrations recorded as applied
+    pub fn recorded(&self) -> &[String] {
+        &self.recorded
+    }
+
+    /// Descriptions of source files and bookkeeping rows that could not be
+    /// imported, such as Flyway repeatable migrations
+    pub fn skipped(&self) -> &[String] {
+        &self.skipped
+    }
+
+    /// Whether the tool's bookkeeping table was found and read
+    pub fn history_found(&self) -> bool {
+        self.history_found
+    }
+}
+
+/// A fresh directory beside `location`, on the same filesystem so staged
+/// migrations can be renamed into place
+fn staging_dir(location: &Path) -> Result<PathBuf> {
+    let name = location
+        .file_name()
+        .and_then(OsStr::to_str)
+        .ok_or_else(|| err!(PathError, "Invalid migration location: {:?}", location))?;
+    let staging = location.with_file_name(format!(".{}.import-{}", name, std::process::id()));
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(&staging)?;
+    Ok(staging)
+}
+
+/// A migration version: its numeric parts, without trailing zero parts
+/// (Flyway treats `1.0` and `1` as the same version)
+type Version = Vec<u64>;
+
+/// A migration read from another tool's directory
+#[derive(Debug)]
+struct SourceMigration {
+    version: Version,
+    raw_version: String,
+    name: String,
+    up: String,
+    down: Option<String>,
+    no_transaction: bool,
+    path: PathBuf,
+}
+
+impl SourceMigration {
+    fn up_sql(&self) -> String {
+        self.with_directive(&self.up)
+    }
+
+    fn down_sql(&self, source: ImportSource) -> String {
+        match self.down {
+            Some(ref down) => self.with_directive(down),
+            None => format!(
+                "-- No down migration: imported from the irreversible {} migration {:?}\n",
+                source, self.path
+            ),
+        }
+    }
+
+    fn with_directive(&self, sql: &str) -> String {
+        if self.no_transaction {
+            format!("-- {}\n{}", NO_TRANSACTION_DIRECTIVE, sql)
+        } else {
+            sql.to_owned()
+        }
+    }
+}
+
+/// Applied versions read from a bookkeeping table
+#[derive(Debug, Default)]
+struct History {
+    applied: HashSet<Version>,
+    /// Every version up to and including this one is applied
+    up_to: Option<Version>,
+}
+
+impl History {
+    fn is_applied(&self, version: &Version) -> bool {
+        self.applied.contains(version) || self.up_to.as_ref().is_some_and(|up_to| version <= up_to)
+    }
+}
+
+/// Split `.up.sql` / `.down.sql` off a file name. With `allow_plain`, a
+/// plain `.sql` file is an irreversible migration.
+fn split_direction(name: &str, allow_plain: bool) -> Option<(&str, Option<Direction>)> {
+    if let Some(stem) = name.strip_suffix(".up.sql") {
+        Some((stem, Some(Direction::Up)))
+    } else if let Some(stem) = name.strip_suffix(".down.sql") {
+        Some((stem, Some(Direction::Down)))
+    } else if allow_plain {
+        name.strip_suffix(".sql").map(|stem| (stem, None))
+    } else {
+        None
+    }
+}
+
+fn file_name(path: &Path) -> Result<&str> {
+    path.file_name()
+        .and_then(OsStr::to_str)
+        .ok_or_else(|| err!(PathError, "Error extracting file-name from: {:?}", path))
+}
+
+/// Parse a version as `source` writes it. diesel versions are the digits of
+/// a `2020-01-01-120000` style prefix, Flyway's are `.` or `_` separated.
+fn parse_version(source: ImportSource, raw: &str) -> Option<Version> {
+    let raw = match source {
+        ImportSource::Diesel => raw.replace('-', ""),
+        _ => raw.to_owned(),
+    };
+    let separators: &[char] = match source {
+        ImportSource::Flyway => &['.', '_'],
+        _ => &[],
+    };
+    let mut parts = raw
+        .split(separators)
+        .map(|part| {
+            if !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()) {
+                part.parse::<u64>().ok()
+            } else {
+                None
+            }
+        })
+        .collect::<Option<Vec<_>>>()?;
+    while parts.last() == Some(&0) {
+        parts.pop();
+    }
+    Some(parts)
+}
+
+fn format_version(version: &Version) -> String {
+    match version.as_slice() {
+        [] => "0".to_owned(),
+        parts => parts
+            .iter()
+            .map(u64::to_string)
+            .collect::<Vec<_>>()
+            .join("."),
+    }
+}
+
+/// `<version>_<tag>` names for version-sorted migrations.
+///
+/// Under the timestamp scheme, a version that is a `YYYYMMDDHHMMSS` timestamp
+/// later than the previous migration's is kept, any other gets the second
+/// after the previous one (from `19700101000000`). Under other schemes the
+/// migrations are numbered after the `existing` versions in the target.
+fn assign_tags<'a, I>(
+    migrations: &[SourceMigration],
+    scheme: VersionScheme,
+    existing: I,
+) -> Result<Vec<String>>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    if scheme != VersionScheme::Timestamp {
+        let mut versions = existing.into_iter().map(str::to_string).collect::<Vec<_>>();
+        return migrations
+            .iter()
+            .map(|migration| {
+                let version = scheme.next_version(versions.iter().map(String::as_str))?;
+                let tag = format!("{}_{}", version, slug(&migration.name));
+                versions.push(version);
+                Ok(tag)
+            })
+            .collect();
+    }
+    let mut previous: Option<NaiveDateTime> = None;
+    let mut tags = vec![];
+    for migration in migrations {
+        let stamp = match migration.version.as_slice() {
+            [n] => NaiveDateTime::parse_from_str(&format!("{:014}", n), DT_FORMAT).ok(),
+            _ => None,
+        };
+        let stamp = match (stamp, previous) {
+            (Some(stamp), Some(previous)) if stamp > previous => stamp,
+            (Some(stamp), None) => stamp,
+            (_, Some(previous)) => previous + TimeDelta::seconds(1),
+            (None, None) => DateTime::UNIX_EPOCH.naive_utc(),
+        };
+        previous = Some(stamp);
+        tags.push(format!(
+            "{}_{}",
+            stamp.format(DT_FORMAT),
+            slug(&migration.name)
+        ));
+    }
+    Ok(tags)
+}
+
+/// A `[a-z0-9-]+` tag from a migration name or description
+fn slug(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    while slug.ends_with('-') {
+        slug.pop();
+    }
+    if slug.is_empty() {
+        slug.push_str("migration");
+    }
+    slug
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn migration(version: Version, name: &str) -> SourceMigration {
+        SourceMigration {
+            version,
+            raw_version: String::new(),
+            name: name.to_owned(),
+            up: String::new(),
+            down: None,
+            no_transaction: false,
+            path: PathBuf::new(),
+        }
+    }
+
+    #[test]
+    fn versions_parse_the_way_each_tool_writes_them() {
+        use ImportSource::*;
+        assert_eq!(
+            parse_version(Diesel, "2020-01-01-120000"),
+            Some(vec![20200101120000])
+        );
+        assert_eq!(
+            parse_version(Diesel, "20200101120000"),
+            parse_version(Diesel, "2020-01-01-120000")
+        );
+        assert_eq!(parse_version(Diesel, "00000000000000"), Some(vec![]));
+        assert_eq!(parse_version(Flyway, "1.1"), Some(vec![1, 1]));
+        assert_eq!(parse_version(Flyway, "1_1"), Some(vec![1, 1]));
+        assert_eq!(parse_version(Flyway, "2.0"), parse_version(Flyway, "2"));
+        assert_eq!(parse_version(GolangMigrate, "0003"), Some(vec![3]));
+        assert_eq!(parse_version(Sqlx, "1.1"), None);
+        assert_eq!(parse_version(Refinery, "one"), None);
+        assert_eq!(parse_version(Flyway, "1..1"), None);
+    }
+
+    #[test]
+    fn timestamps_are_kept_and_other_versions_numbered_in_order() {
+        let tags = assign_tags(
+            &[
+                migration(vec![], "diesel_initial_setup"),
+                migration(vec![20200101120000], "Create users"),
+                migration(vec![20200101250000], "not-a-date"),
+            ],
+            VersionScheme::Timestamp,
+            [],
+        )
+        .unwrap();
+        assert_eq!(
+            tags,
+            [
+                "19700101000000_diesel-initial-setup",
+                "20200101120000_create-users",
+                "20200101120001_not-a-date",
+            ]
+        );
+
+        let sources = [
+            migration(vec![1], "init"),
+            migration(vec![1, 1], "Add__column!"),
+            migration(vec![2], "_"),
+        ];
+        let tags = assign_tags(&sources, VersionScheme::Timestamp, []).unwrap();
+        assert_eq!(
+            tags,
+            [
+                "19700101000000_init",
+                "19700101000001_add-column",
+                "19700101000002_migration",
+            ]
+        );
+
+        let tags = assign_tags(&sources, VersionScheme::Sequence, ["0007"]).unwrap();
+        assert_eq!(tags, ["0008_init", "0009_add-column", "0010_migration"]);
+    }
+
+    #[test]
+    fn history_applies_listed_versions_and_everything_up_to_a_baseline() {
+        let history = History {
+            applied: [vec![5]].into_iter().collect(),
+            up_to: Some(vec![2, 1]),
+        };
+        assert!(history.is_applied(&vec![1]));
+        assert!(history.is_applied(&vec![2, 1]));
+        assert!(!history.is_applied(&vec![3]));
+        assert!(history.is_applied(&vec![5]));
+    }
+}
@@ -18,15 +18,23 @@
 | `postgres`  | Enable postgres connectivity |
 | `sqlite`    | Enable sqlite connectivity   |
 | `mysql`     | Enable mysql connectivity    |
+| `duckdb`    | Enable duckdb connectivity   |
 | `all`       | Enable all backends          |
+| `async`     | Enable the async API (`AsyncConfig` / `AsyncMigrator`) for the enabled backends |
+| `r2d2`      | Enable `Config::from_r2d2_pool` for postgres and mysql pools |
+| `native-tls` | Postgres TLS through native-tls (default) |
+| `rustls`    | Postgres TLS through rustls instead of native-tls |
 
 
 *Notes:*
 
-- No features are enabled by default
+- Only `native-tls` is enabled by default. Disable default features to build
+  without OpenSSL, e.g. `default-features = false, features = ["postgres", "rustls"]`.
+  The `mysql` feature always uses native-tls.
 - The `sqlite` feature does not use `rusqlite`s `bundled` feature.
   If you would like `sqlite` to be bundled with your application, you will have to
   include `rusqlite` and enable the `bundled` feature in your project.
+  The same goes for `duckdb` and the `duckdb` crate's `bundled` feature.
 
 
 ## Usage
@@ -43,7 +51,7 @@
   See the [embedded_programmable](https://github.com/jaemk/migrant/blob/main/migrant_lib/examples/embedded_programmable.rs)
   example for a working sample of function migrations.
 - When working with embedded and function migrations, the respective database feature must be
-  enabled (`postgres` / `sqlite` / `mysql`).
+  enabled (`postgres` / `sqlite` / `mysql` / `duckdb`).
 
 
 ```rust,no_run
@@ -101,6 +109,29 @@ config.setup()?;
 ```
 
 
+## Async
+
+With the `async` feature, [`AsyncConfig`] and [`AsyncMigrator`] mirror `Config` and
+`Migrator` for applications running on a tokio runtime. Postgres and MySQL use
+`tokio-postgres` and `mysql_async`; sqlite operations run on tokio's blocking pool.
+The same migrations are shared with the synchronous API, and async function
+migrations can be defined with [`AsyncFnMigration`].
+
+```rust,no_run
+# #[cfg(feature = "async")]
+# async fn run() -> Result<(), Box<dyn std::error::Error>> {
+let config = migrant_lib::AsyncConfig::from_settings_file("path")?;
+config.setup().await?;
+let config = config.reload().await?;
+migrant_lib::AsyncMigrator::with_config(&config)
+    .all(true)
+    .apply()
+    .await?;
+# Ok(())
+# }
+```
+
+
 ## CLI Compatibility
 
 Migration management identical to the [`migrant`](https://github.com/jaemk/migrant) CLI tool can also be embedded.
@@ -131,26 +162,54 @@ use std::fmt;
 
 mod macros;
 
+#[cfg(feature = "async")]
+pub mod asynchronous;
+mod backup;
+pub mod build;
 pub mod config;
 mod connection;
 mod drivers;
 pub mod errors;
+mod import;
 mod migratable;
 pub mod migration;
 mod migrator;
 mod ops;
+mod script;
+mod statements;
 mod tags;
+mod template;
+mod tenants;
 mod util;
+mod value;
 
+#[cfg(feature = "async")]
+pub use crate::asynchronous::{AsyncConfig, AsyncConnConfig, AsyncFnMigration, AsyncMigrator};
 pub use crate::config::{Config, Settings};
 pub use crate::connection::ConnConfig;
+#[cfg(feature = "mysql")]
+pub use crate::connection::MySqlConnection;
+#[cfg(feature = "postgres")]
+pub use crate::connection::PostgresClient;
+pub use crate::drivers::MigrationDriver;
+#[cfg(feature = "r2d2")]
+pub use crate::drivers::PoolableConnection;
 pub use crate::errors::{Error, Result};
+pub use crate::import::{ImportReport, ImportSource, Importer};
 pub use crate::migratable::Migratable;
-pub use crate::migration::{noop, EmbeddedMigration, FileMigration, FnMigration};
-pub use crate::migrator::{Direction, ForceMode, Migrator, Report};
+pub use crate::migration::{
+    noop, EmbeddedMigration, FileMigration, FnMigration, MigrationMetadata,
+};
+pub use crate::migrator::{CancelToken, Direction, ForceMode, Migrator, Report};
 pub use crate::ops::{
-    list, migration_statuses, new, pending_migrations, search_for_settings_file, MigrationStatus,
+    list, migration_statuses, new, new_single_file, pending_migrations, search_for_settings_file,
+    MigrationStatus,
+};
+pub use crate::tags::VersionScheme;
+pub use crate::tenants::{
+    OnTenantFailure, Tenant, TenantReport, TenantResult, TenantSource, Tenants,
 };
+pub use crate::value::{Row, Value};
 
 /// Interactive, terminal-oriented operations used by the `migrant` CLI.
 ///
@@ -160,16 +219,45 @@ pub mod cli {
     pub use crate::ops::{edit, shell};
 }
 
+/// Expand to the `Vec<Box<dyn Migratable>>` of embedded migrations generated
+/// by [`build::embed_migrations`] in the package's build script, ready for
+/// [`Config::use_migrations`].
+///
+/// ```rust,ignore
+/// config.use_migrations(migrant_lib::embed_migrations!())?;
+/// ```
+#[macro_export]
+macro_rules! embed_migrations {
+    () => {
+        include!(concat!(env!("OUT_DIR"), "/migrant_embedded_migrations.rs"))
+    };
+}
+
 /// Re-export of the `rusqlite` crate used by this library, so downstream
 /// code interacting with [`Config::sqlite_connection`] can use matching types
 #[cfg(feature = "sqlite")]
 pub use rusqlite;
 
+/// Re-export of the `postgres` crate used by this library, for
+/// [`ConnConfig::postgres_client`]
+#[cfg(feature = "postgres")]
+pub use postgres;
+
+/// Re-export of the `mysql` crate used by this library, for
+/// [`ConnConfig::mysql_connection`]
+#[cfg(feature = "mysql")]
+pub use mysql;
+
+/// Re-export of the `duckdb` crate used by this library, for
+/// [`Config::duckdb_connection`]
+#[cfg(feature = "duckdb")]
+pub use duckdb;
+
 /// Settings file name: `Migrant.toml`
 pub(crate) const CONFIG_FILE: &str = "Migrant.toml";
 /// Timestamp format used in migration tags
 pub(crate) const DT_FORMAT: &str = "%Y%m%d%H%M%S";
-/// Special sqlite database path selecting an in-memory database
+/// Special sqlite (and duckdb) database path selecting an in-memory database
 pub(crate) const SQLITE_MEMORY_PATH: &str = ":memory:";
 
 /// Database type being used
@@ -182,6 +270,16 @@ pub enum DbKind {
     Postgres,
     /// MySQL database
     MySql,
+    /// DuckDB database
+    DuckDb,
+}
+
+impl DbKind {
+    /// An embedded database (sqlite, duckdb) opened from a file path or
+    /// `:memory:`, rather than a server reached through a connect string
+    pub(crate) fn is_embedded(self) -> bool {
+        matches!(self, DbKind::Sqlite | DbKind::DuckDb)
+    }
 }
 
 impl std::str::FromStr for DbKind {
@@ -191,6 +289,7 @@ impl std::str::FromStr for DbKind {
             "sqlite" => DbKind::Sqlite,
             "postgres" => DbKind::Postgres,
             "mysql" => DbKind::MySql,
+            "duckdb" => DbKind::DuckDb,
             _ => {
                 return Err(Error::InvalidDbKind(format!(
                     "Invalid Database Kind: {}",
@@ -207,6 +306,7 @@ impl fmt::Display for DbKind {
             DbKind::Postgres => write!(f, "postgres"),
             DbKind::Sqlite => write!(f, "sqlite"),
             DbKind::MySql => write!(f, "mysql"),
+            DbKind::DuckDb => write!(f, "duckdb"),
         }
     }
 }
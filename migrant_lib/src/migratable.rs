@@ -3,6 +3,7 @@ The `Migratable` trait
 */
 use std::fmt;
 
+use crate::migration::MigrationMetadata;
 use crate::migrator::Direction;
 use crate::Config;
 
@@ -21,7 +22,10 @@ where
 }
 
 /// A type that can be used to define database migrations
-pub trait Migratable: MigratableClone {
+///
+/// Migrations are `Send + Sync` so a `Config` holding them can be moved onto
+/// another thread, e.g. by the async API's blocking pool.
+pub trait Migratable: MigratableClone + Send + Sync {
     /// Define functionality that runs for `up` migrations
     fn apply_up(&self, _: &Config) -> std::result::Result<(), Box<dyn std::error::Error>> {
         Ok(())
@@ -32,6 +36,37 @@ pub trait Migratable: MigratableClone {
         Ok(())
     }
 
+    /// The SQL this migration runs in the given `direction`, for migrations
+    /// that are defined purely by SQL (`EmbeddedMigration`, `FileMigration`),
+    /// with any `{{ name }}` template variables rendered (see
+    /// [`Config::variable`](crate::Config::variable)).
+    ///
+    /// Returns `None` (the default) for migrations that run code instead. A
+    /// direction with no SQL defined returns an empty string. The async
+    /// migrator uses this to run SQL migrations over its own connection.
+    fn sql(
+        &self,
+        _config: &Config,
+        _direction: Direction,
+    ) -> Option<std::result::Result<String, Box<dyn std::error::Error>>> {
+        None
+    }
+
+    /// Async counterpart of `apply_up`/`apply_down`, used by
+    /// [`AsyncMigrator`](crate::AsyncMigrator).
+    ///
+    /// Returns `None` (the default) for migrations without an async
+    /// implementation; the async migrator then runs their [`sql`](Migratable::sql),
+    /// or falls back to `apply_up`/`apply_down` on tokio's blocking pool.
+    #[cfg(feature = "async")]
+    fn apply_async(
+        &self,
+        _config: &crate::AsyncConfig,
+        _direction: Direction,
+    ) -> Option<crate::asynchronous::AsyncMigrationFuture> {
+        None
+    }
+
     /// A unique identifying tag
     fn tag(&self) -> String;
 
@@ -40,6 +75,15 @@ pub trait Migratable: MigratableClone {
         self.tag()
     }
 
+    /// Descriptive metadata: a human description, author and ticket reference.
+    ///
+    /// `FileMigration` and `EmbeddedMigration` parse it from `-- migrant:description`,
+    /// `-- migrant:author` and `-- migrant:ticket` comment headers in their SQL.
+    /// Defaults to empty metadata.
+    fn metadata(&self, _config: &Config) -> MigrationMetadata {
+        MigrationMetadata::default()
+    }
+
     /// Whether the migrator should wrap this migration's application in the
     /// given `direction`, together with its bookkeeping row (the insert/delete
     /// in `__migrant_migrations`), in a single database transaction so the two
@@ -55,11 +99,14 @@ pub trait Migratable: MigratableClone {
     /// [`EmbeddedMigration`](crate::EmbeddedMigration) and
     /// [`FileMigration`](crate::FileMigration).
     ///
+    /// `config` is the configuration the migration is about to run against, so
+    /// migrations with backend-specific SQL can inspect the SQL that will run.
+    ///
     /// Note: MySQL/MariaDB commit DDL implicitly, so transactional wrapping
     /// there only makes pure-DML migrations atomic; DDL cannot be rolled back
     /// regardless of this setting.
-    fn use_transaction(&self, direction: Direction) -> bool {
-        let _ = direction;
+    fn use_transaction(&self, config: &Config, direction: Direction) -> bool {
+        let _ = (config, direction);
         true
     }
 }
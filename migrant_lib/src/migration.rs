@@ -4,13 +4,14 @@ Embedded / programmable migrations
 use std::borrow::Cow;
 use std::path::{Path, PathBuf};
 
-use chrono::{DateTime, Utc};
-
 use crate::config::Config;
 use crate::connection::ConnConfig;
+use crate::errors::*;
+use crate::macros::bail;
 use crate::migratable::Migratable;
 use crate::migrator::Direction;
-use crate::DT_FORMAT;
+use crate::script;
+use crate::DbKind;
 
 /// SQL comment directive that opts a single migration direction out of the
 /// migrator's automatic transaction wrapping.
@@ -24,31 +25,251 @@ use crate::DT_FORMAT;
 /// ```
 pub(crate) const NO_TRANSACTION_DIRECTIVE: &str = "migrant:no-transaction";
 
-/// Return `true` if `sql` carries the [`NO_TRANSACTION_DIRECTIVE`] on a comment
-/// line. It is matched case-insensitively as the first token of a `--` line
-/// comment, so a trailing explanation is allowed
+/// Section markers splitting a single-file migration into its `up` and `down`
+/// SQL, each on its own `--` comment line:
+///
+/// ```sql
+/// -- migrate:up
+/// create table users (id integer);
+///
+/// -- migrate:down
+/// drop table users;
+/// ```
+pub(crate) const UP_SECTION_MARKER: &str = "migrate:up";
+pub(crate) const DOWN_SECTION_MARKER: &str = "migrate:down";
+
+/// Comment headers describing a migration, each on its own `--` comment line
+/// before its SQL, see [`MigrationMetadata`]:
+///
+/// ```sql
+/// -- migrant:description Add an email column to users
+/// -- migrant:author alice
+/// -- migrant:ticket APP-142
+/// alter table users add column email text;
+/// ```
+pub(crate) const DESCRIPTION_DIRECTIVE: &str = "migrant:description";
+pub(crate) const AUTHOR_DIRECTIVE: &str = "migrant:author";
+pub(crate) const TICKET_DIRECTIVE: &str = "migrant:ticket";
+
+/// Contents of a new single-file migration
+pub(crate) const SINGLE_FILE_TEMPLATE: &str = "-- migrate:up\n\n\n-- migrate:down\n\n";
+
+/// Return `true` if `line` is a `--` comment whose first token is `directive`,
+/// matched case-insensitively so a trailing explanation is allowed
 /// (`-- migrant:no-transaction (enum add)`).
+fn is_comment_directive(line: &str, directive: &str) -> bool {
+    comment_directive_value(line, directive).is_some()
+}
+
+/// The text following `directive` when `line` is a `--` comment whose first
+/// token is `directive`, see `is_comment_directive`
+fn comment_directive_value<'a>(line: &'a str, directive: &str) -> Option<&'a str> {
+    let rest = line.trim().strip_prefix("--")?.trim_start();
+    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    rest[..end]
+        .eq_ignore_ascii_case(directive)
+        .then(|| rest[end..].trim())
+}
+
+/// Descriptive metadata of a migration: a human description, its author and
+/// a ticket reference
+///
+/// SQL migrations declare it with `-- migrant:description`, `-- migrant:author`
+/// and `-- migrant:ticket` comment headers among the comments opening their
+/// SQL (or, for a single-file migration, opening the file). A repeated
+/// description header continues the description, other repeated headers list
+/// several values. Function migrations set it with `with_metadata`. See
+/// [`Migratable::metadata`] and [`MigrationStatus::metadata`](crate::MigrationStatus::metadata).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MigrationMetadata {
+    description: Option<String>,
+    author: Option<String>,
+    ticket: Option<String>,
+}
+
+impl MigrationMetadata {
+    /// Create empty metadata
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the human description
+    pub fn with_description<T: Into<String>>(mut self, description: T) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Set the author
+    pub fn with_author<T: Into<String>>(mut self, author: T) -> Self {
+        self.author = Some(author.into());
+        self
+    }
+
+    /// Set the ticket reference
+    pub fn with_ticket<T: Into<String>>(mut self, ticket: T) -> Self {
+        self.ticket = Some(ticket.into());
+        self
+    }
+
+    /// The human description, if any
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    /// The author, if any
+    pub fn author(&self) -> Option<&str> {
+        self.author.as_deref()
+    }
+
+    /// The ticket reference, if any
+    pub fn ticket(&self) -> Option<&str> {
+        self.ticket.as_deref()
+    }
+
+    /// Whether no metadata is set
+    pub fn is_empty(&self) -> bool {
+        self.description.is_none() && self.author.is_none() && self.ticket.is_none()
+    }
+
+    /// Parse the metadata headers among the comment and blank lines opening `sql`
+    pub(crate) fn from_sql(sql: &str) -> Self {
+        let mut metadata = Self::default();
+        let headers = sql.lines().take_while(|line| {
+            let line = line.trim();
+            line.is_empty() || line.starts_with("--")
+        });
+        for line in headers {
+            let (field, separator, value) =
+                if let Some(value) = comment_directive_value(line, DESCRIPTION_DIRECTIVE) {
+                    (&mut metadata.description, " ", value)
+                } else if let Some(value) = comment_directive_value(line, AUTHOR_DIRECTIVE) {
+                    (&mut metadata.author, ", ", value)
+                } else if let Some(value) = comment_directive_value(line, TICKET_DIRECTIVE) {
+                    (&mut metadata.ticket, ", ", value)
+                } else {
+                    continue;
+                };
+            if value.is_empty() {
+                continue;
+            }
+            match field {
+                Some(field) => {
+                    field.push_str(separator);
+                    field.push_str(value);
+                }
+                None => *field = Some(value.to_string()),
+            }
+        }
+        metadata
+    }
+
+    /// Fill fields missing here from `other`
+    pub(crate) fn or(self, other: Self) -> Self {
+        Self {
+            description: self.description.or(other.description),
+            author: self.author.or(other.author),
+            ticket: self.ticket.or(other.ticket),
+        }
+    }
+}
+
+/// Return `true` if `sql` carries the [`NO_TRANSACTION_DIRECTIVE`] on a comment
+/// line, see `is_comment_directive`.
 pub(crate) fn sql_opts_out_of_transaction(sql: &str) -> bool {
-    sql.lines().any(|line| {
-        matches!(
-            line.trim()
-                .strip_prefix("--")
-                .and_then(|rest| rest.split_whitespace().next()),
-            Some(token) if token.eq_ignore_ascii_case(NO_TRANSACTION_DIRECTIVE)
-        )
-    })
+    sql.lines()
+        .any(|line| is_comment_directive(line, NO_TRANSACTION_DIRECTIVE))
 }
 
-/// Whether the migration file at `path` declares the no-transaction directive.
-/// A missing or unreadable file is treated as not opting out; the subsequent
-/// apply surfaces any real read error.
-fn file_opts_out_of_transaction(path: &Option<PathBuf>) -> bool {
-    match path {
-        Some(p) => std::fs::read_to_string(p)
-            .map(|sql| sql_opts_out_of_transaction(&sql))
-            .unwrap_or(false),
-        None => false,
+/// Split a single-file migration into its `(up, down)` sections.
+///
+/// Both markers are required, once each, in either order; a section may be
+/// empty. Only comments may precede the first marker, and a directive there
+/// is rejected since it would not belong to either direction.
+///
+/// Lines outside a section are blanked rather than removed, so line numbers
+/// within a section's SQL match the file's.
+pub(crate) fn split_sections(sql: &str) -> Result<(String, String)> {
+    let mut up: Option<Vec<&str>> = None;
+    let mut down: Option<Vec<&str>> = None;
+    // `true` while collecting the `up` section, `false` for `down`
+    let mut in_up = None;
+    // first line of SQL before any section marker
+    let mut stray_line = None;
+    for (n, line) in sql.lines().enumerate() {
+        let section = if is_comment_directive(line, UP_SECTION_MARKER) {
+            Some((true, &mut up, UP_SECTION_MARKER))
+        } else if is_comment_directive(line, DOWN_SECTION_MARKER) {
+            Some((false, &mut down, DOWN_SECTION_MARKER))
+        } else {
+            None
+        };
+        if let Some((is_up, lines, marker)) = section {
+            if lines.is_some() {
+                bail!(
+                    Migration,
+                    "Duplicate `-- {}` section marker on line {}",
+                    marker,
+                    n + 1
+                )
+            }
+            // blank lines stand in for everything before the section
+            *lines = Some(vec![""; n + 1]);
+            let other = if is_up { &mut down } else { &mut up };
+            if let Some(other) = other {
+                other.push("");
+            }
+            in_up = Some(is_up);
+            continue;
+        }
+        let (current, other) = match in_up {
+            Some(true) => (&mut up, &mut down),
+            Some(false) => (&mut down, &mut up),
+            None => {
+                let trimmed = line.trim();
+                if is_comment_directive(line, NO_TRANSACTION_DIRECTIVE) {
+                    bail!(
+                        Migration,
+                        "`-- {}` on line {} must be inside a `-- {}` or `-- {}` section",
+                        NO_TRANSACTION_DIRECTIVE,
+                        n + 1,
+                        UP_SECTION_MARKER,
+                        DOWN_SECTION_MARKER
+                    )
+                }
+                if !trimmed.is_empty() && !trimmed.starts_with("--") {
+                    stray_line = stray_line.or(Some(n + 1));
+                }
+                continue;
+            }
+        };
+        current.as_mut().expect("in a section").push(line);
+        if let Some(other) = other {
+            other.push("");
+        }
+    }
+    let (up, down) = match (up, down) {
+        (Some(up), Some(down)) => (up, down),
+        (None, _) => bail!(
+            Migration,
+            "Missing `-- {}` section marker",
+            UP_SECTION_MARKER
+        ),
+        (_, None) => bail!(
+            Migration,
+            "Missing `-- {}` section marker",
+            DOWN_SECTION_MARKER
+        ),
+    };
+    if let Some(line) = stray_line {
+        bail!(
+            Migration,
+            "SQL on line {} comes before the first `-- {}` / `-- {}` section marker",
+            line,
+            UP_SECTION_MARKER,
+            DOWN_SECTION_MARKER
+        )
     }
+    Ok((up.join("\n"), down.join("\n")))
 }
 
 /// Define a migration that uses SQL statements saved in files.
@@ -66,13 +287,24 @@ fn file_opts_out_of_transaction(path: &Option<PathBuf>) -> bool {
 /// or call [`no_transaction`](FileMigration::no_transaction) to opt out both
 /// directions. A file directive takes precedence over the builder flag, so it
 /// works for migrations discovered from disk by the `migrant` CLI.
+///
+/// A migration can also keep both directions in one file, split by
+/// `-- migrate:up` / `-- migrate:down` section markers; see
+/// [`single_file`](FileMigration::single_file).
 #[derive(Clone, Debug)]
 pub struct FileMigration {
     pub(crate) tag: String,
     pub(crate) up: Option<PathBuf>,
     pub(crate) down: Option<PathBuf>,
-    pub(crate) stamp: Option<DateTime<Utc>>,
+    /// The version prefix of a migration found on disk
+    pub(crate) version: Option<String>,
     pub(crate) no_transaction: bool,
+    /// `up` and `down` are the same file, split into sections
+    pub(crate) single_file: bool,
+    /// `up` is an executable script rather than SQL
+    pub(crate) up_script: bool,
+    /// `down` is an executable script rather than SQL
+    pub(crate) down_script: bool,
 }
 
 impl FileMigration {
@@ -82,8 +314,11 @@ impl FileMigration {
             tag: tag.to_owned(),
             up: None,
             down: None,
-            stamp: None,
+            version: None,
             no_transaction: false,
+            single_file: false,
+            up_script: false,
+            down_script: false,
         }
     }
 
@@ -109,6 +344,7 @@ impl FileMigration {
     /// the directory from which the program is run.
     pub fn up<T: AsRef<Path>>(mut self, up_file: T) -> Self {
         self.up = Some(up_file.as_ref().to_owned());
+        self.up_script = false;
         self
     }
 
@@ -120,6 +356,50 @@ impl FileMigration {
     /// the directory from which the program is run.
     pub fn down<T: AsRef<Path>>(mut self, down_file: T) -> Self {
         self.down = Some(down_file.as_ref().to_owned());
+        self.down_script = false;
+        self
+    }
+
+    /// Define an executable script to run for `up` migrations instead of SQL.
+    ///
+    /// The script runs in its own directory: directly if it is executable,
+    /// otherwise with `sh`. Connection details are passed in environment
+    /// variables, never as arguments: `MIGRANT_DATABASE_TYPE`, then
+    /// `MIGRANT_DATABASE_PATH` for sqlite, or `MIGRANT_DATABASE_URL` (without
+    /// the password), `MIGRANT_DATABASE_HOST`, `_PORT`, `_NAME`, `_USER` and
+    /// `_PASSWORD` for server databases, along with `PGPASSWORD` / `MYSQL_PWD`.
+    /// `MIGRANT_TAG` and `MIGRANT_DIRECTION` name the migration being run.
+    ///
+    /// A non-zero exit status fails the migration. Scripts use their own
+    /// connection, so they never run inside the migrator's transaction.
+    pub fn up_script<T: AsRef<Path>>(mut self, script: T) -> Self {
+        self.up = Some(script.as_ref().to_owned());
+        self.up_script = true;
+        self.single_file = false;
+        self
+    }
+
+    /// Define an executable script to run for `down` migrations instead of
+    /// SQL. See [`up_script`](FileMigration::up_script).
+    pub fn down_script<T: AsRef<Path>>(mut self, script: T) -> Self {
+        self.down = Some(script.as_ref().to_owned());
+        self.down_script = true;
+        self.single_file = false;
+        self
+    }
+
+    /// Define a single file holding both directions' SQL, split into sections
+    /// by `-- migrate:up` and `-- migrate:down` marker lines. Replaces any
+    /// `up`/`down` files.
+    ///
+    /// Directives such as `-- migrant:no-transaction` apply to the section
+    /// they appear in. The same file-presence rules as `up`/`down` apply.
+    pub fn single_file<T: AsRef<Path>>(mut self, file: T) -> Self {
+        self.up = Some(file.as_ref().to_owned());
+        self.down = self.up.clone();
+        self.single_file = true;
+        self.up_script = false;
+        self.down_script = false;
         self
     }
 
@@ -128,52 +408,173 @@ impl FileMigration {
         Box::new(self)
     }
 
-    fn apply_file(
+    /// The script to run for `direction`, if that direction is a script
+    fn script(&self, direction: Direction) -> Option<&Path> {
+        match direction {
+            Direction::Up if self.up_script => self.up.as_deref(),
+            Direction::Down if self.down_script => self.down.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Run this direction: its script, or its SQL on the config's connection
+    fn apply(
+        &self,
         config: &Config,
-        file: &Option<PathBuf>,
+        direction: Direction,
     ) -> std::result::Result<(), Box<dyn std::error::Error>> {
-        if let Some(file) = file {
-            let sql = std::fs::read_to_string(file)?;
-            config.execute_sql(&sql)?;
+        match self.script(direction) {
+            Some(script) => script::run_script(config, script, &self.tag(), direction)
+                .map_err(|e| describe_error(&self.source(direction), e).into()),
+            None => apply_sql(self, config, direction, &self.source(direction)),
         }
-        Ok(())
+    }
+
+    /// The file this direction is read from, or the tag when there is none
+    fn source(&self, direction: Direction) -> String {
+        let file = match direction {
+            Direction::Up => &self.up,
+            Direction::Down => &self.down,
+        };
+        file.as_ref()
+            .map(|p| format!("{:?}", p))
+            .unwrap_or_else(|| self.tag())
+    }
+
+    /// Read from the headers of the single file, or of `up` then `down`.
+    /// Script directions and unreadable files contribute nothing.
+    fn read_metadata(&self) -> MigrationMetadata {
+        let read = |path: &Option<PathBuf>| {
+            path.as_ref()
+                .and_then(|path| std::fs::read_to_string(path).ok())
+                .map(|sql| MigrationMetadata::from_sql(&sql))
+                .unwrap_or_default()
+        };
+        if self.single_file {
+            return read(&self.up);
+        }
+        let up = if self.up_script {
+            MigrationMetadata::default()
+        } else {
+            read(&self.up)
+        };
+        let down = if self.down_script {
+            MigrationMetadata::default()
+        } else {
+            read(&self.down)
+        };
+        up.or(down)
+    }
+
+    /// Read this direction's SQL: its file, or its section of the single file
+    fn read_sql(&self, direction: Direction) -> Result<String> {
+        let file = match direction {
+            Direction::Up => &self.up,
+            Direction::Down => &self.down,
+        };
+        let sql = match file {
+            Some(file) => std::fs::read_to_string(file)?,
+            None => return Ok(String::new()),
+        };
+        if !self.single_file {
+            return Ok(sql);
+        }
+        let (up, down) = split_sections(&sql).map_err(|e| match e {
+            Error::Migration(msg) => Error::Migration(format!("{:?}: {}", file, msg)),
+            e => e,
+        })?;
+        Ok(match direction {
+            Direction::Up => up,
+            Direction::Down => down,
+        })
+    }
+}
+
+/// Run a SQL migration's statements for `direction` on the config's
+/// connection, naming `source` in any migration error
+fn apply_sql(
+    migration: &dyn Migratable,
+    config: &Config,
+    direction: Direction,
+    source: &str,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    if let Some(sql) = migration.sql(config, direction) {
+        config
+            .execute_sql(&sql?)
+            .map_err(|e| describe_error(source, e))?;
+    }
+    Ok(())
+}
+
+/// Prefix a migration error's message with where the migration came from:
+/// the file it was read from, or its tag
+pub(crate) fn describe_error(source: &str, e: Error) -> Error {
+    match e {
+        Error::Migration(msg) => Error::Migration(format!("{}: {}", source, msg)),
+        e => e,
     }
 }
 
 impl Migratable for FileMigration {
     fn apply_up(&self, config: &Config) -> std::result::Result<(), Box<dyn std::error::Error>> {
-        Self::apply_file(config, &self.up)
+        self.apply(config, Direction::Up)
     }
 
     fn apply_down(&self, config: &Config) -> std::result::Result<(), Box<dyn std::error::Error>> {
-        Self::apply_file(config, &self.down)
+        self.apply(config, Direction::Down)
+    }
+
+    fn sql(
+        &self,
+        config: &Config,
+        direction: Direction,
+    ) -> Option<std::result::Result<String, Box<dyn std::error::Error>>> {
+        if self.script(direction).is_some() {
+            return None;
+        }
+        let rendered = self
+            .read_sql(direction)
+            .and_then(|sql| config.render_sql(sql))
+            .map_err(|e| describe_error(&self.source(direction), e));
+        Some(rendered.map_err(Into::into))
     }
 
     fn tag(&self) -> String {
-        match self.stamp.as_ref() {
-            Some(dt) => format!("{}_{}", dt.format(DT_FORMAT), self.tag),
+        match self.version.as_ref() {
+            Some(version) => format!("{}_{}", version, self.tag),
             None => self.tag.to_owned(),
         }
     }
 
+    /// The `-- migrant:description` header, falling back to the file this
+    /// direction is read from
     fn description(&self, direction: &Direction) -> String {
-        let file = match direction {
-            Direction::Up => &self.up,
-            Direction::Down => &self.down,
-        };
-        file.as_ref()
-            .map(|p| format!("{:?}", p))
-            .unwrap_or_else(|| self.tag())
+        match self.read_metadata().description {
+            Some(description) => description,
+            None => self.source(*direction),
+        }
     }
 
-    fn use_transaction(&self, direction: Direction) -> bool {
-        let file = match direction {
-            Direction::Up => &self.up,
-            Direction::Down => &self.down,
-        };
-        // A directive in the migration file takes precedence over the
-        // builder-level `no_transaction` flag.
-        if file_opts_out_of_transaction(file) {
+    /// Read from the headers of the single file, or of `up` then `down`.
+    /// Script directions and unreadable files contribute nothing.
+    fn metadata(&self, _config: &Config) -> MigrationMetadata {
+        self.read_metadata()
+    }
+
+    fn use_transaction(&self, _config: &Config, direction: Direction) -> bool {
+        // A script connects on its own, outside any transaction held here
+        if self.script(direction).is_some() {
+            return false;
+        }
+        // A directive in the migration file (or its section of a single file)
+        // takes precedence over the builder-level `no_transaction` flag. A
+        // missing, unreadable or malformed file is treated as not opting out;
+        // the subsequent apply surfaces the real error.
+        let declared = self
+            .read_sql(direction)
+            .map(|sql| sql_opts_out_of_transaction(&sql))
+            .unwrap_or(false);
+        if declared {
             return false;
         }
         !self.no_transaction
@@ -228,6 +629,10 @@ pub struct EmbeddedMigration {
     pub(crate) tag: String,
     pub(crate) up: Option<Cow<'static, str>>,
     pub(crate) down: Option<Cow<'static, str>>,
+    /// Backend-specific `up` SQL, used instead of `up` for that backend
+    pub(crate) up_variants: Vec<(DbKind, Cow<'static, str>)>,
+    /// Backend-specific `down` SQL, used instead of `down` for that backend
+    pub(crate) down_variants: Vec<(DbKind, Cow<'static, str>)>,
     pub(crate) no_transaction: bool,
 }
 
@@ -238,6 +643,8 @@ impl EmbeddedMigration {
             tag: tag.to_owned(),
             up: None,
             down: None,
+            up_variants: vec![],
+            down_variants: vec![],
             no_transaction: false,
         }
     }
@@ -256,6 +663,31 @@ impl EmbeddedMigration {
         self
     }
 
+    /// Create a new `EmbeddedMigration` from a single string holding both
+    /// directions' SQL, split into sections by `-- migrate:up` and
+    /// `-- migrate:down` marker lines (the format of a single-file migration,
+    /// so it pairs with `include_str!`). Directives such as
+    /// `-- migrant:no-transaction` apply to the section they appear in.
+    ///
+    /// ```rust
+    /// # use migrant_lib::EmbeddedMigration;
+    /// # fn main() { run().unwrap(); }
+    /// # fn run() -> Result<(), Box<dyn std::error::Error>> {
+    /// EmbeddedMigration::from_sections(
+    ///     "create-places-table",
+    ///     "-- migrate:up\n\
+    ///      create table places(id integer);\n\
+    ///      -- migrate:down\n\
+    ///      drop table places;\n",
+    /// )?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_sections(tag: &str, sql: &str) -> Result<Self> {
+        let (up, down) = split_sections(sql)?;
+        Ok(Self::with_tag(tag).up(up).down(down))
+    }
+
     /// `&'static str` or `String` of statements to use for `up` migrations
     pub fn up<T: Into<Cow<'static, str>>>(mut self, stmt: T) -> Self {
         self.up = Some(stmt.into());
@@ -268,25 +700,70 @@ impl EmbeddedMigration {
         self
     }
 
+    /// Statements to use for `up` migrations on `db_kind` databases only,
+    /// taking precedence over [`up`](EmbeddedMigration::up) there.
+    ///
+    /// ```rust
+    /// # use migrant_lib::{DbKind, EmbeddedMigration};
+    /// EmbeddedMigration::with_tag("create-places-table")
+    ///     .up("create table places(id integer primary key autoincrement);")
+    ///     .up_for(DbKind::Postgres, "create table places(id serial primary key);")
+    ///     .down("drop table places;");
+    /// ```
+    pub fn up_for<T: Into<Cow<'static, str>>>(mut self, db_kind: DbKind, stmt: T) -> Self {
+        self.up_variants.retain(|(kind, _)| *kind != db_kind);
+        self.up_variants.push((db_kind, stmt.into()));
+        self
+    }
+
+    /// Statements to use for `down` migrations on `db_kind` databases only,
+    /// taking precedence over [`down`](EmbeddedMigration::down) there.
+    pub fn down_for<T: Into<Cow<'static, str>>>(mut self, db_kind: DbKind, stmt: T) -> Self {
+        self.down_variants.retain(|(kind, _)| *kind != db_kind);
+        self.down_variants.push((db_kind, stmt.into()));
+        self
+    }
+
     /// Box this migration up so it can be stored with other migrations
     pub fn boxed(self) -> Box<dyn Migratable> {
         Box::new(self)
     }
+
+    /// This direction's SQL for `db_kind`: its backend variant if defined,
+    /// otherwise the generic SQL
+    fn sql_for(&self, db_kind: DbKind, direction: Direction) -> Option<&str> {
+        let (generic, variants) = match direction {
+            Direction::Up => (&self.up, &self.up_variants),
+            Direction::Down => (&self.down, &self.down_variants),
+        };
+        variants
+            .iter()
+            .find(|(kind, _)| *kind == db_kind)
+            .map(|(_, sql)| sql)
+            .or(generic.as_ref())
+            .map(|sql| sql.as_ref())
+    }
 }
 
 impl Migratable for EmbeddedMigration {
     fn apply_up(&self, config: &Config) -> std::result::Result<(), Box<dyn std::error::Error>> {
-        if let Some(ref up) = self.up {
-            config.execute_sql(up.as_ref())?;
-        }
-        Ok(())
+        apply_sql(self, config, Direction::Up, &self.tag())
     }
 
     fn apply_down(&self, config: &Config) -> std::result::Result<(), Box<dyn std::error::Error>> {
-        if let Some(ref down) = self.down {
-            config.execute_sql(down.as_ref())?;
-        }
-        Ok(())
+        apply_sql(self, config, Direction::Down, &self.tag())
+    }
+
+    fn sql(
+        &self,
+        config: &Config,
+        direction: Direction,
+    ) -> Option<std::result::Result<String, Box<dyn std::error::Error>>> {
+        let sql = self.sql_for(config.database_type(), direction);
+        let rendered = config
+            .render_sql(sql.unwrap_or_default().to_owned())
+            .map_err(|e| describe_error(&self.tag(), e));
+        Some(rendered.map_err(Into::into))
     }
 
     fn tag(&self) -> String {
@@ -297,17 +774,23 @@ impl Migratable for EmbeddedMigration {
         self.tag()
     }
 
-    fn use_transaction(&self, direction: Direction) -> bool {
-        let sql = match direction {
-            Direction::Up => &self.up,
-            Direction::Down => &self.down,
+    /// Read from the headers of the `up` then `down` SQL for the config's
+    /// database type
+    fn metadata(&self, config: &Config) -> MigrationMetadata {
+        let read = |direction| {
+            self.sql_for(config.database_type(), direction)
+                .map(MigrationMetadata::from_sql)
+                .unwrap_or_default()
         };
+        read(Direction::Up).or(read(Direction::Down))
+    }
+
+    fn use_transaction(&self, config: &Config, direction: Direction) -> bool {
         // A directive embedded in this direction's SQL takes precedence over the
         // builder-level `no_transaction` flag.
-        let declared = match sql {
-            Some(s) => sql_opts_out_of_transaction(s),
-            None => false,
-        };
+        let declared = self
+            .sql_for(config.database_type(), direction)
+            .is_some_and(sql_opts_out_of_transaction);
         if declared {
             return false;
         }
@@ -352,12 +835,22 @@ pub struct FnMigration<T, U> {
     pub(crate) tag: String,
     pub(crate) up: Option<T>,
     pub(crate) down: Option<U>,
+    pub(crate) metadata: MigrationMetadata,
+    pub(crate) in_transaction: bool,
 }
 
 impl<T, U> FnMigration<T, U>
 where
-    T: 'static + Clone + Fn(ConnConfig) -> std::result::Result<(), Box<dyn std::error::Error>>,
-    U: 'static + Clone + Fn(ConnConfig) -> std::result::Result<(), Box<dyn std::error::Error>>,
+    T: 'static
+        + Clone
+        + Send
+        + Sync
+        + Fn(ConnConfig) -> std::result::Result<(), Box<dyn std::error::Error>>,
+    U: 'static
+        + Clone
+        + Send
+        + Sync
+        + Fn(ConnConfig) -> std::result::Result<(), Box<dyn std::error::Error>>,
 {
     /// Create a new `FnMigration` with the given tag
     pub fn with_tag(tag: &str) -> Self {
@@ -365,9 +858,34 @@ where
             tag: tag.to_owned(),
             up: None,
             down: None,
+            metadata: MigrationMetadata::default(),
+            in_transaction: false,
         }
     }
 
+    /// Describe this migration, see [`MigrationMetadata`]
+    pub fn with_metadata(mut self, metadata: MigrationMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Run both directions inside the migrator's transaction, together with
+    /// the migration's bookkeeping row.
+    ///
+    /// Only statements issued on the migrator's own connection take part in
+    /// the transaction: use `ConnConfig::sqlite_connection`,
+    /// `ConnConfig::postgres_client` or `ConnConfig::mysql_connection`, not a
+    /// connection opened from [`ConnConfig::connect_string`]. A new connection
+    /// would not see the uncommitted work and could block on its locks.
+    ///
+    /// An `AsyncMigrator` on postgres or mysql holds its transaction on the
+    /// async connection, which a synchronous function can't reach; use an
+    /// `AsyncFnMigration` there.
+    pub fn in_transaction(mut self) -> Self {
+        self.in_transaction = true;
+        self
+    }
+
     /// Function to use for `up` migrations
     ///
     /// Function must have the signature `fn(ConnConfig) -> Result<(), Box<dyn std::error::Error>>`.
@@ -392,8 +910,16 @@ where
 
 impl<T, U> Migratable for FnMigration<T, U>
 where
-    T: 'static + Clone + Fn(ConnConfig) -> std::result::Result<(), Box<dyn std::error::Error>>,
-    U: 'static + Clone + Fn(ConnConfig) -> std::result::Result<(), Box<dyn std::error::Error>>,
+    T: 'static
+        + Clone
+        + Send
+        + Sync
+        + Fn(ConnConfig) -> std::result::Result<(), Box<dyn std::error::Error>>,
+    U: 'static
+        + Clone
+        + Send
+        + Sync
+        + Fn(ConnConfig) -> std::result::Result<(), Box<dyn std::error::Error>>,
 {
     fn apply_up(&self, config: &Config) -> std::result::Result<(), Box<dyn std::error::Error>> {
         if let Some(ref up) = self.up {
@@ -417,17 +943,34 @@ where
         self.tag()
     }
 
+    fn metadata(&self, _config: &Config) -> MigrationMetadata {
+        self.metadata.clone()
+    }
+
     /// Function migrations run arbitrary code (and may open their own
-    /// connections), so the migrator cannot wrap them in a single transaction
-    /// on its connection. Always `false`.
-    fn use_transaction(&self, _direction: Direction) -> bool {
-        false
+    /// connections), so they are only wrapped in the migrator's transaction
+    /// when opted in with [`FnMigration::in_transaction`].
+    fn use_transaction(&self, _config: &Config, _direction: Direction) -> bool {
+        self.in_transaction
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::Settings;
+
+    fn config_for(db_kind: DbKind) -> Config {
+        let settings = match db_kind {
+            DbKind::Postgres => Settings::configure_postgres()
+                .database_name("db")
+                .database_user("user")
+                .database_password("pass")
+                .build(),
+            _ => Settings::configure_sqlite().memory().build(),
+        };
+        Config::with_settings(settings.unwrap())
+    }
 
     #[test]
     fn detects_no_transaction_directive() {
@@ -470,15 +1013,171 @@ mod tests {
         let m = EmbeddedMigration::with_tag("m")
             .up("-- migrant:no-transaction\nalter type mood add value 'x';")
             .down("drop type mood;");
-        assert!(!m.use_transaction(Direction::Up));
-        assert!(m.use_transaction(Direction::Down));
+        let config = config_for(DbKind::Sqlite);
+        assert!(!m.use_transaction(&config, Direction::Up));
+        assert!(m.use_transaction(&config, Direction::Down));
 
         // builder-level opt-out applies to any direction without a directive
         let m2 = EmbeddedMigration::with_tag("m2")
             .up("select 1;")
             .down("select 1;")
             .no_transaction();
-        assert!(!m2.use_transaction(Direction::Up));
-        assert!(!m2.use_transaction(Direction::Down));
+        assert!(!m2.use_transaction(&config, Direction::Up));
+        assert!(!m2.use_transaction(&config, Direction::Down));
+    }
+
+    #[test]
+    fn splits_sections_in_either_order() {
+        let (up, down) = split_sections(
+            "-- header comment\n\n-- migrate:up\ncreate table t (x integer);\n\
+             -- migrate:down\ndrop table t;\n",
+        )
+        .unwrap();
+        assert_eq!(up.trim(), "create table t (x integer);");
+        assert_eq!(down.trim(), "drop table t;");
+        // sections keep the file's line numbers
+        assert_eq!(up.lines().position(|l| l.starts_with("create")), Some(3));
+        assert_eq!(down.lines().position(|l| l.starts_with("drop")), Some(5));
+
+        let (up, down) = split_sections("-- MIGRATE:DOWN\ndrop table t;\n--migrate:up\n").unwrap();
+        assert_eq!(up.trim(), "");
+        assert_eq!(down.trim(), "drop table t;");
+    }
+
+    #[test]
+    fn rejects_malformed_sections() {
+        let cases = [
+            ("create table t (x integer);", "Missing `-- migrate:up`"),
+            ("-- migrate:up\nselect 1;", "Missing `-- migrate:down`"),
+            (
+                "-- migrate:up\n-- migrate:down\n-- migrate:up",
+                "Duplicate `-- migrate:up`",
+            ),
+            (
+                "select 1;\n-- migrate:up\n-- migrate:down",
+                "line 1 comes before",
+            ),
+            (
+                "-- migrant:no-transaction\n-- migrate:up\n-- migrate:down",
+                "must be inside",
+            ),
+        ];
+        for (sql, expected) in cases {
+            let err = split_sections(sql).unwrap_err().to_string();
+            assert!(err.contains(expected), "{:?}: {}", sql, err);
+        }
+    }
+
+    #[test]
+    fn section_directives_apply_per_direction() {
+        let m = EmbeddedMigration::from_sections(
+            "m",
+            "-- migrate:up\n-- migrant:no-transaction\nalter type mood add value 'x';\n\
+             -- migrate:down\nselect 1;\n",
+        )
+        .unwrap();
+        let config = config_for(DbKind::Sqlite);
+        assert!(!m.use_transaction(&config, Direction::Up));
+        assert!(m.use_transaction(&config, Direction::Down));
+    }
+
+    #[test]
+    fn embedded_backend_variants_take_precedence() {
+        let m = EmbeddedMigration::with_tag("m")
+            .up("create table t (id integer primary key autoincrement);")
+            .up_for(
+                DbKind::Postgres,
+                "-- migrant:no-transaction\ncreate table t (id serial);",
+            )
+            .down("drop table t;");
+        let sqlite = config_for(DbKind::Sqlite);
+        let postgres = config_for(DbKind::Postgres);
+        assert!(m
+            .sql(&sqlite, Direction::Up)
+            .unwrap()
+            .unwrap()
+            .contains("autoincrement"));
+        assert!(m
+            .sql(&postgres, Direction::Up)
+            .unwrap()
+            .unwrap()
+            .contains("serial"));
+        assert_eq!(
+            m.sql(&postgres, Direction::Down).unwrap().unwrap(),
+            "drop table t;"
+        );
+        // directives are read from the SQL that will actually run
+        assert!(m.use_transaction(&sqlite, Direction::Up));
+        assert!(!m.use_transaction(&postgres, Direction::Up));
+    }
+
+    #[test]
+    fn metadata_headers_are_parsed_from_the_opening_comments() {
+        let metadata = MigrationMetadata::from_sql(
+            "-- Users get an email\n\
+             -- migrant:description Add an email column\n\
+             -- MIGRANT:DESCRIPTION to users\n\
+             --migrant:author alice\n\
+             -- migrant:author bob\n\
+             -- migrant:ticket\n\
+             \n\
+             alter table users add column email text;\n\
+             -- migrant:ticket APP-1\n",
+        );
+        assert_eq!(metadata.description(), Some("Add an email column to users"));
+        assert_eq!(metadata.author(), Some("alice, bob"));
+        // empty, or after the SQL starts
+        assert_eq!(metadata.ticket(), None);
+        assert!(MigrationMetadata::from_sql("select 1;").is_empty());
+    }
+
+    #[test]
+    fn file_metadata_reads_up_then_down_or_the_single_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let up = dir.path().join("up.sql");
+        let down = dir.path().join("down.sql");
+        std::fs::write(&up, "-- migrant:description Create users\nselect 1;").unwrap();
+        std::fs::write(
+            &down,
+            "-- migrant:description Drop users\n-- migrant:ticket APP-2\nselect 1;",
+        )
+        .unwrap();
+        let config = config_for(DbKind::Sqlite);
+        let metadata = FileMigration::with_tag("m")
+            .up(&up)
+            .down(&down)
+            .metadata(&config);
+        assert_eq!(metadata.description(), Some("Create users"));
+        assert_eq!(metadata.ticket(), Some("APP-2"));
+
+        let single = dir.path().join("m.sql");
+        std::fs::write(
+            &single,
+            "-- migrant:author alice\n-- migrate:up\nselect 1;\n-- migrate:down\nselect 2;\n",
+        )
+        .unwrap();
+        let metadata = FileMigration::with_tag("m")
+            .single_file(&single)
+            .metadata(&config);
+        assert_eq!(metadata.author(), Some("alice"));
+        assert!(FileMigration::with_tag("missing")
+            .up(dir.path().join("nope.sql"))
+            .metadata(&config)
+            .is_empty());
+    }
+
+    #[test]
+    fn file_description_prefers_the_header_over_the_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let up = dir.path().join("up.sql");
+        let down = dir.path().join("down.sql");
+        std::fs::write(&up, "-- migrant:description Create users\nselect 1;").unwrap();
+        std::fs::write(&down, "select 1;").unwrap();
+        let migration = FileMigration::with_tag("m").up(&up).down(&down);
+        assert_eq!(migration.description(&Direction::Up), "Create users");
+        assert_eq!(migration.description(&Direction::Down), "Create users");
+
+        std::fs::write(&up, "select 1;").unwrap();
+        assert_eq!(migration.description(&Direction::Up), format!("{:?}", up));
     }
 }
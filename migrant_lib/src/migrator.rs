@@ -3,14 +3,17 @@ Migration application
 */
 use std::collections::HashSet;
 use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
+use crate::backup;
 use crate::config::Config;
 use crate::errors::*;
 use crate::macros::bail;
 use crate::migratable::Migratable;
 use crate::ops;
+use crate::tenants::TenantReport;
 use crate::util::print_flush;
-use crate::DbKind;
 
 /// Represents direction to apply migrations.
 /// `Up`   -> up.sql
@@ -73,6 +76,41 @@ impl std::str::FromStr for ForceMode {
     }
 }
 
+/// A shared flag requesting that a migration run stop early.
+///
+/// Clones share the same flag, so one clone can be handed to a [`Migrator`]
+/// with [`Migrator::cancel_on`] while another is cancelled from elsewhere --
+/// a signal handler, another thread, a shutdown hook.
+///
+/// ```rust
+/// let token = migrant_lib::CancelToken::new();
+/// let handle = token.clone();
+/// assert!(!token.is_cancelled());
+/// handle.cancel();
+/// assert!(token.is_cancelled());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    /// Create a new, un-cancelled token
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request cancellation. Idempotent.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// `true` once [`cancel`](CancelToken::cancel) has been called on any clone
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
 /// Summary of a migration run returned by [`Migrator::apply`].
 ///
 /// `tags` holds the migration tags whose bookkeeping this run changed, in the
@@ -80,18 +118,21 @@ impl std::str::FromStr for ForceMode {
 /// `Down` run the migrations reverted. A `force`d `accept-failures` run includes
 /// a tag it recorded despite the migration failing; a `skip-failures` run does
 /// not include a skipped tag. An empty report means the database was already up
-/// to date (or fully reverted) and nothing ran.
+/// to date (or fully reverted) and nothing ran -- unless the run was cancelled
+/// ([`Report::is_cancelled`]) before it got that far.
 #[derive(Debug, Clone)]
 pub struct Report {
     direction: Direction,
     tags: Vec<String>,
+    cancelled: bool,
 }
 
 impl Report {
-    fn new(direction: Direction) -> Self {
+    pub(crate) fn new(direction: Direction) -> Self {
         Self {
             direction,
             tags: Vec::new(),
+            cancelled: false,
         }
     }
 
@@ -115,10 +156,26 @@ impl Report {
     pub fn len(&self) -> usize {
         self.tags.len()
     }
+
+    /// `true` if the run stopped early because its [`CancelToken`] was
+    /// cancelled. `tags` still lists the migrations completed before that.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+
+    pub(crate) fn push(&mut self, tag: String) {
+        self.tags.push(tag);
+    }
+
+    /// Mark the run as cancelled, returning the report
+    pub(crate) fn cancelled(mut self) -> Self {
+        self.cancelled = true;
+        self
+    }
 }
 
 /// Outcome of attempting the next migration in a run.
-enum Step {
+pub(crate) enum Step {
     /// A migration's bookkeeping was changed (applied/reverted, faked, or
     /// force-recorded); carries its tag.
     Applied(String),
@@ -149,6 +206,8 @@ pub struct Migrator {
     all: bool,
     show_output: bool,
     synchronized: bool,
+    cancel: Option<CancelToken>,
+    backup: Option<String>,
 }
 
 impl Migrator {
@@ -162,6 +221,8 @@ impl Migrator {
             all: false,
             show_output: true,
             synchronized: true,
+            cancel: None,
+            backup: None,
         }
     }
 
@@ -213,8 +274,12 @@ impl Migrator {
     /// migrators -- for example several application instances booting at once --
     /// apply migrations one at a time instead of racing. The lock is released
     /// when the run finishes, and automatically by the database if the process
-    /// dies mid-run. Sqlite has no cross-process migration concurrency to guard
-    /// against, so this setting has no effect there.
+    /// dies mid-run. A sqlite file is locked through an exclusive lock on a
+    /// `<database>-migrant-lock` file next to it, which the operating system
+    /// releases if the process dies; the sqlite `lock_timeout` setting bounds
+    /// the wait. In-memory and duckdb databases can't be migrated concurrently,
+    /// so this setting has no effect there. A custom
+    /// [`MigrationDriver`](crate::MigrationDriver) is always asked for its lock.
     ///
     /// Disable it only when an outer mechanism already serializes migrations.
     pub fn synchronized(mut self, synchronized: bool) -> Self {
@@ -222,6 +287,44 @@ impl Migrator {
         self
     }
 
+    /// Stop the run early once `token` is cancelled.
+    ///
+    /// The token is checked before each migration and while waiting for the
+    /// advisory lock. A migration already running is never interrupted: it
+    /// finishes and commits (or fails and rolls back) as usual, then the run
+    /// stops, releases the lock and returns a [`Report`] marked
+    /// [`cancelled`](Report::is_cancelled). A single run therefore never leaves
+    /// a migration half-applied because of a cancellation.
+    pub fn cancel_on(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Back the sqlite database up before a run with migrations to apply, and
+    /// restore the backup if the run fails.
+    ///
+    /// `path_template` names the backup file: `{database}` is replaced with the
+    /// database's file name and `{timestamp}` with the current UTC time, e.g.
+    /// `backups/{database}.{timestamp}.bak`. A relative path is resolved like a
+    /// relative `migration_location`. The backup is taken with sqlite's online
+    /// backup API once the migration lock is held. When a run then fails, the
+    /// database is restored to its state before the run -- including undoing
+    /// migrations the run had already applied -- and the error is returned.
+    ///
+    /// Without this, the `backup_dir` sqlite setting enables backups named
+    /// `{database}.{timestamp}.bak` in that directory; `backup_keep` limits how
+    /// many are kept, and then `{timestamp}` may only appear in the template's
+    /// file name. In-memory databases and faked runs are not backed up.
+    pub fn backup_before_run<T: Into<String>>(mut self, path_template: T) -> Self {
+        self.backup = Some(path_template.into());
+        self
+    }
+
+    /// `true` if this run's cancel token has been cancelled
+    fn is_cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(CancelToken::is_cancelled)
+    }
+
     /// Apply migrations using the current configuration.
     ///
     /// Returns a [`Report`] of the migration tags whose bookkeeping this run
@@ -232,18 +335,84 @@ impl Migrator {
         self.run()
     }
 
+    /// Apply migrations to every tenant configured in the settings'
+    /// [`Tenants`](crate::Tenants), see [`Config::for_each_tenant`].
+    ///
+    /// Each tenant runs with this migrator's settings on its own derived
+    /// config, after `Config::setup` ensures its migrations table exists. A
+    /// synchronized run takes a tenant-scoped advisory lock, so tenants sharing
+    /// a database server don't serialize each other. With `show_output`, one
+    /// summary line is printed per tenant as it finishes, instead of the
+    /// per-migration progress (which would interleave across tenants).
+    ///
+    /// Returns one [`TenantReport`] per tenant, in tenant order. Failed
+    /// tenants do not make this return an error; check each report. An error
+    /// is returned only if the tenants could not be resolved. Once a
+    /// [`cancel_on`](Migrator::cancel_on) token is cancelled, running tenants
+    /// stop after their current migration and the remaining tenants report an
+    /// empty, cancelled `Report`.
+    pub fn apply_tenants(&self) -> Result<Vec<TenantReport>> {
+        self.config.for_each_tenant(|config| {
+            let res = if self.is_cancelled() {
+                // leave tenants that haven't started yet untouched
+                Ok(Report::new(self.direction).cancelled())
+            } else {
+                config.setup().and_then(|_| {
+                    Migrator {
+                        config: config.clone(),
+                        show_output: false,
+                        ..self.clone()
+                    }
+                    .run()
+                })
+            };
+            if self.show_output {
+                let tenant = config.tenant().map(|t| t.name()).unwrap_or_default();
+                match res {
+                    Ok(ref report) if report.is_cancelled() => println!(
+                        "Tenant `{}`: cancelled after {} migration(s)",
+                        tenant,
+                        report.len()
+                    ),
+                    Ok(ref report) if report.is_empty() => {
+                        println!("Tenant `{}`: nothing to apply ✓", tenant)
+                    }
+                    Ok(ref report) => println!(
+                        "Tenant `{}`: applied[{}] {} migration(s) ✓",
+                        tenant,
+                        report.direction(),
+                        report.len()
+                    ),
+                    Err(ref e) => println!("Tenant `{}`: ** Error ** {}", tenant, e),
+                }
+            }
+            res
+        })
+    }
+
     /// Apply migrations until complete (`all`) or a single one has been applied
     fn run(&self) -> Result<Report> {
         let mut config = self.config.clone();
+        let report = Report::new(self.direction);
+        if self.is_cancelled() {
+            return Ok(report.cancelled());
+        }
 
-        // For server databases, take the migration advisory lock so concurrent
-        // migrators (e.g. several app instances booting at once) serialize
-        // instead of racing. Acquire it *before* re-reading applied state so we
-        // observe any migrations a peer committed while we were waiting and
-        // don't re-run them. Sqlite has no such lock (and no cross-process
-        // concurrency), so it skips the lock.
-        let lock = if self.synchronized && config.database_type() != DbKind::Sqlite {
-            config.acquire_migration_lock()?;
+        // Take the migration lock so concurrent migrators (e.g. several app
+        // instances booting at once) serialize instead of racing. Acquire it
+        // *before* re-reading applied state so we observe any migrations a peer
+        // committed while we were waiting and don't re-run them. In-memory and
+        // duckdb databases have no peers and skip the lock.
+        let lock = if self.synchronized && config.uses_migration_lock() {
+            match self.cancel {
+                // Poll for the lock so a cancellation can end the wait
+                Some(ref token) => {
+                    if !config.acquire_migration_lock_unless_cancelled(token)? {
+                        return Ok(report.cancelled());
+                    }
+                }
+                None => config.acquire_migration_lock()?,
+            }
             Some(MigrationLock::new(&config))
         } else {
             None
@@ -262,15 +431,42 @@ impl Migrator {
         // `Config::reload` themselves before applying.
         config.refresh_applied()?;
 
+        let backup = if self.fake {
+            None
+        } else {
+            backup::before_run(&config, self.backup.as_deref(), self.direction)?
+        };
+        if let Some(ref path) = backup {
+            self.println(&format!("Backed up the database to {:?}", path));
+        }
+        match (self.run_steps(&mut config, report, lock_generation), backup) {
+            (Err(e), Some(path)) => {
+                self.println(&format!("Restoring the database from {:?}", path));
+                Err(backup::restore_after_failure(&config, &path, e))
+            }
+            (res, _) => res,
+        }
+    }
+
+    /// Apply migrations one at a time, until complete (`all`) or a single one
+    /// has been applied
+    fn run_steps(
+        &self,
+        config: &mut Config,
+        mut report: Report,
+        lock_generation: Option<u64>,
+    ) -> Result<Report> {
         // Tags that failed under `ForceMode::SkipFailures`, excluded from
         // migration selection for the remainder of this run.
         let mut skipped = HashSet::new();
-        let mut report = Report::new(self.direction);
         loop {
-            self.check_lock_still_held(&config, lock_generation)?;
-            match self.apply_next(&config, &mut skipped, lock_generation)? {
+            if self.is_cancelled() {
+                return Ok(report.cancelled());
+            }
+            self.check_lock_still_held(config, lock_generation)?;
+            match self.apply_next(config, &mut skipped, lock_generation)? {
                 Step::Applied(tag) => {
-                    report.tags.push(tag);
+                    report.push(tag);
                     if !self.all {
                         return Ok(report);
                     }
@@ -307,22 +503,26 @@ impl Migrator {
 
     /// The set of migrations being managed: either those explicitly defined
     /// on the config, or file-migrations discovered under `migration_location`
-    fn available_migrations(config: &Config) -> Result<Vec<Box<dyn Migratable>>> {
+    pub(crate) fn available_migrations(config: &Config) -> Result<Vec<Box<dyn Migratable>>> {
         Ok(match config.migrations {
             Some(ref migrations) => migrations.clone(),
             None => {
                 let location = config.migration_location()?;
-                ops::search_for_migrations(&location)?
-                    .into_iter()
-                    .map(|fm| fm.boxed())
-                    .collect()
+                ops::search_for_migrations(
+                    &location,
+                    config.database_type(),
+                    config.settings.version_scheme,
+                )?
+                .into_iter()
+                .map(|fm| fm.boxed())
+                .collect()
             }
         })
     }
 
     /// Return the next available up or down migration, excluding any tags
     /// skipped earlier in this run (`ForceMode::SkipFailures`)
-    fn next_available<'a>(
+    pub(crate) fn next_available<'a>(
         direction: Direction,
         available: &'a [Box<dyn Migratable>],
         applied: &[String],
@@ -391,7 +591,7 @@ impl Migrator {
         // Wrap the migration's SQL and its bookkeeping row in one transaction so
         // they commit or roll back together, per direction (see
         // `Migratable::use_transaction`).
-        let transactional = next.use_transaction(self.direction);
+        let transactional = next.use_transaction(config, self.direction);
         if transactional {
             config.begin_transaction()?;
         }
@@ -544,13 +744,15 @@ mod tests {
             .fake(true)
             .all(true)
             .show_output(false)
-            .synchronized(false);
+            .synchronized(false)
+            .backup_before_run("{database}.bak");
         assert_eq!(migrator.direction, Direction::Down);
         assert_eq!(migrator.force, ForceMode::AcceptFailures);
         assert!(migrator.fake);
         assert!(migrator.all);
         assert!(!migrator.show_output);
         assert!(!migrator.synchronized);
+        assert_eq!(migrator.backup.as_deref(), Some("{database}.bak"));
     }
 
     #[test]
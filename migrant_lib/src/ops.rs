@@ -1,7 +1,7 @@
 /*!
 File-based migration management operations, compatible with the `migrant` CLI tool
 */
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::ffi::OsStr;
 use std::fs;
@@ -9,7 +9,6 @@ use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::process::Command;
 
-use chrono::{NaiveDateTime, Utc};
 use log::warn;
 use walkdir::WalkDir;
 
@@ -17,10 +16,12 @@ use crate::config::{Config, DbSettings};
 use crate::errors::*;
 use crate::macros::{bail, err};
 use crate::migratable::Migratable;
-use crate::migration::FileMigration;
+use crate::migration::{FileMigration, MigrationMetadata, SINGLE_FILE_TEMPLATE};
 use crate::migrator::Direction;
+use crate::script;
+use crate::tags::{Version, VersionScheme};
 use crate::util::{open_file_in_fg, prompt};
-use crate::{tags, DbKind, CONFIG_FILE, DT_FORMAT};
+use crate::{tags, DbKind, CONFIG_FILE};
 
 /// Search for a `Migrant.toml` settings file in the given directory
 /// and all of its parent directories
@@ -36,89 +37,299 @@ pub fn search_for_settings_file<T: AsRef<Path>>(base: T) -> Option<PathBuf> {
     None
 }
 
-/// Search for available migrations in the given migration directory
-///
-/// Migration directories are expected to be named `<14-digit-timestamp>_<tag>`
-/// and contain `up.sql` / `down.sql` files.
-///
-/// Intended only for use with `FileMigration`s not managed directly in source
-/// with `Config::use_migrations`.
-pub(crate) fn search_for_migrations(mig_root: &Path) -> Result<Vec<FileMigration>> {
-    // collect any .sql files into a Map<parent-dir, Vec<up&down files>>
+/// A direction's files in a migration directory: the generic `up.sql` /
+/// `down.sql`, any per-backend variants such as `up.postgres.sql`, or an
+/// executable script such as `up.py`
+#[derive(Debug, Default)]
+pub(crate) struct DirectionFiles {
+    pub(crate) generic: Option<PathBuf>,
+    pub(crate) variants: Vec<(DbKind, PathBuf)>,
+    pub(crate) script: Option<PathBuf>,
+}
+
+impl DirectionFiles {
+    /// The file to run against `db_kind`: the script, its variant, or the
+    /// generic file. `true` marks a script.
+    fn resolve(&self, db_kind: DbKind) -> Option<(&PathBuf, bool)> {
+        if let Some(ref script) = self.script {
+            return Some((script, true));
+        }
+        self.variants
+            .iter()
+            .find(|(kind, _)| *kind == db_kind)
+            .map(|(_, path)| path)
+            .or(self.generic.as_ref())
+            .map(|path| (path, false))
+    }
+}
+
+/// The files making up a migration found on disk
+#[derive(Debug)]
+pub(crate) enum MigrationFiles {
+    /// A `<timestamp>_<tag>` directory
+    Directory {
+        up: DirectionFiles,
+        down: DirectionFiles,
+    },
+    /// A `<timestamp>_<tag>.sql` file split into sections
+    SingleFile(PathBuf),
+}
+
+/// A migration found on disk by `scan_migrations`
+#[derive(Debug)]
+pub(crate) struct FoundMigration {
+    /// The version prefix, as written
+    pub(crate) version: String,
+    key: Version,
+    pub(crate) tag: String,
+    pub(crate) files: MigrationFiles,
+}
+
+impl FoundMigration {
+    /// The full `<version>_<tag>` tag
+    pub(crate) fn full_tag(&self) -> String {
+        format!("{}_{}", self.version, self.tag)
+    }
+}
+
+/// Find the migrations in the given migration directory, sorted by version,
+/// with every per-backend variant file. See `search_for_migrations`.
+pub(crate) fn scan_migrations(
+    mig_root: &Path,
+    scheme: VersionScheme,
+) -> Result<Vec<FoundMigration>> {
+    // collect the files of each migration directory into a
+    // Map<parent-dir, Vec<files>>, except single-file migrations sitting
+    // directly in the root
     let mut files: HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    let mut migrations = vec![];
     for entry in WalkDir::new(mig_root).into_iter().filter_map(|e| e.ok()) {
         let path = entry.path();
-        if path.extension() != Some(OsStr::new("sql")) {
+        if !entry.file_type().is_file() {
             continue;
         }
         let Some(parent) = path.parent() else {
             continue;
         };
+        if entry.depth() == 1 {
+            if path.extension() != Some(OsStr::new("sql")) {
+                continue;
+            }
+            let full_name = path
+                .file_stem()
+                .and_then(OsStr::to_str)
+                .ok_or_else(|| err!(PathError, "Error extracting file-name from: {:?}", path))?;
+            let (version, key, tag) = scheme.split_tag(full_name)?;
+            migrations.push(FoundMigration {
+                version: version.to_owned(),
+                key,
+                tag: tag.to_owned(),
+                files: MigrationFiles::SingleFile(path.to_path_buf()),
+            });
+            continue;
+        }
         files
             .entry(parent.to_path_buf())
             .or_default()
             .push(path.to_path_buf());
     }
 
-    // transform up&down files into a Vec<FileMigration>
-    let mut migrations = vec![];
+    // sort each directory's up&down files by direction, backend and kind
     for (dir, migs) in &files {
+        let mut up = DirectionFiles::default();
+        let mut down = DirectionFiles::default();
+        // executable files, settled once it's known whether there is sql
+        let mut up_scripts = vec![];
+        let mut down_scripts = vec![];
+        // directories holding no sql files or scripts aren't migrations
+        let mut relevant = false;
+        for mig in migs {
+            let name = mig.file_name().and_then(OsStr::to_str).unwrap_or_default();
+            let (direction, extension) = match name.split_once('.') {
+                Some((direction, extension)) => (direction, Some(extension)),
+                None => (name, None),
+            };
+            let is_sql = mig.extension() == Some(OsStr::new("sql"));
+            relevant |= is_sql;
+            let (files, scripts) = match direction {
+                "up" => (&mut up, &mut up_scripts),
+                "down" => (&mut down, &mut down_scripts),
+                _ => {
+                    if is_sql {
+                        warn!("Ignoring unexpected sql file: {:?}", mig);
+                    }
+                    continue;
+                }
+            };
+            if !is_sql {
+                if !script::is_script(mig) {
+                    warn!("Ignoring migration file that is not executable: {:?}", mig);
+                    continue;
+                }
+                scripts.push(mig.clone());
+                relevant = true;
+                continue;
+            }
+            match extension
+                .and_then(|e| e.strip_suffix(".sql"))
+                .map(str::parse::<DbKind>)
+            {
+                None => files.generic = Some(mig.clone()),
+                Some(Ok(kind)) => files.variants.push((kind, mig.clone())),
+                Some(Err(_)) => warn!("Ignoring sql file for an unknown database type: {:?}", mig),
+            }
+        }
+        for (direction, files, scripts) in [
+            ("up", &mut up, up_scripts),
+            ("down", &mut down, down_scripts),
+        ] {
+            let has_sql = files.generic.is_some() || !files.variants.is_empty();
+            // next to sql files only a `.sh` script counts, anything else
+            // executable is taken for a stray copy (`up.sql~`, `up.sql.orig`)
+            let (scripts, strays): (Vec<_>, Vec<_>) = scripts
+                .into_iter()
+                .partition(|path| !has_sql || path.extension() == Some(OsStr::new("sh")));
+            for stray in strays {
+                warn!(
+                    "Ignoring executable file next to {} sql files: {:?}",
+                    direction, stray
+                );
+            }
+            let mut scripts = scripts.into_iter();
+            let Some(script) = scripts.next() else {
+                continue;
+            };
+            if let Some(other) = scripts.next() {
+                bail!(
+                    Migration,
+                    "Multiple {} scripts in migration directory {:?}: {:?} and {:?}",
+                    direction,
+                    dir,
+                    script,
+                    other
+                )
+            }
+            if has_sql {
+                bail!(
+                    Migration,
+                    "Migration directory {:?} has both a {} script {:?} and {} sql files",
+                    dir,
+                    direction,
+                    script,
+                    direction
+                )
+            }
+            files.script = Some(script);
+        }
+        if !relevant {
+            continue;
+        }
         let full_name = dir
             .file_name()
             .and_then(OsStr::to_str)
             .ok_or_else(|| err!(PathError, "Error extracting file-name from: {:?}", dir))?;
-        let (stamp, tag) = full_name.split_once('_').ok_or_else(|| {
-            err!(
+        let (version, key, tag) = scheme.split_tag(full_name)?;
+        migrations.push(FoundMigration {
+            version: version.to_owned(),
+            key,
+            tag: tag.to_owned(),
+            files: MigrationFiles::Directory { up, down },
+        });
+    }
+
+    // sort by version: chronologically for timestamps, numerically otherwise.
+    // Migrations created in the same second share a timestamp and are ordered
+    // by tag; other schemes number each migration, so a shared version is
+    // ambiguous.
+    migrations.sort_by(|a, b| a.key.cmp(&b.key).then_with(|| a.tag.cmp(&b.tag)));
+    // a directory and a single file can claim the same full tag
+    let mut seen = HashSet::new();
+    for mig in &migrations {
+        let tag = mig.full_tag();
+        if !seen.insert(tag.clone()) {
+            bail!(
                 TagError,
-                "Invalid tag format: {:?}, must follow `<timestamp>_<tag>`",
-                full_name
+                "Duplicate migration tag `{}`: defined by both a migration directory \
+                 and a single-file migration",
+                tag
             )
-        })?;
-        let stamp = NaiveDateTime::parse_from_str(stamp, DT_FORMAT)
-            .map(|dt| dt.and_utc())
-            .map_err(|_| {
-                err!(
-                    TagError,
-                    "Invalid timestamp format {:?}, on tag: {:?}, must follow `{}`",
-                    stamp,
-                    full_name,
-                    DT_FORMAT
-                )
-            })?;
-
-        let mut up = None;
-        let mut down = None;
-        for mig in migs {
-            match mig.file_stem().and_then(OsStr::to_str) {
-                Some("up") => up = Some(mig.clone()),
-                Some("down") => down = Some(mig.clone()),
-                _ => warn!("Ignoring unexpected sql file: {:?}", mig),
-            }
         }
-        if up.is_none() {
-            bail!(MigrationNotFound, "Up migration not found for tag: {}", tag)
-        }
-        if down.is_none() {
+    }
+    if scheme != VersionScheme::Timestamp {
+        if let Some(pair) = migrations
+            .windows(2)
+            .find(|pair| pair[0].key == pair[1].key)
+        {
             bail!(
-                MigrationNotFound,
-                "Down migration not found for tag: {}",
-                tag
+                TagError,
+                "Duplicate migration version: `{}` and `{}` are the same {} version",
+                pair[0].full_tag(),
+                pair[1].full_tag(),
+                scheme
             )
         }
-        migrations.push(FileMigration {
-            up,
-            down,
-            tag: tag.to_owned(),
-            stamp: Some(stamp),
-            no_transaction: false,
-        });
     }
-
-    // sort by timestamps chronologically
-    migrations.sort_by_key(|m| m.stamp);
     Ok(migrations)
 }
 
+/// Search for available migrations in the given migration directory
+///
+/// Migration directories are expected to be named `<version>_<tag>`, where the
+/// version follows `scheme` (by default a 14-digit timestamp), and contain
+/// `up.sql` / `down.sql` files. A directory may also hold per-backend variants
+/// (`up.postgres.sql`, `down.sqlite.sql`, ...): the variant for `db_kind` is
+/// used when present, falling back to `up.sql` / `down.sql`.
+/// Instead of SQL, a direction may be an executable script named `up` / `down`
+/// with any extension (or a `up.sh` / `down.sh` shell script), see
+/// [`FileMigration::up_script`]. Single-file migrations are
+/// `<version>_<tag>.sql` files directly in the migration directory,
+/// split into sections by `-- migrate:up` / `-- migrate:down` marker lines.
+///
+/// Intended only for use with `FileMigration`s not managed directly in source
+/// with `Config::use_migrations`.
+pub(crate) fn search_for_migrations(
+    mig_root: &Path,
+    db_kind: DbKind,
+    scheme: VersionScheme,
+) -> Result<Vec<FileMigration>> {
+    scan_migrations(mig_root, scheme)?
+        .into_iter()
+        .map(|found| {
+            let migration = FileMigration {
+                version: Some(found.version),
+                ..FileMigration::with_tag(&found.tag)
+            };
+            let (up, down) = match found.files {
+                MigrationFiles::SingleFile(path) => return Ok(migration.single_file(path)),
+                MigrationFiles::Directory { up, down } => (up, down),
+            };
+            let not_found = |direction: &str| {
+                err!(
+                    MigrationNotFound,
+                    "{} migration not found for tag: {}, expected `{}.sql`, `{}.{}.sql` \
+                     or an executable `{}` script",
+                    if direction == "up" { "Up" } else { "Down" },
+                    found.tag,
+                    direction,
+                    direction,
+                    db_kind,
+                    direction
+                )
+            };
+            let migration = match up.resolve(db_kind).ok_or_else(|| not_found("up"))? {
+                (up, true) => migration.up_script(up),
+                (up, false) => migration.up(up),
+            };
+            Ok(
+                match down.resolve(db_kind).ok_or_else(|| not_found("down"))? {
+                    (down, true) => migration.down_script(down),
+                    (down, false) => migration.down(down),
+                },
+            )
+        })
+        .collect()
+}
+
 /// The status of a single migration
 #[derive(Debug, Clone)]
 pub struct MigrationStatus {
@@ -126,6 +337,8 @@ pub struct MigrationStatus {
     tag: String,
     /// Whether the migration is currently applied
     applied: bool,
+    /// The migration's description, author and ticket
+    metadata: MigrationMetadata,
 }
 
 impl MigrationStatus {
@@ -138,6 +351,11 @@ impl MigrationStatus {
     pub fn applied(&self) -> bool {
         self.applied
     }
+
+    /// The migration's description, author and ticket, see [`Migratable::metadata`]
+    pub fn metadata(&self) -> &MigrationMetadata {
+        &self.metadata
+    }
 }
 
 /// Return the status of all migrations being managed: either those explicitly
@@ -147,20 +365,31 @@ impl MigrationStatus {
 /// migrations is current.
 pub fn migration_statuses(config: &Config) -> Result<Vec<MigrationStatus>> {
     let available = match config.migrations {
-        Some(ref migs) => migs.iter().map(|m| m.tag()).collect::<Vec<_>>(),
+        Some(ref migs) => migs
+            .iter()
+            .map(|m| (m.tag(), m.metadata(config)))
+            .collect::<Vec<_>>(),
         None => {
             let location = config.migration_location()?;
-            search_for_migrations(&location)?
-                .into_iter()
-                .map(|m| m.tag())
-                .collect()
+            search_for_migrations(
+                &location,
+                config.database_type(),
+                config.settings.version_scheme,
+            )?
+            .into_iter()
+            .map(|m| (m.tag(), m.metadata(config)))
+            .collect()
         }
     };
     Ok(available
         .into_iter()
-        .map(|tag| {
+        .map(|(tag, metadata)| {
             let applied = config.applied.contains(&tag);
-            MigrationStatus { tag, applied }
+            MigrationStatus {
+                tag,
+                applied,
+                metadata,
+            }
         })
         .collect())
 }
@@ -206,12 +435,40 @@ pub fn list(config: &Config) -> Result<()> {
 
 /// Create a new migration with the given tag
 ///
-/// Generated tags will follow the format `{DT-STAMP}_{TAG}`
+/// Generated tags will follow the format `{VERSION}_{TAG}`, where the version
+/// is the current timestamp or, under a sequence or semver
+/// [`VersionScheme`](crate::VersionScheme), the one after the highest
+/// existing migration's.
 ///
 /// Intended only for use when running in "migrant CLI compatibility mode"
 /// where migrations (`FileMigration`s) are all files with names following
-/// the expected timestamp formatted name.
+/// the expected versioned name.
 pub fn new(config: &Config, tag: &str) -> Result<()> {
+    let mig_dir = config
+        .migration_location()?
+        .join(new_migration_name(config, tag)?);
+    fs::create_dir_all(&mig_dir)?;
+
+    for name in ["up.sql", "down.sql"] {
+        fs::File::create(mig_dir.join(name))?;
+    }
+    Ok(())
+}
+
+/// Create a new single-file migration with the given tag
+///
+/// Like [`new`], but generates one `{VERSION}_{TAG}.sql` file holding empty
+/// `-- migrate:up` and `-- migrate:down` sections instead of a directory.
+pub fn new_single_file(config: &Config, tag: &str) -> Result<()> {
+    let location = config.migration_location()?;
+    fs::create_dir_all(&location)?;
+    let path = location.join(format!("{}.sql", new_migration_name(config, tag)?));
+    fs::write(path, SINGLE_FILE_TEMPLATE)?;
+    Ok(())
+}
+
+/// Validate `tag` and prefix it with the next version: `{VERSION}_{TAG}`
+fn new_migration_name(config: &Config, tag: &str) -> Result<String> {
     if !tags::is_valid_simple_tag(tag) {
         bail!(
             Migration,
@@ -219,16 +476,16 @@ pub fn new(config: &Config, tag: &str) -> Result<()> {
             tag
         );
     }
-    let now = Utc::now();
-    let folder = format!("{stamp}_{tag}", stamp = now.format(DT_FORMAT), tag = tag);
-
-    let mig_dir = config.migration_location()?.join(folder);
-    fs::create_dir_all(&mig_dir)?;
-
-    for name in ["up.sql", "down.sql"] {
-        fs::File::create(mig_dir.join(name))?;
-    }
-    Ok(())
+    let scheme = config.settings.version_scheme;
+    let location = config.migration_location()?;
+    // timestamps don't depend on the existing migrations
+    let existing = if scheme != VersionScheme::Timestamp && location.is_dir() {
+        scan_migrations(&location, scheme)?
+    } else {
+        vec![]
+    };
+    let version = scheme.next_version(existing.iter().map(|found| found.version.as_str()))?;
+    Ok(format!("{}_{}", version, tag))
 }
 
 /// Open a repl connection to the given `Config` settings
@@ -294,7 +551,7 @@ fn program_on_path(name: &str) -> bool {
 fn build_shell_command(config: &Config, mysqlsh_available: bool) -> Result<ShellCommandSpec> {
     match config.database_type() {
         DbKind::Sqlite => {
-            if config.settings.inner.is_memory_sqlite() {
+            if config.settings.inner.is_memory() {
                 bail!(
                     ShellCommand,
                     "shell is not supported for in-memory sqlite databases"
@@ -307,46 +564,95 @@ fn build_shell_command(config: &Config, mysqlsh_available: bool) -> Result<Shell
                 envs: vec![],
             })
         }
+        DbKind::DuckDb => {
+            if config.settings.inner.is_memory() {
+                bail!(
+                    ShellCommand,
+                    "shell is not supported for in-memory duckdb databases"
+                )
+            }
+            let db_path = config.database_path_string()?;
+            Ok(ShellCommandSpec {
+                program: "duckdb".to_string(),
+                args: vec![db_path],
+                envs: vec![],
+            })
+        }
         DbKind::Postgres => {
             let (uri, password) = connect_uri_without_password(config)?;
-            // psql reads the password from `PGPASSWORD` when present.
+            // psql reads the password from `PGPASSWORD` when present, and
+            // certificate files from `PGSSL*`.
+            let mut envs = vec![("PGPASSWORD".to_string(), password)];
+            if let DbSettings::Postgres(ref s) = config.settings.inner {
+                let mut env_path = |var: &str, path: &Path| {
+                    envs.push((var.to_string(), path.to_string_lossy().into_owned()))
+                };
+                if let Some(ref root) = s.ssl_cert_file {
+                    env_path("PGSSLROOTCERT", root);
+                }
+                // psql only reads PEM client certificates, not PKCS#12 archives
+                if let (Some(cert), Some(key)) = (&s.ssl_client_cert, &s.ssl_client_key) {
+                    env_path("PGSSLCERT", cert);
+                    env_path("PGSSLKEY", key);
+                }
+            }
             Ok(ShellCommandSpec {
                 program: "psql".to_string(),
                 args: vec![uri],
-                envs: vec![("PGPASSWORD".to_string(), password)],
+                envs,
             })
         }
         DbKind::MySql => {
             let (uri, password) = connect_uri_without_password(config)?;
+            let server = match &config.settings.inner {
+                DbSettings::MySql(s) => s,
+                _ => unreachable!("database_type checked above"),
+            };
             // Both clients honor `MYSQL_PWD` for classic-protocol password auth.
-            if mysqlsh_available {
-                Ok(ShellCommandSpec {
-                    program: "mysqlsh".to_string(),
-                    args: vec!["--sql".to_string(), "--uri".to_string(), uri],
-                    envs: vec![("MYSQL_PWD".to_string(), password)],
-                })
-            } else {
+            let envs = vec![("MYSQL_PWD".to_string(), password)];
+            // Neither client reads a `socket` URI parameter, so a socket is
+            // passed with the flags instead of the URI.
+            let user_and_db = vec![
+                "-u".to_string(),
+                server.database_user.clone(),
+                "-D".to_string(),
+                server.database_name.clone(),
+            ];
+            let args = match (server.socket(), mysqlsh_available) {
+                (None, true) => vec!["--sql".to_string(), "--uri".to_string(), uri],
+                (Some(socket), true) => {
+                    let mut args = vec!["--sql".to_string(), "-S".to_string(), socket.into()];
+                    args.extend(user_and_db);
+                    args
+                }
                 // The classic `mysql` client does not accept a URI; build its
                 // flags from the settings directly.
-                let server = match &config.settings.inner {
-                    DbSettings::MySql(s) => s,
-                    _ => unreachable!("database_type checked above"),
-                };
-                Ok(ShellCommandSpec {
-                    program: "mysql".to_string(),
-                    args: vec![
+                (None, false) => {
+                    let mut args = vec![
                         "-h".to_string(),
                         server.host_or_default(),
                         "-P".to_string(),
                         server.port_or_default("3306"),
-                        "-u".to_string(),
-                        server.database_user.clone(),
-                        "-D".to_string(),
-                        server.database_name.clone(),
-                    ],
-                    envs: vec![("MYSQL_PWD".to_string(), password)],
-                })
-            }
+                    ];
+                    args.extend(user_and_db);
+                    args
+                }
+                (Some(socket), false) => {
+                    let mut args = vec!["-S".to_string(), socket.into()];
+                    args.extend(user_and_db);
+                    args
+                }
+            };
+            let program = if mysqlsh_available {
+                "mysqlsh"
+            } else {
+                "mysql"
+            };
+            Ok(ShellCommandSpec {
+                program: program.to_string(),
+                args,
+                envs,
+            })
         }
     }
 }
@@ -357,10 +663,10 @@ fn build_shell_command(config: &Config, mysqlsh_available: bool) -> Result<Shell
 /// as a command-line argument. The password is returned separately as its raw
 /// (non-percent-encoded) value, taken straight from the settings so it never
 /// round-trips through URL encoding.
-fn connect_uri_without_password(config: &Config) -> Result<(String, String)> {
+pub(crate) fn connect_uri_without_password(config: &Config) -> Result<(String, String)> {
     let raw_password = match &config.settings.inner {
         DbSettings::Postgres(s) | DbSettings::MySql(s) => s.database_password.clone(),
-        DbSettings::Sqlite(_) => String::new(),
+        DbSettings::Sqlite(_) | DbSettings::DuckDb(_) => String::new(),
     };
     let mut url = url::Url::parse(&config.connect_string()?)?;
     url.set_password(None).map_err(|_| {
@@ -410,7 +716,11 @@ fn select_from_matches<'a>(tag: &str, matches: &'a [FileMigration]) -> Result<&'
 pub fn edit(config: &Config, tag: &str, up_down: &Direction) -> Result<()> {
     let mig_dir = config.migration_location()?;
 
-    let available = search_for_migrations(&mig_dir)?;
+    let available = search_for_migrations(
+        &mig_dir,
+        config.database_type(),
+        config.settings.version_scheme,
+    )?;
     if available.is_empty() {
         println!("No migrations found under {:?}", mig_dir);
         return Ok(());
@@ -451,6 +761,41 @@ pub fn edit(config: &Config, tag: &str, up_down: &Direction) -> Result<()> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn discovers_directory_and_single_file_migrations() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let mig_dir = root.join("20200101000000_first");
+        fs::create_dir(&mig_dir).unwrap();
+        fs::write(mig_dir.join("up.sql"), "").unwrap();
+        fs::write(mig_dir.join("down.sql"), "").unwrap();
+        fs::write(root.join("20200102000000_second.sql"), SINGLE_FILE_TEMPLATE).unwrap();
+
+        let migrations =
+            search_for_migrations(root, DbKind::Sqlite, VersionScheme::Timestamp).unwrap();
+        let tags: Vec<String> = migrations.iter().map(|m| m.tag()).collect();
+        assert_eq!(tags, ["20200101000000_first", "20200102000000_second"]);
+        assert!(!migrations[0].single_file);
+        assert!(migrations[1].single_file);
+        assert_eq!(migrations[1].up, migrations[1].down);
+    }
+
+    #[test]
+    fn duplicate_directory_and_single_file_tag_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let mig_dir = root.join("20200101000000_first");
+        fs::create_dir(&mig_dir).unwrap();
+        fs::write(mig_dir.join("up.sql"), "").unwrap();
+        fs::write(mig_dir.join("down.sql"), "").unwrap();
+        fs::write(root.join("20200101000000_first.sql"), SINGLE_FILE_TEMPLATE).unwrap();
+
+        match search_for_migrations(root, DbKind::Sqlite, VersionScheme::Timestamp) {
+            Err(Error::TagError(msg)) => assert!(msg.contains("Duplicate"), "{}", msg),
+            other => panic!("expected a duplicate TagError, got: {:?}", other),
+        }
+    }
+
     #[test]
     fn settings_file_search_walks_up() {
         let dir = tempfile::tempdir().unwrap();
@@ -471,13 +816,16 @@ mod tests {
         let status = MigrationStatus {
             tag: "20200101000000_first".to_string(),
             applied: true,
+            metadata: MigrationMetadata::new().with_author("alice"),
         };
         assert_eq!(status.tag(), "20200101000000_first");
         assert!(status.applied());
+        assert_eq!(status.metadata().author(), Some("alice"));
 
         let unapplied = MigrationStatus {
             tag: "20200102000000_second".to_string(),
             applied: false,
+            metadata: MigrationMetadata::default(),
         };
         assert_eq!(unapplied.tag(), "20200102000000_second");
         assert!(!unapplied.applied());
@@ -496,12 +844,46 @@ mod tests {
             fs::write(d.join("up.sql"), up).unwrap();
             fs::write(d.join("down.sql"), down).unwrap();
         }
-        let migs = search_for_migrations(root).unwrap();
+        let migs = search_for_migrations(root, DbKind::Sqlite, VersionScheme::Timestamp).unwrap();
         assert_eq!(2, migs.len());
         assert_eq!("20190101000000_first", migs[0].tag());
         assert_eq!("20200101000000_second", migs[1].tag());
     }
 
+    #[test]
+    fn migration_search_rejects_equal_versions() {
+        let write = |root: &Path, folder: &str| {
+            let d = root.join(folder);
+            fs::create_dir_all(&d).unwrap();
+            fs::write(d.join("up.sql"), "").unwrap();
+            fs::write(d.join("down.sql"), "").unwrap();
+        };
+        for (scheme, first, second) in [
+            (VersionScheme::Sequence, "0001_a", "1_b"),
+            (VersionScheme::Semver, "1_a", "1.0.0_b"),
+        ] {
+            let dir = tempfile::tempdir().unwrap();
+            write(dir.path(), first);
+            write(dir.path(), second);
+            let err = search_for_migrations(dir.path(), DbKind::Sqlite, scheme).unwrap_err();
+            assert!(err.is_tag_error(), "{}", err);
+            assert!(
+                err.to_string().contains("Duplicate migration version"),
+                "{}",
+                err
+            );
+        }
+
+        // migrations created in the same second are ordered by tag
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "20200101000000_b");
+        write(dir.path(), "20200101000000_a");
+        let migs =
+            search_for_migrations(dir.path(), DbKind::Sqlite, VersionScheme::Timestamp).unwrap();
+        let tags: Vec<String> = migs.iter().map(|m| m.tag()).collect();
+        assert_eq!(tags, ["20200101000000_a", "20200101000000_b"]);
+    }
+
     #[test]
     fn migration_search_requires_up_and_down() {
         let dir = tempfile::tempdir().unwrap();
@@ -509,7 +891,86 @@ mod tests {
         let d = root.join("20190101000000_first");
         fs::create_dir_all(&d).unwrap();
         fs::write(d.join("up.sql"), "select 1;").unwrap();
-        assert!(search_for_migrations(root).is_err());
+        assert!(search_for_migrations(root, DbKind::Sqlite, VersionScheme::Timestamp).is_err());
+    }
+
+    #[test]
+    fn migration_search_prefers_backend_variants() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let d = root.join("20190101000000_first");
+        fs::create_dir_all(&d).unwrap();
+        for file in ["up.sql", "up.postgres.sql", "down.sql", "down.sqlite.sql"] {
+            fs::write(d.join(file), "").unwrap();
+        }
+
+        let migs = search_for_migrations(root, DbKind::Postgres, VersionScheme::Timestamp).unwrap();
+        assert_eq!(migs[0].up, Some(d.join("up.postgres.sql")));
+        assert_eq!(migs[0].down, Some(d.join("down.sql")));
+
+        let migs = search_for_migrations(root, DbKind::Sqlite, VersionScheme::Timestamp).unwrap();
+        assert_eq!(migs[0].up, Some(d.join("up.sql")));
+        assert_eq!(migs[0].down, Some(d.join("down.sqlite.sql")));
+
+        // a variant for another backend does not stand in for the generic file
+        fs::remove_file(d.join("up.sql")).unwrap();
+        match search_for_migrations(root, DbKind::MySql, VersionScheme::Timestamp) {
+            Err(Error::MigrationNotFound(msg)) => assert!(msg.contains("up.mysql.sql"), "{}", msg),
+            other => panic!("expected MigrationNotFound, got: {:?}", other),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn migration_search_finds_executable_scripts() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let d = root.join("20190101000000_backfill");
+        fs::create_dir_all(&d).unwrap();
+        fs::write(d.join("up.py"), "").unwrap();
+        fs::set_permissions(d.join("up.py"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(d.join("down.sh"), "").unwrap();
+        fs::write(d.join("helpers.py"), "").unwrap();
+        // not executable, so not a script
+        fs::write(d.join("up.txt"), "").unwrap();
+
+        let migs = search_for_migrations(root, DbKind::Sqlite, VersionScheme::Timestamp).unwrap();
+        assert_eq!(migs.len(), 1);
+        assert_eq!(migs[0].up, Some(d.join("up.py")));
+        assert!(migs[0].up_script);
+        assert_eq!(migs[0].down, Some(d.join("down.sh")));
+        assert!(migs[0].down_script);
+
+        fs::write(d.join("down.sql"), "").unwrap();
+        match search_for_migrations(root, DbKind::Sqlite, VersionScheme::Timestamp) {
+            Err(Error::Migration(msg)) => assert!(msg.contains("both a down script"), "{}", msg),
+            other => panic!("expected Migration error, got: {:?}", other),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn migration_search_ignores_executable_backups_next_to_sql() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        let d = root.join("20190101000000_first");
+        fs::create_dir_all(&d).unwrap();
+        // as on a filesystem that marks every file executable
+        for file in ["up.sql", "up.sql.bak", "down.sql", "down.sql~"] {
+            fs::write(d.join(file), "").unwrap();
+            fs::set_permissions(d.join(file), fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        let migs = search_for_migrations(root, DbKind::Sqlite, VersionScheme::Timestamp).unwrap();
+        assert_eq!(migs.len(), 1);
+        assert_eq!(migs[0].up, Some(d.join("up.sql")));
+        assert!(!migs[0].up_script);
+        assert_eq!(migs[0].down, Some(d.join("down.sql")));
+        assert!(!migs[0].down_script);
     }
 
     // A password with characters that must be percent-encoded in a URL: `@`
@@ -574,6 +1035,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn postgres_shell_passes_certificate_files() {
+        let settings = crate::config::Settings::configure_postgres()
+            .database_name("db")
+            .database_user("user")
+            .database_password("pass")
+            .ssl_cert_file("/certs/root.pem")
+            .ssl_client_cert("/certs/client.pem")
+            .ssl_client_key("/certs/client.key")
+            .build()
+            .unwrap();
+        let spec = build_shell_command(&Config::with_settings(settings), true).unwrap();
+        for (var, path) in [
+            ("PGSSLROOTCERT", "/certs/root.pem"),
+            ("PGSSLCERT", "/certs/client.pem"),
+            ("PGSSLKEY", "/certs/client.key"),
+        ] {
+            assert!(
+                spec.envs.iter().any(|(k, v)| k == var && v == path),
+                "{} not found in {:?}",
+                var,
+                spec.envs
+            );
+        }
+    }
+
     #[test]
     fn mysqlsh_shell_keeps_password_out_of_argv() {
         let spec = build_shell_command(&mysql_config(), true).unwrap();
@@ -619,6 +1106,49 @@ mod tests {
         );
     }
 
+    #[test]
+    fn shells_connect_over_the_socket() {
+        let settings = crate::config::Settings::configure_postgres()
+            .database_name("db")
+            .database_user("user")
+            .database_password("pass")
+            .database_socket("/var/run/postgresql")
+            .build()
+            .unwrap();
+        let spec = build_shell_command(&Config::with_settings(settings), true).unwrap();
+        assert_eq!(
+            spec.args,
+            vec!["postgres://user@%2Fvar%2Frun%2Fpostgresql:5432/db"]
+        );
+
+        let settings = crate::config::Settings::configure_mysql()
+            .database_name("db")
+            .database_user("user")
+            .database_password("pass")
+            .database_socket("/run/mysqld/mysqld.sock")
+            .build()
+            .unwrap();
+        let config = Config::with_settings(settings);
+        let spec = build_shell_command(&config, true).unwrap();
+        assert_eq!(
+            spec.args,
+            vec![
+                "--sql",
+                "-S",
+                "/run/mysqld/mysqld.sock",
+                "-u",
+                "user",
+                "-D",
+                "db"
+            ]
+        );
+        let spec = build_shell_command(&config, false).unwrap();
+        assert_eq!(
+            spec.args,
+            vec!["-S", "/run/mysqld/mysqld.sock", "-u", "user", "-D", "db"]
+        );
+    }
+
     #[test]
     fn sqlite_shell_opens_database_path() {
         let settings = crate::config::Settings::configure_sqlite()
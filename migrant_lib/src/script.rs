@@ -0,0 +1,240 @@
+/*!
+Executable script migrations
+*/
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use log::debug;
+
+use crate::config::{Config, DbSettings};
+use crate::errors::*;
+use crate::macros::{bail, err};
+use crate::migrator::Direction;
+use crate::ops::connect_uri_without_password;
+use crate::DbKind;
+
+/// Lines of a failed script's output included in the migration error
+const OUTPUT_TAIL_LINES: usize = 20;
+
+/// Whether `path` can run as a script migration: an executable file, or a
+/// `.sh` file run with `sh`
+pub(crate) fn is_script(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "sh") || is_executable(path)
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> bool {
+    false
+}
+
+/// A fully-resolved script invocation, see `ops::ShellCommandSpec`.
+///
+/// The database password is carried in `envs`, never in `args`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ScriptCommandSpec {
+    program: PathBuf,
+    args: Vec<PathBuf>,
+    envs: Vec<(String, String)>,
+    dir: PathBuf,
+}
+
+/// Build the invocation of the script at `path` for migration `tag`, without
+/// running anything.
+///
+/// The script runs in its own directory. Executable scripts run directly and
+/// other `.sh` files run with `sh`. Connection details are passed in
+/// `MIGRANT_*` environment variables, plus `PGPASSWORD` / `MYSQL_PWD` so
+/// `psql` / `mysql` connect without a password argument.
+pub(crate) fn build_script_command(
+    config: &Config,
+    path: &Path,
+    tag: &str,
+    direction: Direction,
+) -> Result<ScriptCommandSpec> {
+    let path = fs::canonicalize(path)
+        .map_err(|e| err!(MigrationNotFound, "Script {:?} not found: {}", path, e))?;
+    let dir = path
+        .parent()
+        .ok_or_else(|| err!(PathError, "Script {:?} has no parent directory", path))?
+        .to_path_buf();
+    let (program, args) = if is_executable(&path) {
+        (path, vec![])
+    } else {
+        (PathBuf::from("sh"), vec![path])
+    };
+
+    let direction = match direction {
+        Direction::Up => "up",
+        Direction::Down => "down",
+    };
+    let mut envs = vec![
+        ("MIGRANT_TAG".to_string(), tag.to_string()),
+        ("MIGRANT_DIRECTION".to_string(), direction.to_string()),
+        (
+            "MIGRANT_DATABASE_TYPE".to_string(),
+            config.database_type().to_string(),
+        ),
+    ];
+    if let Some(tenant) = config.tenant() {
+        envs.push(("MIGRANT_TENANT".to_string(), tenant.name().to_string()));
+    }
+    match &config.settings.inner {
+        DbSettings::Sqlite(s) | DbSettings::DuckDb(s) => {
+            if s.is_memory() {
+                bail!(
+                    Migration,
+                    "Script migrations cannot run against an in-memory {} database",
+                    config.database_type()
+                )
+            }
+            envs.push((
+                "MIGRANT_DATABASE_PATH".to_string(),
+                config.database_path_string()?,
+            ));
+        }
+        DbSettings::Postgres(s) | DbSettings::MySql(s) => {
+            let (url, password) = connect_uri_without_password(config)?;
+            let (default_port, password_var) = match config.database_type() {
+                DbKind::Postgres => ("5432", "PGPASSWORD"),
+                _ => ("3306", "MYSQL_PWD"),
+            };
+            envs.extend([
+                ("MIGRANT_DATABASE_URL".to_string(), url),
+                ("MIGRANT_DATABASE_HOST".to_string(), s.host_or_default()),
+                (
+                    "MIGRANT_DATABASE_PORT".to_string(),
+                    s.port_or_default(default_port),
+                ),
+                ("MIGRANT_DATABASE_NAME".to_string(), s.database_name.clone()),
+                ("MIGRANT_DATABASE_USER".to_string(), s.database_user.clone()),
+                ("MIGRANT_DATABASE_PASSWORD".to_string(), password.clone()),
+                (password_var.to_string(), password),
+            ]);
+            if let Some(socket) = s.socket() {
+                envs.push(("MIGRANT_DATABASE_SOCKET".to_string(), socket.to_string()));
+            }
+        }
+    }
+    Ok(ScriptCommandSpec {
+        program,
+        args,
+        envs,
+        dir,
+    })
+}
+
+/// Run the script at `path` for migration `tag`. A non-zero exit status is a
+/// migration failure carrying the end of the script's output.
+pub(crate) fn run_script(
+    config: &Config,
+    path: &Path,
+    tag: &str,
+    direction: Direction,
+) -> Result<()> {
+    let spec = build_script_command(config, path, tag, direction)?;
+    let output = Command::new(&spec.program)
+        .args(&spec.args)
+        .envs(spec.envs.iter().map(|(k, v)| (k, v)))
+        .current_dir(&spec.dir)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| {
+            err!(
+                ShellCommand,
+                "Error running script {:?}: {}",
+                spec.args.first().unwrap_or(&spec.program),
+                e
+            )
+        })?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    debug!("script {:?} stdout:\n{}", path, stdout);
+    debug!("script {:?} stderr:\n{}", path, stderr);
+    if output.status.success() {
+        return Ok(());
+    }
+    let status = match output.status.code() {
+        Some(code) => format!("exited with status {}", code),
+        None => "was terminated by a signal".to_string(),
+    };
+    let shown = if stderr.trim().is_empty() {
+        stdout
+    } else {
+        stderr
+    };
+    let lines = shown.trim_end().lines().collect::<Vec<_>>();
+    let tail = lines[lines.len().saturating_sub(OUTPUT_TAIL_LINES)..].join("\n    | ");
+    if tail.is_empty() {
+        bail!(Migration, "script {}", status)
+    }
+    bail!(Migration, "script {}:\n    | {}", status, tail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Settings;
+
+    fn script() -> tempfile::NamedTempFile {
+        tempfile::Builder::new().suffix(".sh").tempfile().unwrap()
+    }
+
+    #[test]
+    fn server_credentials_are_passed_in_env_not_argv() {
+        let settings = Settings::configure_postgres()
+            .database_name("app")
+            .database_user("migrator")
+            .database_password("s3cret")
+            .database_host("db.internal")
+            .build()
+            .unwrap();
+        let config = Config::with_settings(settings);
+        let script = script();
+
+        let spec =
+            build_script_command(&config, script.path(), "20200101000000_x", Direction::Down)
+                .unwrap();
+        assert_eq!(spec.program, PathBuf::from("sh"));
+        assert_eq!(spec.args, [fs::canonicalize(script.path()).unwrap()]);
+        assert_eq!(
+            spec.dir,
+            fs::canonicalize(script.path().parent().unwrap()).unwrap()
+        );
+        let env = |name: &str| {
+            spec.envs
+                .iter()
+                .find(|(k, _)| k == name)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(env("MIGRANT_TAG"), Some("20200101000000_x"));
+        assert_eq!(env("MIGRANT_DIRECTION"), Some("down"));
+        assert_eq!(env("MIGRANT_DATABASE_TYPE"), Some("postgres"));
+        assert_eq!(env("MIGRANT_DATABASE_HOST"), Some("db.internal"));
+        assert_eq!(env("MIGRANT_DATABASE_PORT"), Some("5432"));
+        assert_eq!(env("MIGRANT_DATABASE_PASSWORD"), Some("s3cret"));
+        assert_eq!(env("PGPASSWORD"), Some("s3cret"));
+        let url = env("MIGRANT_DATABASE_URL").unwrap();
+        assert!(url.starts_with("postgres://migrator@"), "{}", url);
+        assert!(!url.contains("s3cret"), "{}", url);
+        assert!(spec
+            .args
+            .iter()
+            .all(|arg| !arg.to_string_lossy().contains("s3cret")));
+    }
+
+    #[test]
+    fn memory_sqlite_is_rejected() {
+        let settings = Settings::configure_sqlite().memory().build().unwrap();
+        let config = Config::with_settings(settings);
+        let script = script();
+        let err = build_script_command(&config, script.path(), "x", Direction::Up).unwrap_err();
+        assert!(err.to_string().contains("in-memory"), "{}", err);
+    }
+}
@@ -0,0 +1,623 @@
+/*!
+Dialect-aware splitting of migration SQL into statements
+*/
+use std::fmt;
+
+use crate::errors::*;
+use crate::macros::bail;
+use crate::DbKind;
+
+/// Lines of a failing statement shown in its error
+const SNIPPET_LINES: usize = 4;
+/// Characters of each snippet line shown before truncating
+const SNIPPET_LINE_WIDTH: usize = 100;
+
+/// A 1-based line and column (in characters) in a SQL source
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Position {
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+}
+
+impl Position {
+    /// The position of the byte `offset` in `sql`
+    fn of(sql: &str, offset: usize) -> Self {
+        let before = &sql[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Self {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// A single statement split out of a migration's SQL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Statement {
+    /// The statement's text, without its terminator
+    pub(crate) sql: String,
+    /// 1-based index of the statement in its source
+    pub(crate) index: usize,
+    /// Byte offset in `sql` of its first character that isn't a comment
+    body: usize,
+    /// Position of the statement's first character that isn't a comment
+    pub(crate) start: Position,
+    /// Position of the statement's last character
+    pub(crate) end: Position,
+}
+
+impl Statement {
+    /// Wrap an error from running this statement, one of `total`, with its
+    /// index, line/column range and a snippet of its text
+    pub(crate) fn failed(&self, total: usize, error: Error) -> Error {
+        let msg = match error {
+            Error::Migration(msg) => msg,
+            e => e.to_string(),
+        };
+        Error::Migration(format!(
+            "statement {} of {} (lines {}-{}) failed: {}\n{}",
+            self.index,
+            total,
+            self.start,
+            self.end,
+            msg,
+            self.snippet()
+        ))
+    }
+
+    /// The statement's first lines, indented and truncated
+    fn snippet(&self) -> String {
+        let mut snippet = Vec::new();
+        for (n, line) in self.sql[self.body..].lines().enumerate() {
+            if n == SNIPPET_LINES {
+                snippet.push("    | ...".to_string());
+                break;
+            }
+            let mut line = line.to_string();
+            if let Some((cut, _)) = line.char_indices().nth(SNIPPET_LINE_WIDTH) {
+                line.truncate(cut);
+                line.push_str("...");
+            }
+            snippet.push(format!("    | {}", line));
+        }
+        snippet.join("\n")
+    }
+}
+
+/// Split `sql` into statements as `db_kind`'s own client would.
+///
+/// Statements end at `;` outside of string literals, quoted identifiers and
+/// comments, and for each dialect:
+///
+/// - postgres: `$tag$ ... $tag$` dollar-quoted bodies, `E'...'` backslash
+///   escapes, nested `/* */` comments and `BEGIN ATOMIC ... END` bodies
+/// - mysql: backslash escapes in strings, `#` comments, `/*! */` executable
+///   comments and `DELIMITER` lines changing the terminator
+/// - sqlite: `[identifier]` quoting and `CREATE TRIGGER ... BEGIN ... END` bodies
+/// - duckdb: postgres' dollar-quoted bodies and `E'...'` strings
+///
+/// Statements consisting only of comments are dropped. An unterminated string,
+/// identifier, comment or dollar-quoted body is an error naming its position.
+pub(crate) fn split(sql: &str, db_kind: DbKind) -> Result<Vec<Statement>> {
+    Splitter {
+        sql,
+        db_kind,
+        pos: 0,
+        delimiter: ";".to_string(),
+        start: None,
+        first_significant: None,
+        words: Vec::new(),
+        block_depth: 0,
+        statements: Vec::new(),
+    }
+    .run()
+}
+
+struct Splitter<'a> {
+    sql: &'a str,
+    db_kind: DbKind,
+    /// Byte offset of the next character to read
+    pos: usize,
+    /// Current statement terminator, changed by mysql `DELIMITER`
+    delimiter: String,
+    /// Byte offset where the current statement's text starts
+    start: Option<usize>,
+    /// Byte offset of the current statement's first non-comment character
+    first_significant: Option<usize>,
+    /// The current statement's words so far, lowercased
+    words: Vec<String>,
+    /// Open `BEGIN ... END` blocks, inside which `;` does not end a statement
+    block_depth: usize,
+    statements: Vec<Statement>,
+}
+
+impl<'a> Splitter<'a> {
+    fn run(mut self) -> Result<Vec<Statement>> {
+        while let Some(c) = self.peek() {
+            if self.start.is_none() && self.db_kind == DbKind::MySql && self.at_delimiter_command()
+            {
+                self.read_delimiter_command()?;
+                continue;
+            }
+            if self.block_depth == 0 && self.rest().starts_with(self.delimiter.as_str()) {
+                self.finish_statement(self.pos);
+                self.pos += self.delimiter.len();
+                continue;
+            }
+            if c.is_whitespace() {
+                self.pos += c.len_utf8();
+                continue;
+            }
+            if self.starts_line_comment() {
+                self.mark_start(false);
+                self.skip_line();
+                continue;
+            }
+            if self.rest().starts_with("/*") {
+                // mysql runs `/*! ... */` and honors `/*+ ... */` optimizer hints
+                let executable = self.db_kind == DbKind::MySql
+                    && (self.rest().starts_with("/*!") || self.rest().starts_with("/*+"));
+                self.mark_start(executable);
+                self.skip_block_comment()?;
+                continue;
+            }
+            self.mark_start(true);
+            match c {
+                '\'' => {
+                    let backslash_escapes = self.db_kind == DbKind::MySql
+                        || (self.db_kind.is_postgres_dialect() && self.follows_escape_prefix());
+                    self.skip_quoted('\'', '\'', backslash_escapes, "string literal")?
+                }
+                // a string literal in mysql, an identifier elsewhere
+                '"' if self.db_kind == DbKind::MySql => {
+                    self.skip_quoted('"', '"', true, "string literal")?
+                }
+                '"' => self.skip_quoted('"', '"', false, "quoted identifier")?,
+                '`' if !self.db_kind.is_postgres_dialect() => {
+                    self.skip_quoted('`', '`', false, "quoted identifier")?
+                }
+                '[' if self.db_kind == DbKind::Sqlite => {
+                    self.skip_quoted('[', ']', false, "quoted identifier")?
+                }
+                '$' if self.db_kind.is_postgres_dialect() => self.skip_dollar_quoted()?,
+                c if c.is_alphabetic() || c == '_' => self.read_word(),
+                c => self.pos += c.len_utf8(),
+            }
+        }
+        self.finish_statement(self.sql.len());
+        Ok(self.statements)
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.sql[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    /// Record the current position as the start of a statement, if none has
+    /// started. `significant` is `false` for comments, which belong to the
+    /// statement's text but not to its reported position.
+    fn mark_start(&mut self, significant: bool) {
+        self.start.get_or_insert(self.pos);
+        if significant {
+            self.first_significant.get_or_insert(self.pos);
+        }
+    }
+
+    /// End the current statement at byte offset `end`
+    fn finish_statement(&mut self, end: usize) {
+        let (start, first) = match (self.start.take(), self.first_significant.take()) {
+            (Some(start), Some(first)) => (start, first),
+            // nothing but comments
+            _ => {
+                self.words.clear();
+                self.block_depth = 0;
+                return;
+            }
+        };
+        let text = self.sql[start..end].trim_end();
+        let last = start
+            + text
+                .char_indices()
+                .last()
+                .map(|(i, _)| i)
+                .unwrap_or_default();
+        self.statements.push(Statement {
+            sql: text.to_string(),
+            body: first - start,
+            index: self.statements.len() + 1,
+            start: Position::of(self.sql, first),
+            end: Position::of(self.sql, last),
+        });
+        self.words.clear();
+        self.block_depth = 0;
+    }
+
+    fn error_at(&self, offset: usize, what: &str) -> Error {
+        Error::Migration(format!(
+            "Unterminated {} starting at {}",
+            what,
+            Position::of(self.sql, offset)
+        ))
+    }
+
+    fn starts_line_comment(&self) -> bool {
+        let rest = self.rest();
+        match self.db_kind {
+            // mysql requires whitespace after `--`, and also has `#` comments
+            DbKind::MySql => {
+                rest.starts_with('#')
+                    || (rest.starts_with("--")
+                        && rest[2..].chars().next().is_none_or(char::is_whitespace))
+            }
+            _ => rest.starts_with("--"),
+        }
+    }
+
+    fn skip_line(&mut self) {
+        self.pos = match self.rest().find('\n') {
+            Some(i) => self.pos + i + 1,
+            None => self.sql.len(),
+        };
+    }
+
+    /// Skip a `/* */` comment; postgres comments nest
+    fn skip_block_comment(&mut self) -> Result<()> {
+        let open = self.pos;
+        let nests = self.db_kind == DbKind::Postgres;
+        let mut depth = 0;
+        while !self.rest().is_empty() {
+            if self.rest().starts_with("/*") && (depth == 0 || nests) {
+                depth += 1;
+                self.pos += 2;
+            } else if self.rest().starts_with("*/") {
+                depth -= 1;
+                self.pos += 2;
+                if depth == 0 {
+                    return Ok(());
+                }
+            } else {
+                self.pos += self.peek().map_or(1, char::len_utf8);
+            }
+        }
+        Err(self.error_at(open, "comment"))
+    }
+
+    /// Skip a literal or identifier from its `open` quote to its `close` quote.
+    /// A doubled `close` is an escaped quote, as is any character after a
+    /// backslash when `backslash_escapes` is set.
+    fn skip_quoted(
+        &mut self,
+        open: char,
+        close: char,
+        backslash_escapes: bool,
+        what: &str,
+    ) -> Result<()> {
+        let start = self.pos;
+        self.pos += open.len_utf8();
+        let mut chars = self.rest().char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            if backslash_escapes && c == '\\' {
+                chars.next();
+            } else if c == close {
+                if chars.peek().is_some_and(|&(_, next)| next == close) {
+                    chars.next();
+                } else {
+                    self.pos += i + close.len_utf8();
+                    return Ok(());
+                }
+            }
+        }
+        Err(self.error_at(start, what))
+    }
+
+    /// Is the quote at the current position the start of a postgres `E'...'`
+    /// escape string: directly preceded by a standalone `E`
+    fn follows_escape_prefix(&self) -> bool {
+        let before = &self.sql[..self.pos];
+        let mut prev = before.chars().rev();
+        matches!(prev.next(), Some('e' | 'E'))
+            && !prev
+                .next()
+                .is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '$')
+    }
+
+    /// Skip a postgres `$tag$ ... $tag$` body, or a lone `$` (such as a `$1`
+    /// parameter) that doesn't open one
+    fn skip_dollar_quoted(&mut self) -> Result<()> {
+        let tag = match dollar_quote_tag(self.rest()) {
+            Some(tag) => tag,
+            None => {
+                self.pos += 1;
+                return Ok(());
+            }
+        };
+        let rest = self.rest();
+        let start = self.pos;
+        match rest[tag.len()..].find(tag) {
+            Some(i) => {
+                self.pos += tag.len() + i + tag.len();
+                Ok(())
+            }
+            None => Err(self.error_at(start, "dollar-quoted string")),
+        }
+    }
+
+    /// Read a keyword or identifier, tracking the `BEGIN ... END` blocks whose
+    /// `;`s don't end the statement
+    fn read_word(&mut self) {
+        let len = self
+            .rest()
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+            .unwrap_or(self.rest().len());
+        let word = self.rest()[..len].to_lowercase();
+        self.pos += len;
+
+        let opens_block = match (self.db_kind, word.as_str()) {
+            (DbKind::Sqlite, "begin") => self.in_create_trigger(),
+            (DbKind::Postgres, "atomic") => self.words.last().is_some_and(|w| w == "begin"),
+            (_, "case") => self.block_depth > 0,
+            _ => false,
+        };
+        if opens_block {
+            self.block_depth += 1;
+        } else if word == "end" {
+            self.block_depth = self.block_depth.saturating_sub(1);
+        }
+        self.words.push(word);
+    }
+
+    /// Is the current statement a sqlite `CREATE [TEMP] TRIGGER`
+    fn in_create_trigger(&self) -> bool {
+        let words: Vec<&str> = self.words.iter().take(3).map(String::as_str).collect();
+        matches!(
+            words.as_slice(),
+            ["create", "trigger", ..] | ["create", "temp" | "temporary", "trigger"]
+        )
+    }
+
+    /// Is the current line a mysql client `DELIMITER` command
+    fn at_delimiter_command(&self) -> bool {
+        let rest = self.rest();
+        rest.get(..9)
+            .is_some_and(|word| word.eq_ignore_ascii_case("delimiter"))
+            && rest[9..].starts_with([' ', '\t'])
+    }
+
+    fn read_delimiter_command(&mut self) -> Result<()> {
+        let start = self.pos;
+        let line_end = self
+            .rest()
+            .find('\n')
+            .map_or(self.sql.len(), |i| self.pos + i);
+        let delimiter = self.sql[start + 9..line_end].trim();
+        if delimiter.is_empty() || delimiter.contains(char::is_whitespace) {
+            bail!(
+                Migration,
+                "Invalid DELIMITER command at {}, expected one delimiter",
+                Position::of(self.sql, start)
+            )
+        }
+        self.delimiter = delimiter.to_string();
+        self.pos = line_end;
+        Ok(())
+    }
+}
+
+/// Rewrite `?` parameter placeholders as postgres' numbered `$1`, `$2`, ...,
+/// leaving `?`s in strings, quoted identifiers, comments and dollar-quoted
+/// bodies alone
+#[allow(dead_code)] // only used by the postgres driver
+pub(crate) fn number_placeholders(sql: &str) -> String {
+    let mut out = String::with_capacity(sql.len());
+    let mut count = 0;
+    let mut pos = 0;
+    while let Some(c) = sql[pos..].chars().next() {
+        let rest = &sql[pos..];
+        let len = match c {
+            '\'' | '"' => rest[1..].find(c).map_or(rest.len(), |end| end + 2),
+            '-' if rest.starts_with("--") => rest.find('\n').unwrap_or(rest.len()),
+            '/' if rest.starts_with("/*") => rest[2..].find("*/").map_or(rest.len(), |end| end + 4),
+            '$' => dollar_quote_tag(rest).map_or(1, |tag| {
+                rest[tag.len()..]
+                    .find(tag)
+                    .map_or(rest.len(), |i| tag.len() + i + tag.len())
+            }),
+            '?' => {
+                count += 1;
+                out.push_str(&format!("${}", count));
+                pos += 1;
+                continue;
+            }
+            _ => c.len_utf8(),
+        };
+        out.push_str(&rest[..len]);
+        pos += len;
+    }
+    out
+}
+
+/// The `$tag$` opening a postgres dollar-quoted body at the start of `rest`
+fn dollar_quote_tag(rest: &str) -> Option<&str> {
+    let tag_len = rest[1..]
+        .char_indices()
+        .find(|&(i, c)| !(c.is_alphabetic() || c == '_' || (i > 0 && c.is_ascii_digit())));
+    match tag_len {
+        Some((len, '$')) => Some(&rest[..len + 2]),
+        _ => None,
+    }
+}
+
+impl DbKind {
+    /// DuckDB follows postgres' quoting rules
+    fn is_postgres_dialect(self) -> bool {
+        matches!(self, DbKind::Postgres | DbKind::DuckDb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(sql: &str, db_kind: DbKind) -> Vec<String> {
+        split(sql, db_kind)
+            .unwrap()
+            .into_iter()
+            .map(|s| s.sql)
+            .collect()
+    }
+
+    #[test]
+    fn splits_on_terminators_outside_quotes_and_comments() {
+        let sql = "create table t (x text); -- trailing; comment\n\
+                   insert into t values ('a;b'), ('it''s');\n\
+                   /* block; comment */\n\
+                   insert into \"odd;name\" values (1)";
+        assert_eq!(
+            texts(sql, DbKind::Sqlite),
+            [
+                "create table t (x text)",
+                "-- trailing; comment\ninsert into t values ('a;b'), ('it''s')",
+                "/* block; comment */\ninsert into \"odd;name\" values (1)",
+            ]
+        );
+        assert!(texts("-- only a comment\n;;\n", DbKind::Postgres).is_empty());
+    }
+
+    #[test]
+    fn reports_statement_positions() {
+        let sql = "select 1;\n\n  -- note\n  insert into t\n  values (2);";
+        let statements = split(sql, DbKind::Postgres).unwrap();
+        assert_eq!(statements.len(), 2);
+        let second = &statements[1];
+        assert_eq!(second.index, 2);
+        assert_eq!(second.start, Position { line: 4, column: 3 });
+        assert_eq!(
+            second.end,
+            Position {
+                line: 5,
+                column: 12
+            }
+        );
+
+        let err = second
+            .failed(2, Error::Migration("relation \"t\" does not exist".into()))
+            .to_string();
+        assert!(
+            err.contains("statement 2 of 2 (lines 4:3-5:12) failed: relation \"t\" does not exist"),
+            "{}",
+            err
+        );
+        assert!(
+            err.contains("    | insert into t\n    |   values (2)"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn duckdb_follows_postgres_quoting() {
+        let sql = "create macro m() as $$ select ';' $$;\nselect E'it\\'s;';\nselect 1;";
+        let statements = texts(sql, DbKind::DuckDb);
+        assert_eq!(statements.len(), 3, "{:#?}", statements);
+        assert_eq!(statements[1], "select E'it\\'s;'");
+    }
+
+    #[test]
+    fn postgres_dollar_quotes_escape_strings_and_atomic_bodies() {
+        let sql = "create function f() returns int as $body$ select 1; $body$ language sql;\n\
+                   do $$ begin perform 1; end $$;\n\
+                   select E'it\\'s;', $1;\n\
+                   /* outer /* inner; */ still; */ select 2;\n\
+                   create function g() returns int begin atomic select 1; select case when true then 2 end; end;";
+        let statements = texts(sql, DbKind::Postgres);
+        assert_eq!(statements.len(), 5, "{:#?}", statements);
+        assert!(statements[1].ends_with("end $$"));
+        assert_eq!(statements[2], "select E'it\\'s;', $1");
+        assert!(statements[4].ends_with("then 2 end; end"));
+    }
+
+    #[test]
+    fn mysql_delimiters_hash_comments_and_backslashes() {
+        let sql = "# setup; comment\n\
+                   insert into t values ('a\\';b');\n\
+                   DELIMITER //\n\
+                   create procedure p() begin select 1; select 2; end//\n\
+                   delimiter ;\n\
+                   select `a;b` from t;";
+        assert_eq!(
+            texts(sql, DbKind::MySql),
+            [
+                "# setup; comment\ninsert into t values ('a\\';b')",
+                "create procedure p() begin select 1; select 2; end",
+                "select `a;b` from t",
+            ]
+        );
+        assert!(split("delimiter  \nselect 1;", DbKind::MySql).is_err());
+    }
+
+    #[test]
+    fn sqlite_trigger_bodies_stay_whole() {
+        let sql = "create temp trigger tr after insert on t begin\n\
+                   update t set x = case when new.x > 0 then 1 else 0 end;\n\
+                   insert into log values (new.x);\n\
+                   end;\n\
+                   select [weird;name] from t;\n\
+                   begin; commit;";
+        let statements = texts(sql, DbKind::Sqlite);
+        assert_eq!(statements.len(), 4, "{:#?}", statements);
+        assert!(statements[0].ends_with("(new.x);\nend"));
+        assert_eq!(statements[2], "begin");
+    }
+
+    #[test]
+    fn unterminated_tokens_are_located() {
+        let cases = [
+            (
+                "select 1;\nselect 'oops",
+                DbKind::Sqlite,
+                "string literal starting at 2:8",
+            ),
+            (
+                "select $x$ body",
+                DbKind::Postgres,
+                "dollar-quoted string starting at 1:8",
+            ),
+            ("/* open", DbKind::MySql, "comment starting at 1:1"),
+            (
+                "select \"id",
+                DbKind::Postgres,
+                "quoted identifier starting at 1:8",
+            ),
+        ];
+        for (sql, db_kind, expected) in cases {
+            let err = split(sql, db_kind).unwrap_err().to_string();
+            assert!(err.contains(expected), "{:?}: {}", sql, err);
+        }
+    }
+
+    #[test]
+    fn numbers_placeholders_outside_quotes_and_comments() {
+        assert_eq!(
+            number_placeholders("update t set a = ? where b = ? and c = 'x?'"),
+            "update t set a = $1 where b = $2 and c = 'x?'"
+        );
+        assert_eq!(
+            number_placeholders("select \"?\", ? -- why?\n/* ? */ from t where x = ?"),
+            "select \"?\", $1 -- why?\n/* ? */ from t where x = $2"
+        );
+        assert_eq!(
+            number_placeholders("do $body$ select '?' $body$; select ?, $2"),
+            "do $body$ select '?' $body$; select $1, $2"
+        );
+        assert_eq!(number_placeholders("select 'é', ?"), "select 'é', $1");
+    }
+}
@@ -1,37 +1,200 @@
 /*!
-Migration tag validation
+Migration tag validation and version schemes
 */
+use std::fmt;
 
-/// A "simple" tag contains only `[a-z0-9-]` characters
-pub(crate) fn is_valid_simple_tag(tag: &str) -> bool {
-    !tag.is_empty()
-        && tag
-            .bytes()
-            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
-}
+use chrono::{NaiveDateTime, Utc};
 
-/// A "full" tag is a simple tag with a 14-digit (timestamp-style) prefix: `[0-9]{14}_[a-z0-9-]+`
+use crate::errors::*;
+use crate::macros::{bail, err};
+use crate::DT_FORMAT;
+
+/// Width of the first sequence number generated for a project
+const SEQUENCE_WIDTH: usize = 4;
+
+/// Sort key of a migration version: its numeric components, without
+/// trailing zeros so `1.0` and `1.0.0` compare equal
+pub(crate) type Version = Vec<u64>;
+
+/// How the version prefix of `<version>_<tag>` migration names is formed
 ///
-/// This is the format generated by the `migrant` CLI tool and `migrant_lib::new`
-pub(crate) fn is_valid_full_tag(tag: &str) -> bool {
-    match tag.split_once('_') {
-        Some((stamp, rest)) => {
-            stamp.len() == 14
-                && stamp.bytes().all(|b| b.is_ascii_digit())
-                && is_valid_simple_tag(rest)
+/// Configured with `version_scheme` in a settings file or
+/// [`Settings::with_version_scheme`](crate::Settings::with_version_scheme).
+/// Migrations are ordered by their version, and `migrant new` generates the
+/// version of a new migration.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum VersionScheme {
+    /// `20170812145327_<tag>`: a `YYYYMMDDHHMMSS` UTC timestamp (default).
+    /// New migrations are stamped with the current time.
+    #[default]
+    Timestamp,
+    /// `0001_<tag>`: a zero-padded sequence number. New migrations take the
+    /// number after the highest existing one.
+    Sequence,
+    /// `1.2.0_<tag>`: dot-separated numbers compared component by component.
+    /// New migrations increment the last component of the highest version.
+    Semver,
+}
+
+impl VersionScheme {
+    /// The sort key of `version`, or `None` when it doesn't follow this scheme
+    pub(crate) fn parse_version(self, version: &str) -> Option<Version> {
+        let all_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+        let mut parts = match self {
+            VersionScheme::Timestamp => {
+                if version.len() != 14 || !all_digits(version) {
+                    return None;
+                }
+                NaiveDateTime::parse_from_str(version, DT_FORMAT).ok()?;
+                vec![version.parse().ok()?]
+            }
+            VersionScheme::Sequence => {
+                if !all_digits(version) {
+                    return None;
+                }
+                vec![version.parse().ok()?]
+            }
+            VersionScheme::Semver => version
+                .split('.')
+                .map(|part| all_digits(part).then(|| part.parse().ok()).flatten())
+                .collect::<Option<Vec<u64>>>()?,
+        };
+        while parts.len() > 1 && parts.last() == Some(&0) {
+            parts.pop();
+        }
+        Some(parts)
+    }
+
+    /// Split a full `<version>_<tag>` tag into its version, the version's
+    /// sort key and the simple tag
+    pub(crate) fn split_tag(self, full_tag: &str) -> Result<(&str, Version, &str)> {
+        let Some((version, tag)) = full_tag.split_once('_') else {
+            bail!(
+                TagError,
+                "Invalid tag format: {:?}, must follow `{}`",
+                full_tag,
+                self.pattern()
+            )
+        };
+        match self.parse_version(version) {
+            Some(key) => Ok((version, key, tag)),
+            None => bail!(
+                TagError,
+                "Invalid {} version {:?}, on tag: {:?}, must follow `{}`",
+                self,
+                version,
+                full_tag,
+                self.pattern()
+            ),
+        }
+    }
+
+    /// Whether `tag` is a simple tag prefixed with a version of this scheme:
+    /// the format generated by the `migrant` CLI tool and `migrant_lib::new`
+    pub(crate) fn is_valid_full_tag(self, tag: &str) -> bool {
+        self.split_tag(tag)
+            .is_ok_and(|(_, _, tag)| is_valid_simple_tag(tag))
+    }
+
+    /// A simple tag optionally prefixed with a version of this scheme
+    pub(crate) fn is_valid_opt_versioned_tag(self, tag: &str) -> bool {
+        is_valid_simple_tag(tag) || self.is_valid_full_tag(tag)
+    }
+
+    /// The pattern full tags follow, for error messages
+    pub(crate) fn pattern(self) -> &'static str {
+        match self {
+            VersionScheme::Timestamp => "[0-9]{14}_[a-z0-9-]+",
+            VersionScheme::Sequence => "[0-9]+_[a-z0-9-]+",
+            VersionScheme::Semver => "[0-9]+(.[0-9]+)*_[a-z0-9-]+",
+        }
+    }
+
+    /// The version of a new migration, given the versions of the existing ones
+    pub(crate) fn next_version<'a, I>(self, existing: I) -> Result<String>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let latest = existing
+            .into_iter()
+            .filter_map(|version| Some((self.parse_version(version)?, version)))
+            .max();
+        let overflow = |version: &str| {
+            err!(
+                TagError,
+                "Cannot number a migration after version `{}`: the version is at its maximum",
+                version
+            )
+        };
+        Ok(match self {
+            VersionScheme::Timestamp => Utc::now().format(DT_FORMAT).to_string(),
+            VersionScheme::Sequence => match latest {
+                Some((key, version)) => format!(
+                    "{:0width$}",
+                    key[0].checked_add(1).ok_or_else(|| overflow(version))?,
+                    width = version.len().max(SEQUENCE_WIDTH)
+                ),
+                None => format!("{:0width$}", 1, width = SEQUENCE_WIDTH),
+            },
+            VersionScheme::Semver => match latest {
+                Some((_, version)) => {
+                    let mut parts = version
+                        .split('.')
+                        .map(|part| part.parse::<u64>().expect("validated version"))
+                        .collect::<Vec<_>>();
+                    let last = parts.last_mut().expect("non-empty version");
+                    *last = last.checked_add(1).ok_or_else(|| overflow(version))?;
+                    parts
+                        .iter()
+                        .map(u64::to_string)
+                        .collect::<Vec<_>>()
+                        .join(".")
+                }
+                None => "1.0.0".to_string(),
+            },
+        })
+    }
+}
+
+impl std::str::FromStr for VersionScheme {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "timestamp" => VersionScheme::Timestamp,
+            "sequence" => VersionScheme::Sequence,
+            "semver" => VersionScheme::Semver,
+            _ => bail!(
+                Config,
+                "Unknown version_scheme `{}`, expected one of: timestamp, sequence, semver",
+                s
+            ),
+        })
+    }
+}
+
+impl fmt::Display for VersionScheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VersionScheme::Timestamp => write!(f, "timestamp"),
+            VersionScheme::Sequence => write!(f, "sequence"),
+            VersionScheme::Semver => write!(f, "semver"),
         }
-        None => false,
     }
 }
 
-/// A simple tag optionally prefixed with a timestamp: `([0-9]{14}_)?[a-z0-9-]+`
-pub(crate) fn is_valid_opt_stamped_tag(tag: &str) -> bool {
-    is_valid_simple_tag(tag) || is_valid_full_tag(tag)
+/// A "simple" tag contains only `[a-z0-9-]` characters
+pub(crate) fn is_valid_simple_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use VersionScheme::*;
 
     #[test]
     fn simple_tags() {
@@ -46,21 +209,75 @@ mod tests {
 
     #[test]
     fn full_tags() {
-        assert!(is_valid_full_tag("20170812145327_initial"));
-        assert!(is_valid_full_tag("20170812145327_create-users"));
-        assert!(!is_valid_full_tag("initial"));
-        assert!(!is_valid_full_tag("2017_initial"));
-        assert!(!is_valid_full_tag("2017081214532x_initial"));
-        assert!(!is_valid_full_tag("20170812145327_"));
-        assert!(!is_valid_full_tag("20170812145327_two_parts"));
-        assert!(!is_valid_full_tag("201708121453270_initial"));
+        assert!(Timestamp.is_valid_full_tag("20170812145327_initial"));
+        assert!(Timestamp.is_valid_full_tag("20170812145327_create-users"));
+        assert!(!Timestamp.is_valid_full_tag("initial"));
+        assert!(!Timestamp.is_valid_full_tag("2017_initial"));
+        assert!(!Timestamp.is_valid_full_tag("2017081214532x_initial"));
+        assert!(!Timestamp.is_valid_full_tag("20170812145327_"));
+        assert!(!Timestamp.is_valid_full_tag("20170812145327_two_parts"));
+        assert!(!Timestamp.is_valid_full_tag("201708121453270_initial"));
     }
 
     #[test]
     fn opt_stamped_tags() {
-        assert!(is_valid_opt_stamped_tag("initial"));
-        assert!(is_valid_opt_stamped_tag("20170812145327_initial"));
-        assert!(!is_valid_opt_stamped_tag("bad_tag"));
-        assert!(!is_valid_opt_stamped_tag("Initial"));
+        assert!(Timestamp.is_valid_opt_versioned_tag("initial"));
+        assert!(Timestamp.is_valid_opt_versioned_tag("20170812145327_initial"));
+        assert!(!Timestamp.is_valid_opt_versioned_tag("bad_tag"));
+        assert!(!Timestamp.is_valid_opt_versioned_tag("Initial"));
+    }
+
+    #[test]
+    fn sequence_and_semver_tags() {
+        assert!(Sequence.is_valid_full_tag("0001_initial"));
+        assert!(Sequence.is_valid_full_tag("12_add-users"));
+        assert!(!Sequence.is_valid_full_tag("1.2_add-users"));
+        assert!(!Sequence.is_valid_full_tag("_initial"));
+        assert!(Semver.is_valid_full_tag("1.2.0_add-users"));
+        assert!(Semver.is_valid_full_tag("3_add-users"));
+        assert!(!Semver.is_valid_full_tag("1..2_add-users"));
+        assert!(!Semver.is_valid_full_tag("v1.2_add-users"));
+        assert!(!Timestamp.is_valid_full_tag("0001_initial"));
+        assert!(!Timestamp.is_valid_full_tag("20171312145327_initial"));
+    }
+
+    #[test]
+    fn versions_order_numerically() {
+        assert!(Sequence.parse_version("0010") > Sequence.parse_version("9"));
+        assert!(Semver.parse_version("1.10") > Semver.parse_version("1.9.1"));
+        assert_eq!(Semver.parse_version("1.0.0"), Semver.parse_version("1"));
+        assert!(
+            Timestamp.parse_version("20170812145327") > Timestamp.parse_version("20160812145327")
+        );
+    }
+
+    #[test]
+    fn next_versions() {
+        assert_eq!(Sequence.next_version([]).unwrap(), "0001");
+        assert_eq!(
+            Sequence.next_version(["0001", "0009", "0002"]).unwrap(),
+            "0010"
+        );
+        assert_eq!(Sequence.next_version(["9999"]).unwrap(), "10000");
+        assert_eq!(Sequence.next_version(["000041"]).unwrap(), "000042");
+        assert_eq!(Semver.next_version([]).unwrap(), "1.0.0");
+        assert_eq!(
+            Semver.next_version(["1.0.0", "1.2.9", "1.2"]).unwrap(),
+            "1.2.10"
+        );
+        assert_eq!(
+            Timestamp.next_version(["20170812145327"]).unwrap().len(),
+            14
+        );
+        assert!(Sequence.next_version(["18446744073709551615"]).is_err());
+        assert!(Semver.next_version(["1.18446744073709551615"]).is_err());
+    }
+
+    #[test]
+    fn schemes_parse_from_settings_names() {
+        for scheme in [Timestamp, Sequence, Semver] {
+            assert_eq!(scheme.to_string().parse::<VersionScheme>().unwrap(), scheme);
+        }
+        assert!("numbered".parse::<VersionScheme>().is_err());
     }
 }
@@ -0,0 +1,108 @@
+/*!
+`{{ variable }}` templating of migration SQL
+*/
+use crate::errors::*;
+use crate::macros::bail;
+
+/// Is `name` a valid template variable name: `[A-Za-z_][A-Za-z0-9_]*`
+pub(crate) fn is_valid_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Render `{{ name }}` placeholders in `sql` with values from `lookup`.
+///
+/// Whitespace inside the braces is optional. A placeholder naming a variable
+/// `lookup` does not define, an unterminated `{{`, or an invalid variable name
+/// is an error naming the offending line.
+pub(crate) fn render<'a>(sql: &str, lookup: impl Fn(&str) -> Option<&'a str>) -> Result<String> {
+    let mut out = String::with_capacity(sql.len());
+    let mut rest = sql;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let line = line_of(sql, sql.len() - rest.len() + start);
+        let after = &rest[start + 2..];
+        let end = match after.find("}}") {
+            Some(end) => end,
+            None => bail!(
+                Migration,
+                "Unterminated `{{{{` template placeholder on line {}",
+                line
+            ),
+        };
+        let name = after[..end].trim();
+        if !is_valid_variable_name(name) {
+            bail!(
+                Migration,
+                "Invalid template variable name `{}` on line {}",
+                name,
+                line
+            )
+        }
+        match lookup(name) {
+            Some(value) => out.push_str(value),
+            None => bail!(
+                Migration,
+                "Undefined template variable `{}` on line {}",
+                name,
+                line
+            ),
+        }
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// 1-based line number of the byte `offset` in `s`
+fn line_of(s: &str, offset: usize) -> usize {
+    s[..offset].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(name: &str) -> Option<&'static str> {
+        match name {
+            "app_role" => Some("app_rw"),
+            "schema" => Some("tenant_a"),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn renders_placeholders_with_optional_whitespace() {
+        let sql = "grant select on {{schema}}.users to {{ app_role }};\n";
+        assert_eq!(
+            render(sql, vars).unwrap(),
+            "grant select on tenant_a.users to app_rw;\n"
+        );
+        assert_eq!(render("select 1;", vars).unwrap(), "select 1;");
+        assert_eq!(render("select '}}';", vars).unwrap(), "select '}}';");
+    }
+
+    #[test]
+    fn rejects_undefined_unterminated_and_invalid_placeholders() {
+        let cases = [
+            (
+                "select 1;\nselect {{ nope }};",
+                "Undefined template variable `nope` on line 2",
+            ),
+            (
+                "select {{ app_role ;",
+                "Unterminated `{{` template placeholder on line 1",
+            ),
+            (
+                "select {{ app-role }};",
+                "Invalid template variable name `app-role`",
+            ),
+            ("select {{}};", "Invalid template variable name ``"),
+        ];
+        for (sql, expected) in cases {
+            let err = render(sql, vars).unwrap_err().to_string();
+            assert!(err.contains(expected), "{:?}: {}", sql, err);
+        }
+    }
+}
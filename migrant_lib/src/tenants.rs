@@ -0,0 +1,529 @@
+/*!
+Multi-tenant fan-out
+
+A settings file (or `Settings`) can list a set of tenant targets that share
+one migration set: schemas on postgres, databases on mysql, or database files
+on sqlite. Each tenant is migrated through its own derived [`Config`], with
+bounded parallelism and a configurable failure policy.
+*/
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::thread;
+
+use serde::Deserialize;
+
+use crate::config::{Config, DbSettings};
+use crate::errors::*;
+use crate::macros::{bail, err};
+
+/// Default number of tenants migrated concurrently
+const DEFAULT_PARALLELISM: usize = 4;
+
+/// Where the set of tenants comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TenantSource {
+    /// An explicit list of schema names (postgres), database names (mysql),
+    /// or database file paths (sqlite)
+    List(Vec<String>),
+    /// A query, run against the configured database, whose first column
+    /// enumerates the tenant schemas (postgres) or databases (mysql)
+    Query(String),
+    /// A glob of sqlite database files, e.g. `tenants/*.db`. Wildcards
+    /// (`*`, `?`) are only supported in the file name.
+    SqliteGlob(String),
+}
+
+/// How a fan-out run handles a tenant that fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[non_exhaustive]
+pub enum OnTenantFailure {
+    /// Keep migrating the remaining tenants (default)
+    #[default]
+    Continue,
+    /// Start no further tenants. Tenants already running are finished.
+    Stop,
+}
+
+impl fmt::Display for OnTenantFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OnTenantFailure::Continue => write!(f, "continue"),
+            OnTenantFailure::Stop => write!(f, "stop"),
+        }
+    }
+}
+
+impl std::str::FromStr for OnTenantFailure {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "continue" => OnTenantFailure::Continue,
+            "stop" => OnTenantFailure::Stop,
+            _ => bail!(
+                Config,
+                "Invalid tenant failure policy: `{}`. Expected one of: continue, stop",
+                s
+            ),
+        })
+    }
+}
+
+/// Multi-tenant settings: the tenant source, concurrency and failure policy
+///
+/// ```rust,no_run
+/// # fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// use migrant_lib::{OnTenantFailure, Settings, Tenants};
+///
+/// let settings = Settings::configure_postgres()
+///     .database_name("app")
+///     .database_user("app")
+///     .database_password("pass")
+///     .build()?
+///     .with_tenants(
+///         Tenants::query("select nspname from pg_namespace where nspname like 'tenant_%'")
+///             .parallelism(8)
+///             .on_failure(OnTenantFailure::Stop),
+///     );
+/// # let _ = settings;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tenants {
+    pub(crate) source: TenantSource,
+    pub(crate) parallelism: usize,
+    pub(crate) on_failure: OnTenantFailure,
+}
+
+impl Tenants {
+    fn with_source(source: TenantSource) -> Self {
+        Self {
+            source,
+            parallelism: DEFAULT_PARALLELISM,
+            on_failure: OnTenantFailure::default(),
+        }
+    }
+
+    /// Tenants listed explicitly: schema names (postgres), database names
+    /// (mysql), or database file paths (sqlite)
+    pub fn list<T: AsRef<str>>(targets: &[T]) -> Self {
+        Self::with_source(TenantSource::List(
+            targets.iter().map(|t| t.as_ref().to_owned()).collect(),
+        ))
+    }
+
+    /// Tenants enumerated by a query (postgres/mysql) returning one name per row
+    pub fn query(sql: &str) -> Self {
+        Self::with_source(TenantSource::Query(sql.to_owned()))
+    }
+
+    /// Tenants matched by a glob of sqlite database files
+    pub fn sqlite_glob(pattern: &str) -> Self {
+        Self::with_source(TenantSource::SqliteGlob(pattern.to_owned()))
+    }
+
+    /// Set the number of tenants migrated concurrently. Default is `4`.
+    /// Values below `1` are treated as `1`.
+    pub fn parallelism(mut self, n: usize) -> Self {
+        self.parallelism = n.max(1);
+        self
+    }
+
+    /// Set how a fan-out run handles a failing tenant.
+    /// Default is `OnTenantFailure::Continue`.
+    pub fn on_failure(mut self, policy: OnTenantFailure) -> Self {
+        self.on_failure = policy;
+        self
+    }
+
+    /// The configured tenant source
+    pub fn source(&self) -> &TenantSource {
+        &self.source
+    }
+}
+
+/// The `[tenants]` table of a settings file
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TenantsTable {
+    list: Option<Vec<String>>,
+    query: Option<String>,
+    sqlite_glob: Option<String>,
+    parallelism: Option<usize>,
+    on_failure: Option<String>,
+}
+
+impl TenantsTable {
+    /// Validate the table into `Tenants`, resolving `env:VAR` values with `resolve`
+    pub(crate) fn into_tenants(self, resolve: impl Fn(&str) -> Result<String>) -> Result<Tenants> {
+        let source = match (self.list, self.query, self.sqlite_glob) {
+            (Some(list), None, None) => TenantSource::List(
+                list.iter()
+                    .map(|t| resolve(t))
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+            (None, Some(query), None) => TenantSource::Query(resolve(&query)?),
+            (None, None, Some(glob)) => TenantSource::SqliteGlob(resolve(&glob)?),
+            _ => bail!(
+                Config,
+                "`[tenants]` must set exactly one of `list`, `query` or `sqlite_glob`"
+            ),
+        };
+        let mut tenants = Tenants::with_source(source);
+        if let Some(n) = self.parallelism {
+            if n == 0 {
+                bail!(Config, "`[tenants]` `parallelism` must be at least 1")
+            }
+            tenants.parallelism = n;
+        }
+        if let Some(policy) = self.on_failure {
+            tenants.on_failure = policy.parse()?;
+        }
+        Ok(tenants)
+    }
+}
+
+/// A single tenant target
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Tenant {
+    name: String,
+}
+
+impl Tenant {
+    pub(crate) fn new(name: String) -> Self {
+        Self { name }
+    }
+
+    /// The tenant's schema name (postgres), database name (mysql),
+    /// or database file path (sqlite)
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl fmt::Display for Tenant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// The result of running an operation against one tenant during a fan-out
+#[derive(Debug)]
+pub struct TenantResult<T> {
+    tenant: Tenant,
+    result: Option<Result<T>>,
+}
+
+/// The per-tenant outcome of [`Migrator::apply_tenants`](crate::Migrator::apply_tenants)
+pub type TenantReport = TenantResult<crate::Report>;
+
+impl<T> TenantResult<T> {
+    /// The tenant this result belongs to
+    pub fn tenant(&self) -> &Tenant {
+        &self.tenant
+    }
+
+    /// The operation's result, or `None` if the tenant was never started
+    /// because an earlier tenant failed under `OnTenantFailure::Stop`
+    pub fn result(&self) -> Option<&Result<T>> {
+        self.result.as_ref()
+    }
+
+    /// Consume this, returning the operation's result (see [`TenantResult::result`])
+    pub fn into_result(self) -> Option<Result<T>> {
+        self.result
+    }
+
+    /// `true` if the tenant ran and succeeded
+    pub fn is_ok(&self) -> bool {
+        matches!(self.result, Some(Ok(_)))
+    }
+
+    /// `true` if the tenant ran and failed
+    pub fn is_err(&self) -> bool {
+        matches!(self.result, Some(Err(_)))
+    }
+}
+
+/// Tenant names for server databases are interpolated into connection
+/// options, so they are restricted to a safe identifier charset
+fn check_server_tenant_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        bail!(
+            Config,
+            "Invalid tenant name `{}`: postgres/mysql tenants may only contain `[A-Za-z0-9_-]`",
+            name
+        )
+    }
+    Ok(())
+}
+
+/// Derive the database settings for a tenant from the base settings
+pub(crate) fn tenant_db_settings(
+    inner: &DbSettings,
+    tenant: &Tenant,
+    base_dir: &Path,
+) -> Result<DbSettings> {
+    Ok(match inner {
+        DbSettings::Sqlite(s) | DbSettings::DuckDb(s) => {
+            let mut s = s.clone();
+            let path = PathBuf::from(tenant.name());
+            let path = if path.is_absolute() {
+                path
+            } else {
+                base_dir.join(path)
+            };
+            s.database_path = path
+                .to_str()
+                .ok_or_else(|| err!(PathError, "Invalid utf8 path: {:?}", path))?
+                .to_owned();
+            if let DbSettings::DuckDb(_) = inner {
+                DbSettings::DuckDb(s)
+            } else {
+                DbSettings::Sqlite(s)
+            }
+        }
+        DbSettings::Postgres(s) => {
+            check_server_tenant_name(tenant.name())?;
+            let mut s = s.clone();
+            // Point the session's search_path at the tenant schema, keeping any
+            // configured `options` (a later `-c` wins over an earlier one). The
+            // space-free `-cname=value` form survives query-string encoding.
+            let search_path = format!("-csearch_path=\"{}\"", tenant.name());
+            let params = s.database_params.get_or_insert_with(BTreeMap::new);
+            let options = match params.get("options") {
+                Some(options) if !options.is_empty() => format!("{} {}", options, search_path),
+                _ => search_path,
+            };
+            params.insert("options".to_owned(), options);
+            DbSettings::Postgres(s)
+        }
+        DbSettings::MySql(s) => {
+            check_server_tenant_name(tenant.name())?;
+            let mut s = s.clone();
+            s.database_name = tenant.name().to_owned();
+            DbSettings::MySql(s)
+        }
+    })
+}
+
+/// Resolve the configured tenant source into the list of tenants
+pub(crate) fn resolve_tenants(config: &Config, tenants: &Tenants) -> Result<Vec<Tenant>> {
+    let names = match tenants.source {
+        TenantSource::List(ref names) => names.clone(),
+        TenantSource::Query(ref sql) => {
+            if config.database_type().is_embedded() {
+                bail!(
+                    Config,
+                    "A tenant `query` is only supported for postgres and mysql, use `list` or `sqlite_glob`"
+                )
+            }
+            config.query_column(sql)?
+        }
+        TenantSource::SqliteGlob(ref pattern) => {
+            if !config.database_type().is_embedded() {
+                bail!(
+                    Config,
+                    "A tenant `sqlite_glob` is only supported for sqlite and duckdb, use `list` or `query`"
+                )
+            }
+            glob_files(pattern, &config.relative_base_dir()?)?
+        }
+    };
+    let mut seen = std::collections::HashSet::with_capacity(names.len());
+    for name in &names {
+        if !seen.insert(name) {
+            bail!(Config, "Tenants must be unique. Found duplicate: {}", name)
+        }
+    }
+    Ok(names.into_iter().map(Tenant::new).collect())
+}
+
+/// Expand a glob whose wildcards (`*`, `?`) appear only in the file name.
+/// Relative patterns are matched against `base_dir`, but returned as written.
+fn glob_files(pattern: &str, base_dir: &Path) -> Result<Vec<String>> {
+    let pattern_path = Path::new(pattern);
+    let file_pattern = pattern_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| err!(PathError, "Invalid sqlite tenant glob: {:?}", pattern))?;
+    let parent = pattern_path.parent().unwrap_or_else(|| Path::new(""));
+    if parent.to_string_lossy().contains(['*', '?']) {
+        bail!(
+            Config,
+            "Sqlite tenant glob `{}` may only use wildcards in the file name",
+            pattern
+        )
+    }
+    let dir = if parent.is_absolute() {
+        parent.to_owned()
+    } else {
+        base_dir.join(parent)
+    };
+    let mut matches = vec![];
+    for entry in fs::read_dir(&dir).map_err(|e| {
+        err!(
+            PathError,
+            "Unable to read tenant directory {:?}: {}",
+            dir,
+            e
+        )
+    })? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        if wildcard_match(file_pattern, name) {
+            let path = parent.join(name);
+            matches.push(
+                path.to_str()
+                    .ok_or_else(|| err!(PathError, "Invalid utf8 path: {:?}", path))?
+                    .to_owned(),
+            );
+        }
+    }
+    matches.sort();
+    Ok(matches)
+}
+
+/// Match `name` against a pattern where `*` matches any run of characters
+/// and `?` matches exactly one
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` in the pattern, and the name position it matched up to
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_p, star_n)) => {
+                    p = star_p + 1;
+                    n = star_n + 1;
+                    star = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Run `f` against every configured tenant's derived `Config`, honoring the
+/// configured parallelism and failure policy. Results are in tenant order.
+pub(crate) fn fan_out<T, F>(config: &Config, f: F) -> Result<Vec<TenantResult<T>>>
+where
+    T: Send,
+    F: Fn(&Config) -> Result<T> + Sync,
+{
+    let settings = config
+        .settings
+        .tenants
+        .as_ref()
+        .ok_or_else(|| err!(Config, "No `[tenants]` are configured in the settings"))?;
+    let tenants = resolve_tenants(config, settings)?;
+
+    let next = AtomicUsize::new(0);
+    let stopped = AtomicBool::new(false);
+    let results: Vec<Mutex<Option<Result<T>>>> = tenants.iter().map(|_| Mutex::new(None)).collect();
+    let workers = settings.parallelism.clamp(1, tenants.len().max(1));
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                if stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                let Some(tenant) = tenants.get(i) else {
+                    break;
+                };
+                let res = config.for_tenant(tenant).and_then(|c| f(&c));
+                if res.is_err() && settings.on_failure == OnTenantFailure::Stop {
+                    stopped.store(true, Ordering::SeqCst);
+                }
+                *results[i].lock().unwrap_or_else(PoisonError::into_inner) = Some(res);
+            });
+        }
+    });
+
+    Ok(tenants
+        .into_iter()
+        .zip(results)
+        .map(|(tenant, result)| TenantResult {
+            tenant,
+            result: result.into_inner().unwrap_or_else(PoisonError::into_inner),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcard_matching() {
+        assert!(wildcard_match("*.db", "acme.db"));
+        assert!(wildcard_match("tenant_?.db", "tenant_a.db"));
+        assert!(wildcard_match("*", "anything"));
+        assert!(wildcard_match("a*b*c", "aXXbYYc"));
+        assert!(!wildcard_match("*.db", "acme.db-journal"));
+        assert!(!wildcard_match("tenant_?.db", "tenant_ab.db"));
+        assert!(!wildcard_match("a*b", "ac"));
+    }
+
+    #[test]
+    fn tenants_table_requires_exactly_one_source() {
+        let table: TenantsTable = toml::from_str("parallelism = 2").unwrap();
+        assert!(table.into_tenants(|s| Ok(s.to_owned())).is_err());
+
+        let table: TenantsTable = toml::from_str("list = [\"a\"]\nquery = \"select 1\"").unwrap();
+        assert!(table.into_tenants(|s| Ok(s.to_owned())).is_err());
+
+        let table: TenantsTable =
+            toml::from_str("list = [\"a\", \"b\"]\nparallelism = 2\non_failure = \"stop\"")
+                .unwrap();
+        let tenants = table.into_tenants(|s| Ok(s.to_owned())).unwrap();
+        assert_eq!(
+            tenants.source,
+            TenantSource::List(vec!["a".into(), "b".into()])
+        );
+        assert_eq!(tenants.parallelism, 2);
+        assert_eq!(tenants.on_failure, OnTenantFailure::Stop);
+    }
+
+    #[test]
+    fn tenants_table_rejects_zero_parallelism() {
+        let table: TenantsTable = toml::from_str("list = [\"a\"]\nparallelism = 0").unwrap();
+        let err = table.into_tenants(|s| Ok(s.to_owned())).unwrap_err();
+        assert!(err.to_string().contains("parallelism"), "{}", err);
+    }
+
+    #[test]
+    fn server_tenant_names_are_restricted() {
+        assert!(check_server_tenant_name("tenant_a-1").is_ok());
+        assert!(check_server_tenant_name("").is_err());
+        assert!(check_server_tenant_name("a\" b").is_err());
+        assert!(check_server_tenant_name("a;drop").is_err());
+    }
+}
@@ -5,7 +5,7 @@ use std::io::{self, Write};
 use std::path::Path;
 use std::process::Command;
 
-use percent_encoding::{percent_encode, NON_ALPHANUMERIC};
+use percent_encoding::{percent_encode, AsciiSet, NON_ALPHANUMERIC};
 
 use crate::errors::*;
 use crate::macros::bail;
@@ -15,6 +15,27 @@ pub(crate) fn encode(s: &str) -> String {
     percent_encode(s.as_bytes(), NON_ALPHANUMERIC).to_string()
 }
 
+/// Everything but RFC 3986 unreserved characters
+const QUERY_COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Percent encode a query key or value, spaces as `%20` rather than `+`
+pub(crate) fn encode_query(s: &str) -> String {
+    percent_encode(s.as_bytes(), QUERY_COMPONENT).to_string()
+}
+
+/// A hash that is stable across processes, builds and platforms (64-bit
+/// FNV-1a), for deriving shared identifiers such as advisory lock keys
+#[cfg(any(feature = "postgres", feature = "mysql"))]
+pub(crate) fn stable_hash(s: &str) -> u64 {
+    s.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
 /// Write the provided bytes to the specified path
 pub(crate) fn write_to_path(path: &Path, content: &[u8]) -> Result<()> {
     std::fs::write(path, content)?;
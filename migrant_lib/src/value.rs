@@ -0,0 +1,424 @@
+/*!
+Backend-neutral values and rows for the `ConnConfig` sql helpers
+*/
+use std::sync::Arc;
+
+/// A sql parameter or column value, modeled on sqlite's storage classes so it
+/// maps onto every supported backend.
+///
+/// Booleans are stored as `Integer` (`0`/`1`): postgres `bool` columns read as
+/// integers, and integer parameters bind to postgres `bool` parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// sql `NULL`
+    Null,
+    /// A signed integer
+    Integer(i64),
+    /// A floating point number
+    Real(f64),
+    /// A utf-8 string
+    Text(String),
+    /// Raw bytes
+    Blob(Vec<u8>),
+}
+
+impl Value {
+    /// `true` for `Value::Null`
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    /// The value of an `Integer`
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Value::Integer(i) => Some(i),
+            _ => None,
+        }
+    }
+
+    /// The value of a `Real`, or an `Integer` converted to a float
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Real(f) => Some(f),
+            Value::Integer(i) => Some(i as f64),
+            _ => None,
+        }
+    }
+
+    /// The value of a `Text`
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Text(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The bytes of a `Blob` or `Text`
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Blob(b) => Some(b),
+            Value::Text(s) => Some(s.as_bytes()),
+            _ => None,
+        }
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Integer(i)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(i: i32) -> Self {
+        Value::Integer(i.into())
+    }
+}
+
+impl From<u32> for Value {
+    fn from(i: u32) -> Self {
+        Value::Integer(i.into())
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Integer(b.into())
+    }
+}
+
+impl From<f64> for Value {
+    fn from(f: f64) -> Self {
+        Value::Real(f)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::Text(s.to_owned())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Text(s)
+    }
+}
+
+impl From<&[u8]> for Value {
+    fn from(b: &[u8]) -> Self {
+        Value::Blob(b.to_vec())
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(b: Vec<u8>) -> Self {
+        Value::Blob(b)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(Value::Null, Into::into)
+    }
+}
+
+/// A row returned by [`ConnConfig::query_rows`](crate::ConnConfig::query_rows)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    columns: Arc<[String]>,
+    values: Vec<Value>,
+}
+
+impl Row {
+    #[allow(dead_code)] // unused when no database feature is enabled
+    pub(crate) fn new(columns: Arc<[String]>, values: Vec<Value>) -> Self {
+        Self { columns, values }
+    }
+
+    /// The names of the row's columns, in order
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// The row's values, in column order
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    /// The value of the column at `index`
+    pub fn get(&self, index: usize) -> Option<&Value> {
+        self.values.get(index)
+    }
+
+    /// The value of the first column named `name`
+    pub fn column(&self, name: &str) -> Option<&Value> {
+        let index = self.columns.iter().position(|c| c == name)?;
+        self.values.get(index)
+    }
+
+    /// Take the row's values, in column order
+    pub fn into_values(self) -> Vec<Value> {
+        self.values
+    }
+}
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use rusqlite::types::{ToSqlOutput, ValueRef};
+
+    use super::Value;
+
+    impl rusqlite::ToSql for Value {
+        fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+            Ok(ToSqlOutput::Borrowed(match self {
+                Value::Null => ValueRef::Null,
+                Value::Integer(i) => ValueRef::Integer(*i),
+                Value::Real(f) => ValueRef::Real(*f),
+                Value::Text(s) => ValueRef::Text(s.as_bytes()),
+                Value::Blob(b) => ValueRef::Blob(b),
+            }))
+        }
+    }
+
+    impl From<ValueRef<'_>> for Value {
+        fn from(value: ValueRef<'_>) -> Self {
+            match value {
+                ValueRef::Null => Value::Null,
+                ValueRef::Integer(i) => Value::Integer(i),
+                ValueRef::Real(f) => Value::Real(f),
+                ValueRef::Text(s) => Value::Text(String::from_utf8_lossy(s).into_owned()),
+                ValueRef::Blob(b) => Value::Blob(b.to_vec()),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "postgres")]
+mod pg {
+    use std::error::Error;
+
+    use bytes::BytesMut;
+    use postgres::types::{to_sql_checked, FromSql, IsNull, ToSql, Type};
+
+    use super::Value;
+
+    type BoxError = Box<dyn Error + Sync + Send>;
+
+    fn mismatch(value: &Value, ty: &Type) -> BoxError {
+        let kind = match value {
+            Value::Null => "null",
+            Value::Integer(_) => "an integer",
+            Value::Real(_) => "a real",
+            Value::Text(_) => "text",
+            Value::Blob(_) => "a blob",
+        };
+        format!("cannot bind {} to a postgres `{}` parameter", kind, ty).into()
+    }
+
+    /// Binds to the parameter type postgres inferred for the statement
+    impl ToSql for Value {
+        fn to_sql(&self, ty: &Type, out: &mut BytesMut) -> Result<IsNull, BoxError> {
+            match self {
+                Value::Null => Ok(IsNull::Yes),
+                Value::Integer(i) => match *ty {
+                    Type::BOOL => (*i != 0).to_sql(ty, out),
+                    Type::CHAR => i8::try_from(*i)?.to_sql(ty, out),
+                    Type::INT2 => i16::try_from(*i)?.to_sql(ty, out),
+                    Type::INT4 => i32::try_from(*i)?.to_sql(ty, out),
+                    Type::INT8 => i.to_sql(ty, out),
+                    Type::OID => u32::try_from(*i)?.to_sql(ty, out),
+                    Type::FLOAT4 => (*i as f32).to_sql(ty, out),
+                    Type::FLOAT8 => (*i as f64).to_sql(ty, out),
+                    _ => Err(mismatch(self, ty)),
+                },
+                Value::Real(f) => match *ty {
+                    Type::FLOAT4 => (*f as f32).to_sql(ty, out),
+                    Type::FLOAT8 => f.to_sql(ty, out),
+                    _ => Err(mismatch(self, ty)),
+                },
+                Value::Text(s) if <&str as ToSql>::accepts(ty) => s.as_str().to_sql(ty, out),
+                Value::Blob(b) if *ty == Type::BYTEA => b.as_slice().to_sql(ty, out),
+                _ => Err(mismatch(self, ty)),
+            }
+        }
+
+        fn accepts(_: &Type) -> bool {
+            true
+        }
+
+        to_sql_checked!();
+    }
+
+    impl<'a> FromSql<'a> for Value {
+        fn from_sql(ty: &Type, raw: &'a [u8]) -> Result<Self, BoxError> {
+            Ok(match *ty {
+                Type::BOOL => Value::Integer(bool::from_sql(ty, raw)?.into()),
+                Type::CHAR => Value::Integer(i8::from_sql(ty, raw)?.into()),
+                Type::INT2 => Value::Integer(i16::from_sql(ty, raw)?.into()),
+                Type::INT4 => Value::Integer(i32::from_sql(ty, raw)?.into()),
+                Type::INT8 => Value::Integer(i64::from_sql(ty, raw)?),
+                Type::OID => Value::Integer(u32::from_sql(ty, raw)?.into()),
+                Type::FLOAT4 => Value::Real(f32::from_sql(ty, raw)?.into()),
+                Type::FLOAT8 => Value::Real(f64::from_sql(ty, raw)?),
+                Type::BYTEA => Value::Blob(raw.to_vec()),
+                _ => Value::Text(String::from_sql(ty, raw)?),
+            })
+        }
+
+        fn from_sql_null(_: &Type) -> Result<Self, BoxError> {
+            Ok(Value::Null)
+        }
+
+        fn accepts(ty: &Type) -> bool {
+            matches!(
+                *ty,
+                Type::BOOL
+                    | Type::CHAR
+                    | Type::INT2
+                    | Type::INT4
+                    | Type::INT8
+                    | Type::OID
+                    | Type::FLOAT4
+                    | Type::FLOAT8
+                    | Type::BYTEA
+            ) || <String as FromSql>::accepts(ty)
+        }
+    }
+}
+
+#[cfg(feature = "mysql")]
+mod mysql {
+    use super::Value;
+
+    /// mysql's `binary` character set, marking a byte column as a blob
+    const BINARY_CHARSET: u16 = 63;
+
+    impl From<&Value> for mysql::Value {
+        fn from(value: &Value) -> Self {
+            match value {
+                Value::Null => mysql::Value::NULL,
+                Value::Integer(i) => mysql::Value::Int(*i),
+                Value::Real(f) => mysql::Value::Double(*f),
+                Value::Text(s) => mysql::Value::Bytes(s.as_bytes().to_vec()),
+                Value::Blob(b) => mysql::Value::Bytes(b.clone()),
+            }
+        }
+    }
+
+    impl Value {
+        /// Convert a value read from `column`, whose character set tells text
+        /// from binary data. Dates and times are formatted as text.
+        pub(crate) fn from_mysql(value: mysql::Value, column: &mysql::Column) -> Self {
+            match value {
+                mysql::Value::NULL => Value::Null,
+                mysql::Value::Int(i) => Value::Integer(i),
+                mysql::Value::UInt(u) => {
+                    i64::try_from(u).map_or_else(|_| Value::Text(u.to_string()), Value::Integer)
+                }
+                mysql::Value::Float(f) => Value::Real(f.into()),
+                mysql::Value::Double(f) => Value::Real(f),
+                mysql::Value::Bytes(b) if column.character_set() == BINARY_CHARSET => {
+                    Value::Blob(b)
+                }
+                mysql::Value::Bytes(b) => Value::Text(
+                    String::from_utf8(b)
+                        .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned()),
+                ),
+                mysql::Value::Date(y, mo, d, h, mi, s, us) => {
+                    let mut text = format!("{:04}-{:02}-{:02}", y, mo, d);
+                    if column.column_type() != mysql::consts::ColumnType::MYSQL_TYPE_DATE {
+                        text.push_str(&format!(" {:02}:{:02}:{:02}", h, mi, s));
+                        if us > 0 {
+                            text.push_str(&format!(".{:06}", us));
+                        }
+                    }
+                    Value::Text(text)
+                }
+                mysql::Value::Time(neg, d, h, mi, s, us) => {
+                    let sign = if neg { "-" } else { "" };
+                    let hours = d * 24 + u32::from(h);
+                    let mut text = format!("{}{:02}:{:02}:{:02}", sign, hours, mi, s);
+                    if us > 0 {
+                        text.push_str(&format!(".{:06}", us));
+                    }
+                    Value::Text(text)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "duckdb")]
+mod duckdb {
+    use duckdb::types::{ToSqlOutput, ValueRef};
+
+    use super::Value;
+
+    impl duckdb::ToSql for Value {
+        fn to_sql(&self) -> duckdb::Result<ToSqlOutput<'_>> {
+            Ok(ToSqlOutput::Borrowed(match self {
+                Value::Null => ValueRef::Null,
+                Value::Integer(i) => ValueRef::BigInt(*i),
+                Value::Real(f) => ValueRef::Double(*f),
+                Value::Text(s) => ValueRef::Text(s.as_bytes()),
+                Value::Blob(b) => ValueRef::Blob(b),
+            }))
+        }
+    }
+
+    impl Value {
+        /// Convert a duckdb value, or `None` for types (dates, decimals,
+        /// nested types, ...) without an equivalent
+        pub(crate) fn from_duckdb(value: ValueRef<'_>) -> Option<Self> {
+            Some(match value {
+                ValueRef::Null => Value::Null,
+                ValueRef::Boolean(b) => Value::Integer(b.into()),
+                ValueRef::TinyInt(i) => Value::Integer(i.into()),
+                ValueRef::SmallInt(i) => Value::Integer(i.into()),
+                ValueRef::Int(i) => Value::Integer(i.into()),
+                ValueRef::BigInt(i) => Value::Integer(i),
+                ValueRef::HugeInt(i) => Value::Integer(i64::try_from(i).ok()?),
+                ValueRef::UTinyInt(i) => Value::Integer(i.into()),
+                ValueRef::USmallInt(i) => Value::Integer(i.into()),
+                ValueRef::UInt(i) => Value::Integer(i.into()),
+                ValueRef::UBigInt(i) => Value::Integer(i64::try_from(i).ok()?),
+                ValueRef::Float(f) => Value::Real(f.into()),
+                ValueRef::Double(f) => Value::Real(f),
+                ValueRef::Text(s) => Value::Text(String::from_utf8_lossy(s).into_owned()),
+                ValueRef::Blob(b) => Value::Blob(b.to_vec()),
+                _ => return None,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions_and_accessors() {
+        assert_eq!(Value::from(3), Value::Integer(3));
+        assert_eq!(Value::from(true), Value::Integer(1));
+        assert_eq!(Value::from("a"), Value::Text("a".into()));
+        assert_eq!(Value::from(None::<i64>), Value::Null);
+        assert_eq!(Value::from(Some(1.5)), Value::Real(1.5));
+        assert_eq!(Value::Integer(2).as_f64(), Some(2.0));
+        assert_eq!(Value::Text("x".into()).as_bytes(), Some(&b"x"[..]));
+        assert!(Value::Real(1.0).as_i64().is_none());
+        assert!(Value::Null.is_null());
+    }
+
+    #[test]
+    fn row_lookup_by_index_and_name() {
+        let row = Row::new(
+            vec!["id".to_owned(), "name".to_owned()].into(),
+            vec![Value::Integer(1), Value::from("me")],
+        );
+        assert_eq!(row.get(0), Some(&Value::Integer(1)));
+        assert_eq!(row.column("name").and_then(Value::as_str), Some("me"));
+        assert_eq!(row.column("missing"), None);
+        assert_eq!(row.into_values().len(), 2);
+    }
+}
@@ -0,0 +1,193 @@
+//! End-to-end tests for the async API against sqlite, whose operations run on
+//! tokio's blocking pool through the synchronous `Config`.
+#![cfg(all(feature = "async", feature = "sqlite"))]
+
+use migrant_lib::asynchronous::AsyncMigrationFuture;
+use migrant_lib::{
+    AsyncConfig, AsyncConnConfig, AsyncFnMigration, AsyncMigrator, CancelToken, ConnConfig,
+    Direction, EmbeddedMigration, FnMigration, Migrator, Settings,
+};
+
+fn seed_users(conn: AsyncConnConfig) -> AsyncMigrationFuture {
+    Box::pin(async move {
+        let handle = conn.sqlite_connection()?;
+        tokio::task::spawn_blocking(move || {
+            let conn = handle.lock().unwrap();
+            conn.execute("insert into users (name) values (?1)", ["james"])
+                .map(|_| ())
+        })
+        .await??;
+        Ok(())
+    })
+}
+
+fn unseed_users(conn: AsyncConnConfig) -> AsyncMigrationFuture {
+    Box::pin(async move {
+        let handle = conn.sqlite_connection()?;
+        let conn = handle.lock().unwrap();
+        conn.execute("delete from users where name = ?1", ["james"])?;
+        Ok(())
+    })
+}
+
+fn seed_admins(conn: ConnConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let handle = conn.sqlite_connection()?;
+    let conn = handle.lock().unwrap();
+    conn.execute("insert into users (name) values (?1)", ["admin"])?;
+    Ok(())
+}
+
+fn async_config() -> AsyncConfig {
+    let settings = Settings::configure_sqlite().memory().build().unwrap();
+    let mut config = AsyncConfig::with_settings(settings);
+    config
+        .use_migrations(&[
+            EmbeddedMigration::with_tag("create-users")
+                .up("create table users (id integer primary key, name text);")
+                .down("drop table users;")
+                .boxed(),
+            AsyncFnMigration::with_tag("seed-users")
+                .up(seed_users)
+                .down(unseed_users)
+                .boxed(),
+            FnMigration::with_tag("seed-admins")
+                .up(seed_admins)
+                .down(migrant_lib::noop)
+                .boxed(),
+        ])
+        .unwrap();
+    config
+}
+
+fn user_count(config: &AsyncConfig) -> i64 {
+    let handle = config.sqlite_connection().unwrap();
+    let conn = handle.lock().unwrap();
+    conn.query_row("select count(*) from users", [], |row| row.get(0))
+        .unwrap()
+}
+
+#[tokio::test]
+async fn async_in_memory_database_end_to_end() {
+    let config = async_config();
+    assert!(config.setup().await.unwrap());
+    let config = config.reload().await.unwrap();
+
+    let report = AsyncMigrator::with_config(&config)
+        .all(true)
+        .show_output(false)
+        .apply()
+        .await
+        .unwrap();
+    assert_eq!(
+        vec!["create-users", "seed-users", "seed-admins"],
+        report.tags()
+    );
+    assert_eq!(2, user_count(&config));
+
+    let config = config.reload().await.unwrap();
+    assert!(config.pending_migrations().unwrap().is_empty());
+
+    let report = AsyncMigrator::with_config(&config)
+        .direction(Direction::Down)
+        .show_output(false)
+        .apply()
+        .await
+        .unwrap();
+    assert_eq!(vec!["seed-admins"], report.tags());
+    let report = AsyncMigrator::with_config(&config)
+        .direction(Direction::Down)
+        .show_output(false)
+        .apply()
+        .await
+        .unwrap();
+    assert_eq!(vec!["seed-users"], report.tags());
+    assert_eq!(1, user_count(&config));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn async_migrator_future_is_send() {
+    let config = async_config();
+    config.setup().await.unwrap();
+    let report = tokio::spawn(async move {
+        AsyncMigrator::with_config(&config)
+            .all(true)
+            .show_output(false)
+            .apply()
+            .await
+    })
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(3, report.len());
+}
+
+#[tokio::test]
+async fn async_failed_migration_rolls_back() {
+    let settings = Settings::configure_sqlite().memory().build().unwrap();
+    let mut config = AsyncConfig::with_settings(settings);
+    config
+        .use_migrations(&[EmbeddedMigration::with_tag("bad")
+            .up("create table good (x integer); insert into does_not_exist values (1);")
+            .down("drop table good;")
+            .boxed()])
+        .unwrap();
+    config.setup().await.unwrap();
+
+    let err = AsyncMigrator::with_config(&config)
+        .show_output(false)
+        .apply()
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("does_not_exist"), "{}", err);
+
+    let config = config.reload().await.unwrap();
+    assert_eq!(vec!["bad"], config.pending_migrations().unwrap());
+    let handle = config.sqlite_connection().unwrap();
+    let conn = handle.lock().unwrap();
+    let exists: bool = conn
+        .query_row(
+            "select exists(select 1 from sqlite_master where name = 'good')",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert!(!exists, "the failed migration's table is rolled back");
+}
+
+#[test]
+fn sync_migrator_rejects_async_fn_migration() {
+    let settings = Settings::configure_sqlite().memory().build().unwrap();
+    let mut config = migrant_lib::Config::with_settings(settings);
+    config
+        .use_migrations(&[AsyncFnMigration::with_tag("async-only")
+            .up(seed_users)
+            .down(unseed_users)
+            .boxed()])
+        .unwrap();
+    config.setup().unwrap();
+    let err = Migrator::with_config(&config)
+        .show_output(false)
+        .apply()
+        .unwrap_err();
+    assert!(err.to_string().contains("AsyncMigrator"), "{}", err);
+}
+
+#[tokio::test]
+async fn async_cancelled_token_runs_nothing() {
+    let config = async_config();
+    config.setup().await.unwrap();
+
+    let token = CancelToken::new();
+    token.cancel();
+    let report = AsyncMigrator::with_config(&config)
+        .all(true)
+        .show_output(false)
+        .cancel_on(token)
+        .apply()
+        .await
+        .unwrap();
+    assert!(report.is_cancelled());
+    assert!(report.is_empty());
+    let config = config.reload().await.unwrap();
+    assert_eq!(config.pending_migrations().unwrap().len(), 3);
+}
@@ -0,0 +1,190 @@
+//! Migrations run through an application-supplied `MigrationDriver`. No
+//! database feature is needed: the driver records what it is asked to do.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use migrant_lib::{
+    Config, Direction, EmbeddedMigration, Error, MigrationDriver, Migrator, Result, Settings,
+};
+
+#[derive(Default)]
+struct State {
+    table: Option<Vec<String>>,
+    /// Every call, in order
+    log: Vec<String>,
+}
+
+/// An in-memory driver sharing its state with the test
+#[derive(Clone, Default)]
+struct Recorder(Arc<Mutex<State>>);
+
+impl Recorder {
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.0.lock().unwrap()
+    }
+
+    fn log(&self) -> Vec<String> {
+        self.state().log.clone()
+    }
+
+    fn record(&self, call: String) {
+        self.state().log.push(call);
+    }
+}
+
+impl MigrationDriver for Recorder {
+    fn migration_table_exists(&mut self) -> Result<bool> {
+        Ok(self.state().table.is_some())
+    }
+
+    fn setup_migration_table(&mut self) -> Result<bool> {
+        self.record("setup".into());
+        let mut state = self.state();
+        let created = state.table.is_none();
+        state.table.get_or_insert_with(Vec::new);
+        Ok(created)
+    }
+
+    fn applied_tags(&mut self) -> Result<Vec<String>> {
+        Ok(self.state().table.clone().unwrap_or_default())
+    }
+
+    fn insert_tag(&mut self, tag: &str) -> Result<()> {
+        self.record(format!("insert {}", tag));
+        self.state()
+            .table
+            .get_or_insert_with(Vec::new)
+            .push(tag.to_owned());
+        Ok(())
+    }
+
+    fn remove_tag(&mut self, tag: &str) -> Result<()> {
+        self.record(format!("remove {}", tag));
+        self.state()
+            .table
+            .get_or_insert_with(Vec::new)
+            .retain(|t| t != tag);
+        Ok(())
+    }
+
+    fn execute_batch(&mut self, sql: &str) -> Result<()> {
+        self.record(format!("exec {}", sql));
+        if sql.contains("boom") {
+            return Err(Error::Migration("boom".into()));
+        }
+        Ok(())
+    }
+
+    fn begin(&mut self) -> Result<()> {
+        self.record("begin".into());
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<()> {
+        self.record("commit".into());
+        Ok(())
+    }
+
+    fn rollback(&mut self) -> Result<()> {
+        self.record("rollback".into());
+        Ok(())
+    }
+
+    fn acquire_lock(&mut self) -> Result<()> {
+        self.record("lock".into());
+        Ok(())
+    }
+
+    fn release_lock(&mut self) -> Result<()> {
+        self.record("unlock".into());
+        Ok(())
+    }
+}
+
+fn config_with(driver: &Recorder, up: &'static str) -> Config {
+    // only the dialect matters, the driver never connects with these
+    let settings = Settings::configure_postgres()
+        .database_name("app")
+        .database_user("app")
+        .database_password("")
+        .build()
+        .unwrap();
+    let mut config = Config::with_settings(settings);
+    config.with_driver(Box::new(driver.clone()));
+    config
+        .use_migrations(&[EmbeddedMigration::with_tag("create-users")
+            .up(up)
+            .down("drop table users;")
+            .boxed()])
+        .unwrap();
+    config
+}
+
+fn applied_tags(config: &Config) -> Vec<String> {
+    migrant_lib::migration_statuses(config)
+        .unwrap()
+        .into_iter()
+        .filter(|m| m.applied())
+        .map(|m| m.tag().to_string())
+        .collect()
+}
+
+#[test]
+fn migrator_runs_through_a_custom_driver() {
+    let driver = Recorder::default();
+    let config = config_with(&driver, "create table users (id int); select $$a;b$$;");
+    assert!(config.setup().unwrap(), "table created by the driver");
+    let config = config.reload().unwrap();
+
+    Migrator::with_config(&config)
+        .show_output(false)
+        .apply()
+        .unwrap();
+    let config = config.reload().unwrap();
+    assert_eq!(applied_tags(&config), ["create-users"]);
+
+    // split with the settings' (postgres) dialect, inside the lock and a transaction
+    assert_eq!(
+        driver.log(),
+        [
+            "setup",
+            "lock",
+            "begin",
+            "exec create table users (id int)",
+            "exec select $$a;b$$",
+            "insert create-users",
+            "commit",
+            "unlock",
+        ]
+    );
+
+    Migrator::with_config(&config)
+        .direction(Direction::Down)
+        .show_output(false)
+        .apply()
+        .unwrap();
+    assert!(applied_tags(&config.reload().unwrap()).is_empty());
+}
+
+#[test]
+fn failed_migration_rolls_back_on_the_custom_driver() {
+    let driver = Recorder::default();
+    let config = config_with(&driver, "create table users (id int); boom;");
+    config.setup().unwrap();
+    let config = config.reload().unwrap();
+
+    let res = Migrator::with_config(&config).show_output(false).apply();
+    assert!(res.is_err());
+    let log = driver.log();
+    assert!(log.contains(&"rollback".to_owned()), "{:?}", log);
+    assert!(
+        !log.contains(&"insert create-users".to_owned()),
+        "{:?}",
+        log
+    );
+    assert_eq!(log.last().map(String::as_str), Some("unlock"));
+
+    // the driver survives the failure: it isn't replaced by a settings-built
+    // connection (which would fail, there is no such database)
+    assert!(applied_tags(&config.reload().unwrap()).is_empty());
+}
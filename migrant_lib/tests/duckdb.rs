@@ -0,0 +1,196 @@
+//! End-to-end duckdb tests, against in-memory databases (where the connection
+//! handle must be kept alive across all operations) and database files.
+#![cfg(feature = "duckdb")]
+
+use migrant_lib::{
+    Config, ConnConfig, Direction, EmbeddedMigration, FnMigration, Migrator, Settings, Value,
+};
+
+fn seed_users(conn: ConnConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let handle = conn.duckdb_connection()?;
+    let conn = handle.lock().unwrap();
+    conn.execute("insert into users (id, name) values (1, ?)", ["james"])?;
+    Ok(())
+}
+
+fn unseed_users(conn: ConnConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let handle = conn.duckdb_connection()?;
+    let conn = handle.lock().unwrap();
+    conn.execute("delete from users where name = ?", ["james"])?;
+    Ok(())
+}
+
+fn migrations_config(settings: &Settings) -> Config {
+    let mut config = Config::with_settings(settings.clone());
+    config
+        .use_migrations(&[
+            EmbeddedMigration::with_tag("create-users")
+                .up("create table users (id integer primary key, name text);")
+                .down("drop table users;")
+                .boxed(),
+            FnMigration::with_tag("seed-users")
+                .up(seed_users)
+                .down(unseed_users)
+                .boxed(),
+        ])
+        .unwrap();
+    config
+}
+
+fn user_count(config: &Config) -> i64 {
+    let handle = config.duckdb_connection().unwrap();
+    let conn = handle.lock().unwrap();
+    conn.query_row("select count(*) from users", [], |row| row.get(0))
+        .unwrap()
+}
+
+fn applied_tags(config: &Config) -> Vec<String> {
+    migrant_lib::migration_statuses(config)
+        .unwrap()
+        .into_iter()
+        .filter(|m| m.applied())
+        .map(|m| m.tag().to_string())
+        .collect()
+}
+
+fn apply(config: &Config, direction: Direction) -> Config {
+    Migrator::with_config(config)
+        .all(true)
+        .direction(direction)
+        .show_output(false)
+        .apply()
+        .unwrap();
+    config.reload().unwrap()
+}
+
+#[test]
+fn in_memory_database_end_to_end() {
+    let settings = Settings::configure_duckdb().memory().build().unwrap();
+    let config = migrations_config(&settings);
+    assert!(config.setup().unwrap(), "migration table created");
+    let config = config.reload().unwrap();
+
+    let config = apply(&config, Direction::Up);
+    assert_eq!(
+        vec!["create-users".to_string(), "seed-users".to_string()],
+        applied_tags(&config)
+    );
+    assert_eq!(1, user_count(&config));
+
+    let config = apply(&config, Direction::Down);
+    assert!(applied_tags(&config).is_empty());
+}
+
+#[test]
+fn database_file_end_to_end() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("db").join("app.duckdb");
+    let settings = Settings::configure_duckdb()
+        .database_path(&path)
+        .unwrap()
+        .build()
+        .unwrap();
+    let config = migrations_config(&settings);
+    config.setup().unwrap();
+    assert!(path.exists(), "setup creates the database file");
+    let config = apply(&config.reload().unwrap(), Direction::Up);
+    assert_eq!(1, user_count(&config));
+    drop(config);
+
+    // a fresh connection to the same file sees the applied migrations
+    let config = migrations_config(&settings).reload().unwrap();
+    assert_eq!(2, applied_tags(&config).len());
+    assert_eq!(1, user_count(&config));
+}
+
+#[test]
+fn failed_migration_rolls_back_atomically() {
+    let settings = Settings::configure_duckdb().memory().build().unwrap();
+    let mut config = Config::with_settings(settings);
+    config
+        .use_migrations(&[EmbeddedMigration::with_tag("bad")
+            .up("create table good (x integer); insert into does_not_exist values (1);")
+            .down("drop table good;")
+            .boxed()])
+        .unwrap();
+    config.setup().unwrap();
+    let config = config.reload().unwrap();
+
+    let res = Migrator::with_config(&config).show_output(false).apply();
+    assert!(res.is_err(), "a migration with invalid sql must fail");
+
+    let config = config.reload().unwrap();
+    let handle = config.duckdb_connection().unwrap();
+    let tables: i64 = handle
+        .lock()
+        .unwrap()
+        .query_row(
+            "select count(*) from information_schema.tables where table_name = 'good'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(0, tables, "partial DDL must be rolled back");
+    assert!(applied_tags(&config).is_empty());
+}
+
+#[test]
+fn conn_config_sql_helpers() {
+    fn backfill(conn: ConnConfig) -> Result<(), Box<dyn std::error::Error>> {
+        assert!(conn.table_exists("users")?);
+        assert!(!conn.table_exists("nope")?);
+        assert!(!conn.column_exists("users", "email")?);
+        conn.execute_batch("alter table users add column email text;")?;
+        assert!(conn.column_exists("users", "email")?);
+
+        let changed = conn.execute(
+            "insert into users (id, name, email) values (?, ?, ?), (?, ?, ?)",
+            &[
+                1.into(),
+                "a".into(),
+                "A@X.COM".into(),
+                2.into(),
+                "b".into(),
+                Value::Null,
+            ],
+        )?;
+        assert_eq!(changed, 2);
+        for row in conn.query_rows("select id, email from users where email is not null", &[])? {
+            let email = row.column("email").and_then(Value::as_str).unwrap();
+            conn.execute(
+                "update users set email = ? where id = ?",
+                &[email.to_lowercase().into(), row.values()[0].clone()],
+            )?;
+        }
+        Ok(())
+    }
+
+    let settings = Settings::configure_duckdb().memory().build().unwrap();
+    let mut config = Config::with_settings(settings);
+    config
+        .use_migrations(&[
+            EmbeddedMigration::with_tag("create-users")
+                .up("create table users (id integer primary key, name text);")
+                .down("drop table users;")
+                .boxed(),
+            FnMigration::with_tag("backfill")
+                .up(backfill)
+                .down(migrant_lib::migration::noop)
+                .in_transaction()
+                .boxed(),
+        ])
+        .unwrap();
+    config.setup().unwrap();
+    let config = apply(&config.reload().unwrap(), Direction::Up);
+
+    let handle = config.duckdb_connection().unwrap();
+    let conn = handle.lock().unwrap();
+    let emails: Vec<Option<String>> = conn
+        .prepare("select email from users order by id")
+        .unwrap()
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(emails, [Some("a@x.com".to_owned()), None]);
+}
@@ -0,0 +1,331 @@
+//! Importing other tools' migrations and bookkeeping into a sqlite project.
+#![cfg(feature = "sqlite")]
+
+use std::fs;
+use std::path::Path;
+
+use migrant_lib::{Config, Error, ImportSource, Importer, Migrator, Settings};
+
+/// A cli-compatible config for a sqlite database and `migrations` directory in `dir`
+fn project(dir: &Path) -> Config {
+    let settings = Settings::configure_sqlite()
+        .database_path(dir.join("db.db"))
+        .unwrap()
+        .migration_location(dir.join("migrations"))
+        .unwrap()
+        .build()
+        .unwrap();
+    let mut config = Config::with_settings(settings);
+    config.use_cli_compatible_tags(true);
+    config
+}
+
+/// Run `sql` on the project's database, as the other tool would have
+fn seed_history(config: &Config, sql: &str) {
+    let handle = config.sqlite_connection().unwrap();
+    handle.lock().unwrap().execute_batch(sql).unwrap();
+}
+
+fn write(path: &Path, contents: &str) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+fn applied_tags(config: &Config) -> Vec<String> {
+    migrant_lib::migration_statuses(&config.reload().unwrap())
+        .unwrap()
+        .into_iter()
+        .filter(|m| m.applied())
+        .map(|m| m.tag().to_string())
+        .collect()
+}
+
+#[test]
+fn diesel_migrations_and_history_are_imported() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("diesel");
+    write(
+        &source.join("00000000000000_diesel_initial_setup/up.sql"),
+        "create table setup (id integer);",
+    );
+    write(
+        &source.join("00000000000000_diesel_initial_setup/down.sql"),
+        "drop table setup;",
+    );
+    write(
+        &source.join("2020-01-01-120000_create_users/up.sql"),
+        "create table users (id integer);",
+    );
+    write(
+        &source.join("2020-01-01-120000_create_users/down.sql"),
+        "drop table users;",
+    );
+    write(
+        &source.join("2020-01-01-120000_create_users/metadata.toml"),
+        "run_in_transaction = false\n",
+    );
+    write(
+        &source.join("2020-02-01-000000_add_email/up.sql"),
+        "alter table users add column email text;",
+    );
+    let config = project(dir.path());
+    seed_history(
+        &config,
+        "create table setup (id integer);
+         create table users (id integer);
+         create table __diesel_schema_migrations (version varchar(50) primary key, run_on timestamp);
+         insert into __diesel_schema_migrations (version) values ('00000000000000'), ('20200101120000');",
+    );
+
+    let report = Importer::new(&config, ImportSource::Diesel, &source)
+        .import()
+        .unwrap();
+    assert_eq!(
+        report.migrations(),
+        [
+            "19700101000000_diesel-initial-setup",
+            "20200101120000_create-users",
+            "20200201000000_add-email",
+        ]
+    );
+    assert_eq!(report.recorded(), &report.migrations()[..2]);
+    assert!(report.history_found());
+
+    let migrations = dir.path().join("migrations");
+    let up = fs::read_to_string(migrations.join("20200101120000_create-users/up.sql")).unwrap();
+    assert_eq!(
+        up,
+        "-- migrant:no-transaction\ncreate table users (id integer);"
+    );
+    let down = fs::read_to_string(migrations.join("20200201000000_add-email/down.sql")).unwrap();
+    assert!(down.starts_with("-- No down migration"), "{}", down);
+
+    assert_eq!(applied_tags(&config), &report.migrations()[..2]);
+    let report_up = Migrator::with_config(&config.reload().unwrap())
+        .all(true)
+        .show_output(false)
+        .apply()
+        .unwrap();
+    assert_eq!(report_up.tags(), ["20200201000000_add-email"]);
+
+    // the tags now exist, so a second import changes nothing
+    match Importer::new(&config, ImportSource::Diesel, &source).import() {
+        Err(Error::TagError(msg)) => assert!(msg.contains("already exists"), "{}", msg),
+        other => panic!("expected TagError, got: {:?}", other),
+    }
+}
+
+#[test]
+fn sqlx_history_only_counts_successful_migrations() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("sqlx");
+    write(
+        &source.join("20200101000000_create_users.sql"),
+        "-- no-transaction\ncreate table users (id integer);\n",
+    );
+    write(
+        &source.join("20200102000000_seed_users.up.sql"),
+        "insert into users values (1);",
+    );
+    write(
+        &source.join("20200102000000_seed_users.down.sql"),
+        "delete from users;",
+    );
+    let config = project(dir.path());
+    seed_history(
+        &config,
+        "create table _sqlx_migrations (version bigint primary key, description text, success boolean);
+         insert into _sqlx_migrations values (20200101000000, 'create users', true),
+                                             (20200102000000, 'seed users', false);",
+    );
+
+    let report = Importer::new(&config, ImportSource::Sqlx, &source)
+        .import()
+        .unwrap();
+    assert_eq!(
+        report.migrations(),
+        ["20200101000000_create-users", "20200102000000_seed-users"]
+    );
+    assert_eq!(report.recorded(), ["20200101000000_create-users"]);
+    let migrations = dir.path().join("migrations");
+    let up = fs::read_to_string(migrations.join("20200101000000_create-users/up.sql")).unwrap();
+    assert_eq!(
+        up,
+        "-- migrant:no-transaction\ncreate table users (id integer);\n"
+    );
+    let down = fs::read_to_string(migrations.join("20200102000000_seed-users/down.sql")).unwrap();
+    assert_eq!(down, "delete from users;");
+}
+
+#[test]
+fn golang_migrate_version_marks_earlier_migrations_applied() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("golang-migrate");
+    for (version, title) in [(1, "init"), (2, "add_users"), (10, "add_posts")] {
+        write(
+            &source.join(format!("{}_{}.up.sql", version, title)),
+            "select 1;",
+        );
+        write(
+            &source.join(format!("{}_{}.down.sql", version, title)),
+            "select 1;",
+        );
+    }
+    let config = project(dir.path());
+    seed_history(
+        &config,
+        "create table schema_migrations (version bigint not null primary key, dirty boolean not null);
+         insert into schema_migrations values (2, true);",
+    );
+
+    // a dirty database is refused before anything is written
+    match Importer::new(&config, ImportSource::GolangMigrate, &source).import() {
+        Err(Error::Migration(msg)) => assert!(msg.contains("dirty at version 2"), "{}", msg),
+        other => panic!("expected a dirty-database error, got: {:?}", other),
+    }
+    assert!(!dir.path().join("migrations").exists());
+
+    seed_history(&config, "update schema_migrations set dirty = false;");
+    let report = Importer::new(&config, ImportSource::GolangMigrate, &source)
+        .import()
+        .unwrap();
+    assert_eq!(
+        report.migrations(),
+        [
+            "19700101000000_init",
+            "19700101000001_add-users",
+            "19700101000002_add-posts"
+        ]
+    );
+    assert_eq!(report.recorded(), &report.migrations()[..2]);
+}
+
+#[test]
+fn flyway_history_is_replayed_and_repeatables_skipped() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("flyway");
+    write(&source.join("V1__Init.sql"), "select 1;");
+    write(&source.join("v2/V1_1__Add_column.sql"), "select 1;");
+    write(&source.join("v2/U1_1__Add_column.sql"), "select 2;");
+    write(&source.join("V2__More.sql"), "select 1;");
+    write(
+        &source.join("V2__More.sql.conf"),
+        "executeInTransaction=false\n",
+    );
+    write(&source.join("R__Views.sql"), "select 1;");
+    let config = project(dir.path());
+    seed_history(
+        &config,
+        "create table flyway_schema_history (
+             installed_rank int primary key, version varchar(50), type varchar(20), success boolean
+         );
+         insert into flyway_schema_history values (1, null, 'SCHEMA', 1),
+                                                   (2, '1', 'SQL', 1),
+                                                   (3, '1.1', 'SQL', 1),
+                                                   (4, '1.1', 'UNDO_SQL', 1),
+                                                   (5, '2', 'SQL', 0),
+                                                   (6, '3', 'SQL', 1);",
+    );
+
+    let report = Importer::new(&config, ImportSource::Flyway, &source)
+        .import()
+        .unwrap();
+    assert_eq!(
+        report.migrations(),
+        [
+            "19700101000000_init",
+            "19700101000001_add-column",
+            "19700101000002_more"
+        ]
+    );
+    assert_eq!(report.recorded(), ["19700101000000_init"]);
+    assert_eq!(report.skipped().len(), 2, "{:?}", report.skipped());
+    assert!(report.skipped()[0].contains("R__Views.sql"));
+    assert!(report.skipped()[1].contains("version 3"));
+
+    let migrations = dir.path().join("migrations");
+    let down = fs::read_to_string(migrations.join("19700101000001_add-column/down.sql")).unwrap();
+    assert_eq!(down, "select 2;");
+    let up = fs::read_to_string(migrations.join("19700101000002_more/up.sql")).unwrap();
+    assert_eq!(up, "-- migrant:no-transaction\nselect 1;");
+}
+
+#[test]
+fn import_without_history_leaves_the_database_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("refinery");
+    write(&source.join("V1__init.sql"), "select 1;");
+    write(&source.join("V2__second.rs"), "");
+    let config = project(dir.path());
+
+    let report = Importer::new(&config, ImportSource::Refinery, &source)
+        .record_applied(false)
+        .import()
+        .unwrap();
+    assert_eq!(report.migrations(), ["19700101000000_init"]);
+    assert!(report.recorded().is_empty());
+    assert!(!report.history_found());
+    assert!(report.skipped()[0].contains("Rust migrations"));
+    assert!(!dir.path().join("db.db").exists());
+}
+
+#[test]
+fn a_failure_to_record_writes_no_migrations() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("refinery");
+    write(
+        &source.join("V1__init.sql"),
+        "create table users (id integer);",
+    );
+    let config = project(dir.path());
+    // A view in the way of `__migrant_migrations` makes recording fail
+    seed_history(
+        &config,
+        "create table refinery_schema_history (version int4 primary key, name varchar(255),
+             applied_on varchar(255), checksum varchar(255));
+         insert into refinery_schema_history (version, name) values (1, 'init');
+         create view __migrant_migrations as select 1 as tag;",
+    );
+
+    Importer::new(&config, ImportSource::Refinery, &source)
+        .import()
+        .unwrap_err();
+    let entries = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .filter(|name| name != "db.db" && name != "refinery")
+        .collect::<Vec<_>>();
+    assert!(entries.is_empty(), "{:?}", entries);
+}
+
+#[test]
+fn a_failure_to_record_moves_the_migrations_back_out() {
+    let dir = tempfile::tempdir().unwrap();
+    let source = dir.path().join("refinery");
+    write(
+        &source.join("V1__init.sql"),
+        "create table users (id integer);",
+    );
+    let location = dir.path().join("migrations");
+    fs::create_dir_all(&location).unwrap();
+    let config = project(dir.path());
+    seed_history(
+        &config,
+        "create table refinery_schema_history (version int4 primary key, name varchar(255),
+             applied_on varchar(255), checksum varchar(255));
+         insert into refinery_schema_history (version, name) values (1, 'init');
+         create view __migrant_migrations as select 1 as tag;",
+    );
+
+    // the migrations are in place by the time recording fails
+    Importer::new(&config, ImportSource::Refinery, &source)
+        .import()
+        .unwrap_err();
+    assert_eq!(fs::read_dir(&location).unwrap().count(), 0);
+    let entries = fs::read_dir(dir.path())
+        .unwrap()
+        .map(|e| e.unwrap().file_name().into_string().unwrap())
+        .filter(|name| !["db.db", "refinery", "migrations"].contains(&name.as_str()))
+        .collect::<Vec<_>>();
+    assert!(entries.is_empty(), "{:?}", entries);
+}
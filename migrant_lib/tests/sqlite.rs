@@ -3,8 +3,8 @@
 #![cfg(feature = "sqlite")]
 
 use migrant_lib::{
-    Config, ConnConfig, Direction, EmbeddedMigration, FileMigration, FnMigration, ForceMode,
-    Migrator, Settings,
+    CancelToken, Config, ConnConfig, Direction, EmbeddedMigration, FileMigration, FnMigration,
+    ForceMode, Migrator, Settings, Value,
 };
 
 fn seed_users(conn: ConnConfig) -> Result<(), Box<dyn std::error::Error>> {
@@ -285,6 +285,105 @@ fn no_transaction_migration_leaves_partial_state() {
     );
 }
 
+#[test]
+fn in_transaction_fn_migration_rolls_back_with_the_migrator() {
+    fn seed_then_fail(conn: ConnConfig) -> Result<(), Box<dyn std::error::Error>> {
+        seed_users(conn)?;
+        Err("seeding failed".into())
+    }
+
+    let settings = Settings::configure_sqlite().memory().build().unwrap();
+    let mut config = Config::with_settings(settings);
+    config
+        .use_migrations(&[
+            EmbeddedMigration::with_tag("create-users")
+                .up("create table users (id integer primary key, name text);")
+                .down("drop table users;")
+                .boxed(),
+            FnMigration::with_tag("seed-users")
+                .up(seed_then_fail)
+                .down(unseed_users)
+                .in_transaction()
+                .boxed(),
+        ])
+        .unwrap();
+    config.setup().unwrap();
+    let config = config.reload().unwrap();
+
+    let err = Migrator::with_config(&config)
+        .all(true)
+        .show_output(false)
+        .apply()
+        .expect_err("the function migration fails");
+    assert!(err.to_string().contains("seeding failed"), "{}", err);
+
+    // the insert ran on the migrator's connection inside its transaction, so
+    // it is rolled back along with the (never written) bookkeeping row
+    let config = config.reload().unwrap();
+    assert_eq!(user_count(&config), 0);
+    assert_eq!(applied_tags(&config), ["create-users"]);
+}
+
+#[test]
+fn conn_config_sql_helpers() {
+    fn backfill(conn: ConnConfig) -> Result<(), Box<dyn std::error::Error>> {
+        assert!(conn.table_exists("users")?);
+        assert!(!conn.table_exists("nope")?);
+        assert!(!conn.column_exists("users", "email")?);
+        conn.execute_batch("alter table users add column email text;")?;
+        assert!(conn.column_exists("users", "email")?);
+
+        let changed = conn.execute(
+            "insert into users (name, email) values (?, ?), (?, ?)",
+            &["a".into(), "A@X.COM".into(), "b".into(), Value::Null],
+        )?;
+        assert_eq!(changed, 2);
+        for row in conn.query_rows("select id, email from users where email is not null", &[])? {
+            let email = row.column("email").and_then(Value::as_str).unwrap();
+            conn.execute(
+                "update users set email = ? where id = ?",
+                &[email.to_lowercase().into(), row.values()[0].clone()],
+            )?;
+        }
+        Ok(())
+    }
+
+    let settings = Settings::configure_sqlite().memory().build().unwrap();
+    let mut config = Config::with_settings(settings);
+    config
+        .use_migrations(&[
+            EmbeddedMigration::with_tag("create-users")
+                .up("create table users (id integer primary key, name text);")
+                .down("drop table users;")
+                .boxed(),
+            FnMigration::with_tag("backfill")
+                .up(backfill)
+                .down(migrant_lib::migration::noop)
+                .in_transaction()
+                .boxed(),
+        ])
+        .unwrap();
+    config.setup().unwrap();
+    let config = config.reload().unwrap();
+    Migrator::with_config(&config)
+        .all(true)
+        .show_output(false)
+        .apply()
+        .unwrap();
+
+    let config = config.reload().unwrap();
+    let handle = config.sqlite_connection().unwrap();
+    let conn = handle.lock().unwrap();
+    let emails: Vec<Option<String>> = conn
+        .prepare("select email from users order by id")
+        .unwrap()
+        .query_map([], |row| row.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(emails, [Some("a@x.com".to_owned()), None]);
+}
+
 #[test]
 fn embedded_directive_opts_up_out_of_transaction() {
     // `up` carries the `-- migrant:no-transaction` directive and fails partway.
@@ -381,6 +480,186 @@ fn file_migration_reads_no_transaction_directive() {
     assert!(applied_tags(&config).is_empty());
 }
 
+/// A single-file migration runs each direction's section, and a directive in
+/// one section only affects that direction.
+#[test]
+fn single_file_migration_sections_and_directives() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("20200101000000_single.sql");
+    std::fs::write(
+        &file,
+        "-- migrate:up\n\
+         create table single (x integer);\n\
+         -- migrate:down\n\
+         -- migrant:no-transaction\n\
+         drop table single;\n\
+         insert into nope values (1);\n",
+    )
+    .unwrap();
+
+    let settings = Settings::configure_sqlite().memory().build().unwrap();
+    let mut config = Config::with_settings(settings);
+    config
+        .use_migrations(&[FileMigration::with_tag("single").single_file(&file).boxed()])
+        .unwrap();
+    config.setup().unwrap();
+
+    Migrator::with_config(&config)
+        .show_output(false)
+        .apply()
+        .unwrap();
+    let config = config.reload().unwrap();
+    assert!(table_exists(&config, "single"));
+    assert_eq!(applied_tags(&config), vec!["single"]);
+
+    // the down section fails partway, outside a transaction: its first
+    // statement sticks and the migration stays recorded as applied
+    let res = Migrator::with_config(&config)
+        .direction(Direction::Down)
+        .show_output(false)
+        .apply();
+    assert!(res.is_err(), "a section with invalid sql must fail");
+    let config = config.reload().unwrap();
+    assert!(!table_exists(&config, "single"));
+    assert_eq!(applied_tags(&config), vec!["single"]);
+}
+
+/// `{{ name }}` placeholders render from the settings file's `[variables]`
+/// (including `env:` values), with `Config::variable` taking precedence.
+#[test]
+fn templated_sql_renders_variables_from_settings_and_config() {
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_var("MIGRANT_TEST_TEMPLATE_COLUMN", "email");
+    let settings_path = dir.path().join("Migrant.toml");
+    std::fs::write(
+        &settings_path,
+        format!(
+            "database_type = \"sqlite\"\n\
+             database_path = {:?}\n\
+             [variables]\n\
+             table = \"from_file\"\n\
+             column = \"env:MIGRANT_TEST_TEMPLATE_COLUMN\"\n",
+            dir.path().join("test.db")
+        ),
+    )
+    .unwrap();
+    let up = dir.path().join("up.sql");
+    let down = dir.path().join("down.sql");
+    std::fs::write(&up, "create table {{ table }} ({{column}} text);").unwrap();
+    std::fs::write(&down, "drop table {{ table }};").unwrap();
+
+    let settings = Settings::from_file(&settings_path).unwrap();
+    assert_eq!(settings.variables()["column"], "email");
+
+    let mut config = Config::from_settings_file(&settings_path).unwrap();
+    config
+        .variable("table", "accounts")
+        .use_migrations(&[
+            FileMigration::with_tag("templated")
+                .up(&up)
+                .down(&down)
+                .boxed(),
+            EmbeddedMigration::with_tag("templated-index")
+                .up("create index {{ table }}_{{ column }} on {{ table }} ({{ column }});")
+                .down("drop index {{ table }}_{{ column }};")
+                .boxed(),
+        ])
+        .unwrap();
+    config.setup().unwrap();
+
+    let config = config.reload().unwrap();
+    Migrator::with_config(&config)
+        .all(true)
+        .show_output(false)
+        .apply()
+        .unwrap();
+    let config = config.reload().unwrap();
+    assert!(table_exists(&config, "accounts"));
+    assert!(!table_exists(&config, "from_file"));
+    assert_eq!(applied_tags(&config).len(), 2);
+
+    Migrator::with_config(&config)
+        .direction(Direction::Down)
+        .all(true)
+        .show_output(false)
+        .apply()
+        .unwrap();
+    assert!(!table_exists(&config.reload().unwrap(), "accounts"));
+}
+
+#[test]
+fn undefined_template_variable_fails_the_migration() {
+    let settings = Settings::configure_sqlite().memory().build().unwrap();
+    let mut config = Config::with_settings(settings);
+    config
+        .variable("app_role", "app_rw")
+        .use_migrations(&[EmbeddedMigration::with_tag("grants")
+            .up("create table {{ app_rol }} (x integer);")
+            .boxed()])
+        .unwrap();
+    config.setup().unwrap();
+
+    let err = Migrator::with_config(&config)
+        .show_output(false)
+        .apply()
+        .unwrap_err()
+        .to_string();
+    assert!(
+        err.contains("Undefined template variable `app_rol` on line 1"),
+        "{}",
+        err
+    );
+    assert!(applied_tags(&config.reload().unwrap()).is_empty());
+}
+
+/// A failing statement is reported with its file, index, line range and a
+/// snippet; for a single-file migration the lines are the file's.
+#[test]
+fn failing_statement_is_located_in_its_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("20200101000000_located.sql");
+    std::fs::write(
+        &file,
+        "-- migrate:up\n\
+         create table located (x integer);\n\
+         insert into located values ('a;b');\n\
+         \n\
+         insert into missing\n\
+         values (1);\n\
+         -- migrate:down\n\
+         drop table located;\n",
+    )
+    .unwrap();
+
+    let settings = Settings::configure_sqlite().memory().build().unwrap();
+    let mut config = Config::with_settings(settings);
+    config
+        .use_migrations(&[FileMigration::with_tag("located")
+            .single_file(&file)
+            .boxed()])
+        .unwrap();
+    config.setup().unwrap();
+
+    let err = Migrator::with_config(&config)
+        .show_output(false)
+        .apply()
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("20200101000000_located.sql"), "{}", err);
+    assert!(
+        err.contains("statement 3 of 3 (lines 5:1-6:10) failed: no such table: missing"),
+        "{}",
+        err
+    );
+    assert!(
+        err.contains("    | insert into missing\n    | values (1)"),
+        "{}",
+        err
+    );
+    // the migration's earlier statements were rolled back with it
+    assert!(!table_exists(&config, "located"));
+}
+
 #[test]
 fn file_database_end_to_end() {
     let dir = tempfile::tempdir().unwrap();
@@ -405,3 +684,262 @@ fn file_database_end_to_end() {
     assert_eq!(2, applied_tags(&config).len());
     assert_eq!(1, user_count(&config));
 }
+
+/// Cancelling mid-run lets the running migration finish, then stops before the
+/// next one and marks the report cancelled.
+#[test]
+fn cancel_stops_between_migrations() {
+    let token = CancelToken::new();
+    let handle = token.clone();
+    let cancel_during = move |_: ConnConfig| -> Result<(), Box<dyn std::error::Error>> {
+        handle.cancel();
+        Ok(())
+    };
+    let settings = Settings::configure_sqlite().memory().build().unwrap();
+    let mut config = Config::with_settings(settings);
+    config
+        .use_migrations(&[
+            EmbeddedMigration::with_tag("first")
+                .up("create table first (x integer);")
+                .down("drop table first;")
+                .boxed(),
+            FnMigration::with_tag("cancel")
+                .up(cancel_during.clone())
+                .down(cancel_during)
+                .boxed(),
+            EmbeddedMigration::with_tag("never")
+                .up("create table never (x integer);")
+                .down("drop table never;")
+                .boxed(),
+        ])
+        .unwrap();
+    config.setup().unwrap();
+
+    let report = Migrator::with_config(&config)
+        .all(true)
+        .show_output(false)
+        .cancel_on(token)
+        .apply()
+        .unwrap();
+    assert!(report.is_cancelled());
+    assert_eq!(report.tags(), ["first", "cancel"]);
+
+    let config = config.reload().unwrap();
+    assert_eq!(applied_tags(&config), vec!["first", "cancel"]);
+    assert!(!table_exists(&config, "never"));
+}
+
+#[test]
+fn cancelled_token_runs_nothing() {
+    let settings = Settings::configure_sqlite().memory().build().unwrap();
+    let config = migrations_config(&settings);
+    config.setup().unwrap();
+
+    let token = CancelToken::new();
+    token.cancel();
+    let report = Migrator::with_config(&config)
+        .all(true)
+        .show_output(false)
+        .cancel_on(token)
+        .apply()
+        .unwrap();
+    assert!(report.is_cancelled());
+    assert!(report.is_empty());
+    assert!(applied_tags(&config.reload().unwrap()).is_empty());
+}
+
+#[test]
+fn adopted_connection_keeps_application_pragmas() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("unused.db");
+    let settings = Settings::configure_sqlite()
+        .database_path(&path)
+        .unwrap()
+        .build()
+        .unwrap();
+
+    // an in-memory database the application set up, with foreign keys enforced
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    conn.pragma_update(None, "foreign_keys", true).unwrap();
+    let mut config = Config::from_rusqlite_connection(settings, conn).unwrap();
+    assert!(config.setup().unwrap());
+    assert!(!path.exists(), "the settings' database is never opened");
+    assert_orphan_post_is_rejected(&mut config);
+}
+
+/// Apply a migration inserting a row that violates a foreign key, which
+/// must fail on a connection enforcing foreign keys
+fn assert_orphan_post_is_rejected(config: &mut Config) {
+    config
+        .use_migrations(&[
+            EmbeddedMigration::with_tag("create-users")
+                .up("create table users (id integer primary key, name text);")
+                .down("drop table users;")
+                .boxed(),
+            EmbeddedMigration::with_tag("orphan-post")
+                .up(
+                    "create table posts (user_id integer references users(id)); \
+                     insert into posts values (42);",
+                )
+                .down("drop table posts;")
+                .boxed(),
+        ])
+        .unwrap();
+    let res = Migrator::with_config(config)
+        .all(true)
+        .show_output(false)
+        .apply();
+    assert!(res.is_err(), "foreign keys are enforced");
+
+    let config = config.reload().unwrap();
+    assert_eq!(applied_tags(&config), ["create-users"]);
+    assert!(!table_exists(&config, "posts"));
+}
+
+#[test]
+fn connection_options_apply_to_migration_runs() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.db");
+    let builder = Settings::configure_sqlite().database_path(&path).unwrap();
+
+    let settings = builder.clone().create(false).build().unwrap();
+    let err = Config::with_settings(settings).setup().unwrap_err();
+    assert!(err.is_config(), "{}", err);
+    assert!(!path.exists(), "a missing database isn't created");
+
+    let settings = builder
+        .journal_mode("wal")
+        .foreign_keys(true)
+        .busy_timeout(std::time::Duration::from_secs(1))
+        .build()
+        .unwrap();
+    let mut config = Config::with_settings(settings);
+    config.setup().unwrap();
+    assert_orphan_post_is_rejected(&mut config);
+}
+
+#[test]
+fn adopted_connection_must_match_the_settings() {
+    let settings = Settings::configure_postgres()
+        .database_name("app")
+        .database_user("app")
+        .database_password("")
+        .build()
+        .unwrap();
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    let err = Config::from_rusqlite_connection(settings, conn).unwrap_err();
+    assert!(err.is_config(), "{}", err);
+}
+
+#[test]
+fn file_database_runs_wait_for_the_migration_lock() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.db");
+    let settings = Settings::configure_sqlite()
+        .database_path(&path)
+        .unwrap()
+        .lock_timeout(std::time::Duration::from_millis(300))
+        .build()
+        .unwrap();
+    let config = migrations_config(&settings);
+    config.setup().unwrap();
+
+    // another migrator holds the lock file
+    let holder = rusqlite::Connection::open(dir.path().join("app.db-migrant-lock")).unwrap();
+    holder.execute_batch("begin exclusive").unwrap();
+    let err = Migrator::with_config(&config)
+        .all(true)
+        .show_output(false)
+        .apply()
+        .unwrap_err();
+    assert!(err.to_string().contains("Timed out"), "{}", err);
+    assert!(applied_tags(&config).is_empty());
+
+    // an unsynchronized run doesn't wait
+    let report = Migrator::with_config(&config)
+        .show_output(false)
+        .synchronized(false)
+        .apply()
+        .unwrap();
+    assert_eq!(report.tags(), ["create-users"]);
+
+    drop(holder);
+    let report = Migrator::with_config(&config)
+        .all(true)
+        .show_output(false)
+        .apply()
+        .unwrap();
+    assert_eq!(report.tags(), ["seed-users"]);
+}
+
+#[test]
+fn failed_runs_restore_the_backup_taken_before_them() {
+    let dir = tempfile::tempdir().unwrap();
+    let backups = dir.path().join("backups");
+    let settings = Settings::configure_sqlite()
+        .database_path(dir.path().join("app.db"))
+        .unwrap()
+        .backup_dir(&backups)
+        .unwrap()
+        .backup_keep(1)
+        .build()
+        .unwrap();
+    let mut config = Config::with_settings(settings);
+    config
+        .use_migrations(&[
+            EmbeddedMigration::with_tag("create-users")
+                .up("create table users (id integer primary key, name text);")
+                .down("drop table users;")
+                .boxed(),
+            EmbeddedMigration::with_tag("broken")
+                .up("insert into missing (id) values (1);")
+                .down("")
+                .boxed(),
+        ])
+        .unwrap();
+    config.setup().unwrap();
+    let list_backups = || {
+        std::fs::read_dir(&backups)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>()
+    };
+
+    // the run fails on `broken`, undoing `create-users` too
+    let err = Migrator::with_config(&config)
+        .all(true)
+        .show_output(false)
+        .apply()
+        .unwrap_err();
+    assert!(err.is_migration(), "{}", err);
+    let config = config.reload().unwrap();
+    assert!(applied_tags(&config).is_empty());
+    assert!(!table_exists(&config, "users"));
+    assert_eq!(list_backups().len(), 1);
+
+    // a successful run keeps its changes, and only the newest backup is kept
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    let report = Migrator::with_config(&config)
+        .show_output(false)
+        .apply()
+        .unwrap();
+    assert_eq!(report.tags(), ["create-users"]);
+    let config = config.reload().unwrap();
+    assert_eq!(applied_tags(&config), ["create-users"]);
+    let kept = list_backups();
+    assert_eq!(kept.len(), 1);
+
+    config.restore_database(&kept[0]).unwrap();
+    let config = config.reload().unwrap();
+    assert!(applied_tags(&config).is_empty());
+
+    // old backups can't be found under a timestamped directory, so none is taken
+    let err = Migrator::with_config(&config)
+        .backup_before_run("{timestamp}/{database}.bak")
+        .show_output(false)
+        .apply()
+        .unwrap_err();
+    assert!(err.is_config(), "{}", err);
+    assert!(applied_tags(&config.reload().unwrap()).is_empty());
+    assert_eq!(list_backups().len(), 1);
+}
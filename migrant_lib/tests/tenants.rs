@@ -0,0 +1,166 @@
+//! Multi-tenant fan-out against sqlite database files.
+#![cfg(feature = "sqlite")]
+
+use migrant_lib::{
+    Config, Direction, EmbeddedMigration, Migrator, OnTenantFailure, Settings, Tenants,
+};
+
+fn migrations() -> Vec<Box<dyn migrant_lib::Migratable>> {
+    vec![
+        EmbeddedMigration::with_tag("create-users")
+            .up("create table users (id integer primary key, name text);")
+            .down("drop table users;")
+            .boxed(),
+        EmbeddedMigration::with_tag("create-places")
+            .up("create table places (id integer primary key, name text);")
+            .down("drop table places;")
+            .boxed(),
+    ]
+}
+
+fn tenant_config(dir: &std::path::Path, tenants: Tenants) -> Config {
+    let settings = Settings::configure_sqlite()
+        .database_path(dir.join("base.db"))
+        .unwrap()
+        .build()
+        .unwrap()
+        .with_tenants(tenants);
+    let mut config = Config::with_settings(settings);
+    config.use_migrations(migrations()).unwrap();
+    config
+}
+
+fn applied_count(config: &Config) -> usize {
+    migrant_lib::migration_statuses(&config.reload().unwrap())
+        .unwrap()
+        .iter()
+        .filter(|s| s.applied())
+        .count()
+}
+
+#[test]
+fn apply_tenants_migrates_every_listed_database() {
+    let dir = tempfile::tempdir().unwrap();
+    let paths: Vec<String> = ["a", "b", "c"]
+        .iter()
+        .map(|t| dir.path().join(format!("{}.db", t)).display().to_string())
+        .collect();
+    let config = tenant_config(dir.path(), Tenants::list(&paths).parallelism(2));
+
+    let reports = Migrator::with_config(&config)
+        .all(true)
+        .show_output(false)
+        .apply_tenants()
+        .unwrap();
+    assert_eq!(3, reports.len());
+    for (report, path) in reports.iter().zip(&paths) {
+        assert_eq!(path, report.tenant().name());
+        let run = report.result().unwrap().as_ref().unwrap();
+        assert_eq!(vec!["create-users", "create-places"], run.tags());
+    }
+
+    for tenant in config.tenants().unwrap() {
+        assert_eq!(2, applied_count(&config.for_tenant(&tenant).unwrap()));
+    }
+
+    let reports = Migrator::with_config(&config)
+        .direction(Direction::Down)
+        .show_output(false)
+        .apply_tenants()
+        .unwrap();
+    assert!(reports.iter().all(|r| r.is_ok()));
+    for tenant in config.tenants().unwrap() {
+        assert_eq!(1, applied_count(&config.for_tenant(&tenant).unwrap()));
+    }
+}
+
+#[test]
+fn sqlite_glob_from_settings_file() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("tenants")).unwrap();
+    for name in ["acme.db", "globex.db", "notes.txt"] {
+        std::fs::write(dir.path().join("tenants").join(name), b"").unwrap();
+    }
+    let settings_path = dir.path().join("Migrant.toml");
+    std::fs::write(
+        &settings_path,
+        "database_type = \"sqlite\"\n\
+         database_path = \"base.db\"\n\
+         \n\
+         [tenants]\n\
+         sqlite_glob = \"tenants/*.db\"\n\
+         parallelism = 2\n",
+    )
+    .unwrap();
+    let mut config = Config::from_settings_file(&settings_path).unwrap();
+    config.use_migrations(migrations()).unwrap();
+
+    let names: Vec<String> = config
+        .tenants()
+        .unwrap()
+        .iter()
+        .map(|t| t.name().to_string())
+        .collect();
+    assert_eq!(vec!["tenants/acme.db", "tenants/globex.db"], names);
+
+    let reports = Migrator::with_config(&config)
+        .all(true)
+        .show_output(false)
+        .apply_tenants()
+        .unwrap();
+    assert!(reports.iter().all(|r| r.is_ok()));
+
+    // a reloaded tenant config stays pointed at its own database
+    let tenant = config.for_tenant(&config.tenants().unwrap()[0]).unwrap();
+    let reloaded = tenant.reload().unwrap();
+    assert_eq!(
+        dir.path().join("tenants/acme.db"),
+        reloaded.database_path().unwrap()
+    );
+    assert_eq!(2, applied_count(&reloaded));
+}
+
+#[test]
+fn stop_policy_leaves_later_tenants_unrun() {
+    let dir = tempfile::tempdir().unwrap();
+    // a directory is not a usable database file, so the first tenant fails
+    std::fs::create_dir(dir.path().join("broken.db")).unwrap();
+    let paths: Vec<String> = ["broken", "b", "c"]
+        .iter()
+        .map(|t| dir.path().join(format!("{}.db", t)).display().to_string())
+        .collect();
+    let config = tenant_config(
+        dir.path(),
+        Tenants::list(&paths)
+            .parallelism(1)
+            .on_failure(OnTenantFailure::Stop),
+    );
+
+    let reports = Migrator::with_config(&config)
+        .all(true)
+        .show_output(false)
+        .apply_tenants()
+        .unwrap();
+    assert!(reports[0].is_err());
+    assert!(reports[1].result().is_none());
+    assert!(reports[2].result().is_none());
+
+    // the default policy continues past the failure
+    let config = tenant_config(dir.path(), Tenants::list(&paths).parallelism(1));
+    let reports = Migrator::with_config(&config)
+        .all(true)
+        .show_output(false)
+        .apply_tenants()
+        .unwrap();
+    assert!(reports[0].is_err());
+    assert!(reports[1].is_ok());
+    assert!(reports[2].is_ok());
+}
+
+#[test]
+fn tenants_require_configuration() {
+    let settings = Settings::configure_sqlite().memory().build().unwrap();
+    let config = Config::with_settings(settings);
+    let err = Migrator::with_config(&config).apply_tenants().unwrap_err();
+    assert!(err.to_string().contains("[tenants]"), "{}", err);
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use clap::{Arg, ArgAction, Command};
 
 /// `--force[=<mode>]`: continue past failed migrations.
@@ -19,17 +21,58 @@ fn force_arg() -> Arg {
         )
 }
 
-/// `--no-sync`: disable the cross-process advisory lock (postgres/mysql).
+/// `--no-sync`: disable the cross-process migration lock (postgres/mysql/sqlite).
 fn no_sync_arg() -> Arg {
     Arg::new("no-sync")
         .long("no-sync")
         .action(ArgAction::SetTrue)
         .help(
-            "Disable the cross-process advisory lock (postgres/mysql). Use only when \
+            "Disable the cross-process migration lock (postgres/mysql/sqlite). Use only when \
              migration runs are serialized by an external mechanism",
         )
 }
 
+/// `--tenants`: fan out over the `[tenants]` configured in `Migrant.toml`.
+fn tenants_arg() -> Arg {
+    Arg::new("tenants")
+        .long("tenants")
+        .action(ArgAction::SetTrue)
+        .help("Run against every tenant listed by the `[tenants]` table in `Migrant.toml`")
+}
+
+/// Parse a duration: a number followed by `ms`, `s`, `m` or `h`, or a bare
+/// number of seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let invalid = || format!("invalid duration `{}`, expected e.g. `30s` or `2m`", s);
+    let n: u64 = number.parse().map_err(|_| invalid())?;
+    let secs = |factor: u64| n.checked_mul(factor).map(Duration::from_secs);
+    match unit.trim() {
+        "ms" => Ok(Duration::from_millis(n)),
+        "" | "s" => Ok(Duration::from_secs(n)),
+        "m" => secs(60).ok_or_else(invalid),
+        "h" => secs(60 * 60).ok_or_else(invalid),
+        other => Err(format!(
+            "invalid duration unit `{}`, expected `ms`, `s`, `m` or `h`",
+            other
+        )),
+    }
+}
+
+/// `--wait <duration>`: wait for the database to accept connections first.
+fn wait_arg() -> Arg {
+    Arg::new("wait")
+        .long("wait")
+        .value_name("duration")
+        .value_parser(parse_duration)
+        .help(
+            "Wait up to this long (e.g. `30s`, `2m`) for the database to accept connections, \
+             retrying with backoff",
+        )
+}
+
 pub fn build_cli() -> Command {
     Command::new("migrant")
         .version(env!("CARGO_PKG_VERSION"))
@@ -78,7 +121,7 @@ pub fn build_cli() -> Command {
                     Arg::new("type")
                         .long("type")
                         .short('t')
-                        .help("Specify the database type (sqlite|postgres|mysql)"),
+                        .help("Specify the database type (sqlite|postgres|mysql|duckdb)"),
                 )
                 .arg(
                     Arg::new("location")
@@ -99,10 +142,26 @@ pub fn build_cli() -> Command {
                         .help("Disable interactive prompts"),
                 ),
         )
-        .subcommand(Command::new("setup").about("Setup migration table"))
+        .subcommand(
+            Command::new("setup")
+                .about("Setup migration table")
+                .arg(wait_arg()),
+        )
+        .subcommand(
+            Command::new("wait")
+                .about("Wait for the database to accept connections, retrying with backoff")
+                .arg(
+                    Arg::new("timeout")
+                        .long("timeout")
+                        .value_name("duration")
+                        .value_parser(parse_duration)
+                        .default_value("60s")
+                        .help("Give up after this long, e.g. `30s` or `2m`"),
+                ),
+        )
         .subcommand(
             Command::new("connect-string")
-                .about("Print out the connection string for server databases (postgres/mysql), or file-path for sqlite"),
+                .about("Print out the connection string for server databases (postgres/mysql), or file-path for sqlite/duckdb"),
         )
         .subcommand(
             Command::new("list").about("List status of applied and available migrations"),
@@ -118,7 +177,8 @@ pub fn build_cli() -> Command {
                         .default_value("text")
                         .value_name("format")
                         .help("Output format: `text` (default) or `json`"),
-                ),
+                )
+                .arg(tenants_arg()),
         )
         .subcommand(
             Command::new("apply")
@@ -144,7 +204,9 @@ pub fn build_cli() -> Command {
                         .action(ArgAction::SetTrue)
                         .help("Updates the migration table without running the migration"),
                 )
-                .arg(no_sync_arg()),
+                .arg(no_sync_arg())
+                .arg(tenants_arg())
+                .arg(wait_arg()),
         )
         .subcommand(
             Command::new("redo")
@@ -159,6 +221,21 @@ pub fn build_cli() -> Command {
                 .arg(force_arg())
                 .arg(no_sync_arg()),
         )
+        .subcommand(
+            Command::new("restore")
+                .about("Replace the sqlite database with a backup taken before a migration run")
+                .arg(
+                    Arg::new("backup")
+                        .required(true)
+                        .help("Path to the backup file"),
+                )
+                .arg(
+                    Arg::new("no-confirm")
+                        .long("no-confirm")
+                        .action(ArgAction::SetTrue)
+                        .help("Disable interactive prompts"),
+                ),
+        )
         .subcommand(
             Command::new("new")
                 .about("Create new migration up/down files")
@@ -166,6 +243,45 @@ pub fn build_cli() -> Command {
                     Arg::new("tag")
                         .required(true)
                         .help("tag to use for new migration"),
+                )
+                .arg(
+                    Arg::new("single-file")
+                        .long("single-file")
+                        .action(ArgAction::SetTrue)
+                        .help(
+                            "Create one `<version>_<tag>.sql` file with `-- migrate:up` and \
+                             `-- migrate:down` sections instead of an up/down directory",
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Convert another tool's migrations into migrant migrations, recording the ones its bookkeeping table lists as applied")
+                .arg(
+                    Arg::new("from")
+                        .long("from")
+                        .required(true)
+                        .value_parser(["diesel", "sqlx", "refinery", "golang-migrate", "flyway"])
+                        .value_name("tool")
+                        .help("Tool the migrations were written for"),
+                )
+                .arg(
+                    Arg::new("dir")
+                        .required(true)
+                        .help("Directory holding the tool's migrations"),
+                )
+                .arg(
+                    Arg::new("history-table")
+                        .long("history-table")
+                        .value_name("table")
+                        .help("Name of the tool's bookkeeping table, if not the tool's default"),
+                )
+                .arg(
+                    Arg::new("no-history")
+                        .long("no-history")
+                        .action(ArgAction::SetTrue)
+                        .conflicts_with("history-table")
+                        .help("Only convert the migration files, without reading the database"),
                 ),
         )
         .subcommand(Command::new("shell").about("Open a repl connection"))
@@ -178,7 +294,7 @@ pub fn build_cli() -> Command {
                         .long("down")
                         .short('d')
                         .action(ArgAction::SetTrue)
-                        .help("Edit the down.sql file"),
+                        .help("Edit the down.sql file (single-file migrations have one file)"),
                 ),
         )
         .subcommand(
@@ -199,4 +315,19 @@ mod tests {
     fn cli_is_well_formed() {
         build_cli().debug_assert();
     }
+
+    #[test]
+    fn durations_parse_with_units() {
+        assert_eq!(Ok(Duration::from_millis(250)), parse_duration("250ms"));
+        assert_eq!(Ok(Duration::from_secs(30)), parse_duration("30s"));
+        assert_eq!(Ok(Duration::from_secs(30)), parse_duration("30"));
+        assert_eq!(Ok(Duration::from_secs(120)), parse_duration("2m"));
+        assert_eq!(Ok(Duration::from_secs(3600)), parse_duration("1h"));
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("s").is_err());
+        assert!(parse_duration("10d").is_err());
+        assert!(parse_duration("-5s").is_err());
+        assert!(parse_duration(&format!("{}m", u64::MAX / 60 + 1)).is_err());
+        assert!(parse_duration(&format!("{}h", u64::MAX)).is_err());
+    }
 }
@@ -2,9 +2,15 @@ use std::env;
 use std::fs;
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use migrant_lib::config::{MySqlSettingsBuilder, PostgresSettingsBuilder, SqliteSettingsBuilder};
-use migrant_lib::{Config, DbKind, Direction, ForceMode, Migrator};
+use migrant_lib::config::{
+    DuckDbSettingsBuilder, MySqlSettingsBuilder, PostgresSettingsBuilder, SqliteSettingsBuilder,
+};
+use migrant_lib::{
+    CancelToken, Config, DbKind, Direction, ForceMode, ImportSource, Importer, Migrator, Report,
+    TenantResult,
+};
 
 mod cli;
 mod status;
@@ -12,6 +18,10 @@ mod tui;
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
+/// Delay before the first retry while waiting for the database, doubled
+/// after each further attempt
+const WAIT_BACKOFF: Duration = Duration::from_millis(250);
+
 #[cfg(feature = "update")]
 static APP_VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -48,6 +58,7 @@ fn run(dir: &Path, matches: &clap::ArgMatches) -> Result<()> {
                     initializer.with_postgres_options(PostgresSettingsBuilder::empty())
                 }
                 DbKind::MySql => initializer.with_mysql_options(MySqlSettingsBuilder::empty()),
+                DbKind::DuckDb => initializer.with_duckdb_options(DuckDbSettingsBuilder::empty()),
                 _ => initializer, // DbKind is #[non_exhaustive]
             };
         }
@@ -72,11 +83,17 @@ fn run(dir: &Path, matches: &clap::ArgMatches) -> Result<()> {
     config.use_cli_compatible_tags(true);
 
     match matches.subcommand() {
-        Some(("setup", _)) => {
+        Some(("setup", matches)) => {
+            wait_for_database(&config, matches)?;
             config.setup()?;
         }
+        Some(("wait", matches)) => {
+            let timeout = *matches.get_one::<Duration>("timeout").expect("defaulted");
+            config.wait_for_database(timeout, WAIT_BACKOFF)?;
+            println!("Database is available");
+        }
         Some(("connect-string", _)) => match config.database_type() {
-            DbKind::Sqlite => {
+            DbKind::Sqlite | DbKind::DuckDb => {
                 let path = config.database_path()?;
                 let path = path
                     .to_str()
@@ -94,6 +111,21 @@ fn run(dir: &Path, matches: &clap::ArgMatches) -> Result<()> {
 
             migrant_lib::list(&config)?;
         }
+        Some(("status", matches)) if matches.get_flag("tenants") => {
+            let results = config.for_each_tenant(|tenant| {
+                // load the tenant's applied migrations from its database
+                migrant_lib::migration_statuses(&tenant.reload()?)
+            })?;
+            let report = status::TenantStatusReport::from_results(&results);
+            let json = matches.get_one::<String>("format").map(String::as_str) == Some("json");
+            if json {
+                println!("{}", report.render_json()?);
+            } else {
+                println!("{}", report.render_text());
+            }
+            // the json output carries each tenant's error; keep stdout parseable
+            check_tenants(&results, !json)?;
+        }
         Some(("status", matches)) => {
             // load applied migrations from the database
             let config = config.reload()?;
@@ -110,13 +142,14 @@ fn run(dir: &Path, matches: &clap::ArgMatches) -> Result<()> {
             let config = config.reload()?;
 
             let tag = matches.get_one::<String>("tag").expect("required arg");
-            migrant_lib::new(&config, tag)?;
+            if matches.get_flag("single-file") {
+                migrant_lib::new_single_file(&config, tag)?;
+            } else {
+                migrant_lib::new(&config, tag)?;
+            }
             migrant_lib::list(&config)?;
         }
         Some(("apply", matches)) => {
-            // load applied migrations from the database
-            let config = config.reload()?;
-
             let force = force_mode(matches)?;
             let fake = matches.get_flag("fake");
             let all = matches.get_flag("all");
@@ -127,16 +160,41 @@ fn run(dir: &Path, matches: &clap::ArgMatches) -> Result<()> {
                 Direction::Up
             };
 
-            Migrator::with_config(&config)
+            wait_for_database(&config, matches)?;
+            let cancel = cancel_on_signal()?;
+
+            if matches.get_flag("tenants") {
+                // each tenant is set up and loads its own applied migrations
+                let reports = Migrator::with_config(&config)
+                    .direction(direction)
+                    .force(force)
+                    .fake(fake)
+                    .all(all)
+                    .synchronized(!no_sync)
+                    .cancel_on(cancel.clone())
+                    .apply_tenants()?;
+                check_tenants(&reports, true)?;
+                if cancel.is_cancelled() {
+                    return Err("Migration run cancelled".into());
+                }
+                return Ok(());
+            }
+
+            // load applied migrations from the database
+            let config = config.reload()?;
+
+            let report = Migrator::with_config(&config)
                 .direction(direction)
                 .force(force)
                 .fake(fake)
                 .all(all)
                 .synchronized(!no_sync)
+                .cancel_on(cancel)
                 .apply()?;
 
             let config = config.reload()?;
             migrant_lib::list(&config)?;
+            check_cancelled(&report)?;
         }
         Some(("redo", matches)) => {
             // load applied migrations from the database
@@ -145,24 +203,78 @@ fn run(dir: &Path, matches: &clap::ArgMatches) -> Result<()> {
             let force = force_mode(matches)?;
             let all = matches.get_flag("all");
             let no_sync = matches.get_flag("no-sync");
+            let cancel = cancel_on_signal()?;
 
-            Migrator::with_config(&config)
+            let report = Migrator::with_config(&config)
                 .direction(Direction::Down)
                 .force(force)
                 .all(all)
                 .synchronized(!no_sync)
+                .cancel_on(cancel.clone())
                 .apply()?;
             let config = config.reload()?;
             migrant_lib::list(&config)?;
+            // don't start the `up` half of a cancelled redo
+            check_cancelled(&report)?;
 
-            Migrator::with_config(&config)
+            let report = Migrator::with_config(&config)
                 .direction(Direction::Up)
                 .force(force)
                 .all(all)
                 .synchronized(!no_sync)
+                .cancel_on(cancel)
                 .apply()?;
             let config = config.reload()?;
             migrant_lib::list(&config)?;
+            check_cancelled(&report)?;
+        }
+        Some(("restore", matches)) => {
+            let backup = dir.join(matches.get_one::<String>("backup").expect("required arg"));
+            if !matches.get_flag("no-confirm") {
+                let prompt = format!(
+                    "** The database will be replaced with the backup: `{}`\n** Is this Ok? [Y/n] ",
+                    backup.display()
+                );
+                confirm(&prompt)?;
+            }
+            config.restore_database(&backup)?;
+            println!("Restored the database from: {}", backup.display());
+
+            // load applied migrations from the restored database
+            let config = config.reload()?;
+            migrant_lib::list(&config)?;
+        }
+        Some(("import", matches)) => {
+            let from = matches.get_one::<String>("from").expect("required arg");
+            let dir = matches.get_one::<String>("dir").expect("required arg");
+            let mut importer = Importer::new(&config, from.parse::<ImportSource>()?, dir)
+                .record_applied(!matches.get_flag("no-history"));
+            if let Some(table) = matches.get_one::<String>("history-table") {
+                importer = importer.history_table(table);
+            }
+            let report = importer.import()?;
+            for tag in report.migrations() {
+                let applied = if report.recorded().contains(tag) {
+                    " (applied)"
+                } else {
+                    ""
+                };
+                println!("Imported: {}{}", tag, applied);
+            }
+            for skipped in report.skipped() {
+                println!("Skipped: {}", skipped);
+            }
+            if !matches.get_flag("no-history") && !report.history_found() {
+                println!(
+                    "No {} bookkeeping table found: nothing recorded as applied",
+                    from
+                );
+            }
+            println!(
+                "{} migration(s) imported, {} recorded as applied",
+                report.migrations().len(),
+                report.recorded().len()
+            );
         }
         Some(("shell", _)) => {
             migrant_lib::cli::shell(&config)?;
@@ -192,6 +304,71 @@ fn run(dir: &Path, matches: &clap::ArgMatches) -> Result<()> {
     Ok(())
 }
 
+/// Wait for the database as long as `--wait` allows, if given
+fn wait_for_database(config: &Config, matches: &clap::ArgMatches) -> Result<()> {
+    if let Some(&timeout) = matches.get_one::<Duration>("wait") {
+        config.wait_for_database(timeout, WAIT_BACKOFF)?;
+    }
+    Ok(())
+}
+
+/// Cancel the returned token on the first SIGINT/SIGTERM, so a migration run
+/// stops after its current migration and releases its lock. A second signal
+/// exits immediately.
+fn cancel_on_signal() -> Result<CancelToken> {
+    let cancel = CancelToken::new();
+    let handle = cancel.clone();
+    ctrlc::set_handler(move || {
+        if handle.is_cancelled() {
+            std::process::exit(130);
+        }
+        eprintln!(
+            "Cancelling: finishing the current migration \
+             (press Ctrl-C again to exit immediately)"
+        );
+        handle.cancel();
+    })?;
+    Ok(cancel)
+}
+
+/// Fail a run that stopped early because it was cancelled
+fn check_cancelled(report: &Report) -> Result<()> {
+    if report.is_cancelled() {
+        return Err(format!(
+            "Migration run cancelled after {} migration(s)",
+            report.len()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Print a one-line summary of a `--tenants` run (if `summary`), failing if
+/// any tenant failed or was not run.
+fn check_tenants<T>(results: &[TenantResult<T>], summary: bool) -> Result<()> {
+    let failed = results.iter().filter(|r| r.is_err()).count();
+    let not_run = results.iter().filter(|r| r.result().is_none()).count();
+    if summary {
+        println!(
+            "Tenants: {} succeeded, {} failed, {} not run ({} total)",
+            results.len() - failed - not_run,
+            failed,
+            not_run,
+            results.len()
+        );
+    }
+    if failed > 0 || not_run > 0 {
+        return Err(format!(
+            "{} failed, {} not run of {} tenants",
+            failed,
+            not_run,
+            results.len()
+        )
+        .into());
+    }
+    Ok(())
+}
+
 /// Map the optional-valued `--force[=<mode>]` flag to a `ForceMode`.
 /// Bare `--force` carries the default-missing-value `accept-failures`.
 fn force_mode(matches: &clap::ArgMatches) -> Result<ForceMode> {
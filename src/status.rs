@@ -5,14 +5,52 @@
 //! serializable [`StatusReport`] so both formats are unit-testable without a
 //! live database.
 
-use migrant_lib::MigrationStatus;
+use migrant_lib::{MigrationStatus, TenantResult};
 use serde::Serialize;
 
-/// A single migration's tag and whether it is currently applied.
-#[derive(Debug, Clone, Serialize)]
+/// A single migration's tag, whether it is currently applied, and its
+/// metadata headers.
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct StatusRow {
     pub tag: String,
     pub applied: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ticket: Option<String>,
+}
+
+impl StatusRow {
+    /// Build a row from the library's migration status.
+    pub fn from_status(status: &MigrationStatus) -> Self {
+        let metadata = status.metadata();
+        StatusRow {
+            tag: status.tag().to_string(),
+            applied: status.applied(),
+            description: metadata.description().map(str::to_string),
+            author: metadata.author().map(str::to_string),
+            ticket: metadata.ticket().map(str::to_string),
+        }
+    }
+
+    /// The row's metadata as text: ` - description (author: .., ticket: ..)`,
+    /// or an empty string without any.
+    pub fn metadata_text(&self) -> String {
+        let mut out = String::new();
+        if let Some(ref description) = self.description {
+            out.push_str(&format!(" - {}", description));
+        }
+        let details = [("author", &self.author), ("ticket", &self.ticket)]
+            .into_iter()
+            .filter_map(|(name, value)| Some(format!("{}: {}", name, value.as_ref()?)))
+            .collect::<Vec<_>>();
+        if !details.is_empty() {
+            out.push_str(&format!(" ({})", details.join(", ")));
+        }
+        out
+    }
 }
 
 /// The full migration-table status: per-migration rows plus summary counts.
@@ -28,13 +66,7 @@ impl StatusReport {
     /// Build a report from the library's migration statuses, computing the
     /// summary counts.
     pub fn from_statuses(statuses: &[MigrationStatus]) -> Self {
-        let migrations: Vec<StatusRow> = statuses
-            .iter()
-            .map(|s| StatusRow {
-                tag: s.tag().to_string(),
-                applied: s.applied(),
-            })
-            .collect();
+        let migrations: Vec<StatusRow> = statuses.iter().map(StatusRow::from_status).collect();
         let applied = migrations.iter().filter(|r| r.applied).count();
         StatusReport {
             total: migrations.len(),
@@ -45,7 +77,7 @@ impl StatusReport {
     }
 
     /// Render the report as human-readable text: a summary line followed by one
-    /// `[✓]`/`[ ]` row per migration.
+    /// `[✓]`/`[ ]` row per migration, with any description, author and ticket.
     pub fn render_text(&self) -> String {
         let mut out = format!(
             "Migration status: {} applied, {} pending ({} total)",
@@ -53,9 +85,10 @@ impl StatusReport {
         );
         for row in &self.migrations {
             out.push_str(&format!(
-                "\n  [{}] {}",
+                "\n  [{}] {}{}",
                 if row.applied { '✓' } else { ' ' },
-                row.tag
+                row.tag,
+                row.metadata_text()
             ));
         }
         out
@@ -67,6 +100,69 @@ impl StatusReport {
     }
 }
 
+/// One tenant's status: its migration report, or why it has none.
+#[derive(Debug, Clone, Serialize)]
+pub struct TenantStatus {
+    pub tenant: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub status: Option<StatusReport>,
+}
+
+/// `status --tenants`: a status report per configured tenant.
+#[derive(Debug, Clone, Serialize)]
+#[serde(transparent)]
+pub struct TenantStatusReport {
+    pub tenants: Vec<TenantStatus>,
+}
+
+impl TenantStatusReport {
+    /// Build a report from the per-tenant migration statuses of a fan-out.
+    pub fn from_results(results: &[TenantResult<Vec<MigrationStatus>>]) -> Self {
+        let tenants = results
+            .iter()
+            .map(|r| {
+                let (status, error) = match r.result() {
+                    Some(Ok(statuses)) => (Some(StatusReport::from_statuses(statuses)), None),
+                    Some(Err(e)) => (None, Some(e.to_string())),
+                    None => (
+                        None,
+                        Some("not run: stopped after an earlier tenant failed".to_string()),
+                    ),
+                };
+                TenantStatus {
+                    tenant: r.tenant().name().to_string(),
+                    error,
+                    status,
+                }
+            })
+            .collect();
+        TenantStatusReport { tenants }
+    }
+
+    /// Render each tenant's text report (or error) under a `Tenant` heading.
+    pub fn render_text(&self) -> String {
+        self.tenants
+            .iter()
+            .map(|t| {
+                let body = match (&t.status, &t.error) {
+                    (Some(status), _) => status.render_text(),
+                    (None, Some(error)) => format!("** Error ** {}", error),
+                    (None, None) => String::new(),
+                };
+                format!("Tenant `{}`: {}", t.tenant, body)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Render the report as a pretty-printed JSON array, one object per tenant.
+    pub fn render_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -76,10 +172,14 @@ mod tests {
             StatusRow {
                 tag: "20170812145327_initial".to_string(),
                 applied: true,
+                ..StatusRow::default()
             },
             StatusRow {
                 tag: "20171126194042_second".to_string(),
                 applied: false,
+                description: Some("Add an email column".to_string()),
+                author: Some("alice".to_string()),
+                ticket: Some("APP-142".to_string()),
             },
         ]
     }
@@ -110,8 +210,10 @@ mod tests {
             text.starts_with("Migration status: 1 applied, 1 pending (2 total)"),
             "unexpected summary line: {text}"
         );
-        assert!(text.contains("[✓] 20170812145327_initial"));
-        assert!(text.contains("[ ] 20171126194042_second"));
+        assert!(text.contains("[✓] 20170812145327_initial\n"));
+        assert!(text.contains(
+            "[ ] 20171126194042_second - Add an email column (author: alice, ticket: APP-142)"
+        ));
         // one summary line + one line per migration
         assert_eq!(text.lines().count(), 3);
     }
@@ -138,5 +240,56 @@ mod tests {
         assert_eq!(value["migrations"][0]["tag"], "20170812145327_initial");
         assert_eq!(value["migrations"][0]["applied"], true);
         assert_eq!(value["migrations"][1]["applied"], false);
+        assert!(value["migrations"][0].get("description").is_none());
+        assert_eq!(value["migrations"][1]["description"], "Add an email column");
+        assert_eq!(value["migrations"][1]["author"], "alice");
+        assert_eq!(value["migrations"][1]["ticket"], "APP-142");
+    }
+
+    #[test]
+    fn metadata_text_omits_missing_fields() {
+        let row = StatusRow {
+            ticket: Some("APP-1".to_string()),
+            ..StatusRow::default()
+        };
+        assert_eq!(row.metadata_text(), " (ticket: APP-1)");
+        assert_eq!(StatusRow::default().metadata_text(), "");
+    }
+
+    fn tenant_report() -> TenantStatusReport {
+        TenantStatusReport {
+            tenants: vec![
+                TenantStatus {
+                    tenant: "acme".to_string(),
+                    error: None,
+                    status: Some(report()),
+                },
+                TenantStatus {
+                    tenant: "globex".to_string(),
+                    error: Some("ConfigError: no such schema".to_string()),
+                    status: None,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn tenant_text_heads_each_tenant() {
+        let text = tenant_report().render_text();
+        assert!(text.starts_with("Tenant `acme`: Migration status: 1 applied"));
+        assert!(text.contains("[✓] 20170812145327_initial"));
+        assert!(text.contains("Tenant `globex`: ** Error ** ConfigError: no such schema"));
+    }
+
+    #[test]
+    fn tenant_json_flattens_status_per_tenant() {
+        let json = tenant_report().render_json().unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value[0]["tenant"], "acme");
+        assert_eq!(value[0]["applied"], 1);
+        assert!(value[0].get("error").is_none());
+        assert_eq!(value[1]["tenant"], "globex");
+        assert_eq!(value[1]["error"], "ConfigError: no such schema");
+        assert!(value[1].get("migrations").is_none());
     }
 }
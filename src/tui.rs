@@ -9,6 +9,8 @@ use ratatui::text::{Line, Span};
 use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
 use ratatui::{DefaultTerminal, Frame};
 
+use crate::status::StatusRow;
+
 pub fn run(config: &Config) -> super::Result<()> {
     use std::io::IsTerminal;
     if !std::io::stdout().is_terminal() {
@@ -183,6 +185,10 @@ impl App {
                 ListItem::new(Line::from(vec![
                     Span::styled(format!("[{}] ", mark), style),
                     Span::raw(mig.tag().to_string()),
+                    Span::styled(
+                        StatusRow::from_status(mig).metadata_text(),
+                        Style::new().fg(Color::DarkGray),
+                    ),
                 ]))
             })
             .collect::<Vec<_>>();
@@ -218,7 +224,7 @@ mod tests {
         config
             .use_migrations(&[
                 EmbeddedMigration::with_tag("create-users")
-                    .up("create table users (id integer primary key);")
+                    .up("-- migrant:description Users\ncreate table users (id integer primary key);")
                     .down("drop table users;")
                     .boxed(),
                 EmbeddedMigration::with_tag("create-posts")
@@ -262,7 +268,7 @@ mod tests {
         let text = buffer_text(&mut app);
         assert!(text.contains("migrant"));
         assert!(text.contains("0/2 applied"));
-        assert!(text.contains("[ ] create-users"));
+        assert!(text.contains("[ ] create-users - Users"));
         assert!(text.contains("[ ] create-posts"));
 
         app.handle_key(key(KeyCode::Char('u')));
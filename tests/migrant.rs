@@ -401,3 +401,466 @@ fn apply_no_sync_applies_migrations() {
         .stdout(predicates::str::is_match(r"\[✓\] \d{14}_first").expect("valid regex"))
         .stdout(predicates::str::is_match(r"\[✓\] \d{14}_second").expect("valid regex"));
 }
+
+#[test]
+fn tenants_apply_and_status() {
+    let dir = sqlite_project();
+    let mut toml = std::fs::read_to_string(dir.path().join("Migrant.toml")).unwrap();
+    toml.push_str("\n[tenants]\nsqlite_glob = \"tenants/*.db\"\n");
+    std::fs::write(dir.path().join("Migrant.toml"), toml).expect("write Migrant.toml");
+    std::fs::create_dir(dir.path().join("tenants")).expect("create tenants dir");
+    for name in ["acme", "globex"] {
+        std::fs::write(dir.path().join("tenants").join(format!("{}.db", name)), "")
+            .expect("create tenant database");
+    }
+    migrant()
+        .current_dir(dir.path())
+        .arg("setup")
+        .assert()
+        .success();
+    new_migration(
+        dir.path(),
+        "first",
+        "create table tenant_a (x integer);",
+        "drop table tenant_a;",
+    );
+
+    migrant()
+        .current_dir(dir.path())
+        .args(["apply", "--all", "--tenants"])
+        .assert()
+        .success()
+        .stdout(contains(
+            "Tenant `tenants/acme.db`: applied[Up] 1 migration(s)",
+        ))
+        .stdout(contains(
+            "Tenant `tenants/globex.db`: applied[Up] 1 migration(s)",
+        ))
+        .stdout(contains(
+            "Tenants: 2 succeeded, 0 failed, 0 not run (2 total)",
+        ));
+
+    migrant()
+        .current_dir(dir.path())
+        .args(["status", "--tenants"])
+        .assert()
+        .success()
+        .stdout(contains("Tenant `tenants/acme.db`:"))
+        .stdout(contains("Migration status: 1 applied, 0 pending (1 total)"));
+
+    let out = migrant()
+        .current_dir(dir.path())
+        .args(["status", "--tenants", "--format", "json"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(out.get_output().stdout.clone()).expect("utf8 stdout");
+    let value: serde_json::Value = serde_json::from_str(&stdout).expect("valid json");
+    let tenants = value.as_array().expect("array");
+    assert_eq!(tenants.len(), 2);
+    assert_eq!(tenants[0]["tenant"], "tenants/acme.db");
+    assert_eq!(tenants[1]["applied"], 1);
+}
+
+#[test]
+fn tenants_stop_reports_tenants_not_run() {
+    let dir = sqlite_project();
+    let mut toml = std::fs::read_to_string(dir.path().join("Migrant.toml")).unwrap();
+    toml.push_str(
+        "\n[tenants]\nsqlite_glob = \"tenants/*.db\"\nparallelism = 1\non_failure = \"stop\"\n",
+    );
+    std::fs::write(dir.path().join("Migrant.toml"), toml).expect("write Migrant.toml");
+    std::fs::create_dir(dir.path().join("tenants")).expect("create tenants dir");
+    // not a sqlite database, so the first tenant fails
+    std::fs::write(dir.path().join("tenants").join("acme.db"), "x".repeat(512))
+        .expect("create tenant database");
+    std::fs::write(dir.path().join("tenants").join("globex.db"), "")
+        .expect("create tenant database");
+    migrant()
+        .current_dir(dir.path())
+        .arg("setup")
+        .assert()
+        .success();
+    new_migration(
+        dir.path(),
+        "first",
+        "create table tenant_a (x integer);",
+        "drop table tenant_a;",
+    );
+
+    migrant()
+        .current_dir(dir.path())
+        .args(["apply", "--all", "--tenants"])
+        .assert()
+        .failure()
+        .stdout(contains(
+            "Tenants: 0 succeeded, 1 failed, 1 not run (2 total)",
+        ))
+        .stderr(contains("1 failed, 1 not run of 2 tenants"));
+}
+
+#[test]
+fn tenants_flag_requires_configuration() {
+    let dir = sqlite_project();
+    migrant()
+        .current_dir(dir.path())
+        .args(["apply", "--tenants"])
+        .assert()
+        .failure()
+        .stderr(contains("tenants"));
+}
+
+#[test]
+fn backend_specific_sql_files_take_precedence() {
+    let dir = sqlite_project();
+    migrant()
+        .current_dir(dir.path())
+        .arg("setup")
+        .assert()
+        .success();
+    migrant()
+        .current_dir(dir.path())
+        .args(["new", "variants"])
+        .assert()
+        .success();
+
+    let mig_dir = std::fs::read_dir(dir.path().join("migrations"))
+        .expect("read migrations dir")
+        .map(|e| e.expect("dir entry").path())
+        .find(|p| p.is_dir())
+        .expect("migration directory created");
+    // the generic file is not valid sqlite, the sqlite variant is
+    std::fs::write(
+        mig_dir.join("up.sql"),
+        "create table t (id serial) nonsense;",
+    )
+    .unwrap();
+    std::fs::write(
+        mig_dir.join("up.sqlite.sql"),
+        "create table t (id integer);",
+    )
+    .unwrap();
+    std::fs::write(mig_dir.join("up.postgres.sql"), "nonsense;").unwrap();
+    std::fs::write(mig_dir.join("down.sql"), "drop table t;").unwrap();
+
+    migrant()
+        .current_dir(dir.path())
+        .arg("apply")
+        .assert()
+        .success()
+        .stdout(predicates::str::contains("up.sqlite.sql"))
+        .stdout(predicates::str::is_match(r"\[✓\] \d{14}_variants").expect("valid regex"));
+    migrant()
+        .current_dir(dir.path())
+        .args(["apply", "--down"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn new_single_file_creates_an_applicable_migration() {
+    let dir = sqlite_project();
+    migrant()
+        .current_dir(dir.path())
+        .arg("setup")
+        .assert()
+        .success();
+    migrant()
+        .current_dir(dir.path())
+        .args(["new", "--single-file", "flat"])
+        .assert()
+        .success();
+
+    let file = std::fs::read_dir(dir.path().join("migrations"))
+        .expect("read migrations dir")
+        .map(|e| e.expect("dir entry").path())
+        .find(|p| p.extension().is_some_and(|e| e == "sql"))
+        .expect("single-file migration created");
+    let stem = file.file_stem().and_then(|s| s.to_str()).unwrap();
+    assert!(stem.ends_with("_flat"), "{}", stem);
+    let template = std::fs::read_to_string(&file).unwrap();
+    assert!(template.contains("-- migrate:up"));
+    assert!(template.contains("-- migrate:down"));
+    std::fs::write(
+        &file,
+        "-- migrate:up\ncreate table flat (x integer);\n-- migrate:down\ndrop table flat;\n",
+    )
+    .unwrap();
+
+    migrant()
+        .current_dir(dir.path())
+        .arg("apply")
+        .assert()
+        .success()
+        .stdout(predicates::str::is_match(r"\[✓\] \d{14}_flat").expect("valid regex"));
+    migrant()
+        .current_dir(dir.path())
+        .args(["apply", "--down"])
+        .assert()
+        .success()
+        .stdout(predicates::str::is_match(r"\[ \] \d{14}_flat").expect("valid regex"));
+}
+
+#[test]
+fn import_converts_another_tools_migrations() {
+    let dir = sqlite_project();
+    let source = dir.path().join("refinery");
+    std::fs::create_dir(&source).unwrap();
+    std::fs::write(
+        source.join("V1__create_things.sql"),
+        "create table things (x integer);",
+    )
+    .unwrap();
+    std::fs::write(
+        source.join("V2__seed_things.sql"),
+        "insert into things values (1);",
+    )
+    .unwrap();
+
+    migrant()
+        .current_dir(dir.path())
+        .args(["import", "--from", "refinery", "refinery"])
+        .assert()
+        .success()
+        .stdout(contains("Imported: 19700101000000_create-things"))
+        .stdout(contains("Imported: 19700101000001_seed-things"))
+        .stdout(contains("No refinery bookkeeping table found"))
+        .stdout(contains("2 migration(s) imported, 0 recorded as applied"));
+    migrant()
+        .current_dir(dir.path())
+        .arg("setup")
+        .assert()
+        .success();
+    migrant()
+        .current_dir(dir.path())
+        .args(["apply", "--all"])
+        .assert()
+        .success()
+        .stdout(contains("[✓] 19700101000001_seed-things"));
+
+    migrant()
+        .current_dir(dir.path())
+        .args(["import", "--from", "liquibase", "refinery"])
+        .assert()
+        .failure();
+}
+
+#[cfg(unix)]
+#[test]
+fn script_migrations_run_with_connection_env() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = sqlite_project();
+    migrant()
+        .current_dir(dir.path())
+        .arg("setup")
+        .assert()
+        .success();
+    new_migration(dir.path(), "backfill", "", "");
+    let mig_dir = std::fs::read_dir(dir.path().join("migrations"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .find(|p| p.is_dir())
+        .unwrap();
+    std::fs::remove_file(mig_dir.join("up.sql")).unwrap();
+    std::fs::remove_file(mig_dir.join("down.sql")).unwrap();
+    // an executable `up` and a plain `down.sh`, run with `sh`
+    let up = mig_dir.join("up");
+    std::fs::write(
+        &up,
+        "#!/bin/sh\n\
+         test -f \"$MIGRANT_DATABASE_PATH\" || exit 9\n\
+         echo \"$MIGRANT_DIRECTION $MIGRANT_DATABASE_TYPE $MIGRANT_TAG\" > ran.txt\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&up, std::fs::Permissions::from_mode(0o755)).unwrap();
+    std::fs::write(
+        mig_dir.join("down.sh"),
+        "echo \"$MIGRANT_DIRECTION\" > ran.txt\necho 'cannot undo' >&2\nexit 3\n",
+    )
+    .unwrap();
+
+    migrant()
+        .current_dir(dir.path())
+        .arg("apply")
+        .assert()
+        .success()
+        .stdout(predicates::str::is_match(r"\[✓\] \d{14}_backfill").expect("valid regex"));
+    let ran = std::fs::read_to_string(mig_dir.join("ran.txt")).unwrap();
+    assert!(ran.starts_with("up sqlite "), "{}", ran);
+    assert!(ran.trim_end().ends_with("_backfill"), "{}", ran);
+
+    migrant()
+        .current_dir(dir.path())
+        .args(["apply", "--down"])
+        .assert()
+        .failure()
+        .stderr(contains("script exited with status 3"))
+        .stderr(contains("cannot undo"));
+    let ran = std::fs::read_to_string(mig_dir.join("ran.txt")).unwrap();
+    assert_eq!(ran, "down\n");
+}
+
+#[test]
+fn sequence_version_scheme_numbers_new_migrations() {
+    let dir = sqlite_project();
+    let config = dir.path().join("Migrant.toml");
+    let toml = std::fs::read_to_string(&config).unwrap();
+    std::fs::write(&config, format!("{}version_scheme = \"sequence\"\n", toml)).unwrap();
+    migrant()
+        .current_dir(dir.path())
+        .arg("setup")
+        .assert()
+        .success();
+    let nine = dir.path().join("migrations/0009_nine");
+    std::fs::create_dir_all(&nine).unwrap();
+    std::fs::write(nine.join("up.sql"), "create table nine (x integer);").unwrap();
+    std::fs::write(nine.join("down.sql"), "drop table nine;").unwrap();
+
+    new_migration(
+        dir.path(),
+        "ten",
+        "insert into nine values (10);",
+        "delete from nine;",
+    );
+    assert!(dir.path().join("migrations/0010_ten").is_dir());
+
+    migrant()
+        .current_dir(dir.path())
+        .args(["apply", "--all"])
+        .assert()
+        .success()
+        .stdout(
+            predicates::str::is_match(r"(?s)\[✓\] 0009_nine.*\[✓\] 0010_ten").expect("valid regex"),
+        );
+
+    // names must follow the scheme
+    std::fs::create_dir(dir.path().join("migrations/v11_bad")).unwrap();
+    std::fs::write(dir.path().join("migrations/v11_bad/up.sql"), "").unwrap();
+    migrant()
+        .current_dir(dir.path())
+        .arg("list")
+        .assert()
+        .failure()
+        .stderr(contains("Invalid sequence version"));
+}
+
+// `backup_dir` backs the database up before `apply`, and `restore` puts a
+// backup back.
+#[test]
+fn apply_backs_up_and_restore_puts_the_backup_back() {
+    let dir = sqlite_project();
+    let toml = dir.path().join("Migrant.toml");
+    let settings = std::fs::read_to_string(&toml).expect("read Migrant.toml");
+    std::fs::write(&toml, settings + "\n[sqlite]\nbackup_dir = \"backups\"\n")
+        .expect("write Migrant.toml");
+    migrant()
+        .current_dir(dir.path())
+        .arg("setup")
+        .assert()
+        .success();
+    new_migration(
+        dir.path(),
+        "users",
+        "create table users (id integer);",
+        "drop table users;",
+    );
+
+    migrant()
+        .current_dir(dir.path())
+        .args(["apply", "--all"])
+        .assert()
+        .success()
+        .stdout(contains("Backed up the database"))
+        .stdout(predicates::str::is_match(r"\[✓\] \d{14}_users").expect("valid regex"));
+    let backups = std::fs::read_dir(dir.path().join("backups"))
+        .expect("read backups dir")
+        .map(|e| e.expect("dir entry").path())
+        .collect::<Vec<_>>();
+    assert_eq!(backups.len(), 1, "{:?}", backups);
+
+    migrant()
+        .current_dir(dir.path())
+        .args(["restore", "--no-confirm"])
+        .arg(&backups[0])
+        .assert()
+        .success()
+        .stdout(contains("Restored the database"))
+        .stdout(predicates::str::is_match(r"\[ \] \d{14}_users").expect("valid regex"));
+
+    migrant()
+        .current_dir(dir.path())
+        .args(["restore", "--no-confirm", "backups/missing.bak"])
+        .assert()
+        .failure()
+        .stderr(contains("Backup file not found"));
+}
+
+#[test]
+fn wait_returns_once_the_database_is_available() {
+    let dir = sqlite_project();
+    migrant()
+        .current_dir(dir.path())
+        .args(["wait", "--timeout", "1s"])
+        .assert()
+        .success()
+        .stdout(contains("Database is available"));
+    migrant()
+        .current_dir(dir.path())
+        .args(["setup", "--wait", "1s"])
+        .assert()
+        .success();
+    new_migration(
+        dir.path(),
+        "initial",
+        "create table t (id integer);",
+        "drop table t;",
+    );
+    migrant()
+        .current_dir(dir.path())
+        .args(["apply", "--wait", "500ms"])
+        .assert()
+        .success();
+    migrant()
+        .current_dir(dir.path())
+        .args(["setup", "--wait", "soon"])
+        .assert()
+        .failure()
+        .stderr(contains("invalid duration"));
+}
+
+#[test]
+fn settings_values_come_from_files_and_opted_in_commands() {
+    let dir = tempfile::tempdir().expect("create tempdir");
+    std::fs::write(dir.path().join("db_path"), "secret.db\n").expect("write db_path");
+    std::fs::write(
+        dir.path().join("Migrant.toml"),
+        "database_type = \"sqlite\"\n\
+         database_path = \"file:db_path\"\n\
+         migration_location = \"cmd:echo migrations\"\n",
+    )
+    .expect("write Migrant.toml");
+
+    migrant()
+        .current_dir(dir.path())
+        .env_remove("MIGRANT_ALLOW_CMD_SECRETS")
+        .arg("setup")
+        .assert()
+        .failure()
+        .stderr(contains("MIGRANT_ALLOW_CMD_SECRETS"));
+    migrant()
+        .current_dir(dir.path())
+        .env("MIGRANT_ALLOW_CMD_SECRETS", "1")
+        .arg("connect-string")
+        .assert()
+        .success()
+        .stdout(contains("secret.db"));
+
+    std::fs::remove_file(dir.path().join("db_path")).expect("remove db_path");
+    migrant()
+        .current_dir(dir.path())
+        .env("MIGRANT_ALLOW_CMD_SECRETS", "1")
+        .arg("setup")
+        .assert()
+        .failure()
+        .stderr(contains("db_path"));
+}